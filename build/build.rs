@@ -1,9 +1,13 @@
 use crate::codegen::*;
 use def::*;
-use std::{fs, path::PathBuf};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
 mod codegen;
 mod def;
+mod validate;
 
 macro_rules! read_from {
     ($path:expr => $type:ident) => {
@@ -36,8 +40,10 @@ macro_rules! process_defs {
         let (defs, paths): (Vec<$type>, Vec<PathBuf>) = read_from!($from => $type).unzip();
         fs::write($to, $gen(&defs)).unwrap();
         println!("Total $type generated: {}", defs.len());
+        // the directory itself catches added and removed definitions
+        println!("cargo:rerun-if-changed={}", $from);
         for path in paths {
-            println!("cargo:rerun-if-changed={:?}", path);
+            println!("cargo:rerun-if-changed={}", path.display());
         }
     }
 }
@@ -59,13 +65,30 @@ fn filter_by_rarity(def: &&EntityDef, rarity: &str) -> bool {
 fn is_def_common(def: &&EntityDef) -> bool { filter_by_rarity(def, "common") }
 fn is_def_rare(def: &&EntityDef) -> bool { filter_by_rarity(def, "rare") }
 fn is_def_legendary(def: &&EntityDef) -> bool { filter_by_rarity(def, "legendary") }
+fn is_def_boss(def: &&EntityDef) -> bool { def.components.contains_key("BossPhases") }
 
 fn main() {
     let out_dir = std::env::var("OUT_DIR").unwrap();
     fs::create_dir_all(format!("{}/generated/", out_dir)).unwrap();
 
+    // the validator looks at every resource (images, sounds, scripts, manifests),
+    // and listing anything disables the default "rerun on any change in the package"
+    println!("cargo:rerun-if-changed=resources");
+    println!("cargo:rerun-if-changed=build");
+
+    // catch broken references here instead of in the generated code
+    let problems = validate::validate(Path::new("resources"));
+    if !problems.is_empty() {
+        for problem in &problems {
+            println!("cargo:warning={}", problem);
+        }
+        panic!("Found {} problems in resources, run `cargo run --bin lint` to see them", problems.len());
+    }
+
     process_defs!("resources/entities" => format!("{}/generated/entity.rs", out_dir), EntityDef, |entities|{
-        generate_full_group(entities, "e").to_string()
+        let mut entities_body = generate_full_group(entities, "e");
+        entities_body.raw(&generate_array_by_filter(entities, "BOSSES", is_def_boss));
+        entities_body.to_string()
     });
 
     process_defs!("resources/items" => format!("{}/generated/item.rs", out_dir), EntityDef, |items|{
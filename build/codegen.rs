@@ -12,10 +12,11 @@ pub fn generate_arenas(arenas: &[ArenaDef]) -> Scope {
         fn_gen.arg("id", "ID");
         fn_gen.arg("arena", "&mut resource::Arena");
         fn_gen.arg("spawn_queue", "&mut resource::SpawnQueue");
+        fn_gen.arg("rng", "&mut resource::RandomGenerator");
         fn_gen.vis("pub");
         fn_gen.line("match id {");
         for arena in arenas {
-            fn_gen.line(&format!("ID::{} => set_{}(arena, spawn_queue),", arena.name.to_camel_case(), arena.name));
+            fn_gen.line(&format!("ID::{} => set_{}(arena, spawn_queue, rng),", arena.name.to_camel_case(), arena.name));
         }
         fn_gen.line("}");
         scope.push_fn(fn_gen);
@@ -24,9 +25,10 @@ pub fn generate_arenas(arenas: &[ArenaDef]) -> Scope {
         let mut fn_gen = Function::new(&format!("set_{}", arena.name));
         fn_gen.arg("arena", "&mut resource::Arena");
         fn_gen.arg("spawn_queue", "&mut resource::SpawnQueue");
+        fn_gen.arg("rng", "&mut resource::RandomGenerator");
         fn_gen.vis("pub");
         fn_gen.line(format!("arena.size = crate::math::Size2f::new({}f32, {}f32);", arena.width, arena.height));
-        fn_gen.line("use rand::{thread_rng, seq::SliceRandom, Rng};");
+        fn_gen.line("use rand::{seq::SliceRandom, Rng};");
         for entity in &arena.entities {
             fn_gen.line(format!(
                 "spawn_queue.0.push_back(resource::SpawnItem::Entity(entity::ID::{}, crate::math::Point2f::new({}f32, {}f32), vec![]));",
@@ -36,8 +38,7 @@ pub fn generate_arenas(arenas: &[ArenaDef]) -> Scope {
         for point in &arena.spawn_points {
             let halfr = point.radius * 0.5;
             fn_gen.line("{");
-            fn_gen.line("let mut rng = thread_rng();");
-            fn_gen.line("let generated = SPAWN_GROUPS.choose(&mut rng).unwrap().spawn(arena.difficulty);");
+            fn_gen.line("let generated = SPAWN_GROUPS.choose(rng).unwrap().spawn(arena.difficulty, rng);");
             fn_gen.line("for id in generated {");
             fn_gen.line(format!(
                 "spawn_queue.0.push_back(resource::SpawnItem::Entity(id,\
@@ -54,6 +55,7 @@ pub fn generate_arenas(arenas: &[ArenaDef]) -> Scope {
         names.clone().join(",")
     ));
     scope.raw(&format!("pub const IDS: [ID; {}] = [{}];", names.len(), names.map(|n| format!("ID::{}", n)).join(",")));
+    scope.raw(&generate_names_impl(&arenas.iter().map(|def| def.name.as_str()).collect_vec()));
     scope
 }
 
@@ -84,10 +86,10 @@ pub fn generate_spawn_groups(spawn_groups: &[SpawnGroupDef]) -> Scope {
     fn_gen.vis("pub");
     fn_gen.arg_self();
     fn_gen.arg("difficulty", "f32");
+    fn_gen.arg("rng", "&mut resource::RandomGenerator");
     fn_gen.ret("Vec<entity::ID>");
     fn_gen.line("use rand::distributions::weighted::alias_method::WeightedIndex;");
     fn_gen.line("use rand::prelude::*;");
-    fn_gen.line("let mut rng = thread_rng();");
     fn_gen.line("match self {");
     for grp in spawn_groups {
         let shouty = grp.name.to_shouty_snake_case();
@@ -96,7 +98,7 @@ pub fn generate_spawn_groups(spawn_groups: &[SpawnGroupDef]) -> Scope {
                 let dist = WeightedIndex::new({}_WEIGHTS.to_vec()).unwrap();\
                 let size = {}u32 + (difficulty * {}f32).floor() as u32;\
                 let mut v = Vec::with_capacity(size as usize);\
-                for _ in 0..=size {{ v.push({}_CHOICES[dist.sample(&mut rng)]) }}\
+                for _ in 0..=size {{ v.push({}_CHOICES[dist.sample(rng)]) }}\
                 v }}",
             grp.name.to_camel_case(),
             shouty,
@@ -143,7 +145,20 @@ pub fn generate_names_enum(defs: &[EntityDef]) -> String {
         names.clone().join(",")
     );
     let array = format!("pub const IDS: [ID; {}] = [{}];", names.len(), names.map(|n| format!("ID::{}", n)).join(","));
-    format!("{}\n{}", r#enum, array)
+    let names_impl = generate_names_impl(&defs.iter().map(|def| def.name.as_str()).collect_vec());
+    format!("{}\n{}\n{}", r#enum, array, names_impl)
+}
+
+// conversion between generated ID and original yaml file names
+pub fn generate_names_impl(names: &[&str]) -> String {
+    format!(
+        "impl ID {{\
+            pub fn name(&self) -> &'static str {{ match self {{ {} }} }}\
+            pub fn from_name(name: &str) -> Option<Self> {{ match name {{ {}, _ => None }} }}\
+        }}",
+        names.iter().map(|n| format!("ID::{} => \"{}\"", n.to_camel_case(), n)).join(","),
+        names.iter().map(|n| format!("\"{}\" => Some(ID::{})", n, n.to_camel_case())).join(",")
+    )
 }
 
 pub fn generate_array_by_filter<F: FnMut(&&EntityDef) -> bool>(
@@ -165,7 +180,7 @@ pub fn generate_generic_spawn_fn(defs: &[EntityDef]) -> Function {
     fn_gen
         .arg("id", "ID")
         .arg("world", "&specs::World")
-        .arg("ctx", "&mut ggez::Context")
+        .arg("ctx", "&mut dyn crate::assets::AssetContext")
         .arg("assets", "&mut crate::assets::AssetManager");
     fn_gen.ret("specs::Entity");
     fn_gen.vis("pub");
@@ -201,7 +216,10 @@ fn get_view_from<'a>(
 
 pub fn generate_generic_view_fn(defs: &[EntityDef]) -> Function {
     let mut fn_gen = Function::new("view");
-    fn_gen.arg("id", "ID").arg("ctx", "&mut ggez::Context").arg("assets", "&mut crate::assets::AssetManager");
+    fn_gen
+        .arg("id", "ID")
+        .arg("ctx", "&mut dyn crate::assets::AssetContext")
+        .arg("assets", "&mut crate::assets::AssetManager");
     fn_gen.ret("Option<(std::sync::Arc<crate::assets::ImageAsset>, crate::math::Size2f)>");
     fn_gen.vis("pub");
 
@@ -286,7 +304,7 @@ pub fn generate_spawn_fn(def: &EntityDef, reflection_prefix: &str) -> Function {
     let mut fn_gen = Function::new(&format!("spawn_{}", def.name));
     fn_gen
         .arg("world", "&specs::World")
-        .arg("ctx", "&mut ggez::Context")
+        .arg("ctx", "&mut dyn crate::assets::AssetContext")
         .arg("assets", "&mut crate::assets::AssetManager");
     fn_gen.ret("specs::Entity");
     fn_gen.vis("pub");
@@ -325,7 +343,7 @@ pub fn generate_spawn_fn(def: &EntityDef, reflection_prefix: &str) -> Function {
     for (name, _) in &def.shared_components {
         fn_gen.line(format!(".with(component::Shared{}::from(shared_{}))", name, name.to_snake_case()));
     }
-    fn_gen.line(&format!(".with(component::Reflection{{id:\"{}_{}\"}})", reflection_prefix, def.name));
+    fn_gen.line(&format!(".with(component::Reflection{{id:\"{}_{}\".into()}})", reflection_prefix, def.name));
     fn_gen.line(".build();");
     for line in buffers.1 {
         fn_gen.line(&line);
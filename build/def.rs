@@ -1,4 +1,4 @@
-use heck::{CamelCase, ShoutySnakeCase};
+use heck::CamelCase;
 use serde::{
     de::{self, MapAccess, SeqAccess, Visitor},
     Deserialize, Deserializer,
@@ -59,6 +59,62 @@ pub struct EntityDef {
     pub tags: Vec<String>,
 }
 
+/// Keys of a map with a value per damage type
+pub const DAMAGE_TYPES: [&str; 4] = ["Physical", "Impact", "Lightning", "Fire"];
+
+/// Components which can be described in yaml, so the validator accepts them and
+/// `src/prefab.rs` builds them (its tests check that every one of them is handled).
+/// Reloadable ones only hold definition values (and some runtime state
+/// `Prefab::reapply` takes care of), so hot reload replaces them on live entities.
+pub const COMPONENTS: [(&str, bool); 30] = [
+    ("Sprite", true),
+    ("Physic", false),
+    ("Transform", false),
+    ("Movement", true),
+    ("Directional", false),
+    ("Named", true),
+    ("Quality", true),
+    ("Stackable", true),
+    ("WeaponAttack", true),
+    ("WeaponProperties", true),
+    ("RandomizedWeaponProperties", true),
+    ("Weaponry", false),
+    ("Inventory", false),
+    ("Hotbar", false),
+    ("Consumer", false),
+    ("Consumable", false),
+    ("Shapeshifter", false),
+    ("BossPhases", false),
+    ("HealthPool", true),
+    ("DamageReciever", false),
+    ("Faction", false),
+    ("Target", false),
+    ("SearchForTarget", true),
+    ("FollowTarget", false),
+    ("ShootTarget", false),
+    ("AvoidShots", false),
+    ("SoundEffects", true),
+    ("ParticleProperties", false),
+    ("AnimatedSprite", false),
+    ("StatusEffects", false),
+];
+pub const SHARED_COMPONENTS: [&str; 3] = ["DropTable", "ParticleDef", "AnimationDef"];
+pub const TAGS: [&str; 5] = ["Player", "LevelChanger", "PendingDestruction", "Container", "LastShot"];
+
+/// Behaviours implemented in code which definitions refer to by name
+/// (`src/prefab.rs` tests check that every one of them resolves)
+pub const ATTACK_PATTERNS: [&str; 5] = ["Generic", "Scripted", "Ram", "SelfDestruct", "Lightning"];
+pub const CONSUMABLE_BEHAVIOURS: [&str; 4] = ["Scripted", "Pitaya", "Coconut", "Starfruit"];
+pub const SHAPESHIFTER_FORMS: [&str; 7] = [
+    "CrabUnderwaterForm",
+    "CrabShooterForm",
+    "CrabJrUnderwaterForm",
+    "CrabJrShooterForm",
+    "CrabJrShockedForm",
+    "MimicSleep",
+    "MimicAttack",
+];
+
 #[derive(Default)]
 pub struct ComponentDef {
     pub default: bool,
@@ -76,10 +132,17 @@ pub enum PartValue {
     Faction(String),
     CollisionGroup(String),
     Rarity(String),
+    DamageType(String),
+    PerDamageType(Map<String, PartValue>),
     AttackPattern(String, Map<String, PartValue>),
+    SubProjectiles(Map<String, PartValue>),
     ConsumableBehaviour(String, Map<String, PartValue>),
     ShapeshifterForms(Vec<PartValue>),
+    ScriptedForm(Map<String, PartValue>),
+    AnimationClip(String, Map<String, PartValue>),
+    BossPhase(String, Map<String, PartValue>),
     Item(String),
+    Sound(String),
     Range(Box<PartValue>, Box<PartValue>),
     Directional {
         north: Box<PartValue>,
@@ -125,21 +188,77 @@ impl std::fmt::Display for PartValue {
                 "vec![{}].drain(..).collect()",
                 vec.iter().map(|x| format!("{}", x)).collect::<Vec<String>>().join(",")
             ),
-            PartValue::Str(value) => write!(f, "\"{}\"", value),
+            PartValue::Str(value) => write!(f, "\"{}\".into()", value),
             PartValue::Numf(value) => write!(f, "{}f32", value),
             PartValue::Numi(value) => write!(f, "{}", value),
             PartValue::Bool(value) => write!(f, "{}", value),
             PartValue::Image(path) => write!(f, "assets.get::<crate::assets::ImageAsset>(\"{}\", ctx).unwrap()", path),
             PartValue::Faction(faction) => write!(f, "component::FactionId::{}", faction.to_camel_case()),
             PartValue::Item(id) => write!(f, "crate::item::spawn_{}(world, ctx, assets).into()", id),
+            PartValue::Sound(path) => write!(f, "Some(\"{}\".into())", path),
             PartValue::CollisionGroup(group) => {
                 write!(f, "(component::CollisionGroup::{} as usize)", group.to_camel_case())
             },
-            PartValue::ShapeshifterForms(..) => write!(f, "&SHAPESHIFTER_FORMS"),
+            PartValue::ShapeshifterForms(..) => write!(f, "shapeshifter_forms.clone()"),
+            PartValue::ScriptedForm(fields) => write!(f, "{}", struct_literal("ScriptedForm", fields)),
+            PartValue::AnimationClip(name, fields) => {
+                // every field but the name can be omitted
+                let fields: Vec<String> = fields
+                    .iter()
+                    .filter(|(k, _)| *k != "__default")
+                    .map(|(k, v)| match (k.as_str(), v) {
+                        ("next", PartValue::Str(next)) => format!("next:Some(\"{}\".into())", next),
+                        _ => format!("{}:{}", k, v),
+                    })
+                    .collect();
+                write!(
+                    f,
+                    "component::AnimationClip{{name:\"{}\".into(),{}..component::AnimationClip::DEFAULT}}",
+                    name,
+                    fields.iter().map(|field| format!("{},", field)).collect::<String>()
+                )
+            },
+            PartValue::BossPhase(name, fields) => {
+                // every field but the name can be omitted, weapon and forms are optional on their own
+                let fields: Vec<String> = fields
+                    .iter()
+                    .filter(|(k, _)| *k != "__default")
+                    .map(|(k, v)| match (k.as_str(), v) {
+                        ("weapon", _) | (_, PartValue::Range(..)) => format!("{}:{}", k, v),
+                        _ => format!("{}:Some({})", k, v),
+                    })
+                    .collect();
+                write!(
+                    f,
+                    "component::BossPhase{{name:\"{}\".into(),{}..component::BossPhase::DEFAULT}}",
+                    name,
+                    fields.iter().map(|field| format!("{},", field)).collect::<String>()
+                )
+            },
             PartValue::Rarity(rarity) => write!(f, "component::Rarity::{}", rarity.to_camel_case()),
-            PartValue::AttackPattern(pattern, ..) => write!(f, "&ATTACK_PATTERN_{}", pattern.to_shouty_snake_case()),
-            PartValue::ConsumableBehaviour(consumable, ..) => {
-                write!(f, "&CONSUMABLE_BEHAVIOUR_{}", consumable.to_shouty_snake_case())
+            PartValue::DamageType(damage_type) => write!(f, "component::DamageType::{}", damage_type.to_camel_case()),
+            PartValue::PerDamageType(values) => {
+                // types which are not listed are zero
+                write!(f, "{{let mut map=enum_map::EnumMap::<component::DamageType,f32>::default();")?;
+                for (damage_type, value) in values {
+                    let value = match value {
+                        PartValue::Numi(value) => format!("{}f32", value),
+                        value => format!("{}", value),
+                    };
+                    write!(f, "map[component::DamageType::{}]={};", damage_type.to_camel_case(), value)?;
+                }
+                write!(f, "map}}")
+            },
+            PartValue::AttackPattern(pattern, fields) => {
+                let struct_name = format!("crate::attack::{}", pattern.to_camel_case());
+                write!(f, "std::sync::Arc::new({})", struct_literal(&struct_name, fields))
+            },
+            PartValue::ConsumableBehaviour(consumable, fields) => {
+                let struct_name = format!("crate::item::{}", consumable.to_camel_case());
+                write!(f, "std::sync::Arc::new({})", struct_literal(&struct_name, fields))
+            },
+            PartValue::SubProjectiles(fields) => {
+                write!(f, "Some({})", struct_literal("crate::attack::SubProjectiles", fields))
             },
             PartValue::Range(start, end) => write!(f, "({}..={}).into()", start, end),
             PartValue::Directional { north, east, south, west } => write!(
@@ -163,6 +282,21 @@ impl std::fmt::Display for PartValue {
     }
 }
 
+// utility: struct literal with given fields, `__default: true` fills the rest from `DEFAULT` const
+fn struct_literal(struct_name: &str, fields: &Map<String, PartValue>) -> String {
+    let default = matches!(fields.get("__default"), Some(PartValue::Bool(true)));
+    let mut fields: Vec<String> =
+        fields.iter().filter(|(k, _)| *k != "__default").map(|(k, v)| format!("{}:{}", k, v)).collect();
+    if default {
+        fields.push(format!("..{}::DEFAULT", struct_name));
+    }
+    if fields.is_empty() {
+        struct_name.to_owned()
+    } else {
+        format!("{}{{{}}}", struct_name, fields.join(","))
+    }
+}
+
 impl PartValue {
     pub fn is_dependent(&self, other: &Self) -> bool {
         match (self, other) {
@@ -175,62 +309,19 @@ impl PartValue {
 
     pub fn initialize(&self) -> Option<String> {
         match self {
-            PartValue::ConsumableBehaviour(consumable, fields) => {
-                let (var_name, struct_name) =
-                    (consumable.to_shouty_snake_case(), format!("crate::item::{}", consumable.to_camel_case()));
-                if fields.is_empty() {
-                    Some(format!("const CONSUMABLE_BEHAVIOUR_{}: {} = {};", var_name, struct_name, struct_name))
-                } else {
-                    Some(format!(
-                        "const CONSUMABLE_BEHAVIOUR_{}: {} = {}{{{}}};",
-                        var_name,
-                        struct_name,
-                        struct_name,
-                        fields.iter().map(|(k, v)| format!("{}:{}", k, v)).collect::<Vec<String>>().join(",")
-                    ))
-                }
-            },
-            PartValue::AttackPattern(pattern, fields) => {
-                let (var_name, struct_name) =
-                    (pattern.to_shouty_snake_case(), format!("crate::attack::{}", pattern.to_camel_case()));
-                if fields.is_empty() {
-                    Some(format!("const ATTACK_PATTERN_{}: {} = {};", var_name, struct_name, struct_name))
-                } else {
-                    Some(format!(
-                        "const ATTACK_PATTERN_{}: {} = {}{{{}}};",
-                        var_name,
-                        struct_name,
-                        struct_name,
-                        fields.iter().map(|(k, v)| format!("{}:{}", k, v)).collect::<Vec<String>>().join(",")
-                    ))
-                }
-            },
             PartValue::ShapeshifterForms(forms) => {
-                let (mut lines, vars): (Vec<_>, Vec<_>) = forms
+                // built-in forms are referred by their struct names
+                let forms: Vec<String> = forms
                     .iter()
-                    .filter_map(|form| {
-                        if let PartValue::Str(struct_name) = form {
-                            let var_name = struct_name.to_shouty_snake_case();
-                            Some((struct_name, var_name))
-                        } else {
-                            None
-                        }
-                    })
-                    .map(|(struct_name, var_name)| {
-                        (
-                            (format!("const {}: {} = {};", var_name, struct_name, struct_name)),
-                            (format!("&{}", var_name)),
-                        )
+                    .filter_map(|form| match form {
+                        PartValue::Str(struct_name) => Some(format!("std::sync::Arc::new({})", struct_name)),
+                        PartValue::ScriptedForm(..) => Some(format!("std::sync::Arc::new({})", form)),
+                        _ => None,
                     })
-                    .unzip();
-                lines.sort_unstable();
-                lines.dedup();
-
+                    .collect();
                 Some(format!(
-                    "{}\nconst SHAPESHIFTER_FORMS: [&'static dyn component::ShapeshifterForm; {}] = [{}];",
-                    lines.join("\n"),
-                    vars.len(),
-                    vars.join(",")
+                    "let shapeshifter_forms: Vec<std::sync::Arc<dyn component::ShapeshifterForm>> = vec![{}];",
+                    forms.join(",")
                 ))
             },
             PartValue::Body { mass, status } => Some(format!(
@@ -316,7 +407,7 @@ impl PartValue {
     pub fn finalize(&self) -> Option<String> {
         match self {
             PartValue::ShapeshifterForms(..) => Some(
-                "SHAPESHIFTER_FORMS[0].on_begin(entity, &world.read_resource::<specs::LazyUpdate>(), (ctx, assets));"
+                "shapeshifter_forms[0].on_begin(entity, &world.read_resource::<specs::LazyUpdate>(), (ctx, assets));"
                     .into(),
             ),
             PartValue::Body { .. } => Some(
@@ -401,6 +492,8 @@ impl<'de> Visitor<'de> for PartValueVisitor {
                 ("faction", PartValue::Str(value)) => return Ok(PartValue::Faction(value)),
                 ("rarity", PartValue::Str(value)) => return Ok(PartValue::Rarity(value)),
                 ("item", PartValue::Str(value)) => return Ok(PartValue::Item(value)),
+                ("sound", PartValue::Str(value)) => return Ok(PartValue::Sound(value)),
+                ("damage_type", PartValue::Str(value)) => return Ok(PartValue::DamageType(value)),
                 (key, value) => {
                     buffer.insert(key.to_owned(), value);
                 },
@@ -444,6 +537,9 @@ impl<'de> Visitor<'de> for PartValueVisitor {
             Ok(PartValue::AttackPattern(pattern, buffer))
         } else if let Some(PartValue::Str(consumable)) = buffer.remove("consumable_behaviour") {
             Ok(PartValue::ConsumableBehaviour(consumable, buffer))
+        } else if let Some(count) = buffer.remove("sub_projectiles") {
+            buffer.insert("count".to_owned(), count);
+            Ok(PartValue::SubProjectiles(buffer))
         } else if let (Some(start), Some(end)) = (buffer.remove("start"), buffer.remove("end")) {
             Ok(PartValue::Range(Box::new(start), Box::new(end)))
         } else if let (Some(PartValue::Bool(sensor)), Some(shape), hitbox, Some(PartValue::Seq(collision_membership))) = (
@@ -460,6 +556,14 @@ impl<'de> Visitor<'de> for PartValueVisitor {
             })
         } else if let Some(PartValue::Seq(forms)) = buffer.remove("shapeshifter_forms") {
             Ok(PartValue::ShapeshifterForms(forms))
+        } else if let Some(PartValue::Str(clip)) = buffer.remove("clip") {
+            Ok(PartValue::AnimationClip(clip, buffer))
+        } else if let Some(PartValue::Str(phase)) = buffer.remove("phase") {
+            Ok(PartValue::BossPhase(phase, buffer))
+        } else if buffer.contains_key("script") {
+            Ok(PartValue::ScriptedForm(buffer))
+        } else if !buffer.is_empty() && buffer.keys().all(|key| DAMAGE_TYPES.contains(&key.to_camel_case().as_str())) {
+            Ok(PartValue::PerDamageType(buffer))
         } else {
             Err(de::Error::custom(format!("No special fields defined. Here is buffer: {:?}", buffer)))
         }
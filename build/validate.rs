@@ -0,0 +1,401 @@
+use crate::def::{
+    ArenaDef, EntityDef, PartValue, SpawnGroupDef, ATTACK_PATTERNS, COMPONENTS, CONSUMABLE_BEHAVIOURS, DAMAGE_TYPES,
+    SHAPESHIFTER_FORMS, SHARED_COMPONENTS, TAGS,
+};
+use heck::CamelCase;
+use serde::{de::DeserializeOwned, Deserialize};
+use std::{
+    collections::{BTreeMap as Map, BTreeSet as Set},
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+// Names the game knows about. Keep those in sync with `src/ecs/component.rs`
+// (components, tags and behaviours implemented in code are listed in `def.rs`).
+const CLIP_FIELDS: [&str; 5] = ["first", "frames", "time_per_frame", "looping", "next"];
+const PHASE_FIELDS: [&str; 7] = ["hp_below", "event", "forms", "weapon", "max_velocity", "acceleration_flat", "sprite"];
+const FACTIONS: [&str; 4] = ["Good", "Pirates", "Crabs", "Mythical"];
+const COLLISION_GROUPS: [&str; 5] = ["Players", "Enemies", "Props", "Projectiles", "Hitbox"];
+const RARITIES: [&str; 3] = ["Common", "Rare", "Legendary"];
+const BODY_STATUSES: [&str; 4] = ["Dynamic", "Static", "Kinematic", "Disabled"];
+
+/// Something wrong with the resources. Line of problems which are not
+/// syntax errors is the first line mentioning the wrong thing.
+pub struct Problem {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub message: String,
+}
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+// the same layout as `Manifest` in `src/manifest.rs`
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct ManifestDef {
+    include: Vec<String>,
+    prefabs: Vec<String>,
+    images: Vec<String>,
+    sounds: Vec<String>,
+    scripts: Vec<String>,
+}
+
+struct Source<T> {
+    name: String,
+    path: PathBuf,
+    content: String,
+    def: T,
+}
+
+/// Check every definition in `root` (usually `resources`) and references
+/// between them, returning all the problems instead of stopping at the first one
+pub fn validate(root: &Path) -> Vec<Problem> {
+    let mut validator = Validator { root, problems: vec![] };
+    let entities: Vec<Source<EntityDef>> = validator.read_all("entities");
+    let items: Vec<Source<EntityDef>> = validator.read_all("items");
+    let particles: Vec<Source<EntityDef>> = validator.read_all("particles");
+    let arenas: Vec<Source<ArenaDef>> = validator.read_all("arenas");
+    let spawn_groups: Vec<Source<SpawnGroupDef>> = validator.read_all("spawn_groups");
+
+    let entity_names: Set<&str> = entities.iter().map(|s| s.name.as_str()).collect();
+    let item_names: Set<&str> = items.iter().map(|s| s.name.as_str()).collect();
+
+    for source in entities.iter().chain(&items).chain(&particles) {
+        validator.check_entity(source, &item_names);
+    }
+    for source in &arenas {
+        for entity in &source.def.entities {
+            if !entity_names.contains(entity.id.as_str()) {
+                validator.report(source, &entity.id, format!("Arena references unknown entity {:?}", entity.id));
+            }
+        }
+    }
+    for source in &spawn_groups {
+        if source.def.weighted.is_empty() {
+            validator.report(source, "weighted", "Spawn group has nothing to spawn".to_owned());
+        }
+        for weighted in &source.def.weighted {
+            if !entity_names.contains(weighted.id.as_str()) {
+                let message = format!("Spawn group references unknown entity {:?}", weighted.id);
+                validator.report(source, &weighted.id, message);
+            }
+        }
+    }
+    // preload manifests are optional
+    if root.join("manifests").is_dir() {
+        let manifests: Vec<Source<ManifestDef>> = validator.read_all("manifests");
+        let manifest_names: Set<&str> = manifests.iter().map(|s| s.name.as_str()).collect();
+        let prefab_ids: Set<String> = (entity_names.iter().map(|n| format!("e_{}", n)))
+            .chain(item_names.iter().map(|n| format!("i_{}", n)))
+            .chain(particles.iter().map(|s| format!("p_{}", s.name)))
+            .collect();
+        for source in &manifests {
+            validator.check_manifest(source, &manifest_names, &prefab_ids);
+        }
+    }
+    validator.problems
+}
+
+struct Validator<'a> {
+    root: &'a Path,
+    problems: Vec<Problem>,
+}
+
+impl<'a> Validator<'a> {
+    fn read_all<T: DeserializeOwned>(&mut self, dir: &str) -> Vec<Source<T>> {
+        let entries = match fs::read_dir(self.root.join(dir)) {
+            Ok(entries) => entries,
+            Err(err) => {
+                self.problems.push(Problem { path: self.root.join(dir), line: None, message: err.to_string() });
+                return vec![];
+            },
+        };
+        let mut paths: Vec<PathBuf> =
+            entries.filter_map(|r| r.ok()).map(|e| e.path()).filter(|p| p.is_file()).collect();
+        paths.sort();
+
+        let mut sources = vec![];
+        for path in paths {
+            let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default().to_owned();
+            let content = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(err) => {
+                    self.problems.push(Problem { path, line: None, message: err.to_string() });
+                    continue;
+                },
+            };
+            match serde_yaml::from_str::<T>(&content) {
+                Ok(def) => sources.push(Source { name, path, content, def }),
+                Err(err) => {
+                    let line = err.location().map(|l| l.line());
+                    self.problems.push(Problem { path, line, message: err.to_string() });
+                },
+            }
+        }
+        sources
+    }
+
+    fn report<T>(&mut self, source: &Source<T>, mention: &str, message: String) {
+        let line = source.content.lines().position(|line| line.contains(mention)).map(|i| i + 1);
+        self.problems.push(Problem { path: source.path.clone(), line, message });
+    }
+
+    fn check_manifest(&mut self, source: &Source<ManifestDef>, manifests: &Set<&str>, prefabs: &Set<String>) {
+        for name in &source.def.include {
+            if !manifests.contains(name.as_str()) {
+                self.report(source, name, format!("Manifest includes unknown manifest {:?}", name));
+            }
+        }
+        for id in &source.def.prefabs {
+            if !prefabs.contains(id) {
+                self.report(source, id, format!("Manifest mentions unknown prefab {:?}", id));
+            }
+        }
+        let files = (source.def.images.iter().map(|p| (p, "Image")))
+            .chain(source.def.sounds.iter().map(|p| (p, "Sound")))
+            .chain(source.def.scripts.iter().map(|p| (p, "Script")));
+        for (path, what) in files {
+            self.check_file(source, path, what);
+        }
+    }
+
+    fn check_entity(&mut self, source: &Source<EntityDef>, items: &Set<&str>) {
+        for tag in &source.def.tags {
+            if !TAGS.contains(&tag.to_camel_case().as_str()) {
+                self.report(source, tag, format!("Unknown tag {:?}", tag));
+            }
+        }
+        for (name, component) in &source.def.components {
+            if !COMPONENTS.iter().any(|(component, _)| component == name) {
+                self.report(source, name, format!("Unknown component {:?}", name));
+            }
+            self.check_parts(source, &component.parts, items);
+        }
+        for (name, component) in &source.def.shared_components {
+            if !SHARED_COMPONENTS.contains(&name.as_str()) {
+                self.report(source, name, format!("Unknown shared component {:?}", name));
+            }
+            self.check_parts(source, &component.parts, items);
+        }
+        let animated = source.def.components.contains_key("AnimatedSprite");
+        if animated && !source.def.shared_components.contains_key("AnimationDef") {
+            let message = "AnimatedSprite needs a shared AnimationDef".to_owned();
+            self.report(source, "AnimatedSprite", message);
+        }
+        if let Some(boss) = source.def.components.get("BossPhases") {
+            self.check_boss(source, &boss.parts);
+        }
+    }
+
+    // phases are switched by hp, and pick forms by their indices
+    fn check_boss(&mut self, source: &Source<EntityDef>, parts: &Map<String, PartValue>) {
+        if !source.def.components.contains_key("HealthPool") {
+            self.report(source, "BossPhases", "BossPhases needs a HealthPool".to_owned());
+        }
+        let form_count = match source.def.components.get("Shapeshifter").and_then(|s| s.parts.get("forms")) {
+            Some(PartValue::ShapeshifterForms(forms)) => forms.len(),
+            _ => 0,
+        };
+        let phases = match parts.get("phases") {
+            Some(PartValue::Seq(phases)) => phases,
+            _ => return,
+        };
+        for phase in phases {
+            let (name, fields) = match phase {
+                PartValue::BossPhase(name, fields) => (name, fields),
+                _ => {
+                    self.report(source, "phases", "Boss phase should have a `phase` name".to_owned());
+                    continue;
+                },
+            };
+            let fits = match fields.get("forms") {
+                Some(PartValue::Range(start, end)) => match (&**start, &**end) {
+                    (PartValue::Numi(start), PartValue::Numi(end)) => {
+                        0 <= *start && start <= end && (*end as usize) < form_count
+                    },
+                    _ => false,
+                },
+                Some(_) => false,
+                None => true,
+            };
+            if !fits {
+                let message = format!("Forms of phase {:?} should be a range of shapeshifter form indices", name);
+                self.report(source, name, message);
+            }
+        }
+    }
+
+    fn check_parts(&mut self, source: &Source<EntityDef>, parts: &Map<String, PartValue>, items: &Set<&str>) {
+        for part in parts.values() {
+            self.check_part(source, part, items);
+        }
+    }
+
+    fn check_part(&mut self, source: &Source<EntityDef>, part: &PartValue, items: &Set<&str>) {
+        match part {
+            PartValue::Seq(values) => {
+                for value in values {
+                    self.check_part(source, value, items);
+                }
+            },
+            PartValue::Image(path) => self.check_file(source, path, "Image"),
+            PartValue::Sound(path) => self.check_file(source, path, "Sound"),
+            PartValue::Item(id) => {
+                if !items.contains(id.as_str()) {
+                    self.report(source, id, format!("Unknown item {:?}", id));
+                }
+            },
+            PartValue::Faction(name) => self.check_name(source, name, &FACTIONS, "faction"),
+            PartValue::CollisionGroup(name) => self.check_name(source, name, &COLLISION_GROUPS, "collision group"),
+            PartValue::Rarity(name) => self.check_name(source, name, &RARITIES, "rarity"),
+            PartValue::DamageType(name) => self.check_name(source, name, &DAMAGE_TYPES, "damage type"),
+            PartValue::PerDamageType(values) => {
+                for (name, value) in values {
+                    if !matches!(value, PartValue::Numf(..) | PartValue::Numi(..)) {
+                        self.report(source, name, format!("Value for damage type {:?} should be a number", name));
+                    }
+                }
+            },
+            PartValue::Body { status, .. } => self.check_name(source, status, &BODY_STATUSES, "body status"),
+            PartValue::AttackPattern(name, fields) => {
+                self.check_name(source, name, &ATTACK_PATTERNS, "attack pattern");
+                self.check_script(source, fields);
+                self.check_parts(source, fields, items);
+            },
+            PartValue::ConsumableBehaviour(name, fields) => {
+                self.check_name(source, name, &CONSUMABLE_BEHAVIOURS, "consumable behaviour");
+                self.check_script(source, fields);
+                self.check_parts(source, fields, items);
+            },
+            PartValue::ShapeshifterForms(forms) => {
+                for form in forms {
+                    match form {
+                        // forms are looked up by their exact name
+                        PartValue::Str(name) if !SHAPESHIFTER_FORMS.contains(&name.as_str()) => {
+                            self.report(source, name, format!("Unknown shapeshifter form {:?}", name))
+                        },
+                        PartValue::Str(..) => {},
+                        PartValue::ScriptedForm(..) => self.check_part(source, form, items),
+                        _ => {
+                            let message = "Form should be either a name or a script".to_owned();
+                            self.report(source, "shapeshifter_forms", message)
+                        },
+                    }
+                }
+            },
+            PartValue::ScriptedForm(fields) | PartValue::SubProjectiles(fields) => {
+                self.check_script(source, fields);
+                self.check_parts(source, fields, items);
+            },
+            PartValue::AnimationClip(name, fields) => {
+                for key in fields.keys().filter(|key| !CLIP_FIELDS.contains(&key.as_str())) {
+                    self.report(source, name, format!("Unknown field {:?} of clip {:?}", key, name));
+                }
+                self.check_parts(source, fields, items);
+            },
+            PartValue::BossPhase(name, fields) => {
+                for key in fields.keys().filter(|key| !PHASE_FIELDS.contains(&key.as_str())) {
+                    self.report(source, name, format!("Unknown field {:?} of phase {:?}", key, name));
+                }
+                self.check_parts(source, fields, items);
+            },
+            PartValue::Range(start, end) => {
+                self.check_part(source, start, items);
+                self.check_part(source, end, items);
+            },
+            PartValue::Directional { north, east, west, south } => {
+                for value in &[north, east, west, south] {
+                    self.check_part(source, value, items);
+                }
+            },
+            PartValue::Single { value } => self.check_part(source, value, items),
+            PartValue::Collide { collision_membership, shape, hitbox, .. } => {
+                for value in collision_membership {
+                    self.check_part(source, value, items);
+                }
+                self.check_part(source, shape, items);
+                if let Some(hitbox) = hitbox {
+                    self.check_part(source, hitbox, items);
+                }
+            },
+            PartValue::Str(..)
+            | PartValue::Numf(..)
+            | PartValue::Numi(..)
+            | PartValue::Bool(..)
+            | PartValue::Size { .. }
+            | PartValue::Vec { .. }
+            | PartValue::Box { .. } => {},
+        }
+    }
+
+    fn check_name(&mut self, source: &Source<EntityDef>, name: &str, known: &[&str], what: &str) {
+        if !known.contains(&name.to_camel_case().as_str()) {
+            self.report(source, name, format!("Unknown {} {:?} (expected one of: {})", what, name, known.join(", ")));
+        }
+    }
+
+    fn check_script(&mut self, source: &Source<EntityDef>, fields: &Map<String, PartValue>) {
+        if let Some(PartValue::Str(path)) = fields.get("script") {
+            self.check_file(source, path, "Script");
+        }
+    }
+
+    // paths in definitions are relative to the resources root, just like ggez sees them
+    fn check_file<T>(&mut self, source: &Source<T>, path: &str, what: &str) {
+        if !self.root.join(path.trim_start_matches('/')).is_file() {
+            self.report(source, path, format!("{} {:?} doesn't exist", what, path));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resources_are_valid() {
+        let problems: Vec<String> = validate(Path::new("resources")).iter().map(Problem::to_string).collect();
+        assert!(problems.is_empty(), "{:#?}", problems);
+    }
+
+    #[test]
+    fn broken_resources_are_reported() {
+        let root = Path::new("tests/fixtures/broken_resources");
+        let (syntax, problems): (Vec<Problem>, Vec<Problem>) =
+            validate(root).into_iter().partition(|problem| problem.path.ends_with("syntax.yaml"));
+        assert!(matches!(syntax.as_slice(), [Problem { line: Some(_), .. }]));
+
+        let mut problems: Vec<String> = problems
+            .into_iter()
+            .map(|problem| Problem { path: problem.path.strip_prefix(root).unwrap().to_owned(), ..problem })
+            .map(|problem| problem.to_string())
+            .collect();
+        problems.sort();
+        let mut expected = vec![
+            r#"arenas/arena.yaml:6: Arena references unknown entity "kraken""#,
+            r#"entities/boss.yaml:2: BossPhases needs a HealthPool"#,
+            r#"entities/boss.yaml:4: Forms of phase "angry" should be a range of shapeshifter form indices"#,
+            r#"entities/boss.yaml:4: Unknown field "speed" of phase "angry""#,
+            r#"entities/ship.yaml:11: AnimatedSprite needs a shared AnimationDef"#,
+            r#"entities/ship.yaml:13: Unknown tag "flying""#,
+            r#"entities/ship.yaml:3: Image "/sprites/missing.png" doesn't exist"#,
+            r#"entities/ship.yaml:6: Unknown item "cannon""#,
+            r#"entities/ship.yaml:8: Unknown faction "ninjas" (expected one of: Good, Pirates, Crabs, Mythical)"#,
+            r#"entities/ship.yaml:9: Unknown component "Levitation""#,
+            r#"items/gun.yaml:3: Script "/scripts/missing.rhai" doesn't exist"#,
+            r#"items/gun.yaml:3: Unknown attack pattern "laser" (expected one of: Generic, Scripted, Ram, SelfDestruct, Lightning)"#,
+            r#"items/gun.yaml:5: Unknown damage type "poison" (expected one of: Physical, Impact, Lightning, Fire)"#,
+            r#"manifests/level.yaml:1: Manifest includes unknown manifest "missing""#,
+            r#"manifests/level.yaml:2: Manifest mentions unknown prefab "e_kraken""#,
+            r#"spawn_groups/empty.yaml: Spawn group has nothing to spawn"#,
+        ];
+        expected.sort();
+        assert_eq!(problems, expected);
+    }
+}
@@ -1,6 +1,57 @@
 #![allow(warnings)]
-use crate::{ecs::resource, entity, item};
+use crate::{ecs::resource, entity, item, prefab::Prefabs};
+use rand::{seq::SliceRandom, Rng};
+
+pub mod generator;
 
 // see `build/build.rs` for code generation
 include!(concat!(env!("OUT_DIR"), "/generated/arena.rs"));
 include!(concat!(env!("OUT_DIR"), "/generated/spawn_group.rs"));
+
+pub const BOSS_EVERY: u32 = 5;
+
+/// Either hand-made arena from `resources/arenas`, a generated one,
+/// which is fully defined by its seed, an endless open world
+/// generated chunk by chunk (see `ChunkSystem`) or a boss fight
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Kind {
+    Static(ID),
+    Generated { seed: u64 },
+    Open { seed: u64 },
+    Boss { seed: u64 },
+}
+impl Kind {
+    /// Pick any closed arena, static or generated
+    pub fn random(rng: &mut resource::RandomGenerator) -> Self {
+        if rng.gen_bool(0.5) {
+            Kind::Static(*IDS.choose(rng).unwrap())
+        } else {
+            Kind::Generated { seed: rng.gen() }
+        }
+    }
+
+    /// Arena behind the level changer: every `BOSS_EVERY` level is a boss fight
+    pub fn for_level(level: u32, rng: &mut resource::RandomGenerator) -> Self {
+        if level % BOSS_EVERY == 0 {
+            Kind::Boss { seed: rng.gen() }
+        } else {
+            Kind::random(rng)
+        }
+    }
+
+    pub fn set(
+        self,
+        arena: &mut resource::Arena,
+        spawn_queue: &mut resource::SpawnQueue,
+        rng: &mut resource::RandomGenerator,
+        prefabs: &Prefabs,
+    ) {
+        match self {
+            Kind::Static(id) => prefabs.set_arena(id, arena, spawn_queue, rng),
+            Kind::Generated { seed } => generator::generate(seed, arena, spawn_queue, prefabs),
+            // open world has no size, chunks are spawned as player moves
+            Kind::Open { .. } => arena.size = crate::math::Size2f::zero(),
+            Kind::Boss { seed } => generator::generate_boss(seed, arena, spawn_queue),
+        }
+    }
+}
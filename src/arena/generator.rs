@@ -0,0 +1,239 @@
+use crate::{
+    ecs::resource::{Arena, ChunkPos, Chunks, RandomGenerator, SpawnItem, SpawnQueue},
+    entity,
+    math::*,
+    prefab::Prefabs,
+};
+use rand::{seq::SliceRandom, Rng};
+
+// Props which can be placed on generated arena, with radius
+// of the circle they fit in and chance to be chosen
+const PROPS: [(entity::ID, f32, u32); 6] = [
+    (entity::ID::Reef1, 120.0, 4),
+    (entity::ID::Reef2, 95.0, 4),
+    (entity::ID::Reef3, 115.0, 3),
+    (entity::ID::Reef4, 30.0, 8),
+    (entity::ID::Wreck1, 105.0, 1),
+    (entity::ID::Wreck2, 110.0, 1),
+];
+// Islands are made of big reefs only
+const ISLAND_PROPS: [(entity::ID, f32, u32); 3] =
+    [(entity::ID::Reef1, 120.0, 2), (entity::ID::Reef2, 95.0, 2), (entity::ID::Reef3, 115.0, 3)];
+const ISLAND_RADIUS: f32 = 300.0;
+const ISLAND_CHANCE: f64 = 0.4;
+// area of the arena per one prop
+const PROP_DENSITY: f32 = 90_000.0;
+// minimal gap between any two props, island pieces are allowed to overlap a bit
+const PROP_GAP: f32 = 20.0;
+const ISLAND_GAP: f32 = -40.0;
+// nothing is placed that close to the player start (which is always origin)
+const START_CLEARANCE: f32 = 250.0;
+// enemies spawn at least that far from the player start
+const SPAWN_CLEARANCE: f32 = 600.0;
+const SPAWN_RADIUS: f32 = 100.0;
+const SPAWN_POINTS: usize = 4;
+const CHUNK_ENEMIES_CHANCE: f64 = 0.5;
+// boss arenas don't grow, bosses are hard enough as they are
+const BOSS_ARENA_WIDTH: f32 = 2400.0;
+const BOSS_ARENA_HEIGHT: f32 = 1500.0;
+const BOSS_RADIUS: f32 = 200.0;
+const BOSS_PROPS: usize = 6;
+// rejection sampling attempts per each thing to place
+const ATTEMPTS: usize = 30;
+
+/// Keeps track of taken space (as circles) inside of a rectangle
+/// to place things without overlapping
+struct Placer {
+    center: Point2f,
+    half: Size2f,
+    taken: Vec<(Point2f, f32)>,
+}
+impl Placer {
+    fn new(center: Point2f, size: Size2f) -> Self { Self { center, half: size * 0.5, taken: vec![] } }
+
+    fn fits(&self, pos: Point2f, radius: f32, gap: f32) -> bool {
+        let local = pos - self.center;
+        local.x.abs() < self.half.width - radius
+            && local.y.abs() < self.half.height - radius
+            && pos.to_vector().length() > START_CLEARANCE + radius
+            && self.taken.iter().all(|(other, other_r)| (*other - pos).length() > radius + other_r + gap)
+    }
+
+    /// Try to place a circle somewhere around `around` (or anywhere if it's None)
+    fn place(
+        &mut self,
+        rng: &mut RandomGenerator,
+        radius: f32,
+        gap: f32,
+        around: Option<(Point2f, f32)>,
+        filter: impl Fn(Point2f) -> bool,
+    ) -> Option<Point2f> {
+        for _ in 0..ATTEMPTS {
+            let pos = match around {
+                Some((center, spread)) => {
+                    center + Vec2f::new(rng.gen_range(-spread, spread), rng.gen_range(-spread, spread))
+                },
+                None => {
+                    self.center
+                        + Vec2f::new(
+                            rng.gen_range(-self.half.width, self.half.width),
+                            rng.gen_range(-self.half.height, self.half.height),
+                        )
+                },
+            };
+            if filter(pos) && self.fits(pos, radius, gap) {
+                self.taken.push((pos, radius));
+                return Some(pos);
+            }
+        }
+        None
+    }
+}
+
+fn spawn_enemies(
+    rng: &mut RandomGenerator,
+    point: Point2f,
+    difficulty: f32,
+    prefabs: &Prefabs,
+) -> Vec<(entity::ID, Point2f)> {
+    let halfr = SPAWN_RADIUS * 0.5;
+    let generated = prefabs.spawn_group(difficulty, rng);
+    generated
+        .into_iter()
+        .map(|id| (id, point + Vec2f::new(rng.gen_range(-halfr, halfr), rng.gen_range(-halfr, halfr))))
+        .collect()
+}
+
+/// Fill arena with props and enemies. The same seed and difficulty always
+/// produce the same arena.
+pub fn generate(seed: u64, arena: &mut Arena, spawn_queue: &mut SpawnQueue, prefabs: &Prefabs) {
+    let mut rng = RandomGenerator::new(seed);
+
+    // arenas grow with difficulty
+    let width = (1600.0 + 200.0 * arena.difficulty).min(3200.0) * rng.gen_range(0.9, 1.1);
+    let height = width * rng.gen_range(0.55, 0.7);
+    arena.size = Size2f::new(width, height);
+    let mut placer = Placer::new(Point2f::zero(), arena.size);
+
+    let spawn_points: Vec<Point2f> = (0..SPAWN_POINTS)
+        .filter_map(|_| {
+            placer.place(&mut rng, SPAWN_RADIUS, PROP_GAP, None, |pos| pos.to_vector().length() > SPAWN_CLEARANCE)
+        })
+        .collect();
+
+    let props_count = (width * height / PROP_DENSITY) as usize;
+    for _ in 0..props_count {
+        let (id, radius, _) = *PROPS.choose_weighted(&mut rng, |p| p.2).unwrap();
+        if let Some(pos) = placer.place(&mut rng, radius, PROP_GAP, None, |_| true) {
+            spawn_queue.0.push_back(SpawnItem::Entity(id, pos, vec![]));
+        }
+    }
+    log::debug!("Generated arena {:?} with {} props", arena.size, placer.taken.len() - spawn_points.len());
+
+    for point in spawn_points {
+        for (id, pos) in spawn_enemies(&mut rng, point, arena.difficulty, prefabs) {
+            spawn_queue.0.push_back(SpawnItem::Entity(id, pos, vec![]));
+        }
+    }
+}
+
+/// Arena with one of the bosses across from the player start and some props
+/// to hide behind. If there are no bosses at all, the way out opens right away.
+pub fn generate_boss(seed: u64, arena: &mut Arena, spawn_queue: &mut SpawnQueue) {
+    let mut rng = RandomGenerator::new(seed);
+    arena.size = Size2f::new(BOSS_ARENA_WIDTH, BOSS_ARENA_HEIGHT);
+    let mut placer = Placer::new(Point2f::zero(), arena.size);
+
+    let boss_pos = Point2f::new(0.0, -BOSS_ARENA_HEIGHT * 0.3);
+    placer.taken.push((boss_pos, BOSS_RADIUS));
+    for _ in 0..BOSS_PROPS {
+        let (id, radius, _) = *PROPS.choose_weighted(&mut rng, |p| p.2).unwrap();
+        if let Some(pos) = placer.place(&mut rng, radius, PROP_GAP, None, |_| true) {
+            spawn_queue.0.push_back(SpawnItem::Entity(id, pos, vec![]));
+        }
+    }
+
+    match entity::BOSSES.choose(&mut rng) {
+        Some(id) => spawn_queue.0.push_back(SpawnItem::Entity(*id, boss_pos, vec![])),
+        None => log::warn!("There are no bosses to fight"),
+    }
+}
+
+/// Props, islands and enemies of one open world chunk. Every chunk has its own
+/// seed derived from the world seed, so chunks can be generated in any order.
+pub fn generate_chunk(seed: u64, chunk: ChunkPos, difficulty: f32, prefabs: &Prefabs) -> Vec<(entity::ID, Point2f)> {
+    let chunk_seed = seed
+        ^ (chunk.0 as i64 as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (chunk.1 as i64 as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+    let mut rng = RandomGenerator::new(chunk_seed);
+    let mut placer = Placer::new(Chunks::center(chunk), Size2f::new(Chunks::SIZE, Chunks::SIZE));
+    let mut result = vec![];
+
+    if rng.gen_bool(ISLAND_CHANCE) {
+        if let Some(center) = placer.place(&mut rng, ISLAND_RADIUS * 0.25, PROP_GAP, None, |_| true) {
+            // island center is not a prop, just a seed for pieces around it
+            placer.taken.pop();
+            for _ in 0..rng.gen_range(3, 8) {
+                let (id, radius, _) = *ISLAND_PROPS.choose_weighted(&mut rng, |p| p.2).unwrap();
+                if let Some(pos) = placer.place(&mut rng, radius, ISLAND_GAP, Some((center, ISLAND_RADIUS)), |_| true) {
+                    result.push((id, pos));
+                }
+            }
+        }
+    }
+
+    for _ in 0..rng.gen_range(2, 6) {
+        let (id, radius, _) = *PROPS.choose_weighted(&mut rng, |p| p.2).unwrap();
+        if let Some(pos) = placer.place(&mut rng, radius, PROP_GAP, None, |_| true) {
+            result.push((id, pos));
+        }
+    }
+
+    if rng.gen_bool(CHUNK_ENEMIES_CHANCE) {
+        let spawn_point =
+            placer.place(&mut rng, SPAWN_RADIUS, PROP_GAP, None, |pos| pos.to_vector().length() > SPAWN_CLEARANCE);
+        if let Some(point) = spawn_point {
+            result.extend(spawn_enemies(&mut rng, point, difficulty, prefabs));
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entities(seed: u64, boss: bool) -> Vec<(entity::ID, Point2f)> {
+        let (mut arena, mut queue) = (Arena { difficulty: 2.0, ..Arena::default() }, SpawnQueue::default());
+        if boss {
+            generate_boss(seed, &mut arena, &mut queue);
+        } else {
+            generate(seed, &mut arena, &mut queue, &Prefabs::load());
+        }
+        queue
+            .0
+            .into_iter()
+            .filter_map(|item| match item {
+                SpawnItem::Entity(id, pos, _) => Some((id, pos)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_same_arena() {
+        assert_eq!(entities(5, false), entities(5, false));
+        assert_ne!(entities(5, false), entities(6, false));
+        assert_eq!(entities(5, true), entities(5, true));
+    }
+
+    #[test]
+    fn chunks_are_independent() {
+        // chunk content doesn't depend on what was generated before it
+        let prefabs = Prefabs::load();
+        let first = generate_chunk(5, (1, -2), 1.0, &prefabs);
+        generate_chunk(5, (0, 0), 1.0, &prefabs);
+        assert_eq!(generate_chunk(5, (1, -2), 1.0, &prefabs), first);
+    }
+}
@@ -1,26 +1,143 @@
-use crate::shader::ShaderInName;
+use crate::{prefab::RESOURCES_PATH, shader::ShaderInName};
 use gfx::{memory::Pod, pso::buffer::Structure, shade::ConstFormat};
-use ggez::graphics::{FilterMode, Image, Shader, WrapMode};
+use ggez::{
+    audio::SoundData,
+    graphics::{FilterMode, Image, Shader, WrapMode},
+};
 use log::debug;
-use std::{any::Any, collections::BTreeMap, sync::Arc};
+use std::{
+    any::Any,
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
+/// Loads assets once and keeps them while they are in use. Assets nobody
+/// but the manager holds are dropped by `evict_unused`, unless they were
+/// preloaded by a manifest which is still active (see `src/manifest.rs`).
 #[derive(Default)]
-pub struct AssetManager(BTreeMap<String, Arc<dyn Any + Send + Sync>>, u32);
+pub struct AssetManager {
+    assets: BTreeMap<String, Arc<dyn Any + Send + Sync>>,
+    last_id: u32,
+    // keys of preloaded assets by name of the manifest
+    pinned: BTreeMap<String, BTreeSet<String>>,
+    // keys which failed to load, so they are not read from disk every frame
+    failed: BTreeMap<String, String>,
+    preloading: bool,
+    stats: AssetStats,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AssetStats {
+    // currently loaded assets
+    pub cached: usize,
+    // loads from disk, ever
+    pub loaded: u32,
+    // loaded outside of preloading, which means in the middle of a frame
+    pub lazy: u32,
+    pub hits: u32,
+    pub failed: u32,
+    pub evicted: u32,
+    pub load_time: Duration,
+}
+
+#[derive(Debug)]
+pub enum AssetError {
+    Load { key: String, reason: String },
+    WrongType { key: String, expected: &'static str },
+}
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetError::Load { key, reason } => write!(f, "Unable to load asset {:?}: {}", key, reason),
+            AssetError::WrongType { key, expected } => write!(f, "Asset {:?} is not {}", key, expected),
+        }
+    }
+}
+impl std::error::Error for AssetError {}
 
 impl AssetManager {
-    pub fn get<A: Asset + 'static>(&mut self, key: &str, ctx: &mut A::Context) -> anyhow::Result<Arc<A>> {
-        if let Some(asset) = self.0.get(key) {
-            Ok(asset.to_owned().downcast::<A>().unwrap())
-        } else {
-            let asset = Arc::new(A::load(key, self.1 + 1, ctx)?);
-            self.1 += 1;
-            self.0.insert(key.to_owned(), asset.clone());
-            Ok(asset)
+    pub fn get<A: Asset + 'static>(&mut self, key: &str, ctx: &mut A::Context) -> Result<Arc<A>, AssetError> {
+        if let Some(asset) = self.assets.get(key) {
+            self.stats.hits += 1;
+            return asset.to_owned().downcast::<A>().map_err(|_| AssetError::WrongType {
+                key: key.to_owned(),
+                expected: std::any::type_name::<A>(),
+            });
+        }
+        if let Some(reason) = self.failed.get(key) {
+            return Err(AssetError::Load { key: key.to_owned(), reason: reason.to_owned() });
+        }
+
+        let start = Instant::now();
+        match A::load(key, self.last_id + 1, ctx) {
+            Ok(asset) => {
+                let asset = Arc::new(asset);
+                self.last_id += 1;
+                self.assets.insert(key.to_owned(), asset.clone());
+                self.stats.loaded += 1;
+                self.stats.load_time += start.elapsed();
+                if !self.preloading {
+                    self.stats.lazy += 1;
+                }
+                Ok(asset)
+            },
+            Err(err) => {
+                let reason = format!("{:#}", err);
+                log::error!("Unable to load asset {:?}: {}", key, reason);
+                self.failed.insert(key.to_owned(), reason.clone());
+                self.stats.failed += 1;
+                Err(AssetError::Load { key: key.to_owned(), reason })
+            },
         }
     }
 
+    /// Load the asset and keep it until `release` of the manifest
+    pub fn preload<A: Asset + 'static>(
+        &mut self,
+        manifest: &str,
+        key: &str,
+        ctx: &mut A::Context,
+    ) -> Result<Arc<A>, AssetError> {
+        self.preloading = true;
+        let asset = self.get::<A>(key, ctx);
+        self.preloading = false;
+        self.pinned.entry(manifest.to_owned()).or_default().insert(key.to_owned());
+        asset
+    }
+
+    /// Let assets preloaded by the manifest go with the next `evict_unused`
+    pub fn release(&mut self, manifest: &str) { self.pinned.remove(manifest); }
+
+    /// Drop every asset which is neither used outside of the manager nor
+    /// preloaded. Returns how many assets were dropped.
+    pub fn evict_unused(&mut self) -> usize {
+        let pinned = &self.pinned;
+        let before = self.assets.len();
+        self.assets.retain(|key, asset| Arc::strong_count(asset) > 1 || pinned.values().any(|keys| keys.contains(key)));
+        let evicted = before - self.assets.len();
+        self.stats.evicted += evicted as u32;
+        evicted
+    }
+
+    /// Already loaded asset, this never loads anything
+    pub fn cached<A: Asset + 'static>(&self, key: &str) -> Option<Arc<A>> {
+        self.assets.get(key).and_then(|asset| asset.to_owned().downcast::<A>().ok())
+    }
+
+    /// Forget the asset, so the next `get` loads it again.
+    /// Returns false if it wasn't loaded in the first place.
+    pub fn invalidate(&mut self, key: &str) -> bool {
+        let failed = self.failed.remove(key).is_some();
+        self.assets.remove(key).is_some() || failed
+    }
+
+    pub fn stats(&self) -> AssetStats { AssetStats { cached: self.assets.len(), ..self.stats } }
+
     pub fn _key_for<A: Asset + 'static>(&self, asset: &A) -> Option<&String> {
-        self.0
+        self.assets
             .iter()
             .find(|(_, a)| if let Some(a) = a.downcast_ref::<A>() { a.id() == asset.id() } else { false })
             .map(|(key, _)| key)
@@ -28,34 +145,92 @@ impl AssetManager {
 }
 
 pub trait Asset: Sized + Send + Sync {
-    type Context;
+    type Context: ?Sized;
     fn load(key: &str, id: u32, ctx: &mut Self::Context) -> anyhow::Result<Self>;
     fn id(&self) -> u32;
 }
 
+/// Anything assets can be loaded with. Headless contexts have neither
+/// graphics nor audio, so assets loaded with them can't be rendered or played.
+// 'static bound makes `&mut dyn AssetContext` usable as `Asset::Context`
+// no matter how long the reference itself lives
+pub trait AssetContext: 'static {
+    fn ggez(&mut self) -> Option<&mut ggez::Context>;
+}
+impl AssetContext for ggez::Context {
+    fn ggez(&mut self) -> Option<&mut ggez::Context> { Some(self) }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Headless;
+impl AssetContext for Headless {
+    fn ggez(&mut self) -> Option<&mut ggez::Context> { None }
+}
+
+/// Image which can be drawn. Loaded headless it only knows its size,
+/// which is read from the file header in `resources`.
 #[derive(Debug, Clone)]
-pub struct ImageAsset(u32, Image);
-impl std::ops::Deref for ImageAsset {
-    type Target = Image;
+pub struct ImageAsset(u32, Option<Image>, String, (u16, u16));
+impl ImageAsset {
+    /// Key it was loaded with, which is also its path
+    pub fn key(&self) -> &str { &self.2 }
 
-    fn deref(&self) -> &Self::Target { &self.1 }
+    pub fn image(&self) -> Option<&Image> { self.1.as_ref() }
+
+    pub fn width(&self) -> u16 { (self.3).0 }
+
+    pub fn height(&self) -> u16 { (self.3).1 }
 }
 impl std::cmp::PartialEq for ImageAsset {
     fn eq(&self, other: &Self) -> bool { self.id() == other.id() }
 }
-impl AsRef<Image> for ImageAsset {
-    fn as_ref(&self) -> &Image { &self.1 }
-}
 
 impl Asset for ImageAsset {
-    type Context = ggez::Context;
+    type Context = dyn AssetContext;
+
+    fn load(key: &str, id: u32, ctx: &mut Self::Context) -> anyhow::Result<Self> {
+        match ctx.ggez() {
+            Some(ctx) => {
+                debug!("Loading image asset {:?}", key);
+                let mut img = Image::new(ctx, key)?;
+                img.set_filter(FilterMode::Linear);
+                img.set_wrap(WrapMode::Tile, WrapMode::Tile);
+                let size = (img.width(), img.height());
+                Ok(ImageAsset(id, Some(img), key.to_owned(), size))
+            },
+            None => {
+                let path = Path::new(RESOURCES_PATH).join(key.trim_start_matches('/'));
+                let (width, height) = image::image_dimensions(path)?;
+                Ok(ImageAsset(id, None, key.to_owned(), (width as u16, height as u16)))
+            },
+        }
+    }
+
+    fn id(&self) -> u32 { self.0 }
+}
+
+/// Sound data which can be played any number of times at once.
+/// Loaded headless it's silent.
+#[derive(Debug, Clone)]
+pub struct AudioAsset(u32, Option<SoundData>);
+impl AudioAsset {
+    pub fn data(&self) -> Option<&SoundData> { self.1.as_ref() }
+}
+impl std::cmp::PartialEq for AudioAsset {
+    fn eq(&self, other: &Self) -> bool { self.id() == other.id() }
+}
+
+impl Asset for AudioAsset {
+    type Context = dyn AssetContext;
 
     fn load(key: &str, id: u32, ctx: &mut Self::Context) -> anyhow::Result<Self> {
-        debug!("Loading image asset {:?}", key);
-        let mut img = Image::new(ctx, key)?;
-        img.set_filter(FilterMode::Linear);
-        img.set_wrap(WrapMode::Tile, WrapMode::Tile);
-        Ok(ImageAsset(id, img).into())
+        match ctx.ggez() {
+            Some(ctx) => {
+                debug!("Loading audio asset {:?}", key);
+                Ok(AudioAsset(id, Some(SoundData::new(ctx, key)?)))
+            },
+            None => Ok(AudioAsset(id, None)),
+        }
     }
 
     fn id(&self) -> u32 { self.0 }
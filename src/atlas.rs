@@ -0,0 +1,181 @@
+use anyhow::{Context as _, Result};
+use ggez::{
+    filesystem,
+    graphics::{FilterMode, Image, Rect, WrapMode},
+    Context,
+};
+use image::RgbaImage;
+use std::{cell::RefCell, collections::HashMap, io::Read, path::PathBuf, time::Instant};
+
+/// Sprites which are packed into atlases, everything in those directories.
+/// Map tiles wrap around and ui images are drawn by imgui, so they are left out.
+pub const DIRS: [&str; 3] = ["/sprites/entity", "/sprites/item", "/sprites/projectile"];
+
+const PAGE_SIZE: u32 = 2048;
+// space around every sprite, so neither linear filtering
+// nor outline shader (4 texels away) sample its neighbours
+const PADDING: u32 = 5;
+
+thread_local! {
+    // pages live on the gpu for as long as the context does, so every new game
+    // reuses the ones packed by the first instead of decoding all sprites again
+    static PACKED: RefCell<Option<Atlas>> = RefCell::new(None);
+}
+
+/// Sprites packed into a few big textures (pages) at load time, so the ones
+/// sharing a page are drawn with a single `SpriteBatch`. Sprites are still
+/// loaded by `AssetManager` as usual, this only knows where their copy is.
+#[derive(Default, Clone)]
+pub struct Atlas {
+    pages: Vec<Image>,
+    regions: HashMap<String, Region>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Region {
+    pub page: usize,
+    // normalized, ready to be used as `DrawParam::src`
+    pub uv: Rect,
+    // in pixels
+    pub width: f32,
+    pub height: f32,
+}
+
+// sprite waiting for its place on a page
+struct Pending {
+    key: String,
+    image: RgbaImage,
+}
+
+impl Atlas {
+    /// Atlas packed once per run, pages are shared between its copies
+    pub fn load(ctx: &mut Context) -> Self {
+        PACKED.with(|packed| packed.borrow_mut().get_or_insert_with(|| Self::build(ctx)).clone())
+    }
+
+    fn build(ctx: &mut Context) -> Self {
+        let start = Instant::now();
+        let mut sprites = vec![];
+        for dir in &DIRS {
+            for path in list_images(ctx, PathBuf::from(dir)) {
+                let key = path.to_string_lossy().into_owned();
+                match decode(ctx, &key) {
+                    Ok(image) => sprites.push(Pending { key, image }),
+                    Err(err) => log::warn!("Unable to pack {:?} into atlas: {:#}", key, err),
+                }
+            }
+        }
+
+        let mut atlas = Self::default();
+        // tall ones first, so shelves waste less space
+        sprites.sort_by_key(|s| std::cmp::Reverse((s.image.height(), s.image.width())));
+        let (fits, too_big): (Vec<_>, Vec<_>) = sprites.into_iter().partition(|s| {
+            s.image.width() + PADDING * 2 <= PAGE_SIZE && s.image.height() + PADDING * 2 <= PAGE_SIZE
+        });
+        for sprite in too_big {
+            log::warn!("{:?} is too big for atlas, it's drawn on its own", sprite.key);
+        }
+
+        let mut remaining = fits;
+        while !remaining.is_empty() {
+            remaining = match atlas.pack_page(ctx, remaining) {
+                Ok(left) => left,
+                Err(err) => {
+                    log::error!("Unable to create atlas page: {:#}", err);
+                    break;
+                },
+            };
+        }
+        log::info!(
+            "Packed {} sprites into {} atlas pages in {:?}",
+            atlas.regions.len(),
+            atlas.pages.len(),
+            start.elapsed()
+        );
+        atlas
+    }
+
+    // place as many sprites as fits on a new page, returning the rest
+    fn pack_page(&mut self, ctx: &mut Context, sprites: Vec<Pending>) -> Result<Vec<Pending>> {
+        let mut placed: Vec<(Pending, u32, u32)> = vec![];
+        let mut left = vec![];
+        let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+        for sprite in sprites {
+            let (w, h) = (sprite.image.width() + PADDING * 2, sprite.image.height() + PADDING * 2);
+            if x + w > PAGE_SIZE {
+                x = 0;
+                y += shelf_height;
+                shelf_height = 0;
+            }
+            if y + h > PAGE_SIZE {
+                left.push(sprite);
+                continue;
+            }
+            placed.push((sprite, x + PADDING, y + PADDING));
+            x += w;
+            shelf_height = shelf_height.max(h);
+        }
+
+        // the last page is usually half empty
+        let height = y + shelf_height;
+        let mut pixels = vec![0u8; (PAGE_SIZE * height * 4) as usize];
+        for (sprite, px, py) in &placed {
+            let row = sprite.image.width() as usize * 4;
+            for (i, line) in sprite.image.as_raw().chunks(row).enumerate() {
+                let offset = (((*py as usize + i) * PAGE_SIZE as usize) + *px as usize) * 4;
+                pixels[offset..offset + row].copy_from_slice(line);
+            }
+        }
+        let mut page = Image::from_rgba8(ctx, PAGE_SIZE as u16, height as u16, &pixels)?;
+        page.set_filter(FilterMode::Linear);
+        page.set_wrap(WrapMode::Clamp, WrapMode::Clamp);
+
+        let index = self.pages.len();
+        self.pages.push(page);
+        for (sprite, px, py) in placed {
+            let (width, height_px) = (sprite.image.width() as f32, sprite.image.height() as f32);
+            let uv = Rect::new(
+                px as f32 / PAGE_SIZE as f32,
+                py as f32 / height as f32,
+                width / PAGE_SIZE as f32,
+                height_px / height as f32,
+            );
+            self.regions.insert(sprite.key, Region { page: index, uv, width, height: height_px });
+        }
+        Ok(left)
+    }
+
+    pub fn region(&self, key: &str) -> Option<Region> { self.regions.get(key).copied() }
+
+    pub fn page(&self, index: usize) -> &Image { &self.pages[index] }
+
+    /// Sprite changed on disk, so it's drawn on its own from now on (in later games too)
+    pub fn remove(&mut self, key: &str) {
+        self.regions.remove(key);
+        PACKED.with(|packed| {
+            if let Some(atlas) = packed.borrow_mut().as_mut() {
+                atlas.regions.remove(key);
+            }
+        });
+    }
+}
+
+fn list_images(ctx: &mut Context, dir: PathBuf) -> Vec<PathBuf> {
+    let mut images = vec![];
+    let entries: Vec<PathBuf> = filesystem::read_dir(ctx, &dir).map(|e| e.collect()).unwrap_or_default();
+    for path in entries {
+        if filesystem::is_dir(ctx, &path) {
+            images.extend(list_images(ctx, path));
+        } else if path.extension().map_or(false, |ext| ext == "png") {
+            images.push(path);
+        }
+    }
+    images.sort();
+    images
+}
+
+fn decode(ctx: &mut Context, key: &str) -> Result<RgbaImage> {
+    let mut bytes = vec![];
+    filesystem::open(ctx, key)?.read_to_end(&mut bytes)?;
+    Ok(image::load_from_memory(&bytes).context("Not an image")?.to_rgba())
+}
@@ -1,18 +1,28 @@
 use crate::{
-    ecs::component::{CollisionGroup, DamageReciever, DamageType, FactionId, WeaponProperties},
+    assets::{AssetManager, Headless},
+    ecs::{
+        component::{CollisionGroup, DamageReciever, DamageType, DefStr, FactionId, WeaponProperties},
+        resource::{AudioQueue, RandomGenerator, ShakeQueue},
+    },
     math::*,
     particle,
+    script::{Command, ScriptApi, ScriptAsset},
 };
 use nphysics2d::{
     algebra::ForceType,
     math::Force,
     object::{Body, RigidBody},
 };
-use rand::distributions::{uniform::Uniform, Distribution};
+use rand::{
+    distributions::{uniform::Uniform, Distribution},
+    RngCore,
+};
+use std::borrow::Cow;
 
 pub trait ProjectileBuilder {
     fn projectile(&mut self, def: ProjectileDef);
     fn particle(&mut self, particle: particle::ID, pos: Point2f);
+    fn prefab(&mut self, id: String, pos: Point2f);
 }
 
 pub struct AttackPatternData<'a> {
@@ -23,6 +33,10 @@ pub struct AttackPatternData<'a> {
     pub damage_multiplier: f32,
     pub prop: &'a mut WeaponProperties,
     pub projectiles: &'a mut dyn ProjectileBuilder,
+    pub audio: &'a mut AudioQueue,
+    pub shakes: &'a mut ShakeQueue,
+    pub rng: &'a mut RandomGenerator,
+    pub assets: &'a mut AssetManager,
 }
 
 pub struct ProjectileData<'a> {
@@ -34,6 +48,7 @@ pub struct ProjectileData<'a> {
     pub size: Size2f,
     pub ignore_groups: &'a Vec<CollisionGroup>,
     pub projectiles: &'a mut dyn ProjectileBuilder,
+    pub assets: &'a mut AssetManager,
 }
 
 #[derive(Default)]
@@ -43,6 +58,7 @@ pub struct ProjectileDef {
     pub rotate_projectile: bool,
     pub velocity: Vec2f,
     pub distance: f32,
+    pub knockback: f32,
     pub pos: Point2f,
     pub size: Size2f,
     pub ignore_groups: Vec<CollisionGroup>,
@@ -59,7 +75,7 @@ pub trait ProjectileBehaviour: Sync + Send {
     fn on_hit(&self, _data: &mut ProjectileData) -> bool { true }
 }
 
-fn exclude_shooter(shooter: Option<&FactionId>) -> Vec<CollisionGroup> {
+pub fn exclude_shooter(shooter: Option<&FactionId>) -> Vec<CollisionGroup> {
     if let Some(id) = shooter {
         match id {
             &FactionId::Good => vec![CollisionGroup::Players],
@@ -69,16 +85,23 @@ fn exclude_shooter(shooter: Option<&FactionId>) -> Vec<CollisionGroup> {
         vec![]
     }
 }
-fn with_accuracy(normal: Vec2f, accuracy: f32) -> Vec2f {
+fn with_accuracy(rng: &mut RandomGenerator, normal: Vec2f, accuracy: f32) -> Vec2f {
     let bound = 1.5707 * (1.0 - accuracy);
     let u = Uniform::new_inclusive(-bound, bound);
-    let mut rng = rand::thread_rng();
-    with_angle_offset(normal, Angle2f::radians(u.sample(&mut rng)))
+    with_angle_offset(normal, Angle2f::radians(u.sample(rng)))
 }
 fn with_angle_offset(normal: Vec2f, angle: Angle2f) -> Vec2f {
     let (s, c) = angle.sin_cos();
     Vec2f::new(normal.x * c - normal.y * s, normal.x * s + normal.y * c)
 }
+// `count` normals evenly distributed across `angle` around the given one
+fn spread(normal: Vec2f, count: u8, angle: f32) -> impl Iterator<Item = Vec2f> {
+    let (left, right) = (Angle2f::radians(angle * -0.5), Angle2f::radians(angle * 0.5));
+    (0..count).map(move |i| match count {
+        1 => normal,
+        _ => with_angle_offset(normal, left.lerp(right, i as f32 / (count as f32 - 1.0))),
+    })
+}
 
 pub struct Ram {
     pub power: f32,
@@ -127,7 +150,7 @@ impl ProjectileBehaviour for SelfDestruct {
 
 pub struct Lightning;
 impl AttackPattern for Lightning {
-    fn description(&self) -> &str { "Area of effect attack with lightning damage type." }
+    fn description(&self) -> &str { "Area of effect lightning attack, which shocks everything it hits." }
 
     fn attack(&self, data: &mut AttackPatternData) {
         let def = ProjectileDef {
@@ -153,129 +176,50 @@ impl ProjectileBehaviour for Lightning {
     fn on_hit<'a>(&self, _: &mut ProjectileData<'a>) -> bool { false }
 }
 
-pub struct Slingshot;
-impl Slingshot {
-    const DISTANCE: f32 = 400.0;
-    const PROJECTILE_VELOCITY_FLAT: f32 = 250.0;
-}
-impl AttackPattern for Slingshot {
-    fn description(&self) -> &str {
-        "Shoot with mediocre accuracy, mediocre damage and mediocre distance. What did you expect ?"
-    }
-
-    fn attack(&self, data: &mut AttackPatternData) {
-        let shooting_normal = (data.prop.target_pos - data.shooting_at).normalize();
-        let def = ProjectileDef {
-            asset: Some("/sprites/projectile/simple.png".to_owned()),
-            damage: ((data.prop.damage as f32 * data.damage_multiplier) as u32, DamageType::Physical),
-            velocity: with_accuracy(shooting_normal, data.prop.accuracy) * Self::PROJECTILE_VELOCITY_FLAT,
-            distance: Self::DISTANCE,
-            pos: data.shooting_at,
-            size: Size2f::new(10.0, 10.0),
-            ignore_groups: exclude_shooter(data.shooter_faction),
-            ..ProjectileDef::default()
-        };
-        data.projectiles.projectile(def);
-    }
-}
-
-pub struct Railgun;
-impl Railgun {
-    const DISTANCE: f32 = 1000.0;
-    const PROJECTILE_VELOCITY_FLAT: f32 = 1000.0;
-}
-impl AttackPattern for Railgun {
-    fn description(&self) -> &str { "Penetration at extreme speed." }
-
-    fn attack(&self, data: &mut AttackPatternData) {
-        let shooting_normal = (data.prop.target_pos - data.shooting_at).normalize();
-        let def = ProjectileDef {
-            asset: Some("/sprites/projectile/dark.png".to_owned()),
-            damage: ((data.prop.damage as f32 * data.damage_multiplier) as u32, DamageType::Physical),
-            velocity: with_accuracy(shooting_normal, data.prop.accuracy) * Self::PROJECTILE_VELOCITY_FLAT,
-            distance: Self::DISTANCE,
-            pos: data.shooting_at,
-            size: Size2f::new(8.0, 8.0),
-            ignore_groups: exclude_shooter(data.shooter_faction),
-            behaviour: Some(Box::new(Self)),
-            ..ProjectileDef::default()
-        };
-        data.projectiles.projectile(def);
-    }
-}
-impl ProjectileBehaviour for Railgun {
-    fn on_hit<'a>(&self, _: &mut ProjectileData<'a>) -> bool { false }
-}
-
-pub struct Crossbow;
-impl Crossbow {
-    const DISTANCE: f32 = 350.0;
-    const PROJECTILE_VELOCITY_FLAT: f32 = 400.0;
-}
-impl AttackPattern for Crossbow {
-    fn description(&self) -> &str { "Allow for faster shooting with accuracity decrease." }
-
-    fn attack(&self, data: &mut AttackPatternData) {
-        let shooting_normal = (data.prop.target_pos - data.shooting_at).normalize();
-        let def = ProjectileDef {
-            asset: Some("/sprites/projectile/bolt.png".to_owned()),
-            damage: ((data.prop.damage as f32 * data.damage_multiplier) as u32, DamageType::Physical),
-            velocity: with_accuracy(shooting_normal, data.prop.accuracy) * Self::PROJECTILE_VELOCITY_FLAT,
-            distance: Self::DISTANCE,
-            pos: data.shooting_at,
-            size: Size2f::new(15.0, 7.0),
-            ignore_groups: exclude_shooter(data.shooter_faction),
-            rotate_projectile: true,
-            ..ProjectileDef::default()
-        };
-        data.projectiles.projectile(def);
-    }
-}
-
-pub struct Cannon;
-impl Cannon {
-    const DISTANCE: f32 = 200.0;
-    const PROJECTILE_VELOCITY_FLAT: f32 = 200.0;
-    const RECOIL: f32 = 400.0;
-}
-impl AttackPattern for Cannon {
-    fn description(&self) -> &str { "Slow moving projectile which pushes you back at fire." }
-
-    fn attack(&self, data: &mut AttackPatternData) {
-        let shooting_normal = (data.prop.target_pos - data.shooting_at).normalize();
-        if let Some(body) = &mut data.shooter_body {
-            let recoil = shooting_normal * -Self::RECOIL;
-            body.apply_force(0, &Force::linear([recoil.x, recoil.y].into()), ForceType::VelocityChange, true);
-        }
-        let def = ProjectileDef {
-            asset: Some("/sprites/projectile/dark.png".to_owned()),
-            damage: ((data.prop.damage as f32 * data.damage_multiplier) as u32, DamageType::Physical),
-            velocity: with_accuracy(shooting_normal, data.prop.accuracy) * Self::PROJECTILE_VELOCITY_FLAT,
-            distance: Self::DISTANCE,
-            pos: data.shooting_at,
-            size: Size2f::new(15.0, 15.0),
-            ignore_groups: exclude_shooter(data.shooter_faction),
-            ..ProjectileDef::default()
-        };
-        data.projectiles.projectile(def);
-    }
-}
-
-pub struct Shotgun {
-    pub projectile: &'static str,
+/// Generic projectile attack, fully described by its fields, so new
+/// guns can be declared in yaml with `attack_pattern: "generic"`.
+/// Use `__default: true` to only specify fields which differ from `DEFAULT`.
+pub struct Generic {
+    pub description: DefStr,
+    // sprite of the projectile, empty string for invisible projectiles
+    pub projectile: DefStr,
     pub projectile_size: Size2f,
     pub rotate_projectile: bool,
+    pub damage_type: DamageType,
+    pub count: u8,
+    // angle (in radians) between the outermost projectiles
+    pub spread: f32,
+    pub speed: f32,
     pub distance: f32,
-    pub pellets: u8,
+    // push shooter back at fire
     pub recoil: f32,
+    // push the one being hit
+    pub knockback: f32,
+    // projectile keeps flying after hit
+    pub pierce: bool,
+    pub on_hit: Option<SubProjectiles>,
+    pub on_end: Option<SubProjectiles>,
 }
-impl Shotgun {
-    const ANGLE_LEFT_RAD: f32 = -0.392687;
-    const ANGLE_RIGHT_RAD: f32 = 0.392687;
-    const PROJECTILE_VELOCITY_FLAT: f32 = 300.0;
+impl Generic {
+    pub const DEFAULT: Self = Self {
+        description: Cow::Borrowed(""),
+        projectile: Cow::Borrowed("/sprites/projectile/simple.png"),
+        projectile_size: Size2f::new(10.0, 10.0),
+        rotate_projectile: false,
+        damage_type: DamageType::Physical,
+        count: 1,
+        spread: 0.0,
+        speed: 250.0,
+        distance: 400.0,
+        recoil: 0.0,
+        knockback: 0.0,
+        pierce: false,
+        on_hit: None,
+        on_end: None,
+    };
 }
-impl AttackPattern for Shotgun {
-    fn description(&self) -> &str { "Juicy multi-projectile shots." }
+impl AttackPattern for Generic {
+    fn description(&self) -> &str { &self.description }
 
     fn attack(&self, data: &mut AttackPatternData) {
         let shooting_normal = (data.prop.target_pos - data.shooting_at).normalize();
@@ -283,69 +227,54 @@ impl AttackPattern for Shotgun {
             let recoil = shooting_normal * -self.recoil;
             body.apply_force(0, &Force::linear([recoil.x, recoil.y].into()), ForceType::VelocityChange, true);
         }
-        let (left, right) = (Angle2f::radians(Self::ANGLE_LEFT_RAD), Angle2f::radians(Self::ANGLE_RIGHT_RAD));
-        let corrected = with_accuracy(shooting_normal, data.prop.accuracy);
-        for i in 0..self.pellets {
-            let angle_offset = left.lerp(right, i as f32 / (self.pellets as f32 - 1.0));
-            let pellet_normal = with_angle_offset(corrected, angle_offset);
+        if self.recoil > 0.0 {
+            data.shakes.shake(data.shooting_at, (self.recoil / 1000.0).min(0.5));
+        }
+        let corrected = with_accuracy(data.rng, shooting_normal, data.prop.accuracy);
+        let behaviour = GenericBehaviour { pierce: self.pierce, on_hit: self.on_hit, on_end: self.on_end };
+        for normal in spread(corrected, self.count, self.spread) {
             let def = ProjectileDef {
-                asset: Some(self.projectile.to_owned()),
+                asset: Some(self.projectile.to_string()).filter(|a| !a.is_empty()),
                 rotate_projectile: self.rotate_projectile,
-                damage: ((data.prop.damage as f32 * data.damage_multiplier) as u32, DamageType::Physical),
-                velocity: pellet_normal * Self::PROJECTILE_VELOCITY_FLAT,
+                damage: ((data.prop.damage as f32 * data.damage_multiplier) as u32, self.damage_type),
+                velocity: normal * self.speed,
                 distance: self.distance,
+                knockback: self.knockback,
                 pos: data.shooting_at,
                 size: self.projectile_size,
                 ignore_groups: exclude_shooter(data.shooter_faction),
-                ..ProjectileDef::default()
+                behaviour: Some(Box::new(behaviour)),
             };
             data.projectiles.projectile(def);
         }
     }
 }
 
-pub struct Split;
-impl Split {
-    const ANGLE_LEFT_RAD: f32 = -0.196343;
-    const ANGLE_RIGHT_RAD: f32 = 0.196343;
-    const DISTANCE_FIRST: f32 = 200.0;
-    const DISTANCE_SPLIT: f32 = 200.0;
-    const PROJECTILE_VELOCITY_FLAT: f32 = 300.0;
+/// Projectiles spawned by `Generic` pattern on hit or at the end of the flight.
+/// They look and deal damage just like the parent projectile.
+#[derive(Clone, Copy)]
+pub struct SubProjectiles {
+    pub count: u8,
+    pub spread: f32,
+    pub speed: f32,
+    pub distance: f32,
+    pub damage_multiplier: f32,
 }
-impl AttackPattern for Split {
-    fn description(&self) -> &str { "Shoots a projectile that splits mid-air." }
+impl SubProjectiles {
+    pub const DEFAULT: Self =
+        Self { count: 4, spread: 0.392687, speed: 300.0, distance: 200.0, damage_multiplier: 1.0 };
 
-    fn attack(&self, data: &mut AttackPatternData) {
-        let shooting_normal = (data.prop.target_pos - data.shooting_at).normalize();
-        let def = ProjectileDef {
-            asset: Some("/sprites/projectile/bullet.png".to_owned()),
-            damage: ((data.prop.damage as f32 * data.damage_multiplier) as u32, DamageType::Physical),
-            velocity: with_accuracy(shooting_normal, data.prop.accuracy) * Self::PROJECTILE_VELOCITY_FLAT,
-            distance: Self::DISTANCE_FIRST,
-            pos: data.shooting_at,
-            size: Size2f::new(10.0, 8.0),
-            ignore_groups: exclude_shooter(data.shooter_faction),
-            behaviour: Some(Box::new(Self)),
-            rotate_projectile: true,
-            ..ProjectileDef::default()
-        };
-        data.projectiles.projectile(def);
-    }
-}
-impl ProjectileBehaviour for Split {
-    fn on_end<'a>(&self, data: &mut ProjectileData<'a>) {
-        let (left, right) = (Angle2f::radians(Self::ANGLE_LEFT_RAD), Angle2f::radians(Self::ANGLE_RIGHT_RAD));
-        let shooting_normal = data.velocity.normalize();
-        for i in 0..4 {
-            let angle_offset = left.lerp(right, i as f32 / 3.0);
-            let pellet_normal = with_angle_offset(shooting_normal, angle_offset);
+    fn spawn(&self, data: &mut ProjectileData) {
+        // area projectiles don't move, so just shoot to the right
+        let normal = data.velocity.try_normalize().unwrap_or(Vec2f::new(1.0, 0.0));
+        for pellet_normal in spread(normal, self.count, self.spread) {
             let def = ProjectileDef {
-                asset: Some(data.asset.unwrap().clone()),
-                damage: data.damage,
-                velocity: pellet_normal * Self::PROJECTILE_VELOCITY_FLAT,
-                distance: Self::DISTANCE_SPLIT,
-                pos: data.pos.clone(),
-                size: data.size.clone(),
+                asset: data.asset.cloned(),
+                damage: ((data.damage.0 as f32 * self.damage_multiplier) as u32, data.damage.1),
+                velocity: pellet_normal * self.speed,
+                distance: self.distance,
+                pos: data.pos,
+                size: data.size,
                 ignore_groups: data.ignore_groups.clone(),
                 rotate_projectile: true,
                 ..ProjectileDef::default()
@@ -354,3 +283,109 @@ impl ProjectileBehaviour for Split {
         }
     }
 }
+
+#[derive(Clone, Copy)]
+struct GenericBehaviour {
+    pierce: bool,
+    on_hit: Option<SubProjectiles>,
+    on_end: Option<SubProjectiles>,
+}
+impl ProjectileBehaviour for GenericBehaviour {
+    fn on_end<'a>(&self, data: &mut ProjectileData<'a>) {
+        match &self.on_end {
+            Some(sub) => sub.spawn(data),
+            None => data.projectiles.particle(particle::ID::Splash, data.pos),
+        }
+    }
+
+    fn on_hit<'a>(&self, data: &mut ProjectileData<'a>) -> bool {
+        if let Some(sub) = &self.on_hit {
+            sub.spawn(data);
+        }
+        !self.pierce
+    }
+}
+
+/// Attack implemented by the `attack` hook of a script (see `crate::script`).
+/// Projectiles fired with `scripted: true` call `on_hit` and `on_end` hooks of the same script.
+pub struct Scripted {
+    pub description: DefStr,
+    pub script: DefStr,
+}
+impl AttackPattern for Scripted {
+    fn description(&self) -> &str { &self.description }
+
+    fn attack(&self, data: &mut AttackPatternData) {
+        let script = match data.assets.get::<ScriptAsset>(&self.script, &mut Headless) {
+            Ok(script) => script,
+            Err(err) => {
+                log::error!("Unable to load weapon script: {}", err);
+                return;
+            },
+        };
+        let mut api = ScriptApi::new(data.rng.next_u64());
+        api.pos = data.shooting_at;
+        api.target = data.prop.target_pos;
+        api.damage = (data.prop.damage as f32 * data.damage_multiplier) as u32;
+        script.call("attack", &mut api);
+
+        for command in api.commands {
+            match command {
+                Command::Projectile(def) => {
+                    let ignore_groups = exclude_shooter(data.shooter_faction);
+                    data.projectiles.projectile(def.into_def(ignore_groups, self.script.clone()))
+                },
+                Command::Particle(id, pos) => data.projectiles.particle(id, pos),
+                Command::Spawn(id, pos) => data.projectiles.prefab(id, pos),
+                Command::Push(push) => {
+                    if let Some(body) = &mut data.shooter_body {
+                        body.apply_force(0, &Force::linear([push.x, push.y].into()), ForceType::VelocityChange, true);
+                    }
+                },
+                _ => log::warn!("Weapon scripts can't do {:?}, skipping", command),
+            }
+        }
+    }
+}
+
+pub struct ScriptedProjectile {
+    pub script: DefStr,
+}
+impl ScriptedProjectile {
+    fn call(&self, hook: &str, data: &mut ProjectileData) -> Option<bool> {
+        let script = match data.assets.get::<ScriptAsset>(&self.script, &mut Headless) {
+            Ok(script) => script,
+            Err(err) => {
+                log::error!("Unable to load projectile script: {}", err);
+                return None;
+            },
+        };
+        // projectiles have no access to the world rng, but their position is deterministic anyway
+        let mut api = ScriptApi::new((data.pos.x.to_bits() as u64) << 32 | data.pos.y.to_bits() as u64);
+        api.pos = data.pos;
+        api.velocity = data.velocity;
+        api.damage = data.damage.0;
+        let result = script.call(hook, &mut api);
+
+        for command in api.commands {
+            match command {
+                Command::Projectile(def) => {
+                    data.projectiles.projectile(def.into_def(data.ignore_groups.clone(), self.script.clone()))
+                },
+                Command::Particle(id, pos) => data.projectiles.particle(id, pos),
+                Command::Spawn(id, pos) => data.projectiles.prefab(id, pos),
+                _ => log::warn!("Projectile scripts can't do {:?}, skipping", command),
+            }
+        }
+        result.map(|r| r.as_bool().unwrap_or(true))
+    }
+}
+impl ProjectileBehaviour for ScriptedProjectile {
+    fn on_end<'a>(&self, data: &mut ProjectileData<'a>) {
+        if self.call("on_end", data).is_none() {
+            data.projectiles.particle(particle::ID::Splash, data.pos);
+        }
+    }
+
+    fn on_hit<'a>(&self, data: &mut ProjectileData<'a>) -> bool { self.call("on_hit", data).unwrap_or(true) }
+}
@@ -0,0 +1,24 @@
+//! Checks resource definitions and references between them, reporting
+//! every problem found: `cargo run --bin lint [resources dir]`
+
+#[allow(dead_code)]
+#[path = "../../build/def.rs"]
+mod def;
+#[path = "../../build/validate.rs"]
+mod validate;
+
+use std::{path::PathBuf, process};
+
+fn main() {
+    let root = std::env::args().nth(1).map_or_else(|| PathBuf::from("resources"), PathBuf::from);
+    let problems = validate::validate(&root);
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    if problems.is_empty() {
+        println!("No problems found in {:?}", root);
+    } else {
+        println!("Found {} problems in {:?}", problems.len(), root);
+        process::exit(1);
+    }
+}
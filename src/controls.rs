@@ -0,0 +1,489 @@
+use crate::ecs::resource::{Inputs, PlayerInputs, MAX_PLAYERS};
+use anyhow::{Context as _, Result};
+use ggez::{
+    event::Button,
+    input::{keyboard::KeyCode, mouse::MouseButton},
+};
+use heck::SnakeCase;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+};
+
+/// User bindings, written by the controls window
+pub const CONTROLS_PATH: &str = "controls.yaml";
+
+/// Things player can do, gameplay only knows about these
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Interact,
+    Inventory,
+    Pause,
+    // index of hotbar slot
+    UseHotbar(u8),
+    Fire,
+    SwapWeapon,
+    ToggleFraming,
+    ZoomIn,
+    ZoomOut,
+}
+impl Action {
+    // Index in this table is a bit in recorded replays,
+    // so new actions should only be added to the end.
+    pub const ALL: [Action; 16] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Interact,
+        Action::Inventory,
+        Action::Pause,
+        Action::UseHotbar(0),
+        Action::UseHotbar(1),
+        Action::UseHotbar(2),
+        Action::UseHotbar(3),
+        Action::Fire,
+        Action::SwapWeapon,
+        Action::ToggleFraming,
+        Action::ZoomIn,
+        Action::ZoomOut,
+    ];
+
+    /// Name in the config file
+    pub fn name(&self) -> String {
+        match self {
+            Action::UseHotbar(n) => format!("use_hotbar_{}", n + 1),
+            _ => format!("{:?}", self).to_snake_case(),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> { Self::ALL.iter().copied().find(|a| a.name() == name) }
+
+    /// Name in the controls window
+    pub fn label(&self) -> String {
+        match self {
+            Action::MoveUp => "Move up".to_owned(),
+            Action::MoveDown => "Move down".to_owned(),
+            Action::MoveLeft => "Move left".to_owned(),
+            Action::MoveRight => "Move right".to_owned(),
+            Action::Interact => "Interact".to_owned(),
+            Action::Inventory => "Open inventory".to_owned(),
+            Action::Pause => "Pause".to_owned(),
+            Action::UseHotbar(n) => format!("Use hotbar slot {}", n + 1),
+            Action::Fire => "Shoot primary gun".to_owned(),
+            Action::SwapWeapon => "Swap weapons".to_owned(),
+            Action::ToggleFraming => "Frame nearby enemies".to_owned(),
+            Action::ZoomIn => "Zoom in".to_owned(),
+            Action::ZoomOut => "Zoom out".to_owned(),
+        }
+    }
+
+    // gamepad buttons of these still work while the ui is navigated with it
+    fn leaves_ui(&self) -> bool { matches!(self, Action::Pause | Action::Inventory) }
+}
+
+/// Keys which can be bound, the rest of them have no name in the config
+const KEYS: [KeyCode; 90] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+    KeyCode::Key0,
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::F6,
+    KeyCode::F7,
+    KeyCode::F8,
+    KeyCode::F9,
+    KeyCode::F10,
+    KeyCode::F11,
+    KeyCode::F12,
+    KeyCode::Escape,
+    KeyCode::Space,
+    KeyCode::Tab,
+    KeyCode::Return,
+    KeyCode::Back,
+    KeyCode::Insert,
+    KeyCode::Delete,
+    KeyCode::Home,
+    KeyCode::End,
+    KeyCode::PageUp,
+    KeyCode::PageDown,
+    KeyCode::Left,
+    KeyCode::Up,
+    KeyCode::Right,
+    KeyCode::Down,
+    KeyCode::LShift,
+    KeyCode::RShift,
+    KeyCode::LControl,
+    KeyCode::RControl,
+    KeyCode::LAlt,
+    KeyCode::RAlt,
+    KeyCode::Minus,
+    KeyCode::Equals,
+    KeyCode::LBracket,
+    KeyCode::RBracket,
+    KeyCode::Semicolon,
+    KeyCode::Apostrophe,
+    KeyCode::Grave,
+    KeyCode::Comma,
+    KeyCode::Period,
+    KeyCode::Slash,
+    KeyCode::Backslash,
+    KeyCode::Numpad0,
+    KeyCode::Numpad1,
+    KeyCode::Numpad2,
+    KeyCode::Numpad3,
+    KeyCode::Numpad4,
+    KeyCode::Numpad5,
+    KeyCode::Numpad6,
+    KeyCode::Numpad7,
+    KeyCode::Numpad8,
+    KeyCode::Numpad9,
+];
+const MOUSE_BUTTONS: [MouseButton; 3] = [MouseButton::Left, MouseButton::Right, MouseButton::Middle];
+const PAD_BUTTONS: [Button; 18] = [
+    Button::South,
+    Button::East,
+    Button::North,
+    Button::West,
+    Button::C,
+    Button::Z,
+    Button::LeftTrigger,
+    Button::LeftTrigger2,
+    Button::RightTrigger,
+    Button::RightTrigger2,
+    Button::Select,
+    Button::Start,
+    Button::LeftThumb,
+    Button::RightThumb,
+    Button::DPadUp,
+    Button::DPadDown,
+    Button::DPadLeft,
+    Button::DPadRight,
+];
+
+/// Physical input an action is bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    // ctrl+wheel and wheel alone are different bindings
+    Wheel { up: bool, ctrl: bool },
+    Pad(Button),
+}
+impl Binding {
+    /// Name in the config file (and in the controls window)
+    pub fn name(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{:?}", key).to_snake_case(),
+            Binding::Mouse(button) => format!("mouse_{}", format!("{:?}", button).to_snake_case()),
+            Binding::Wheel { up, ctrl } => {
+                format!("{}wheel_{}", if *ctrl { "ctrl+" } else { "" }, if *up { "up" } else { "down" })
+            },
+            Binding::Pad(button) => format!("pad_{}", format!("{:?}", button).to_snake_case()),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let wheels = [(true, false), (false, false), (true, true), (false, true)];
+        KEYS.iter()
+            .map(|k| Binding::Key(*k))
+            .chain(MOUSE_BUTTONS.iter().map(|b| Binding::Mouse(*b)))
+            .chain(wheels.iter().map(|(up, ctrl)| Binding::Wheel { up: *up, ctrl: *ctrl }))
+            .chain(PAD_BUTTONS.iter().map(|b| Binding::Pad(*b)))
+            .find(|b| b.name() == name)
+    }
+
+    pub fn is_pad(&self) -> bool { matches!(self, Binding::Pad(_)) }
+
+    pub fn is_pressed(&self, inputs: &Inputs) -> bool {
+        match self {
+            Binding::Key(key) => inputs.pressed_keys.contains(key),
+            Binding::Mouse(button) => inputs.mouse_pressed.contains(button),
+            Binding::Wheel { up, ctrl } => is_scrolled(inputs, *up, *ctrl),
+            Binding::Pad(button) => inputs.gamepad.pressed.contains(button),
+        }
+    }
+
+    /// Released this frame, wheel is released right away
+    pub fn is_clicked(&self, inputs: &Inputs) -> bool {
+        match self {
+            Binding::Key(key) => inputs.clicked_keys.contains(key),
+            Binding::Mouse(button) => inputs.mouse_clicked.contains(button),
+            Binding::Wheel { up, ctrl } => is_scrolled(inputs, *up, *ctrl),
+            Binding::Pad(button) => inputs.gamepad.clicked.contains(button),
+        }
+    }
+
+    /// Every binding which is held down right now
+    pub fn all_pressed(inputs: &Inputs) -> Vec<Self> {
+        let wheel = match inputs.mouse_scroll {
+            s if s > 0.0 => Some(Binding::Wheel { up: true, ctrl: is_ctrl_pressed(inputs) }),
+            s if s < 0.0 => Some(Binding::Wheel { up: false, ctrl: is_ctrl_pressed(inputs) }),
+            _ => None,
+        };
+        KEYS.iter()
+            .filter(|k| inputs.pressed_keys.contains(k))
+            .map(|k| Binding::Key(*k))
+            .chain(MOUSE_BUTTONS.iter().filter(|b| inputs.mouse_pressed.contains(b)).map(|b| Binding::Mouse(*b)))
+            .chain(wheel)
+            .chain(PAD_BUTTONS.iter().filter(|b| inputs.gamepad.pressed.contains(b)).map(|b| Binding::Pad(*b)))
+            .collect()
+    }
+}
+
+fn is_ctrl_pressed(inputs: &Inputs) -> bool {
+    inputs.pressed_keys.contains(&KeyCode::LControl) || inputs.pressed_keys.contains(&KeyCode::RControl)
+}
+
+fn is_scrolled(inputs: &Inputs, up: bool, ctrl: bool) -> bool {
+    inputs.mouse_scroll != 0.0 && (inputs.mouse_scroll > 0.0) == up && is_ctrl_pressed(inputs) == ctrl
+}
+
+/// Bindings of every action, a table for each player. Actions missing
+/// from the config file keep their default bindings.
+#[derive(Debug, Clone)]
+pub struct Controls {
+    bindings: [HashMap<Action, Vec<Binding>>; MAX_PLAYERS],
+}
+impl Default for Controls {
+    fn default() -> Self {
+        use Binding::*;
+        let first = vec![
+            (Action::MoveUp, vec![Key(KeyCode::W), Key(KeyCode::Up)]),
+            (Action::MoveDown, vec![Key(KeyCode::S), Key(KeyCode::Down)]),
+            (Action::MoveLeft, vec![Key(KeyCode::A), Key(KeyCode::Left)]),
+            (Action::MoveRight, vec![Key(KeyCode::D), Key(KeyCode::Right)]),
+            (Action::Interact, vec![Key(KeyCode::E), Pad(Button::South)]),
+            (Action::Inventory, vec![Key(KeyCode::I), Pad(Button::North)]),
+            (Action::Pause, vec![Key(KeyCode::Escape), Pad(Button::Start)]),
+            (Action::UseHotbar(0), vec![Key(KeyCode::Key1), Pad(Button::DPadUp)]),
+            (Action::UseHotbar(1), vec![Key(KeyCode::Key2), Pad(Button::DPadRight)]),
+            (Action::UseHotbar(2), vec![Key(KeyCode::Key3), Pad(Button::DPadDown)]),
+            (Action::UseHotbar(3), vec![Key(KeyCode::Key4), Pad(Button::DPadLeft)]),
+            (Action::Fire, vec![Mouse(MouseButton::Left), Pad(Button::RightTrigger2)]),
+            (Action::SwapWeapon, vec![
+                Wheel { up: true, ctrl: false },
+                Wheel { up: false, ctrl: false },
+                Pad(Button::LeftTrigger2),
+            ]),
+            (Action::ToggleFraming, vec![Key(KeyCode::Z)]),
+            (Action::ZoomIn, vec![Wheel { up: true, ctrl: true }, Key(KeyCode::Equals)]),
+            (Action::ZoomOut, vec![Wheel { up: false, ctrl: true }, Key(KeyCode::Minus)]),
+        ];
+        // numpad, so it doesn't get in the way of the first player
+        let second = vec![
+            (Action::MoveUp, vec![Key(KeyCode::Numpad8)]),
+            (Action::MoveDown, vec![Key(KeyCode::Numpad5)]),
+            (Action::MoveLeft, vec![Key(KeyCode::Numpad4)]),
+            (Action::MoveRight, vec![Key(KeyCode::Numpad6)]),
+            (Action::Interact, vec![Key(KeyCode::Numpad9), Pad(Button::South)]),
+            (Action::Inventory, vec![Key(KeyCode::Numpad7), Pad(Button::North)]),
+            (Action::Pause, vec![Pad(Button::Start)]),
+            (Action::UseHotbar(0), vec![Pad(Button::DPadUp)]),
+            (Action::UseHotbar(1), vec![Pad(Button::DPadRight)]),
+            (Action::UseHotbar(2), vec![Pad(Button::DPadDown)]),
+            (Action::UseHotbar(3), vec![Pad(Button::DPadLeft)]),
+            (Action::Fire, vec![Key(KeyCode::Numpad0), Pad(Button::RightTrigger2)]),
+            (Action::SwapWeapon, vec![Key(KeyCode::Numpad3), Pad(Button::LeftTrigger2)]),
+        ];
+        Self { bindings: [first.into_iter().collect(), second.into_iter().collect()] }
+    }
+}
+
+impl Controls {
+    /// Default controls overridden by the config file, if there is one
+    pub fn load() -> Self {
+        let mut controls = Self::default();
+        if Path::new(CONTROLS_PATH).is_file() {
+            if let Err(err) = controls.read() {
+                log::error!("Unable to load controls, using defaults: {:#}", err);
+                controls = Self::default();
+            }
+        }
+        for (binding, actions) in controls.conflicts() {
+            let labels: Vec<String> = actions.iter().map(|(player, action)| conflict_label(*player, action)).collect();
+            log::warn!("{:?} is bound to several actions: {}", binding.name(), labels.join(", "));
+        }
+        controls
+    }
+
+    fn read(&mut self) -> Result<()> {
+        let content = fs::read_to_string(CONTROLS_PATH).with_context(|| format!("Can't read {:?}", CONTROLS_PATH))?;
+        let file: BTreeMap<String, BTreeMap<String, Vec<String>>> =
+            serde_yaml::from_str(&content).with_context(|| format!("Invalid controls {:?}", CONTROLS_PATH))?;
+        for (name, actions) in file {
+            let player = match (0..MAX_PLAYERS).find(|p| player_name(*p) == name) {
+                Some(player) => player,
+                None => {
+                    log::warn!("Unknown player {:?} in {:?}, skipping", name, CONTROLS_PATH);
+                    continue;
+                },
+            };
+            for (name, names) in actions {
+                let action = match Action::from_name(&name) {
+                    Some(action) => action,
+                    None => {
+                        log::warn!("Unknown action {:?} in {:?}, skipping", name, CONTROLS_PATH);
+                        continue;
+                    },
+                };
+                let bindings = names
+                    .iter()
+                    .filter_map(|name| {
+                        let binding = Binding::from_name(name);
+                        if binding.is_none() {
+                            log::warn!("Unknown binding {:?} in {:?}, skipping", name, CONTROLS_PATH);
+                        }
+                        binding
+                    })
+                    .collect();
+                self.bindings[player].insert(action, bindings);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write(&self) -> Result<()> {
+        // in the same order as the controls window
+        let mut file = serde_yaml::Mapping::new();
+        for player in 0..MAX_PLAYERS {
+            let mut actions = serde_yaml::Mapping::new();
+            for action in &Action::ALL {
+                let names =
+                    self.bindings(player, *action).iter().map(|b| serde_yaml::Value::String(b.name())).collect();
+                actions.insert(serde_yaml::Value::String(action.name()), serde_yaml::Value::Sequence(names));
+            }
+            file.insert(serde_yaml::Value::String(player_name(player)), serde_yaml::Value::Mapping(actions));
+        }
+        fs::write(CONTROLS_PATH, serde_yaml::to_string(&file)?)
+            .with_context(|| format!("Can't write {:?}", CONTROLS_PATH))?;
+        log::info!("Controls saved to {:?}", CONTROLS_PATH);
+        Ok(())
+    }
+
+    pub fn bindings(&self, player: usize, action: Action) -> &[Binding] {
+        self.bindings[player].get(&action).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    pub fn bind(&mut self, player: usize, action: Action, binding: Binding) {
+        let bindings = self.bindings[player].entry(action).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn unbind(&mut self, player: usize, action: Action, binding: Binding) {
+        if let Some(bindings) = self.bindings[player].get_mut(&action) {
+            bindings.retain(|b| *b != binding);
+        }
+    }
+
+    /// Bindings used by more than one action, along with those actions (and their players).
+    /// Players only share the keyboard, so keys are the only thing which conflicts between them.
+    pub fn conflicts(&self) -> Vec<(Binding, Vec<(usize, Action)>)> {
+        let mut used: Vec<(Binding, Vec<(usize, Action)>)> = vec![];
+        for player in 0..MAX_PLAYERS {
+            for action in &Action::ALL {
+                for binding in self.bindings(player, *action) {
+                    match used.iter_mut().find(|(b, _)| b == binding) {
+                        Some((_, actions)) => actions.push((player, *action)),
+                        None => used.push((*binding, vec![(player, *action)])),
+                    }
+                }
+            }
+        }
+        used.retain(|(binding, actions)| {
+            let is_shared = matches!(binding, Binding::Key(_));
+            (0..MAX_PLAYERS).any(|p| actions.iter().filter(|(player, _)| *player == p).count() > 1)
+                || (is_shared && actions.iter().any(|(player, _)| *player != actions[0].0))
+        });
+        used
+    }
+
+    /// Fill actions of this frame from raw inputs. While the ui is navigated with a
+    /// gamepad, its buttons only work for actions which get the player out of there.
+    ///
+    /// A single player uses every device. With more players, the first one gets
+    /// the mouse, the rest get gamepads, and everyone has their own keys.
+    pub fn resolve(&self, inputs: &mut Inputs, players: usize, is_navigating: bool) {
+        let owns = |player: usize, binding: &Binding| match binding {
+            _ if players == 1 => player == 0,
+            Binding::Key(_) => player < players,
+            Binding::Mouse(_) | Binding::Wheel { .. } => player == 0,
+            Binding::Pad(_) => player != 0 && player < players,
+        };
+        let pad_player = if players == 1 { 0 } else { 1 };
+        let mut resolved_players: [PlayerInputs; MAX_PLAYERS] = Default::default();
+        for (player, resolved) in resolved_players.iter_mut().enumerate() {
+            for action in &Action::ALL {
+                for binding in self.bindings(player, *action) {
+                    if !owns(player, binding) || (is_navigating && binding.is_pad() && !action.leaves_ui()) {
+                        continue;
+                    }
+                    if binding.is_pressed(inputs) {
+                        resolved.pressed_actions.insert(*action);
+                    }
+                    if binding.is_clicked(inputs) {
+                        resolved.clicked_actions.insert(*action);
+                    }
+                }
+            }
+            if player == pad_player {
+                resolved.move_stick = inputs.gamepad.left_stick;
+                resolved.aim_stick = inputs.gamepad.right_stick;
+            }
+        }
+        // the inventory window is used with the mouse
+        resolved_players[0].item_move = inputs.item_move.take();
+        inputs.players = resolved_players;
+    }
+}
+
+/// Name of the player table in the config file
+fn player_name(player: usize) -> String { format!("player_{}", player + 1) }
+
+/// Action label with the player it belongs to, for conflict reports
+pub fn conflict_label(player: usize, action: &Action) -> String {
+    format!("{} (player {})", action.label(), player + 1)
+}
@@ -4,19 +4,24 @@ use crate::{
     item::{self, ConsumeBehaviour},
     math::*,
 };
-use enum_map::{Enum, EnumMap};
+use enum_map::{enum_map, Enum, EnumMap};
 use nphysics2d::{
     ncollide2d::shape::ShapeHandle,
     object::{DefaultBodyHandle, DefaultColliderHandle},
 };
 use specs::{Component, Entity, FlaggedStorage, LazyUpdate, VecStorage, World, WorldExt};
 use std::{
+    borrow::Cow,
     collections::{BTreeMap as Map, HashSet as Set},
     fmt,
     ops::RangeInclusive,
     sync::Arc,
 };
 
+/// Text of a definition. Compiled definitions borrow it, ones loaded at
+/// runtime own it, so reloading them doesn't leak anything.
+pub type DefStr = Cow<'static, str>;
+
 ////////////
 // Active //
 ////////////
@@ -29,27 +34,93 @@ use std::{
 pub struct Shapeshifter {
     pub current: usize,
     pub time: f32,
-    pub forms: &'static [&'static dyn ShapeshifterForm],
+    pub forms: Vec<Arc<dyn ShapeshifterForm>>,
 }
 
-pub type ShapeshifterData<'a> = (&'a mut ggez::Context, &'a mut AssetManager);
+pub type ShapeshifterData<'a> = (&'a mut dyn AssetContext, &'a mut AssetManager);
 pub trait ShapeshifterForm: Sync + Send {
     fn time(&self) -> f32;
     fn can_update(&self, _: Entity, _: &World) -> bool { true }
     fn on_begin<'a>(&self, _: Entity, _: &LazyUpdate, _: ShapeshifterData<'a>) {}
     fn on_end<'a>(&self, _: Entity, _: &LazyUpdate, _: ShapeshifterData<'a>) {}
+    /// One-shot clip of the `AnimatedSprite` is over
+    fn on_animation_end<'a>(&self, _: Entity, _: &str, _: &LazyUpdate, _: ShapeshifterData<'a>) {}
+}
+
+/// Boss fight split into phases. Phases only go forward: the next one starts
+/// once hp drops below its threshold or a script raises its event.
+#[derive(Default, Component)]
+#[storage(VecStorage)]
+pub struct BossPhases {
+    pub name: DefStr,
+    pub current: usize,
+    pub phases: Vec<BossPhase>,
+    // raised by scripts since the last check
+    pub events: Vec<String>,
+}
+impl BossPhases {
+    pub fn phase(&self) -> Option<&BossPhase> { self.phases.get(self.current) }
+
+    /// Range of shapeshifter forms the current phase cycles through,
+    /// if it has one and there are that many forms
+    pub fn forms(&self, count: usize) -> Option<RangeInclusive<usize>> {
+        self.phase().and_then(|phase| phase.forms.clone()).filter(|forms| *forms.end() < count)
+    }
+
+    /// The furthest phase which should start right now, consuming raised events
+    pub fn next(&mut self, hp_ratio: f32) -> Option<usize> {
+        let events = std::mem::take(&mut self.events);
+        self.phases
+            .iter()
+            .enumerate()
+            .skip(self.current + 1)
+            .filter(|(_, phase)| {
+                phase.hp_below.map_or(false, |threshold| hp_ratio < threshold)
+                    || phase.event.as_ref().map_or(false, |event| events.iter().any(|e| *e == *event))
+            })
+            .map(|(i, _)| i)
+            .last()
+    }
+}
+
+/// Everything but the name is optional, things which are not
+/// mentioned stay as they were in the previous phase
+#[derive(Debug, Clone)]
+pub struct BossPhase {
+    pub name: DefStr,
+    // share of max hp
+    pub hp_below: Option<f32>,
+    pub event: Option<DefStr>,
+    // indices of `Shapeshifter` forms
+    pub forms: Option<RangeInclusive<usize>>,
+    pub weapon: ItemBox,
+    pub max_velocity: Option<f32>,
+    pub acceleration_flat: Option<f32>,
+    pub sprite: Option<Arc<ImageAsset>>,
+}
+impl BossPhase {
+    pub const DEFAULT: Self = Self {
+        name: Cow::Borrowed(""),
+        hp_below: None,
+        event: None,
+        forms: None,
+        weapon: None,
+        max_velocity: None,
+        acceleration_flat: None,
+        sprite: None,
+    };
 }
 
 #[derive(Component)]
 #[storage(VecStorage)]
 pub struct WeaponAttack {
-    pub pattern: &'static dyn AttackPattern,
+    pub pattern: Arc<dyn AttackPattern>,
 }
 
 #[derive(Component)]
 #[storage(VecStorage)]
 pub struct Consumable {
-    pub behaviour: &'static dyn ConsumeBehaviour,
+    pub behaviour: Arc<dyn ConsumeBehaviour>,
 }
 
 #[derive(Default, Component)]
@@ -58,8 +129,9 @@ pub struct Consumer {
     pub handles: Vec<ConsumeHandle>,
 }
 pub struct ConsumeHandle {
-    pub behaviour: &'static dyn ConsumeBehaviour,
+    pub behaviour: Arc<dyn ConsumeBehaviour>,
     pub time: f32,
+    pub sound: Option<DefStr>,
 }
 
 /////////////////////////
@@ -98,13 +170,13 @@ impl Default for Content {
 impl Content {
     pub fn add(&mut self, world: &World, item: Entity) {
         let (reflections, mut stacks) = (world.read_storage::<Reflection>(), world.write_storage::<Stackable>());
-        let id = reflections.get(item).unwrap().id;
+        let id = &reflections.get(item).unwrap().id;
         let (mut current, size) = stacks.get(item).map(|s| (s.current, s.stack_size)).unwrap_or((1, 1));
 
         // Try to increment already existent same items
         if size > 1 {
             for e in self.0.iter_mut().filter_map(|i| i.as_mut()) {
-                if reflections.get(*e).unwrap().id == id {
+                if reflections.get(*e).unwrap().id == *id {
                     if let Some(mut e_stack) = stacks.get_mut(*e) {
                         let transfer_count = current.min(e_stack.stack_size - e_stack.current);
                         e_stack.current += transfer_count;
@@ -149,14 +221,18 @@ impl Content {
 
     pub fn have_some(&self) -> bool { self.0.iter().any(|i| i.is_some()) }
 
+    pub fn iter(&self) -> impl Iterator<Item = &ItemBox> { self.0.iter() }
+
     pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut ItemBox> { self.0.iter_mut() }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut ItemBox> { self.0.get_mut(index) }
 }
 
 #[derive(Default, Debug, Component)]
 #[storage(VecStorage)]
 pub struct Named {
-    pub name: &'static str,
-    pub description: &'static str,
+    pub name: DefStr,
+    pub description: DefStr,
 }
 
 #[derive(Debug, Component)]
@@ -164,7 +240,7 @@ pub struct Named {
 pub struct Quality {
     pub rarity: Rarity,
 }
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum Rarity {
     Common,
     Rare,
@@ -334,6 +410,16 @@ pub struct SpriteBlink {
     pub frames_left: u8,
 }
 
+/// Sounds played on entity events, paths are relative to resources
+#[derive(Default, Debug, Component)]
+#[storage(VecStorage)]
+pub struct SoundEffects {
+    pub attack: Option<DefStr>,
+    pub hit: Option<DefStr>,
+    pub death: Option<DefStr>,
+    pub consume: Option<DefStr>,
+}
+
 #[derive(Default, Debug, Component)]
 #[storage(VecStorage)]
 pub struct ParticleProperties {
@@ -362,10 +448,81 @@ pub struct ParticleDef {
     pub size: Size2f,
 }
 
+/// Playback state of the clip, frames are taken from the `Sprite` of
+/// the same entity, which is a spritesheet described by `AnimationDef`
+#[derive(Default, Debug, Component)]
+#[storage(VecStorage)]
+pub struct AnimatedSprite {
+    /// Name of the clip, the first clip of `AnimationDef` if empty
+    pub clip: DefStr,
+    /// Frame within the clip
+    pub frame: u16,
+    pub frame_time: f32,
+    /// One-shot clip without `next` stays on its last frame
+    pub finished: bool,
+}
+impl AnimatedSprite {
+    /// Play the clip from its first frame, even if it's already playing
+    pub fn play(&mut self, clip: DefStr) { *self = Self { clip, ..Self::default() } }
+}
+
+#[derive(Component)]
+#[storage(VecStorage)]
+pub struct SharedAnimationDef(Arc<AnimationDef>);
+impl From<Arc<AnimationDef>> for SharedAnimationDef {
+    fn from(arc: Arc<AnimationDef>) -> Self { Self(arc) }
+}
+impl std::ops::Deref for SharedAnimationDef {
+    type Target = AnimationDef;
+
+    fn deref(&self) -> &Self::Target { self.0.as_ref() }
+}
+
+/// Spritesheet layout, the same for every direction of a directional sprite
+pub struct AnimationDef {
+    pub sheet_width: u16,
+    pub sheet_height: u16,
+    pub clips: Vec<AnimationClip>,
+}
+impl AnimationDef {
+    pub fn clip(&self, name: &str) -> Option<&AnimationClip> {
+        if name.is_empty() {
+            self.clips.first()
+        } else {
+            self.clips.iter().find(|clip| clip.name == name)
+        }
+    }
+
+    /// Frame of the spritesheet to draw, counting from top left
+    pub fn frame(&self, sprite: &AnimatedSprite) -> u16 {
+        self.clip(&sprite.clip).map_or(0, |clip| clip.first + sprite.frame.min(clip.frames.saturating_sub(1)))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimationClip {
+    pub name: DefStr,
+    pub first: u16,
+    pub frames: u16,
+    pub time_per_frame: f32,
+    pub looping: bool,
+    /// Clip to play once this one-shot clip is over
+    pub next: Option<DefStr>,
+}
+impl AnimationClip {
+    pub const DEFAULT: Self =
+        Self { name: Cow::Borrowed(""), first: 0, frames: 1, time_per_frame: 0.1, looping: false, next: None };
+}
+
 ///////////////////////
 // Entity properties //
 ///////////////////////
 
+/// Which of the local players controls this ship, an index into `Inputs::players`
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Component)]
+#[storage(VecStorage)]
+pub struct PlayerIndex(pub usize);
+
 #[derive(Default, Debug, Component)]
 #[storage(FlaggedStorage)]
 pub struct HealthPool {
@@ -373,13 +530,39 @@ pub struct HealthPool {
     pub hp: u32,
 }
 
-#[derive(Default, Debug, Component)]
+#[derive(Debug, Component)]
 #[storage(VecStorage)]
 pub struct DamageReciever {
     pub damage_queue: Vec<(u32, DamageType)>,
     pub damage_immunity: EnumMap<DamageType, Option<f32>>,
+    /// Share of the damage of each type which is ignored,
+    /// negative ones are weaknesses (-0.5 is 1.5x damage)
+    pub resistances: EnumMap<DamageType, f32>,
+    /// Seconds of `BURNING` after a fire hit, 0 to never burn
+    pub burning_time: f32,
+    /// Seconds of `SHOCKED` after a lightning hit, 0 to never be shocked
+    pub shock_time: f32,
+    /// Damage over time which is not dealt yet, since only whole points are
+    pub pending_dot: EnumMap<DamageType, f32>,
+}
+impl Default for DamageReciever {
+    fn default() -> Self {
+        Self {
+            damage_queue: vec![],
+            damage_immunity: EnumMap::default(),
+            resistances: EnumMap::default(),
+            burning_time: 3.0,
+            shock_time: 0.5,
+            pending_dot: EnumMap::default(),
+        }
+    }
 }
 impl DamageReciever {
+    /// Damage left after resistance to its type
+    pub fn resisted(&self, damage: f32, damage_type: DamageType) -> f32 {
+        (damage * (1.0 - self.resistances[damage_type])).max(0.0)
+    }
+
     /// Use this to update immunity safely: if there is already an
     /// immunity to given type, it will update time if it's greater
     pub fn update_immunity(&mut self, damage_type: DamageType, seconds: f32) {
@@ -394,6 +577,26 @@ impl DamageReciever {
     }
 }
 
+/// Applied by fire hits for `DamageReciever::burning_time`
+pub static BURNING: StatusEffectDef = StatusEffectDef {
+    id: "burning",
+    description: "On fire, taking damage over time",
+    icon: None,
+    duration: 3.0,
+    stacking: Stacking::Refresh,
+    modifiers: &[Modifier::DamageOverTime(DamageType::Fire, 4.0)],
+};
+
+/// Applied by lightning hits for `DamageReciever::shock_time`
+pub static SHOCKED: StatusEffectDef = StatusEffectDef {
+    id: "shocked",
+    description: "Stunned, unable to move or shoot",
+    icon: None,
+    duration: 0.5,
+    stacking: Stacking::Refresh,
+    modifiers: &[Modifier::Stun],
+};
+
 #[derive(Debug, Component)]
 #[storage(VecStorage)]
 pub struct DamageDealer {
@@ -413,6 +616,134 @@ impl Default for DamageType {
 pub const DAMAGE_TYPES: [DamageType; 4] =
     [DamageType::Physical, DamageType::Impact, DamageType::Lightning, DamageType::Fire];
 
+/// Timed modifiers of the entity stats. Base values stay where they are
+/// (`Movement`, `Weaponry`, ...) and are never changed by effects, systems
+/// use `stats` instead, which `StatusEffectsSystem` derives every tick.
+#[derive(Default, Debug, Component)]
+#[storage(VecStorage)]
+pub struct StatusEffects {
+    pub active: Vec<ActiveEffect>,
+    pub stats: Stats,
+}
+impl StatusEffects {
+    /// Apply the effect according to its `Stacking`
+    pub fn apply(&mut self, def: &'static StatusEffectDef, source: &'static str) {
+        self.apply_for(def, source, def.duration)
+    }
+
+    /// Same as `apply`, but with a duration other than the one of `def`
+    pub fn apply_for(&mut self, def: &'static StatusEffectDef, source: &'static str, duration: f32) {
+        let current = self.active.iter_mut().find(|effect| effect.def.id == def.id);
+        match (current, def.stacking) {
+            (Some(effect), Stacking::Refresh) => effect.time_left = duration,
+            (Some(effect), Stacking::Extend) => effect.time_left += duration,
+            (Some(effect), Stacking::Stack(max)) => {
+                effect.stacks = (effect.stacks + 1).min(max.max(1));
+                effect.time_left = duration;
+            },
+            (Some(_), Stacking::Unique) => {},
+            (None, _) => self.active.push(ActiveEffect { def, source, time_left: duration, stacks: 1 }),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ActiveEffect {
+    pub def: &'static StatusEffectDef,
+    /// Reflection id of whatever applied the effect (an item, a projectile, ...)
+    pub source: &'static str,
+    pub time_left: f32,
+    pub stacks: u8,
+}
+
+#[derive(Debug)]
+pub struct StatusEffectDef {
+    /// Effects with the same id stack with each other
+    pub id: &'static str,
+    pub description: &'static str,
+    pub icon: Option<&'static str>,
+    pub duration: f32,
+    pub stacking: Stacking,
+    pub modifiers: &'static [Modifier],
+}
+
+/// What happens when an effect is applied while it's already active
+#[derive(Debug, Clone, Copy)]
+pub enum Stacking {
+    /// Duration starts over
+    Refresh,
+    /// Duration is added to the time left
+    Extend,
+    /// Modifiers are applied once per stack, up to the given number of stacks.
+    /// Duration starts over with every new stack.
+    Stack(u8),
+    /// Nothing happens until the effect is over
+    Unique,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Modifier {
+    Add(Stat, f32),
+    Multiply(Stat, f32),
+    Immunity(DamageType),
+    /// Damage per second, dealt through `DamageReciever`
+    DamageOverTime(DamageType, f32),
+    /// No movement and no shooting
+    Stun,
+}
+
+#[derive(Debug, Clone, Copy, Enum)]
+pub enum Stat {
+    MaxVelocity,
+    Acceleration,
+    Steering,
+    Damage,
+    FireRate,
+}
+
+/// Sum of modifiers of every active effect
+#[derive(Debug, Clone)]
+pub struct Stats {
+    add: EnumMap<Stat, f32>,
+    multiply: EnumMap<Stat, f32>,
+    pub immunity: EnumMap<DamageType, bool>,
+    pub damage_over_time: EnumMap<DamageType, f32>,
+    pub stunned: bool,
+}
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            add: EnumMap::default(),
+            multiply: enum_map! { _ => 1.0 },
+            immunity: EnumMap::default(),
+            damage_over_time: EnumMap::default(),
+            stunned: false,
+        }
+    }
+}
+impl Stats {
+    pub fn from_effects(effects: &[ActiveEffect]) -> Self {
+        let mut stats = Self::default();
+        for effect in effects {
+            for modifier in effect.def.modifiers {
+                match *modifier {
+                    Modifier::Add(stat, value) => stats.add[stat] += value * effect.stacks as f32,
+                    Modifier::Multiply(stat, value) => stats.multiply[stat] *= value.powi(effect.stacks as i32),
+                    Modifier::Immunity(damage_type) => stats.immunity[damage_type] = true,
+                    Modifier::DamageOverTime(damage_type, value) => {
+                        stats.damage_over_time[damage_type] += value * effect.stacks as f32
+                    },
+                    Modifier::Stun => stats.stunned = true,
+                }
+            }
+        }
+        stats
+    }
+
+    /// Base value with additive modifiers applied first, then multiplicative ones
+    pub fn apply(&self, stat: Stat, base: f32) -> f32 { (base + self.add[stat]) * self.multiply[stat] }
+}
+
 #[derive(Component)]
 #[storage(VecStorage)]
 pub struct Projectile {
@@ -453,7 +784,14 @@ pub struct AvoidShots {
 #[derive(Debug, Component)]
 #[storage(VecStorage)]
 pub struct Reflection {
-    pub id: &'static str,
+    pub id: DefStr,
+}
+
+/// Entity belongs to the open world chunk and lives as long as the chunk is loaded
+#[derive(Debug, Component)]
+#[storage(VecStorage)]
+pub struct ChunkMember {
+    pub chunk: (i32, i32),
 }
 
 #[derive(Default, Debug, Component)]
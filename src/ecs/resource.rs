@@ -3,54 +3,204 @@ use crate::{
     arena,
     assets::AssetManager,
     attack::{ProjectileBuilder, ProjectileDef},
+    controls::{Action, Controls},
     entity, item,
     math::{Point2f, Size2f, Vec2f},
     particle,
+    settings::{Overrides, Settings},
     ui::*,
 };
-use ggez::{graphics, input};
+use ggez::{
+    event::{Axis, Button},
+    graphics, input,
+};
 use nphysics2d::{
     force_generator::DefaultForceGeneratorSet,
     joint::DefaultJointConstraintSet,
     object::{DefaultBodySet, DefaultColliderHandle, DefaultColliderSet, RigidBody},
     world::{DefaultGeometricalWorld, DefaultMechanicalWorld},
 };
-use specs::prelude::*;
-use std::collections::{HashSet, VecDeque as Queue};
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+use specs::{prelude::*, world::Index};
+use std::collections::{HashMap, HashSet, VecDeque as Queue};
 
 #[derive(Default, Debug)]
 pub struct DeltaTime(pub std::time::Duration);
 
-#[derive(Default, Debug)]
-pub struct InteractionCache {
+/// The only source of randomness for gameplay systems,
+/// so the same seed always leads to the same run
+#[derive(Debug)]
+pub struct RandomGenerator {
+    pub seed: u64,
+    rng: StdRng,
+}
+impl RandomGenerator {
+    pub fn new(seed: u64) -> Self { Self { seed, rng: StdRng::seed_from_u64(seed) } }
+}
+impl Default for RandomGenerator {
+    fn default() -> Self { Self::new(rand::random()) }
+}
+impl RngCore for RandomGenerator {
+    fn next_u32(&mut self) -> u32 { self.rng.next_u32() }
+
+    fn next_u64(&mut self) -> u64 { self.rng.next_u64() }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) { self.rng.fill_bytes(dest) }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> { self.rng.try_fill_bytes(dest) }
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct Interaction {
     pub near_inventory: Option<Entity>,
     pub near_level_changer: Option<Entity>,
 }
 
+/// What every player ship is close enough to interact with
+#[derive(Default, Debug)]
+pub struct InteractionCache(pub HashMap<Entity, Interaction>);
+impl InteractionCache {
+    pub fn get(&self, player: Entity) -> Interaction { self.0.get(&player).copied().unwrap_or_default() }
+
+    /// Anyone is close enough to interact with `e`
+    pub fn is_near(&self, e: Entity) -> bool {
+        self.0.values().any(|i| i.near_inventory == Some(e) || i.near_level_changer == Some(e))
+    }
+}
+
+/// Number of local players the run was started with. Devices of sunk
+/// players stay theirs, so this doesn't change when a ship is gone.
+#[derive(Debug, Clone, Copy)]
+pub struct PlayerCount(pub usize);
+impl Default for PlayerCount {
+    fn default() -> Self { Self(1) }
+}
+
+/// Player of this machine in a network game, or None when every player is local.
+/// Windows are opened only for them, the peer has a screen of their own.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct LocalPlayer(pub Option<usize>);
+impl LocalPlayer {
+    pub fn is_local(&self, index: usize) -> bool { self.0.map_or(true, |local| local == index) }
+
+    /// Both machines simulate the same world, so only inputs of players may change it
+    pub fn is_network(&self) -> bool { self.0.is_some() }
+}
+
 #[derive(Debug)]
 pub struct Arena {
     pub size: Size2f,
     pub difficulty: f32,
+    // level changers passed this run
+    pub level: u32,
     pub borders: [Option<DefaultColliderHandle>; 4],
-    pub change_to: Option<arena::ID>,
+    pub current: Option<arena::Kind>,
+    pub change_to: Option<arena::Kind>,
 }
 impl Default for Arena {
     fn default() -> Self {
-        Self { size: Size2f::new(2000.0, 1200.0), difficulty: 1.0, borders: [None, None, None, None], change_to: None }
+        Self {
+            size: Size2f::new(2000.0, 1200.0),
+            difficulty: 1.0,
+            level: 0,
+            borders: [None, None, None, None],
+            current: None,
+            change_to: None,
+        }
+    }
+}
+
+impl Arena {
+    /// Leave through the level changer, every next arena is harder
+    pub fn next_level(&mut self, rng: &mut RandomGenerator) {
+        self.level += 1;
+        self.difficulty *= 1.5;
+        self.change_to = Some(arena::Kind::for_level(self.level, rng));
     }
 }
 
+pub type ChunkPos = (i32, i32);
+
+/// Open world state. Chunks around camera target are loaded, and the
+/// state of chunks which went out of sight is kept to be restored later.
 #[derive(Default, Debug)]
+pub struct Chunks {
+    // world seed, None if it's not an open world
+    pub seed: Option<u64>,
+    pub loaded: HashSet<ChunkPos>,
+    pub persisted: HashMap<ChunkPos, Vec<ChunkEntity>>,
+}
+impl Chunks {
+    pub const SIZE: f32 = 1200.0;
+
+    pub fn new(seed: u64) -> Self { Self { seed: Some(seed), ..Self::default() } }
+
+    pub fn chunk_at(pos: Point2f) -> ChunkPos {
+        ((pos.x / Self::SIZE + 0.5).floor() as i32, (pos.y / Self::SIZE + 0.5).floor() as i32)
+    }
+
+    pub fn center(chunk: ChunkPos) -> Point2f { Point2f::new(chunk.0 as f32 * Self::SIZE, chunk.1 as f32 * Self::SIZE) }
+}
+
+#[derive(Debug, Clone)]
+pub struct ChunkEntity {
+    pub id: entity::ID,
+    pub pos: Point2f,
+    // None means full health
+    pub hp: Option<u32>,
+}
+
+#[derive(Debug)]
 pub struct Camera {
     pub pos: Vec2f,
-    pub target: Option<Entity>,
+    /// Every player ship, the camera keeps all of them on the screen
+    pub targets: Vec<Entity>,
+    pub mode: CameraMode,
+    /// Scale of the world on the screen, smoothly follows `target_zoom`
+    pub zoom: f32,
+    pub target_zoom: f32,
+    // 0..1, shake is as strong as its square, so small hits barely do anything
+    trauma: f32,
+    shake: Vec2f,
+    // seconds since the start, drives the shake
+    time: f32,
+    // window size as of the last frame, gameplay doesn't know about the window
+    viewport: Size2f,
     draw_params: graphics::DrawParam,
 }
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            pos: Vec2f::zero(),
+            targets: vec![],
+            mode: CameraMode::Follow,
+            zoom: 1.0,
+            target_zoom: 1.0,
+            trauma: 0.0,
+            shake: Vec2f::zero(),
+            time: 0.0,
+            viewport: Size2f::zero(),
+            draw_params: graphics::DrawParam::default(),
+        }
+    }
+}
 impl Camera {
+    pub const MIN_ZOOM: f32 = 0.5;
+    pub const MAX_ZOOM: f32 = 1.5;
+    // shake from further than that is not felt at all
+    const SHAKE_DISTANCE: f32 = 800.0;
+    // in pixels, at full trauma
+    const MAX_SHAKE: f32 = 16.0;
+    // per second
+    const TRAUMA_DECAY: f32 = 1.5;
+
     pub fn apply(&mut self, ctx: &mut ggez::Context) {
         let win_size = graphics::window(ctx).get_inner_size().unwrap();
+        self.viewport = Size2f::new(win_size.width as f32, win_size.height as f32);
+        let center = (self.pos + self.shake) * self.zoom;
         self.draw_params = graphics::DrawParam::new()
-            .dest(Point2f::new(-self.pos.x + win_size.width as f32 * 0.5, -self.pos.y + win_size.height as f32 * 0.5));
+            .dest(Point2f::new(-center.x + self.viewport.width * 0.5, -center.y + self.viewport.height * 0.5))
+            .scale(Vec2f::new(self.zoom, self.zoom));
         graphics::push_transform(ctx, Some(self.draw_params.to_matrix()));
         graphics::apply_transformations(ctx).unwrap();
     }
@@ -60,9 +210,58 @@ impl Camera {
         graphics::apply_transformations(ctx).unwrap();
     }
 
+    /// Where a point of the screen is in the world
     pub fn project(&self, v: &Point2f) -> Point2f {
-        Point2f::new(v.x - self.draw_params.dest.x, v.y - self.draw_params.dest.y)
+        let (dest, scale) = (self.draw_params.dest, self.draw_params.scale);
+        Point2f::new((v.x - dest.x) / scale.x, (v.y - dest.y) / scale.y)
+    }
+
+    /// Opposite of `project`: where a point of the world is on the screen
+    pub fn to_screen(&self, v: &Point2f) -> Point2f {
+        let (dest, scale) = (self.draw_params.dest, self.draw_params.scale);
+        Point2f::new(v.x * scale.x + dest.x, v.y * scale.y + dest.y)
+    }
+
+    pub fn viewport(&self) -> Size2f { self.viewport }
+
+    /// Bounds of every target which is still around
+    pub fn target_bounds(&self, transforms: &ReadStorage<'_, Transform>) -> Option<(Vec2f, Vec2f)> {
+        let mut positions = self.targets.iter().filter_map(|e| transforms.get(*e)).map(|t| t.pos);
+        let first = positions.next()?;
+        Some(positions.fold((first, first), |(min, max), pos| {
+            (Vec2f::new(min.x.min(pos.x), min.y.min(pos.y)), Vec2f::new(max.x.max(pos.x), max.y.max(pos.y)))
+        }))
     }
+
+    /// Size of the world which is visible at the current zoom
+    pub fn visible_size(&self) -> Size2f { self.viewport / self.zoom }
+
+    /// Add trauma from something at `at`, the further it is, the less it shakes.
+    /// It's capped, so a lot of small things don't shake more than a big one.
+    pub fn shake(&mut self, at: Point2f, amount: f32) {
+        let distance = (at.to_vector() - self.pos).length();
+        let amount = amount * (1.0 - distance / Self::SHAKE_DISTANCE).max(0.0);
+        self.trauma = (self.trauma + amount).min(1.0);
+    }
+
+    pub fn update_shake(&mut self, dt: f32) {
+        self.time += dt;
+        self.trauma = (self.trauma - Self::TRAUMA_DECAY * dt).max(0.0);
+        // a few sines of unrelated frequencies are noisy enough and don't use gameplay rng
+        let strength = self.trauma * self.trauma * Self::MAX_SHAKE;
+        let time = self.time;
+        let noise = |f1: f32, f2: f32| ((time * f1).sin() + (time * f2).sin()) * 0.5;
+        self.shake = Vec2f::new(noise(37.0, 61.0), noise(43.0, 53.0)) * strength;
+    }
+}
+
+/// What the camera is looking at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode {
+    /// Just the targets, zoom is up to the player unless they don't fit
+    Follow,
+    /// Targets and the closest ones which are after them, zoom is picked so they all fit
+    Framing,
 }
 
 pub struct PhysicWorld {
@@ -126,6 +325,129 @@ pub struct Inputs {
     pub mouse_pressed: HashSet<input::mouse::MouseButton>,
     pub mouse_pos: Point2f,
     pub mouse_scroll: f32,
+    pub gamepad: GamepadInputs,
+    /// Dropped in the inventory window, it goes to the player of the mouse
+    pub item_move: Option<ItemMove>,
+    /// Actions resolved from everything above by `Controls`, gameplay should
+    /// only look at these
+    pub players: [PlayerInputs; MAX_PLAYERS],
+}
+impl Inputs {
+    /// Clicked by any of the players
+    pub fn is_clicked(&self, action: Action) -> bool {
+        self.players.iter().any(|p| p.clicked_actions.contains(&action))
+    }
+
+    /// Clicked by any of the players of this machine
+    pub fn is_clicked_locally(&self, action: Action, local: &LocalPlayer) -> bool {
+        self.players.iter().enumerate().any(|(i, p)| local.is_local(i) && p.clicked_actions.contains(&action))
+    }
+}
+
+pub const MAX_PLAYERS: usize = 2;
+
+/// Inputs of a single player, from the devices which are theirs
+#[derive(Default, Debug, Clone)]
+pub struct PlayerInputs {
+    pub pressed_actions: HashSet<Action>,
+    pub clicked_actions: HashSet<Action>,
+    pub move_stick: Vec2f,
+    pub aim_stick: Vec2f,
+    pub item_move: Option<ItemMove>,
+    /// World position under the mouse of the player who has it, so aiming
+    /// doesn't depend on the camera (which depends on the size of the window)
+    pub mouse_aim: Option<Point2f>,
+    pub mouse_moved: bool,
+}
+
+/// Item dragged from one slot into another. It's an input rather than
+/// something ui does on its own, so it is recorded and sent to the peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ItemMove {
+    pub from: ItemSlot,
+    pub to: ItemSlot,
+}
+
+/// Slot of an entity, which is referred to by id since inputs are
+/// read before the world they are applied to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ItemSlot {
+    pub owner: Index,
+    pub slot: Slot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    Inventory(usize),
+    Hotbar(usize),
+    Primary,
+    Secondary,
+}
+impl Slot {
+    // kind in the high byte, index in the rest, zero is no slot at all
+    pub fn to_bits(slot: Option<Slot>) -> u32 {
+        match slot {
+            None => 0,
+            Some(Slot::Inventory(i)) => 1 << 24 | (i as u32 & 0xFF_FFFF),
+            Some(Slot::Hotbar(i)) => 2 << 24 | (i as u32 & 0xFF_FFFF),
+            Some(Slot::Primary) => 3 << 24,
+            Some(Slot::Secondary) => 4 << 24,
+        }
+    }
+
+    pub fn from_bits(bits: u32) -> Option<Slot> {
+        let index = (bits & 0xFF_FFFF) as usize;
+        match bits >> 24 {
+            1 => Some(Slot::Inventory(index)),
+            2 => Some(Slot::Hotbar(index)),
+            3 => Some(Slot::Primary),
+            4 => Some(Slot::Secondary),
+            _ => None,
+        }
+    }
+}
+
+/// State of gamepads (all of them at once), built from ggez gamepad events
+#[derive(Default, Debug, Clone)]
+pub struct GamepadInputs {
+    /// Screen directions (y goes down), zero inside of the dead zone
+    pub left_stick: Vec2f,
+    pub right_stick: Vec2f,
+    pub pressed: HashSet<Button>,
+    /// Released this frame, just like `Inputs::clicked_keys`
+    pub clicked: HashSet<Button>,
+}
+impl GamepadInputs {
+    const DEAD_ZONE: f32 = 0.25;
+
+    pub fn button(&mut self, button: Button, is_down: bool) {
+        if is_down {
+            self.pressed.insert(button);
+        } else {
+            self.pressed.remove(&button);
+        }
+    }
+
+    pub fn axis(&mut self, axis: Axis, value: f32) {
+        match axis {
+            Axis::LeftStickX => self.left_stick.x = value,
+            Axis::LeftStickY => self.left_stick.y = -value,
+            Axis::RightStickX => self.right_stick.x = value,
+            Axis::RightStickY => self.right_stick.y = -value,
+            _ => {},
+        }
+    }
+
+    /// What gameplay sees this frame, given the state from events
+    pub fn next_frame(&self, events: &GamepadInputs) -> Self {
+        let dead_zone = |stick: Vec2f| if stick.length() < Self::DEAD_ZONE { Vec2f::zero() } else { stick };
+        Self {
+            left_stick: dead_zone(events.left_stick),
+            right_stick: dead_zone(events.right_stick),
+            pressed: events.pressed.clone(),
+            clicked: self.pressed.difference(&events.pressed).copied().collect(),
+        }
+    }
 }
 
 #[derive(Default, Debug)]
@@ -136,6 +458,7 @@ pub struct SceneControls {
     pub is_debug_physic: bool,
     pub queue_restart: bool,
     pub queue_exit: bool,
+    pub queue_save: bool,
 }
 
 #[derive(SystemData)]
@@ -143,13 +466,16 @@ pub struct UiData<'a> {
     pub entities: Entities<'a>,
     pub reflections: ReadStorage<'a, Reflection>,
     pub player_tag: ReadStorage<'a, tag::Player>,
+    pub player_indices: ReadStorage<'a, PlayerIndex>,
     pub to_destruct: WriteStorage<'a, tag::PendingDestruction>,
 
     pub inventories: WriteStorage<'a, Inventory>,
     pub weaponries: WriteStorage<'a, Weaponry>,
     pub hotbars: WriteStorage<'a, Hotbar>,
     pub hpools: ReadStorage<'a, HealthPool>,
+    pub bosses: ReadStorage<'a, BossPhases>,
     pub consumers: ReadStorage<'a, Consumer>,
+    pub status_effects: ReadStorage<'a, StatusEffects>,
     pub transforms: ReadStorage<'a, Transform>,
 
     pub consumables: ReadStorage<'a, Consumable>,
@@ -164,15 +490,22 @@ pub struct UiData<'a> {
     pub arena: Write<'a, Arena>,
     pub spawn_queue: Write<'a, SpawnQueue>,
     pub inputs: Write<'a, Inputs>,
+    pub player_count: Read<'a, PlayerCount>,
+    pub local_player: Read<'a, LocalPlayer>,
+    pub controls: Write<'a, Controls>,
+    pub settings: Write<'a, Settings>,
+    pub overrides: Read<'a, Overrides>,
     pub scene_controls: Write<'a, SceneControls>,
     pub assets: Write<'a, AssetManager>,
     pub camera: Read<'a, Camera>,
+    pub rng: Write<'a, RandomGenerator>,
 }
 #[derive(Default, Debug)]
 pub struct UiHub {
     pub menu: Menu,
     pub pause: PauseWindow,
     pub game_over: GameOverWindow,
+    pub network: NetworkWindow,
     pub hud: Hud,
     pub debug_window: DebugWindow,
     pub inventory_window: InventoryWindow,
@@ -181,16 +514,19 @@ pub struct UiHub {
 impl<'a> UiBuilder<&mut UiData<'a>> for UiHub {
     fn build<'ctx>(&mut self, ui: &mut imgui::Ui, ctx: &mut UiContext<'ctx>, data: &mut UiData<'a>) {
         self.menu.build(ui, ctx, data);
-        if self.menu.is_show_spawn_window {
+        // debug tools change the world behind the back of the peer
+        let debug_tools = !data.local_player.is_network();
+        if self.menu.is_show_spawn_window && debug_tools {
             self.debug_window.build(ui, ctx, (data, &mut self.menu.is_show_spawn_window));
         }
-        if self.menu.is_show_arena_settings {
+        if self.menu.is_show_arena_settings && debug_tools {
             self.arena_settings.build(ui, ctx, (data, &mut self.menu.is_show_arena_settings));
         }
         self.inventory_window.build(ui, ctx, data);
         self.hud.build(ui, ctx, data);
         self.pause.build(ui, ctx, data);
         self.game_over.build(ui, ctx, data);
+        self.network.build(ui, ctx, data);
     }
 }
 
@@ -201,11 +537,140 @@ impl ProjectileBuilder for SpawnQueue {
     fn projectile(&mut self, def: ProjectileDef) { self.0.push_back(SpawnItem::Projectile(def)); }
 
     fn particle(&mut self, id: particle::ID, pos: Point2f) { self.0.push_back(SpawnItem::Particle(id, pos)); }
+
+    fn prefab(&mut self, id: String, pos: Point2f) { self.0.push_back(SpawnItem::Prefab(id, pos)); }
 }
 
 pub enum SpawnItem {
     Entity(entity::ID, Point2f, Vec<item::ID>),
+    // any entity from `Prefabs` by its id
+    Prefab(String, Point2f),
     Particle(particle::ID, Point2f),
     Item(item::ID, u32, Entity),
     Projectile(ProjectileDef),
+    ChunkEntity(ChunkEntity, ChunkPos),
+}
+
+/// Trauma raised by gameplay (explosions, impacts, recoil). The camera isn't
+/// a part of gameplay, so only `CameraSystem` applies it, whenever it runs next.
+#[derive(Default, Debug)]
+pub struct ShakeQueue(pub Queue<ShakeItem>);
+impl ShakeQueue {
+    pub fn shake(&mut self, pos: Point2f, amount: f32) { self.0.push_back(ShakeItem { pos, amount }); }
+}
+
+#[derive(Debug)]
+pub struct ShakeItem {
+    pub pos: Point2f,
+    pub amount: f32,
+}
+
+#[derive(Default, Debug)]
+pub struct AudioQueue(pub Queue<SoundItem>);
+impl AudioQueue {
+    pub fn play(&mut self, sound: DefStr, pos: Point2f) { self.0.push_back(SoundItem { sound, pos }); }
+}
+
+#[derive(Debug)]
+pub struct SoundItem {
+    pub sound: DefStr,
+    pub pos: Point2f,
+}
+
+/// Numbers rising above whatever was hit, aged by `CombatTextSystem`
+/// and drawn on top of the world by `CombatTextRenderSystem`
+#[derive(Default, Debug)]
+pub struct CombatText {
+    pub texts: Vec<FloatingText>,
+    // alternates the drift, so numbers of the same frame don't overlap,
+    // and gives every text its id
+    counter: u32,
+}
+impl CombatText {
+    pub const LIFETIME: f32 = 0.8;
+
+    /// Hits against a weakness stand out, so it's clear what works against whom
+    pub fn damage(&mut self, pos: Vec2f, damage: u32, damage_type: DamageType, is_weak: bool) {
+        let color = match damage_type {
+            DamageType::Physical => 0xFFFFFFFF,
+            DamageType::Impact => 0xF5E663FF,
+            DamageType::Lightning => 0x6FD6FFFF,
+            DamageType::Fire => 0xFF7A2FFF,
+        };
+        if is_weak {
+            self.push(pos, format!("{}!", damage), color, 26.0);
+        } else {
+            self.push(pos, damage.to_string(), color, 18.0);
+        }
+    }
+
+    pub fn immune(&mut self, pos: Vec2f) { self.push(pos, "IMMUNE".to_owned(), 0xB0B0B0FF, 16.0); }
+
+    fn push(&mut self, pos: Vec2f, text: String, color: u32, size: f32) {
+        self.counter = self.counter.wrapping_add(1);
+        let drift = (self.counter % 5) as f32 - 2.0;
+        let velocity = Vec2f::new(drift * 12.0, -60.0);
+        self.texts.push(FloatingText { id: self.counter, pos, velocity, text, color, size, time: 0.0 });
+    }
+}
+
+#[derive(Debug)]
+pub struct FloatingText {
+    // unique among texts alive at the same time, so their layout can be cached
+    pub id: u32,
+    // in world coordinates
+    pub pos: Vec2f,
+    pub velocity: Vec2f,
+    pub text: String,
+    // rgba
+    pub color: u32,
+    pub size: f32,
+    pub time: f32,
+}
+
+/// One-shot clips which ended this tick, cleared by `AnimationSystem` before the next one
+#[derive(Default, Debug)]
+pub struct AnimationEvents(pub Queue<AnimationEvent>);
+
+#[derive(Debug)]
+pub struct AnimationEvent {
+    pub entity: Entity,
+    pub clip: DefStr,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_numbers() {
+        let (mut a, mut b) = (RandomGenerator::new(1), RandomGenerator::new(1));
+        let numbers = |rng: &mut RandomGenerator| (0..100).map(|_| rng.next_u64()).collect::<Vec<_>>();
+        assert_eq!(numbers(&mut a), numbers(&mut b));
+        assert_ne!(numbers(&mut a), numbers(&mut RandomGenerator::new(2)));
+    }
+
+    #[test]
+    fn same_seed_same_levels() {
+        let levels = |seed| {
+            let (mut arena, mut rng) = (Arena::default(), RandomGenerator::new(seed));
+            (0..20)
+                .map(|_| {
+                    arena.next_level(&mut rng);
+                    arena.change_to
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(levels(3), levels(3));
+    }
+
+    #[test]
+    fn weak_hits_stand_out() {
+        let mut combat_text = CombatText::default();
+        combat_text.damage(Vec2f::zero(), 10, DamageType::Fire, false);
+        combat_text.damage(Vec2f::zero(), 20, DamageType::Fire, true);
+        let (normal, weak) = (&combat_text.texts[0], &combat_text.texts[1]);
+        assert_eq!((normal.text.as_str(), weak.text.as_str()), ("10", "20!"));
+        assert!(weak.size > normal.size);
+    }
 }
@@ -0,0 +1,39 @@
+use super::super::resource::*;
+use crate::assets::{AssetContext, AssetManager, AudioAsset};
+use ggez::audio::{SoundSource, Source};
+use specs::{Read, System, Write, WriteExpect};
+
+// sounds further than that from the camera are not heard at all
+const HEARING_DISTANCE: f32 = 1500.0;
+
+/// Plays everything queued in AudioQueue. Audio requires ggez context,
+/// so with headless context queue is just drained.
+pub struct AudioSystem<'a>(pub &'a mut dyn AssetContext);
+impl<'a> System<'a> for AudioSystem<'a> {
+    type SystemData = (Write<'a, AudioQueue>, WriteExpect<'a, AssetManager>, Read<'a, Camera>);
+
+    fn run(&mut self, (mut queue, mut assets, camera): Self::SystemData) {
+        for item in queue.0.drain(..) {
+            let volume = 1.0 - (item.pos.to_vector() - camera.pos).length() / HEARING_DISTANCE;
+            if volume <= 0.0 {
+                continue;
+            }
+            let asset = match assets.get::<AudioAsset>(&item.sound, self.0) {
+                Ok(asset) => asset,
+                Err(err) => {
+                    log::warn!("Unable to load sound {:?}: {}", item.sound, err);
+                    continue;
+                },
+            };
+            if let (Some(ctx), Some(data)) = (self.0.ggez(), asset.data()) {
+                let played = Source::from_data(ctx, data.clone()).and_then(|mut source| {
+                    source.set_volume(volume);
+                    source.play_detached()
+                });
+                if let Err(err) = played {
+                    log::warn!("Unable to play sound {:?}: {}", item.sound, err);
+                }
+            }
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use super::super::{component::*, resource::*, tag};
 use crate::{
-    assets::AssetManager,
+    assets::{AssetContext, AssetManager},
     attack::{AttackPatternData, ProjectileData},
     entity, item,
     math::*,
@@ -24,15 +24,19 @@ use std::{
 
 pub struct ShotsDodgerSystem;
 impl<'a> System<'a> for ShotsDodgerSystem {
-    type SystemData =
-        (Entities<'a>, WriteStorage<'a, DamageReciever>, WriteStorage<'a, AvoidShots>, WriteStorage<'a, Transform>);
+    type SystemData = (
+        Entities<'a>,
+        Write<'a, RandomGenerator>,
+        WriteStorage<'a, DamageReciever>,
+        WriteStorage<'a, AvoidShots>,
+        WriteStorage<'a, Transform>,
+    );
 
-    fn run(&mut self, (entities, mut dmg_recs, mut dodgers, mut transforms): Self::SystemData) {
+    fn run(&mut self, (entities, mut rng, mut dmg_recs, mut dodgers, mut transforms): Self::SystemData) {
         let mut to_remove: Vec<_> = vec![];
         for (e, dmg_rec, avoid, transform) in (&entities, &mut dmg_recs, &mut dodgers, &mut transforms).join() {
             if dmg_rec.damage_queue.iter().any(|(_, dmg_type)| dmg_rec.damage_immunity[*dmg_type].is_none()) {
                 if avoid.count > 0 {
-                    let mut rng = thread_rng();
                     avoid.count -= 1;
                     transform.pos += Vec2f::new(rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0)).normalize() * 50.0;
                     for damage_type in &DAMAGE_TYPES {
@@ -52,12 +56,23 @@ impl<'a> System<'a> for ShotsDodgerSystem {
 
 pub struct ConsumablesSystem;
 impl<'a> System<'a> for ConsumablesSystem {
-    type SystemData = (Entities<'a>, Read<'a, DeltaTime>, Read<'a, LazyUpdate>, WriteStorage<'a, Consumer>);
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, DeltaTime>,
+        Read<'a, LazyUpdate>,
+        Write<'a, AudioQueue>,
+        ReadStorage<'a, Transform>,
+        WriteStorage<'a, Consumer>,
+    );
 
-    fn run(&mut self, (entities, dt, update, mut consumers): Self::SystemData) {
+    fn run(&mut self, (entities, dt, update, mut audio, transforms, mut consumers): Self::SystemData) {
         let dt = dt.0.as_secs_f32();
-        for (e, consumer) in (&entities, &mut consumers).join() {
+        for (e, transform, consumer) in (&entities, &transforms, &mut consumers).join() {
             for handle in &mut consumer.handles {
+                // sound is played only once, as soon as consumption begins
+                if let Some(sound) = handle.sound.take() {
+                    audio.play(sound, transform.pos.to_point());
+                }
                 handle.time += dt;
             }
             consumer.handles.retain(|handle| !handle.behaviour.update(dt, handle.time, e, &update));
@@ -65,22 +80,103 @@ impl<'a> System<'a> for ConsumablesSystem {
     }
 }
 
-pub struct ShapeshifterSystem<'a>(pub &'a mut ggez::Context);
+/// Bosses enter their next phase here, before shapeshifters update: it
+/// also needs ggez context, as phases begin their first form
+pub struct BossPhaseSystem<'a>(pub &'a mut dyn AssetContext);
+impl<'a> System<'a> for BossPhaseSystem<'a> {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, LazyUpdate>,
+        Write<'a, SpawnQueue>,
+        WriteExpect<'a, AssetManager>,
+        ReadStorage<'a, HealthPool>,
+        ReadStorage<'a, Transform>,
+        WriteStorage<'a, BossPhases>,
+        WriteStorage<'a, Shapeshifter>,
+        WriteStorage<'a, Weaponry>,
+        WriteStorage<'a, Movement>,
+        WriteStorage<'a, Sprite>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            update,
+            mut spawn_queue,
+            mut assets,
+            hpools,
+            transforms,
+            mut bosses,
+            mut shapeshifters,
+            mut weaponries,
+            mut movements,
+            mut sprites,
+        ): Self::SystemData,
+    ) {
+        for (e, boss, hpool) in (&entities, &mut bosses, &hpools).join() {
+            let hp_ratio = hpool.hp as f32 / hpool.max_hp.max(1) as f32;
+            match boss.next(hp_ratio) {
+                Some(next) => boss.current = next,
+                None => continue,
+            }
+            let phase = &boss.phases[boss.current];
+            log::debug!("{} enters phase {:?}", boss.name, phase.name);
+
+            if let (Some(weaponry), Some(weapon)) = (weaponries.get_mut(e), phase.weapon) {
+                weaponry.primary = Some(weapon);
+            }
+            if let Some(movement) = movements.get_mut(e) {
+                movement.max_velocity = phase.max_velocity.unwrap_or(movement.max_velocity);
+                movement.acceleration_flat = phase.acceleration_flat.unwrap_or(movement.acceleration_flat);
+            }
+            if let (Some(sprite), Some(value)) = (sprites.get_mut(e), phase.sprite.clone()) {
+                sprite.asset = SpriteAsset::Single { value };
+            }
+            if let Some(shapeshifter) = shapeshifters.get_mut(e) {
+                if let Some(forms) = boss.forms(shapeshifter.forms.len()) {
+                    shapeshifter.forms[shapeshifter.current].on_end(e, update.deref(), (&mut self.0, &mut assets));
+                    shapeshifter.current = *forms.start();
+                    shapeshifter.time = 0.0;
+                    shapeshifter.forms[shapeshifter.current].on_begin(e, update.deref(), (&mut self.0, &mut assets));
+                }
+            }
+            if let Some(transform) = transforms.get(e) {
+                spawn_queue.0.push_back(SpawnItem::Particle(particle::ID::MediumSplash, transform.pos.to_point()));
+            }
+        }
+    }
+}
+
+pub struct ShapeshifterSystem<'a>(pub &'a mut dyn AssetContext);
 impl<'a> System<'a> for ShapeshifterSystem<'a> {
     type SystemData = (
         Entities<'a>,
         Read<'a, DeltaTime>,
         Read<'a, LazyUpdate>,
+        Read<'a, AnimationEvents>,
         WriteExpect<'a, AssetManager>,
+        ReadStorage<'a, BossPhases>,
         WriteStorage<'a, Shapeshifter>,
     );
 
-    fn run(&mut self, (entities, dt, update, mut assets, mut shapeshifters): Self::SystemData) {
+    fn run(&mut self, (entities, dt, update, events, mut assets, bosses, mut shapeshifters): Self::SystemData) {
         let dt = dt.0.as_secs_f32();
+        for AnimationEvent { entity, clip } in events.0.iter() {
+            if let Some(shapeshifter) = shapeshifters.get(*entity) {
+                let form = shapeshifter.forms[shapeshifter.current].clone();
+                form.on_animation_end(*entity, clip, update.deref(), (&mut self.0, &mut assets));
+            }
+        }
         for (e, shapeshifter) in (&entities, &mut shapeshifters).join() {
             let form_time = shapeshifter.forms[shapeshifter.current].time();
             if shapeshifter.time > form_time {
-                let next = (shapeshifter.current + 1) % shapeshifter.forms.len();
+                let next = match bosses.get(e).and_then(|boss| boss.forms(shapeshifter.forms.len())) {
+                    // bosses go round the forms of their current phase only
+                    Some(forms) if forms.contains(&(shapeshifter.current + 1)) => shapeshifter.current + 1,
+                    Some(forms) => *forms.start(),
+                    None => (shapeshifter.current + 1) % shapeshifter.forms.len(),
+                };
                 shapeshifter.forms[shapeshifter.current].on_end(e, update.deref(), (&mut self.0, &mut assets));
                 shapeshifter.forms[next].on_begin(e, update.deref(), (&mut self.0, &mut assets));
                 shapeshifter.current = next;
@@ -98,6 +194,22 @@ impl<'a> System<'a> for ShapeshifterSystem<'a> {
     }
 }
 
+pub struct StatusEffectsSystem;
+impl<'a> System<'a> for StatusEffectsSystem {
+    type SystemData = (Read<'a, DeltaTime>, WriteStorage<'a, StatusEffects>);
+
+    fn run(&mut self, (dt, mut effects): Self::SystemData) {
+        let dt = dt.0.as_secs_f32();
+        for effects in (&mut effects).join() {
+            for effect in &mut effects.active {
+                effect.time_left -= dt;
+            }
+            effects.active.retain(|effect| effect.time_left > 0.0);
+            effects.stats = Stats::from_effects(&effects.active);
+        }
+    }
+}
+
 pub struct ParticlesSystem;
 impl<'a> System<'a> for ParticlesSystem {
     type SystemData = (
@@ -122,11 +234,78 @@ impl<'a> System<'a> for ParticlesSystem {
     }
 }
 
+pub struct CombatTextSystem;
+impl<'a> System<'a> for CombatTextSystem {
+    type SystemData = (Read<'a, DeltaTime>, Write<'a, CombatText>);
+
+    fn run(&mut self, (dt, mut combat_text): Self::SystemData) {
+        let dt = dt.0.as_secs_f32();
+        for text in &mut combat_text.texts {
+            text.time += dt;
+            text.pos += text.velocity * dt;
+            // slows down while fading
+            text.velocity *= 1.0 - (dt * 3.0).min(1.0);
+        }
+        combat_text.texts.retain(|text| text.time < CombatText::LIFETIME);
+    }
+}
+
+pub struct AnimationSystem;
+impl<'a> System<'a> for AnimationSystem {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, DeltaTime>,
+        Write<'a, AnimationEvents>,
+        WriteStorage<'a, AnimatedSprite>,
+        ReadStorage<'a, SharedAnimationDef>,
+    );
+
+    fn run(&mut self, (entities, dt, mut events, mut sprites, defs): Self::SystemData) {
+        // events only live for one tick, whether anybody was interested in them or not
+        events.0.clear();
+        for (e, sprite, def) in (&entities, &mut sprites, &defs).join() {
+            if sprite.finished {
+                continue;
+            }
+            let clip = match def.clip(&sprite.clip) {
+                Some(clip) => clip,
+                None => continue,
+            };
+
+            sprite.frame_time += dt.0.as_secs_f32();
+            while clip.time_per_frame > 0.0 && sprite.frame_time >= clip.time_per_frame {
+                sprite.frame_time -= clip.time_per_frame;
+                sprite.frame += 1;
+                if sprite.frame < clip.frames {
+                    continue;
+                }
+                if clip.looping {
+                    sprite.frame = 0;
+                } else {
+                    events.0.push_back(AnimationEvent { entity: e, clip: clip.name.clone() });
+                    match clip.next.clone() {
+                        Some(next) => sprite.play(next),
+                        None => {
+                            sprite.frame = clip.frames.saturating_sub(1);
+                            sprite.finished = true;
+                        },
+                    }
+                    break;
+                }
+            }
+        }
+    }
+}
+
 pub struct WeaponrySystem;
 impl<'a> System<'a> for WeaponrySystem {
     type SystemData = (
         Read<'a, DeltaTime>,
         Write<'a, SpawnQueue>,
+        Write<'a, AudioQueue>,
+        Write<'a, ShakeQueue>,
+        Write<'a, RandomGenerator>,
+        WriteExpect<'a, AssetManager>,
         WriteExpect<'a, PhysicWorld>,
         ReadStorage<'a, Physic>,
         ReadStorage<'a, Faction>,
@@ -135,8 +314,10 @@ impl<'a> System<'a> for WeaponrySystem {
         WriteStorage<'a, Weaponry>,
         WriteStorage<'a, WeaponProperties>,
         ReadStorage<'a, WeaponAttack>,
+        ReadStorage<'a, SoundEffects>,
         ReadStorage<'a, tag::LastShot>,
         ReadStorage<'a, tag::PendingDestruction>,
+        ReadStorage<'a, StatusEffects>,
     );
 
     fn run(
@@ -144,6 +325,10 @@ impl<'a> System<'a> for WeaponrySystem {
         (
             dt,
             mut spawn_queue,
+            mut audio,
+            mut shakes,
+            mut rng,
+            mut assets,
             mut pworld,
             physics,
             factions,
@@ -152,8 +337,10 @@ impl<'a> System<'a> for WeaponrySystem {
             mut weaponries,
             mut props,
             attacks,
+            sounds,
             last_shots,
             to_destruct,
+            effects,
         ): Self::SystemData,
     ) {
         fn reload(prop: &mut WeaponProperties, dt: f32) {
@@ -167,7 +354,16 @@ impl<'a> System<'a> for WeaponrySystem {
             }
         }
 
-        for (transform, weaponry, faction_opt, physics_opt, dmg_rec_opt, last_shot_opt, to_destruct_opt) in (
+        for (
+            transform,
+            weaponry,
+            faction_opt,
+            physics_opt,
+            dmg_rec_opt,
+            last_shot_opt,
+            to_destruct_opt,
+            effects_opt,
+        ) in (
             &transforms,
             &mut weaponries,
             (&factions).maybe(),
@@ -175,21 +371,27 @@ impl<'a> System<'a> for WeaponrySystem {
             (&mut dmg_recievers).maybe(),
             (&last_shots).maybe(),
             (&to_destruct).maybe(),
+            (&effects).maybe(),
         )
             .join()
         {
+            let stats = effects_opt.map(|s| s.stats.clone()).unwrap_or_default();
             if let Some(mut prop) = weaponry.secondary.and_then(|w| props.get_mut(w)) {
                 if prop.passive_reloading {
                     reload(&mut prop, dt.0.as_secs_f32());
                 }
             }
-            if let Some((Some(mut prop), Some(attack))) = weaponry.primary.map(|w| (props.get_mut(w), attacks.get(w))) {
+            if let Some((Some(mut prop), Some(attack), sound)) = weaponry
+                .primary
+                .map(|w| (props.get_mut(w), attacks.get(w), sounds.get(w).and_then(|s| s.attack.clone())))
+            {
                 // handle reloading
                 reload(&mut prop, dt.0.as_secs_f32());
 
                 // shot if cooled
                 if prop.cooldown == 0.0 {
-                    if (prop.is_shooting || (last_shot_opt.is_some() && to_destruct_opt.is_some())) && prop.clip > 0 {
+                    let wants_to_shoot = prop.is_shooting || (last_shot_opt.is_some() && to_destruct_opt.is_some());
+                    if wants_to_shoot && prop.clip > 0 && !stats.stunned {
                         let mut data = AttackPatternData {
                             shooter_faction: faction_opt.map(|f| &f.id),
                             shooter_body: physics_opt
@@ -197,16 +399,24 @@ impl<'a> System<'a> for WeaponrySystem {
                                 .and_then(|b| b.downcast_mut::<RigidBody<f32>>()),
                             shooter_damage_reciever: dmg_rec_opt,
                             shooting_at: transform.pos.to_point(),
-                            damage_multiplier: weaponry.damage_multiplier,
+                            damage_multiplier: stats.apply(Stat::Damage, weaponry.damage_multiplier),
                             prop: prop,
                             projectiles: spawn_queue.deref_mut(),
+                            audio: audio.deref_mut(),
+                            shakes: shakes.deref_mut(),
+                            rng: rng.deref_mut(),
+                            assets: assets.deref_mut(),
                         };
                         attack.pattern.attack(&mut data);
+                        if let Some(sound) = sound {
+                            audio.play(sound, transform.pos.to_point());
+                        }
                         prop.cooldown = prop.cooldown_time;
                         prop.clip -= 1;
                     }
                 } else {
-                    prop.cooldown = (prop.cooldown - dt.0.as_secs_f32()).max(0.0);
+                    let fire_rate = stats.apply(Stat::FireRate, 1.0).max(0.0);
+                    prop.cooldown = (prop.cooldown - dt.0.as_secs_f32() * fire_rate).max(0.0);
                 }
             }
         }
@@ -219,6 +429,7 @@ impl ProjectileSystem {
         distance: &'a DistanceCounter,
         transform: &'a Transform,
         spawn_queue: &'a mut SpawnQueue,
+        assets: &'a mut AssetManager,
     ) -> ProjectileData<'a> {
         ProjectileData {
             asset: projectile.def.asset.as_ref(),
@@ -229,13 +440,15 @@ impl ProjectileSystem {
             size: projectile.def.size,
             ignore_groups: &projectile.def.ignore_groups,
             projectiles: spawn_queue,
+            assets,
         }
     }
 }
 impl<'a> System<'a> for ProjectileSystem {
     type SystemData = (
         WriteExpect<'a, SpawnQueue>,
-        ReadExpect<'a, PhysicWorld>,
+        WriteExpect<'a, AssetManager>,
+        WriteExpect<'a, PhysicWorld>,
         ReadStorage<'a, DistanceCounter>,
         ReadStorage<'a, Projectile>,
         ReadStorage<'a, Transform>,
@@ -248,7 +461,8 @@ impl<'a> System<'a> for ProjectileSystem {
         &mut self,
         (
             mut spawn_queue,
-            physic_world,
+            mut assets,
+            mut physic_world,
             distances,
             projectiles,
             transforms,
@@ -268,26 +482,38 @@ impl<'a> System<'a> for ProjectileSystem {
                     .chain(collider2.user_data().and_then(|d| d.downcast_ref::<Entity>()))
                     .collect_tuple::<(&Entity, &Entity)>()
             })
-            .dedup_by(|t1, t2| (t1.0 == t2.0 && t1.1 == t2.1) || (t1.1 == t2.0 && t1.0 == t2.1));
-
-        for (entity1, entity2) in per_entity {
-            let (dmg_rec, dmg_deal, projectile, deal_e) = if let (Some(dmg_rec), Some(dmg_deal), Some(projectile)) =
-                (dmg_recievers.get_mut(*entity1), dmg_dealers.get(*entity2), projectiles.get(*entity2))
-            {
-                (dmg_rec, dmg_deal, projectile, entity2)
-            } else if let (Some(dmg_rec), Some(dmg_deal), Some(projectile)) =
-                (dmg_recievers.get_mut(*entity2), dmg_dealers.get(*entity1), projectiles.get(*entity1))
-            {
-                (dmg_rec, dmg_deal, projectile, entity1)
-            } else {
-                continue;
-            };
+            .dedup_by(|t1, t2| (t1.0 == t2.0 && t1.1 == t2.1) || (t1.1 == t2.0 && t1.0 == t2.1))
+            .map(|(e1, e2)| (*e1, *e2))
+            .collect_vec();
+
+        let mut knockbacks: Vec<(Entity, Vec2f)> = vec![];
+        for (entity1, entity2) in per_entity.iter() {
+            let (dmg_rec, dmg_deal, projectile, deal_e, rec_e) =
+                if let (Some(dmg_rec), Some(dmg_deal), Some(projectile)) =
+                    (dmg_recievers.get_mut(*entity1), dmg_dealers.get(*entity2), projectiles.get(*entity2))
+                {
+                    (dmg_rec, dmg_deal, projectile, entity2, entity1)
+                } else if let (Some(dmg_rec), Some(dmg_deal), Some(projectile)) =
+                    (dmg_recievers.get_mut(*entity2), dmg_dealers.get(*entity1), projectiles.get(*entity1))
+                {
+                    (dmg_rec, dmg_deal, projectile, entity1, entity2)
+                } else {
+                    continue;
+                };
 
             dmg_rec.damage_queue.push((dmg_deal.damage, dmg_deal.damage_type));
+            if projectile.def.knockback > 0.0 {
+                // push away from the projectile, or along its flight if it's right on top
+                let away = match (transforms.get(*deal_e), transforms.get(*rec_e)) {
+                    (Some(from), Some(to)) if from.pos != to.pos => (to.pos - from.pos).normalize(),
+                    _ => projectile.def.velocity.try_normalize().unwrap_or_default(),
+                };
+                knockbacks.push((*rec_e, away * projectile.def.knockback));
+            }
             let consumed = if let (Some(behaviour), Some(distance), Some(transform)) =
                 (&projectile.def.behaviour, distances.get(*deal_e), transforms.get(*deal_e))
             {
-                let mut data = Self::comps_to_data(&projectile, &distance, &transform, &mut spawn_queue);
+                let mut data = Self::comps_to_data(&projectile, &distance, &transform, &mut spawn_queue, &mut assets);
                 behaviour.on_hit(&mut data)
             } else {
                 true
@@ -297,10 +523,19 @@ impl<'a> System<'a> for ProjectileSystem {
             }
         }
 
+        if !knockbacks.is_empty() {
+            for (e, body) in physic_world.bodies_iter_mut() {
+                for (_, push) in knockbacks.iter().filter(|(knocked, _)| *knocked == e) {
+                    body.apply_force(0, &Force::linear([push.x, push.y].into()), ForceType::VelocityChange, true);
+                }
+            }
+        }
+
         for (distance, transform, projectile) in (&distances, &transforms, &projectiles).join() {
             if distance.distance >= projectile.def.distance {
                 if let Some(behaviour) = &projectile.def.behaviour {
-                    let mut data = Self::comps_to_data(&projectile, &distance, &transform, &mut spawn_queue);
+                    let mut data =
+                        Self::comps_to_data(&projectile, &distance, &transform, &mut spawn_queue, &mut assets);
                     behaviour.on_end(&mut data);
                 } else {
                     spawn_queue.0.push_back(SpawnItem::Particle(particle::ID::Splash, transform.pos.to_point()));
@@ -312,29 +547,39 @@ impl<'a> System<'a> for ProjectileSystem {
 
 pub struct PhysicSystem;
 impl<'a> System<'a> for PhysicSystem {
-    type SystemData =
-        (WriteStorage<'a, Transform>, WriteStorage<'a, Movement>, Read<'a, DeltaTime>, WriteExpect<'a, PhysicWorld>);
+    type SystemData = (
+        WriteStorage<'a, Transform>,
+        WriteStorage<'a, Movement>,
+        ReadStorage<'a, StatusEffects>,
+        Read<'a, DeltaTime>,
+        WriteExpect<'a, PhysicWorld>,
+    );
 
-    fn run(&mut self, (mut transforms, mut movements, delta, mut world): Self::SystemData) {
+    fn run(&mut self, (mut transforms, mut movements, effects, delta, mut world): Self::SystemData) {
         // set data before simulation
         for (e, body) in world.bodies_iter_mut() {
             if let Some(movement) = movements.get_mut(e) {
+                let stats = effects.get(e).map(|s| s.stats.clone()).unwrap_or_default();
+                let max_velocity = stats.apply(Stat::MaxVelocity, movement.max_velocity);
+                let acceleration_flat = stats.apply(Stat::Acceleration, movement.acceleration_flat);
+                let steering_difficulty = stats.apply(Stat::Steering, movement.steering_difficulty);
+
                 let velocity_len = movement.velocity.length();
-                body.set_linear_damping((velocity_len / movement.max_velocity).max(1.0));
+                body.set_linear_damping((velocity_len / max_velocity).max(1.0));
 
-                // velocity soft-cap
-                if velocity_len < movement.max_velocity {
-                    let acceleration = movement.target_acceleration_normal * movement.acceleration_flat;
+                // velocity soft-cap, stunned ones just drift
+                if velocity_len < max_velocity && !stats.stunned {
+                    let acceleration = movement.target_acceleration_normal * acceleration_flat;
                     let force = Force::linear([acceleration.x, acceleration.y].into());
                     body.apply_force(0, &force, ForceType::AccelerationChange, true);
 
                     // amount of target acceleration converted directly into
                     // raw velocity
                     // TODO: there should be a way to do this using physic engine itself
-                    if movement.steering_difficulty < 1.0 {
+                    if steering_difficulty < 1.0 {
                         let velocity_compensation = movement.target_acceleration_normal * velocity_len
                             - movement.velocity * movement.target_acceleration_normal.length();
-                        let velocity = velocity_compensation * (1.0 - movement.steering_difficulty);
+                        let velocity = velocity_compensation * (1.0 - steering_difficulty);
                         let force = Force::linear([velocity.x, velocity.y].into());
                         body.apply_force(0, &force, ForceType::VelocityChange, true);
                     }
@@ -567,20 +812,36 @@ impl<'a> System<'a> for ContainerSinkSystem {
     type SystemData = (
         Entities<'a>,
         WriteExpect<'a, SpawnQueue>,
+        Write<'a, AudioQueue>,
         ReadStorage<'a, Transform>,
         ReadStorage<'a, Inventory>,
+        ReadStorage<'a, SoundEffects>,
         ReadStorage<'a, tag::Container>,
         WriteStorage<'a, tag::PendingDestruction>,
     );
 
     fn run(
         &mut self,
-        (entities, mut spawn_queue, transforms, inventories, containers, mut to_destruct): Self::SystemData,
+        (
+            entities,
+            mut spawn_queue,
+            mut audio,
+            transforms,
+            inventories,
+            sounds,
+            containers,
+            mut to_destruct,
+        ): Self::SystemData,
     ) {
-        for (e, transform, inventory, _) in (&entities, &transforms, &inventories, &containers).join() {
+        for (e, transform, inventory, sound, _) in
+            (&entities, &transforms, &inventories, (&sounds).maybe(), &containers).join()
+        {
             if !inventory.content.have_some() {
                 to_destruct.insert(e, tag::PendingDestruction).unwrap();
                 spawn_queue.0.push_back(SpawnItem::Particle(particle::ID::MediumSplash, transform.pos.to_point()));
+                if let Some(sound) = sound.and_then(|s| s.death.clone()) {
+                    audio.play(sound, transform.pos.to_point());
+                }
             }
         }
     }
@@ -590,13 +851,18 @@ pub struct ExplodeOnDeathSystem;
 impl<'a> System<'a> for ExplodeOnDeathSystem {
     type SystemData = (
         WriteExpect<'a, SpawnQueue>,
+        Write<'a, AudioQueue>,
         ReadStorage<'a, Faction>,
         ReadStorage<'a, Transform>,
+        ReadStorage<'a, SoundEffects>,
         ReadStorage<'a, tag::PendingDestruction>,
     );
 
-    fn run(&mut self, (mut spawn_queue, faction, transform, to_destruct): Self::SystemData) {
-        for (faction, transform, _) in (&faction, &transform, &to_destruct).join() {
+    fn run(&mut self, (mut spawn_queue, mut audio, faction, transform, sounds, to_destruct): Self::SystemData) {
+        for (faction, transform, sound, _) in (&faction, &transform, (&sounds).maybe(), &to_destruct).join() {
+            if let Some(sound) = sound.and_then(|s| s.death.clone()) {
+                audio.play(sound, transform.pos.to_point());
+            }
             match faction.id {
                 FactionId::Crabs | FactionId::Mythical => {
                     spawn_queue.0.push_back(SpawnItem::Particle(particle::ID::MediumSplash, transform.pos.to_point()));
@@ -622,14 +888,14 @@ pub struct LootGenerateSystem;
 impl<'a> System<'a> for LootGenerateSystem {
     type SystemData = (
         WriteExpect<'a, SpawnQueue>,
+        Write<'a, RandomGenerator>,
         ReadStorage<'a, SharedDropTable>,
         ReadStorage<'a, Transform>,
         ReadStorage<'a, tag::PendingDestruction>,
     );
 
-    fn run(&mut self, (mut spawn_queue, drops, transform, to_destruct): Self::SystemData) {
+    fn run(&mut self, (mut spawn_queue, mut rng, drops, transform, to_destruct): Self::SystemData) {
         for (drop, transform, _) in (&drops, &transform, &to_destruct).join() {
-            let mut rng = thread_rng();
             if rng.gen::<f32>() <= drop.drop_chance {
                 if rng.gen_range(0, 4) == 0 {
                     spawn_queue.0.push_back(SpawnItem::Entity(entity::ID::Mimic, transform.pos.to_point(), vec![]));
@@ -643,7 +909,7 @@ impl<'a> System<'a> for LootGenerateSystem {
                     }
                     let drop_arr = drop_map.into_iter().collect_vec();
                     let dist = WeightedIndex::new(drop_arr.iter().map(|item| item.1).collect()).unwrap();
-                    let new_drop = drop_arr[dist.sample(&mut rng)].0;
+                    let new_drop = drop_arr[dist.sample(rng.deref_mut())].0;
                     log::debug!("Spawning new lootbox with {:?}", new_drop);
                     spawn_queue
                         .0
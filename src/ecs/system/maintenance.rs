@@ -1,13 +1,13 @@
 use super::super::{component::*, resource::*, tag};
-use crate::{arena, entity, math::*, read_event, ui::system::ImGuiSystem};
-use ggez::input::{keyboard::KeyCode, mouse::MouseButton};
+use crate::{arena, controls::Action, entity, math::*, prefab::Prefabs, read_event, ui::system::ImGuiSystem};
+use ggez::input::mouse::MouseButton;
 use itertools::Itertools;
 use nphysics2d::{
     math::Isometry,
     ncollide2d::shape::{Cuboid, ShapeHandle},
     object::{BodyPartHandle, BodyStatus, ColliderDesc, RigidBodyDesc},
 };
-use rand::{distributions::uniform::Uniform, seq::SliceRandom, thread_rng, Rng};
+use rand::{distributions::uniform::Uniform, Rng};
 use specs::prelude::*;
 use std::ops::DerefMut;
 
@@ -23,23 +23,58 @@ impl<'a> System<'a> for ArenaSystem {
         WriteExpect<'a, PhysicWorld>,
         Write<'a, Arena>,
         Write<'a, SpawnQueue>,
+        Write<'a, RandomGenerator>,
+        Write<'a, Chunks>,
+        ReadExpect<'a, Prefabs>,
     );
 
     fn run(
         &mut self,
-        (entities, transforms, factions, player, lvl_changer, mut to_destruct, mut world, mut arena, mut spawn_queue): Self::SystemData,
+        (
+            entities,
+            transforms,
+            factions,
+            player,
+            lvl_changer,
+            mut to_destruct,
+            mut world,
+            mut arena,
+            mut spawn_queue,
+            mut rng,
+            mut chunks,
+            prefabs,
+        ): Self::SystemData,
     ) {
         // if no enemies left and no portal yet, spawn portal
-        if !(&factions).join().any(|f| f.id != FactionId::Good) && (&lvl_changer).join().next().is_none() {
+        // (there is no way out of the open world though)
+        if chunks.seed.is_none()
+            && !(&factions).join().any(|f| f.id != FactionId::Good)
+            && (&lvl_changer).join().next().is_none()
+        {
             spawn_queue.0.push_back(SpawnItem::Entity(entity::ID::Swirl, Point2f::zero(), vec![]));
         }
 
-        if let Some(id) = arena.change_to.take() {
+        if let Some(kind) = arena.change_to.take() {
             // clear old entities
             for (e, _, _) in (&entities, &transforms, !&player).join() {
                 to_destruct.insert(e, tag::PendingDestruction).unwrap();
             }
-            arena::set(id, arena.deref_mut(), spawn_queue.deref_mut());
+            kind.set(arena.deref_mut(), spawn_queue.deref_mut(), rng.deref_mut(), &prefabs);
+            *chunks = match kind {
+                arena::Kind::Open { seed } => Chunks::new(seed),
+                _ => Chunks::default(),
+            };
+            arena.current = Some(kind);
+        }
+
+        // open world is endless, so no borders at all
+        if chunks.seed.is_some() {
+            for border in arena.borders.iter_mut() {
+                if let Some(collider) = border.take().and_then(|handle| world.colliders.remove(handle)) {
+                    world.bodies.remove(collider.body());
+                }
+            }
+            return;
         }
 
         let size = arena.size;
@@ -68,15 +103,198 @@ impl<'a> System<'a> for ArenaSystem {
     }
 }
 
+/// Streams open world chunks around camera targets: generates or restores
+/// chunks coming into sight and persists the ones going out of it.
+pub struct ChunkSystem;
+impl ChunkSystem {
+    // chunks loaded in every direction from the center one
+    const LOAD_RADIUS: i32 = 1;
+    // chunks are unloaded a bit further, so crossing the edge back
+    // and forth doesn't reload anything
+    const UNLOAD_RADIUS: i32 = 2;
+    // every chunk away from the origin adds that much difficulty
+    const DIFFICULTY_PER_CHUNK: f32 = 0.25;
+}
+impl<'a> System<'a> for ChunkSystem {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, Camera>,
+        Read<'a, Arena>,
+        Write<'a, Chunks>,
+        Write<'a, SpawnQueue>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, Reflection>,
+        ReadStorage<'a, HealthPool>,
+        WriteStorage<'a, ChunkMember>,
+        WriteStorage<'a, tag::PendingDestruction>,
+        ReadExpect<'a, Prefabs>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            camera,
+            arena,
+            mut chunks,
+            mut spawn_queue,
+            transforms,
+            reflections,
+            hpools,
+            mut members,
+            mut to_destruct,
+            prefabs,
+        ): Self::SystemData,
+    ) {
+        let seed = match chunks.seed {
+            Some(seed) => seed,
+            None => return,
+        };
+        let center_pos = camera.target_bounds(&transforms).map(|(min, max)| (min + max) * 0.5).unwrap_or(camera.pos);
+        let center = Chunks::chunk_at(center_pos.to_point());
+        let distance = |chunk: &ChunkPos| (chunk.0 - center.0).abs().max((chunk.1 - center.1).abs());
+
+        // unload chunks which are too far
+        let to_unload = chunks.loaded.iter().filter(|c| distance(c) > Self::UNLOAD_RADIUS).copied().collect_vec();
+        for chunk in &to_unload {
+            chunks.loaded.remove(chunk);
+        }
+        if !to_unload.is_empty() {
+            for (e, member, transform, reflection, hpool) in
+                (&entities, &mut members, &transforms, &reflections, (&hpools).maybe()).join()
+            {
+                if !to_unload.contains(&member.chunk) {
+                    continue;
+                }
+                // entities could've moved into another chunk since they were spawned
+                let now_in = Chunks::chunk_at(transform.pos.to_point());
+                if chunks.loaded.contains(&now_in) {
+                    member.chunk = now_in;
+                    continue;
+                }
+                let id = match reflection.id.strip_prefix("e_").and_then(entity::ID::from_name) {
+                    Some(id) => id,
+                    None => continue,
+                };
+                let hp = hpool.filter(|h| h.hp < h.max_hp).map(|h| h.hp);
+                chunks.persisted.entry(now_in).or_default().push(ChunkEntity {
+                    id,
+                    pos: transform.pos.to_point(),
+                    hp,
+                });
+                to_destruct.insert(e, tag::PendingDestruction).unwrap();
+            }
+        }
+
+        // load chunks around the center
+        for x in -Self::LOAD_RADIUS..=Self::LOAD_RADIUS {
+            for y in -Self::LOAD_RADIUS..=Self::LOAD_RADIUS {
+                let chunk = (center.0 + x, center.1 + y);
+                if !chunks.loaded.insert(chunk) {
+                    continue;
+                }
+                let content = match chunks.persisted.remove(&chunk) {
+                    Some(persisted) => persisted,
+                    None => {
+                        let from_origin = chunk.0.abs().max(chunk.1.abs());
+                        let difficulty = arena.difficulty * (1.0 + Self::DIFFICULTY_PER_CHUNK * from_origin as f32);
+                        log::debug!("Generating chunk {:?} with difficulty {}", chunk, difficulty);
+                        arena::generator::generate_chunk(seed, chunk, difficulty, &prefabs)
+                            .into_iter()
+                            .map(|(id, pos)| ChunkEntity { id, pos, hp: None })
+                            .collect()
+                    },
+                };
+                for def in content {
+                    spawn_queue.0.push_back(SpawnItem::ChunkEntity(def, chunk));
+                }
+            }
+        }
+    }
+}
+
 pub struct CameraSystem;
+impl CameraSystem {
+    // only those close enough are framed
+    const FRAMING_RADIUS: f32 = 700.0;
+    const FRAMING_COUNT: usize = 3;
+    // space around framed entities, in pixels of the screen
+    const FRAMING_MARGIN: f32 = 150.0;
+    const ZOOM_STEP: f32 = 0.1;
+}
 impl<'a> System<'a> for CameraSystem {
-    type SystemData = (WriteExpect<'a, Camera>, Read<'a, DeltaTime>, ReadStorage<'a, Transform>);
+    type SystemData = (
+        WriteExpect<'a, Camera>,
+        Write<'a, ShakeQueue>,
+        Read<'a, DeltaTime>,
+        Read<'a, Inputs>,
+        Read<'a, LocalPlayer>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, Target>,
+    );
+
+    fn run(&mut self, (mut camera, mut shakes, dt, inputs, local_player, transforms, targets): Self::SystemData) {
+        let dt = dt.0.as_secs_f32();
+        // the peer has a camera of their own
+        if inputs.is_clicked_locally(Action::ToggleFraming, &local_player) {
+            camera.mode = match camera.mode {
+                CameraMode::Follow => CameraMode::Framing,
+                CameraMode::Framing => CameraMode::Follow,
+            };
+        }
+        if camera.mode == CameraMode::Follow {
+            let mut zoom = camera.target_zoom;
+            if inputs.is_clicked_locally(Action::ZoomIn, &local_player) {
+                zoom += Self::ZOOM_STEP;
+            }
+            if inputs.is_clicked_locally(Action::ZoomOut, &local_player) {
+                zoom -= Self::ZOOM_STEP;
+            }
+            camera.target_zoom = zoom.max(Camera::MIN_ZOOM).min(Camera::MAX_ZOOM);
+        }
 
-    fn run(&mut self, (mut camera, dt, transforms): Self::SystemData) {
-        if let Some(target_transform) = camera.target.and_then(|e| transforms.get(e)) {
-            let pos_dt = target_transform.pos - camera.pos;
-            camera.pos += pos_dt * dt.0.as_secs_f32() * 4.0;
+        // players' own zoom, unless something has to be fitted in
+        let mut zoom_goal = camera.target_zoom;
+        if let Some((mut min, mut max)) = camera.target_bounds(&transforms) {
+            if camera.mode == CameraMode::Framing {
+                let center = (min + max) * 0.5;
+                let chasers: Vec<Vec2f> = (&transforms, &targets)
+                    .join()
+                    .filter(|(_, target)| target.target.map_or(false, |e| camera.targets.contains(&e)))
+                    .map(|(transform, _)| transform.pos)
+                    .filter(|pos| (*pos - center).length() < Self::FRAMING_RADIUS)
+                    .sorted_by(|a, b| (*a - center).length().partial_cmp(&(*b - center).length()).unwrap())
+                    .take(Self::FRAMING_COUNT)
+                    .collect();
+                for pos in &chasers {
+                    min = Vec2f::new(min.x.min(pos.x), min.y.min(pos.y));
+                    max = Vec2f::new(max.x.max(pos.x), max.y.max(pos.y));
+                }
+            }
+            let focus = (min + max) * 0.5;
+            // window size is only known after the first frame
+            let viewport = camera.viewport();
+            if viewport.width > 0.0 && viewport.height > 0.0 {
+                let fit = ((viewport.width - Self::FRAMING_MARGIN) / (max.x - min.x))
+                    .min((viewport.height - Self::FRAMING_MARGIN) / (max.y - min.y));
+                if camera.mode == CameraMode::Framing {
+                    camera.target_zoom = fit.max(Camera::MIN_ZOOM).min(1.0);
+                    zoom_goal = camera.target_zoom;
+                } else {
+                    // players drifting apart are kept on the screen without touching their zoom
+                    zoom_goal = zoom_goal.min(fit.max(Camera::MIN_ZOOM));
+                }
+            }
+            let pos_dt = focus - camera.pos;
+            camera.pos += pos_dt * dt * 4.0;
         }
+
+        let zoom_dt = zoom_goal - camera.zoom;
+        camera.zoom += zoom_dt * (dt * 4.0).min(1.0);
+        for ShakeItem { pos, amount } in shakes.0.drain(..) {
+            camera.shake(pos, amount);
+        }
+        camera.update_shake(dt);
     }
 }
 
@@ -91,6 +309,105 @@ impl<'a> System<'a> for InventoryMaintenanceSystem {
     }
 }
 
+/// Moves items dragged in the inventory window. Stacks of the same item are
+/// merged, and only consumables go to the hotbar and only weapons to weaponry.
+pub struct ItemMoveSystem;
+impl ItemMoveSystem {
+    fn item_box<'s>(
+        at: &ItemSlot,
+        entities: &Entities,
+        inventories: &'s mut WriteStorage<Inventory>,
+        hotbars: &'s mut WriteStorage<Hotbar>,
+        weaponries: &'s mut WriteStorage<Weaponry>,
+    ) -> Option<&'s mut ItemBox> {
+        let owner = entities.entity(at.owner);
+        if !entities.is_alive(owner) {
+            return None;
+        }
+        match at.slot {
+            Slot::Inventory(i) => inventories.get_mut(owner)?.content.get_mut(i),
+            Slot::Hotbar(i) => hotbars.get_mut(owner)?.content.get_mut(i),
+            Slot::Primary => Some(&mut weaponries.get_mut(owner)?.primary),
+            Slot::Secondary => Some(&mut weaponries.get_mut(owner)?.secondary),
+        }
+    }
+}
+impl<'a> System<'a> for ItemMoveSystem {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, Inputs>,
+        WriteStorage<'a, Inventory>,
+        WriteStorage<'a, Hotbar>,
+        WriteStorage<'a, Weaponry>,
+        WriteStorage<'a, Stackable>,
+        ReadStorage<'a, Reflection>,
+        ReadStorage<'a, Consumable>,
+        ReadStorage<'a, WeaponProperties>,
+        ReadStorage<'a, WeaponAttack>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            inputs,
+            mut inventories,
+            mut hotbars,
+            mut weaponries,
+            mut stacks,
+            reflections,
+            consumables,
+            wpn_props,
+            wpn_attacks,
+        ): Self::SystemData,
+    ) {
+        macro_rules! item_box {
+            ($at:expr) => {
+                Self::item_box($at, &entities, &mut inventories, &mut hotbars, &mut weaponries)
+            };
+        }
+        let same_kind = |a: Entity, b: Entity| reflections.get(a).map(|r| &r.id) == reflections.get(b).map(|r| &r.id);
+
+        for ItemMove { from, to } in inputs.players.iter().filter_map(|p| p.item_move) {
+            let (item, target) = match (item_box!(&from).copied(), item_box!(&to).copied()) {
+                (Some(Some(item)), Some(target)) => (item, target),
+                _ => continue,
+            };
+            let fits = match to.slot {
+                Slot::Inventory(_) => true,
+                Slot::Hotbar(_) => consumables.contains(item),
+                Slot::Primary | Slot::Secondary => wpn_props.contains(item) && wpn_attacks.contains(item),
+            };
+            if !fits {
+                continue;
+            }
+
+            match target {
+                None => {
+                    *item_box!(&to).unwrap() = Some(item);
+                    *item_box!(&from).unwrap() = None;
+                },
+                Some(target) if target != item && same_kind(target, item) => {
+                    let mut current = stacks.get(item).map(|s| s.current).unwrap_or(1);
+                    if let Some(stack_to) = stacks.get_mut(target) {
+                        let transfer_count = current.min(stack_to.stack_size - stack_to.current);
+                        stack_to.current += transfer_count;
+                        current = current.saturating_sub(transfer_count);
+                    }
+                    if let Some(stack_from) = stacks.get_mut(item) {
+                        stack_from.current = current;
+                    }
+                    if current == 0 {
+                        *item_box!(&from).unwrap() = None;
+                        entities.delete(item).unwrap();
+                    }
+                },
+                Some(_) => (),
+            }
+        }
+    }
+}
+
 macro_rules! random_range {
     ($rng:expr; $from:expr => $to:expr) => {
         if let Some(range) = &$from {
@@ -104,13 +421,16 @@ pub struct RandomizedWeaponsSystem {
     inserted: BitSet,
 }
 impl<'a> System<'a> for RandomizedWeaponsSystem {
-    type SystemData = (WriteStorage<'a, RandomizedWeaponProperties>, WriteStorage<'a, WeaponProperties>);
+    type SystemData = (
+        Write<'a, RandomGenerator>,
+        WriteStorage<'a, RandomizedWeaponProperties>,
+        WriteStorage<'a, WeaponProperties>,
+    );
 
-    fn run(&mut self, (mut randoms, mut props): Self::SystemData) {
+    fn run(&mut self, (mut rng, mut randoms, mut props): Self::SystemData) {
         read_event!(randoms, self.reader_id.as_mut().unwrap(); Inserted => self.inserted);
 
         for (random, prop, _) in (&randoms, &mut props, &self.inserted).join() {
-            let mut rng = thread_rng();
             random_range!(rng; random.clip_size => prop.clip_size);
             random_range!(rng; random.reloading_time => prop.reloading_time);
             random_range!(rng; random.cooldown_time => prop.cooldown_time);
@@ -138,40 +458,58 @@ impl<'a> System<'a> for InteractionSystem {
     );
 
     fn run(&mut self, (entities, mut interaction, transforms, inventories, tag, lvl_changer): Self::SystemData) {
-        for (transform, _) in (&transforms, &tag).join() {
+        interaction.0.clear();
+        for (player, transform, _) in (&entities, &transforms, &tag).join() {
             let near_inventory = (&entities, &inventories, &transforms, !&tag)
                 .join()
                 .map(|(e, _, t, _)| (e, (t.pos - transform.pos).length()))
                 .filter(|(_, distance)| *distance <= 50.0)
                 .fold1(|t1, t2| if t1.1 < t2.1 { t1 } else { t2 });
-            interaction.near_inventory = near_inventory.map(|(e, _)| e);
 
             let near_level_changer = (&entities, &transforms, &lvl_changer)
                 .join()
                 .map(|(e, t, _)| (e, (t.pos - transform.pos).length()))
                 .filter(|(_, distance)| *distance <= 50.0)
                 .fold1(|t1, t2| if t1.1 < t2.1 { t1 } else { t2 });
-            interaction.near_level_changer = near_level_changer.map(|(e, _)| e);
+            interaction.0.insert(player, Interaction {
+                near_inventory: near_inventory.map(|(e, _)| e),
+                near_level_changer: near_level_changer.map(|(e, _)| e),
+            });
         }
     }
 }
-pub struct InputsSystem;
+/// Every player ship follows actions of its own player, resolved by `Controls`.
+/// Aiming follows the right stick until the mouse is moved again, players
+/// without the mouse aim where they are heading when the stick is left alone.
+#[derive(Default)]
+pub struct InputsSystem {
+    // None is the mouse for players who have it
+    aim: [Option<Vec2f>; MAX_PLAYERS],
+}
+impl InputsSystem {
+    // how far from the player stick aiming points
+    const AIM_DISTANCE: f32 = 300.0;
+}
 impl<'a> System<'a> for InputsSystem {
     type SystemData = (
         Entities<'a>,
+        ReadStorage<'a, Transform>,
         WriteStorage<'a, Movement>,
         WriteExpect<'a, UiHub>,
         WriteExpect<'a, Arena>,
+        Write<'a, RandomGenerator>,
         Read<'a, InteractionCache>,
         Read<'a, Inputs>,
-        Read<'a, Camera>,
+        Read<'a, LocalPlayer>,
         ReadStorage<'a, tag::Player>,
+        ReadStorage<'a, PlayerIndex>,
         WriteStorage<'a, tag::PendingDestruction>,
         WriteStorage<'a, Hotbar>,
         WriteStorage<'a, Consumer>,
         WriteStorage<'a, Weaponry>,
         WriteStorage<'a, WeaponProperties>,
         ReadStorage<'a, Consumable>,
+        ReadStorage<'a, SoundEffects>,
         WriteStorage<'a, Stackable>,
     );
 
@@ -179,79 +517,103 @@ impl<'a> System<'a> for InputsSystem {
         &mut self,
         (
             entities,
+            transforms,
             mut movements,
             mut ui,
             mut arena,
+            mut rng,
             interaction,
             inputs,
-            camera,
+            local_player,
             tag,
+            indices,
             mut to_destruct,
             mut hotbars,
             mut consumers,
             mut weaponries,
             mut wpn_props,
             consumables,
+            sounds,
             mut stackables,
         ): Self::SystemData,
     ) {
-        for (e, movement, _) in (&entities, &mut movements, &tag).join() {
+        if inputs.is_clicked(Action::Pause) {
+            ui.pause.is_opened = !ui.pause.is_opened;
+        }
+
+        for (e, movement, _, index) in (&entities, &mut movements, &tag, &indices).join() {
+            let player = &inputs.players[index.0];
+            // windows of the peer's player are opened on their screen
+            let is_local = local_player.is_local(index.0);
             let mut direction = Vec2f::zero();
-            for key in &inputs.pressed_keys {
-                match key {
-                    KeyCode::W => direction.y -= 1.0,
-                    KeyCode::A => direction.x -= 1.0,
-                    KeyCode::S => direction.y += 1.0,
-                    KeyCode::D => direction.x += 1.0,
+            for action in &player.pressed_actions {
+                match action {
+                    Action::MoveUp => direction.y -= 1.0,
+                    Action::MoveLeft => direction.x -= 1.0,
+                    Action::MoveDown => direction.y += 1.0,
+                    Action::MoveRight => direction.x += 1.0,
                     _ => (),
                 }
             }
-            movement.target_acceleration_normal = direction.try_normalize().unwrap_or_default();
+            // sticks can be tilted just a bit, keys are all or nothing
+            direction += player.move_stick;
+            if direction.length() > 1.0 {
+                direction = direction.normalize();
+            }
+            movement.target_acceleration_normal = direction;
+
+            if player.aim_stick != Vec2f::zero() {
+                self.aim[index.0] = Some(player.aim_stick.normalize());
+            } else if player.mouse_moved {
+                self.aim[index.0] = None;
+            } else if player.mouse_aim.is_none() && direction != Vec2f::zero() {
+                self.aim[index.0] = Some(direction.normalize());
+            }
 
-            for key in &inputs.clicked_keys {
-                match key {
-                    KeyCode::Escape => {
-                        ui.pause.is_opened = !ui.pause.is_opened;
-                    },
-                    KeyCode::I => {
+            // in the same order every time, as some of them roll the dice
+            for action in Action::ALL.iter().filter(|a| player.clicked_actions.contains(a)) {
+                match action {
+                    Action::Inventory if is_local => {
                         ui.inventory_window.show_inventories_for.insert(e);
                     },
-                    KeyCode::E => {
-                        if let Some(near_inventory_e) = interaction.near_inventory {
+                    Action::Interact => {
+                        let interaction = interaction.get(e);
+                        if let Some(near_inventory_e) = interaction.near_inventory.filter(|_| is_local) {
                             ui.inventory_window.show_inventories_for.insert(near_inventory_e);
                         }
                         if interaction.near_level_changer.is_some() {
-                            arena.difficulty *= 1.5;
-                            let mut rng = thread_rng();
-                            arena.change_to.replace(*arena::IDS.choose(&mut rng).unwrap());
+                            arena.next_level(rng.deref_mut());
                         }
                     },
-                    KeyCode::Key1 | KeyCode::Key2 | KeyCode::Key3 | KeyCode::Key4 => {
-                        let n = *key as usize - KeyCode::Key1 as usize;
+                    Action::UseHotbar(n) => {
+                        let n = *n as usize;
                         if let (Some(consumer), Some(hotbar)) = (consumers.get_mut(e), hotbars.get_mut(e)) {
                             if let (Some(consumable), stackable) = (
                                 hotbar.content[n].and_then(|i| consumables.get(i)),
                                 hotbar.content[n].and_then(|i| stackables.get_mut(i)),
                             ) {
-                                // check if we already have this consumable
-                                if !consumer.handles.iter().any(|h| std::ptr::eq(h.behaviour, consumable.behaviour)) {
-                                    let consume_item = if let Some(stackable) = stackable {
-                                        if stackable.current > 1 {
-                                            stackable.current -= 1;
-                                            false
-                                        } else {
-                                            true
-                                        }
+                                let sound =
+                                    hotbar.content[n].and_then(|i| sounds.get(i)).and_then(|s| s.consume.clone());
+                                let consume_item = if let Some(stackable) = stackable {
+                                    if stackable.current > 1 {
+                                        stackable.current -= 1;
+                                        false
                                     } else {
                                         true
-                                    };
-                                    if consume_item {
-                                        to_destruct
-                                            .insert(hotbar.content[n].take().unwrap(), tag::PendingDestruction)
-                                            .unwrap();
                                     }
-                                    consumer.handles.push(ConsumeHandle { behaviour: consumable.behaviour, time: 0.0 });
+                                } else {
+                                    true
+                                };
+                                if consume_item {
+                                    to_destruct
+                                        .insert(hotbar.content[n].take().unwrap(), tag::PendingDestruction)
+                                        .unwrap();
                                 }
+                                consumer.handles.push(ConsumeHandle {
+                                    behaviour: consumable.behaviour.clone(),
+                                    time: 0.0,
+                                    sound,
+                                });
                             }
                         }
                     },
@@ -259,13 +621,19 @@ impl<'a> System<'a> for InputsSystem {
                 }
             }
         }
-        for (weaponry, _) in (&mut weaponries, &tag).join() {
+        for (weaponry, transform, _, index) in (&mut weaponries, &transforms, &tag, &indices).join() {
+            let player = &inputs.players[index.0];
             if let Some(props) = weaponry.primary.and_then(|i| wpn_props.get_mut(i)) {
-                props.is_shooting = inputs.mouse_pressed.contains(&MouseButton::Left);
-                props.target_pos = camera.project(&inputs.mouse_pos);
+                props.is_shooting = player.pressed_actions.contains(&Action::Fire);
+                props.target_pos = match (self.aim[index.0], player.mouse_aim) {
+                    (Some(aim), _) => (transform.pos + aim * Self::AIM_DISTANCE).to_point(),
+                    (None, Some(mouse_aim)) => mouse_aim,
+                    (None, None) => (transform.pos + Vec2f::new(Self::AIM_DISTANCE, 0.0)).to_point(),
+                };
             }
 
-            if inputs.mouse_scroll != 0.0 && weaponry.primary.is_some() && weaponry.secondary.is_some() {
+            let wants_swap = player.clicked_actions.contains(&Action::SwapWeapon);
+            if wants_swap && weaponry.primary.is_some() && weaponry.secondary.is_some() {
                 std::mem::swap(&mut weaponry.primary, &mut weaponry.secondary);
             }
         }
@@ -283,9 +651,15 @@ impl ImpactDamageSystem {
     }
 }
 impl<'a> System<'a> for ImpactDamageSystem {
-    type SystemData = (ReadExpect<'a, PhysicWorld>, ReadStorage<'a, Movement>, WriteStorage<'a, DamageReciever>);
+    type SystemData = (
+        ReadExpect<'a, PhysicWorld>,
+        Write<'a, ShakeQueue>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, Movement>,
+        WriteStorage<'a, DamageReciever>,
+    );
 
-    fn run(&mut self, (physic_world, movements, mut dmg_recievers): Self::SystemData) {
+    fn run(&mut self, (physic_world, mut shakes, transforms, movements, mut dmg_recievers): Self::SystemData) {
         use nphysics2d::ncollide2d::pipeline::narrow_phase::ContactEvent;
         for contact in physic_world.geometry_world.contact_events() {
             if let ContactEvent::Started(handle1, handle2) = contact {
@@ -298,6 +672,9 @@ impl<'a> System<'a> for ImpactDamageSystem {
                 ) * Self::DAMAGE_MAX;
 
                 if damage > 0.0 {
+                    if let Some(transform) = entity1.and_then(|e| transforms.get(*e)) {
+                        shakes.shake(transform.pos.to_point(), damage / Self::DAMAGE_MAX * 0.6);
+                    }
                     let damage_pack = (damage.floor() as u32, DamageType::Impact);
                     if let Some(rec) = entity1.and_then(|e| dmg_recievers.get_mut(*e)) {
                         rec.damage_queue.push(damage_pack);
@@ -316,23 +693,101 @@ impl<'a> System<'a> for DamageSystem {
     type SystemData = (
         Entities<'a>,
         Read<'a, DeltaTime>,
+        Write<'a, AudioQueue>,
+        Write<'a, CombatText>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, SoundEffects>,
         WriteStorage<'a, HealthPool>,
         WriteStorage<'a, DamageReciever>,
+        WriteStorage<'a, StatusEffects>,
         WriteStorage<'a, tag::PendingDestruction>,
     );
 
-    fn run(&mut self, (entities, dt, mut hpools, mut dmg_recievers, mut to_destruct): Self::SystemData) {
-        for (mut hpool, dmg_rec) in (&mut hpools.restrict_mut(), &mut dmg_recievers).join() {
-            for (damage, damage_type) in dmg_rec.damage_queue.drain(..) {
-                if dmg_rec.damage_immunity[damage_type].is_none() {
-                    let hpool = hpool.get_mut_unchecked();
-                    hpool.hp = hpool.hp.saturating_sub(damage);
+    fn run(
+        &mut self,
+        (
+            entities,
+            dt,
+            mut audio,
+            mut combat_text,
+            transforms,
+            sounds,
+            mut hpools,
+            mut dmg_recievers,
+            mut effects,
+            mut to_destruct,
+        ): Self::SystemData,
+    ) {
+        let dt = dt.0.as_secs_f32();
+        let mut afflicted: Vec<(Entity, &'static StatusEffectDef, &'static str, f32)> = vec![];
+        for (e, mut hpool, dmg_rec, transform, sound, effects_opt) in (
+            &entities,
+            &mut hpools.restrict_mut(),
+            &mut dmg_recievers,
+            (&transforms).maybe(),
+            (&sounds).maybe(),
+            (&effects).maybe(),
+        )
+            .join()
+        {
+            let stats = effects_opt.map(|s| s.stats.clone()).unwrap_or_default();
+            let text_pos = transform.map(|t| t.pos);
+            let mut is_hit = false;
+            let mut is_blocked = false;
+            let mut damage_sum = 0;
+            for (damage, damage_type) in std::mem::take(&mut dmg_rec.damage_queue) {
+                if dmg_rec.damage_immunity[damage_type].is_some() || stats.immunity[damage_type] {
+                    is_blocked = true;
+                    continue;
+                }
+                let damage = dmg_rec.resisted(damage as f32, damage_type).round() as u32;
+                damage_sum += damage;
+                is_hit = true;
+                if let Some(pos) = text_pos {
+                    let is_weak = dmg_rec.resistances[damage_type] < 0.0;
+                    combat_text.damage(pos, damage, damage_type, is_weak);
+                }
+                match damage_type {
+                    DamageType::Fire if dmg_rec.burning_time > 0.0 => {
+                        afflicted.push((e, &BURNING, "fire", dmg_rec.burning_time))
+                    },
+                    DamageType::Lightning if dmg_rec.shock_time > 0.0 => {
+                        afflicted.push((e, &SHOCKED, "lightning", dmg_rec.shock_time))
+                    },
+                    _ => {},
+                }
+            }
+
+            // damage over time is not a hit, so it's silent and doesn't apply effects
+            for (damage_type, per_second) in stats.damage_over_time.iter() {
+                if *per_second <= 0.0 || dmg_rec.damage_immunity[damage_type].is_some() || stats.immunity[damage_type] {
+                    dmg_rec.pending_dot[damage_type] = 0.0;
+                    continue;
                 }
+                let pending = dmg_rec.pending_dot[damage_type] + dmg_rec.resisted(per_second * dt, damage_type);
+                dmg_rec.pending_dot[damage_type] = pending.fract();
+                let damage = pending.floor() as u32;
+                if let (true, Some(pos)) = (damage > 0, text_pos) {
+                    combat_text.damage(pos, damage, damage_type, false);
+                }
+                damage_sum += damage;
+            }
+            // a lot of shots could hit at once, so it's shown once a frame
+            if let (true, false, Some(pos)) = (is_blocked, is_hit, text_pos) {
+                combat_text.immune(pos);
+            }
+
+            if damage_sum > 0 {
+                let hpool = hpool.get_mut_unchecked();
+                hpool.hp = hpool.hp.saturating_sub(damage_sum);
+            }
+            if let (true, Some(transform), Some(sound)) = (is_hit, transform, sound.and_then(|s| s.hit.clone())) {
+                audio.play(sound, transform.pos.to_point());
             }
 
             for (_, time_opt) in dmg_rec.damage_immunity.iter_mut() {
                 if let Some(mut time) = time_opt.take() {
-                    time -= dt.0.as_secs_f32();
+                    time -= dt;
                     if time > 0.0 {
                         time_opt.replace(time);
                     }
@@ -340,6 +795,12 @@ impl<'a> System<'a> for DamageSystem {
             }
         }
 
+        for (e, def, source, duration) in afflicted {
+            if let Ok(entry) = effects.entry(e) {
+                entry.or_insert_with(Default::default).apply_for(def, source, duration);
+            }
+        }
+
         for (e, hpool) in (&entities, &hpools).join() {
             if hpool.hp <= 0 {
                 to_destruct.insert(e, tag::PendingDestruction).unwrap();
@@ -387,19 +848,22 @@ impl<'s> System<'s> for UiSystem<'_> {
         hub.game_over.is_opened = (&tag).join().next().is_none();
 
         let (ctx, imgui) = (&mut self.0, &mut self.1);
+        imgui.set_gamepad(&data.inputs.gamepad);
         if imgui.update(ctx, dt.0, hub.deref_mut(), &mut data) {
             data.inputs.mouse_clicked.remove(&MouseButton::Left);
             data.inputs.mouse_pressed.remove(&MouseButton::Left);
         }
 
         if hub.menu.is_show_inventory {
-            if let Some((player, _)) = (&data.entities, &data.player_tag).join().next() {
-                hub.inventory_window.show_inventories_for.insert(player);
+            for (player, _, index) in (&data.entities, &data.player_tag, &data.player_indices).join() {
+                if data.local_player.is_local(index.0) {
+                    hub.inventory_window.show_inventories_for.insert(player);
+                }
             }
             hub.menu.is_show_inventory = false;
         }
 
-        if let Some(id) = hub.debug_window.selected_entity {
+        if let Some(id) = hub.debug_window.selected_entity.filter(|_| !data.local_player.is_network()) {
             if data.inputs.mouse_clicked.contains(&MouseButton::Left) {
                 log::debug!("Spawn {:?} using debug tools", id);
                 let pos = data.camera.project(&data.inputs.mouse_pos);
@@ -1,15 +1,21 @@
+pub mod audio;
 pub mod behaviours;
 pub mod maintenance;
 pub mod rendering;
 
+pub use audio::*;
 pub use behaviours::*;
 pub use maintenance::*;
 pub use rendering::*;
 
-use crate::math::*;
+use crate::{assets::ImageAsset, math::*};
 use ggez::graphics;
 
-pub fn render_sprite(ctx: &mut ggez::Context, sprite: &graphics::Image, pos: &Vec2f, angle: &Angle2f, size: &Size2f) {
+pub fn render_sprite(ctx: &mut ggez::Context, sprite: &ImageAsset, pos: &Vec2f, angle: &Angle2f, size: &Size2f) {
+    let image = match sprite.image() {
+        Some(image) => image,
+        None => return,
+    };
     let scale = Vec2f::new(size.width / sprite.width() as f32, size.height / sprite.height() as f32);
 
     let param = graphics::DrawParam::default()
@@ -17,17 +23,21 @@ pub fn render_sprite(ctx: &mut ggez::Context, sprite: &graphics::Image, pos: &Ve
         .offset(Point2f::new(0.5, 0.5))
         .rotation(angle.radians)
         .dest(pos.to_point());
-    graphics::draw(ctx, sprite, param).unwrap();
+    graphics::draw(ctx, image, param).unwrap();
 }
 
 pub fn render_fill_sprite(
     ctx: &mut ggez::Context,
-    sprite: &graphics::Image,
+    sprite: &ImageAsset,
     pos: &Vec2f,
     angle: &Angle2f,
     tile_size: &Size2f,
     size: &Size2f,
 ) {
+    let image = match sprite.image() {
+        Some(image) => image,
+        None => return,
+    };
     let scale = Vec2f::new(tile_size.width / sprite.width() as f32, tile_size.height / sprite.height() as f32);
 
     let param = graphics::DrawParam::default()
@@ -36,7 +46,7 @@ pub fn render_fill_sprite(
         .offset(Point2f::new(0.5, 0.5))
         .rotation(angle.radians)
         .dest(pos.to_point());
-    graphics::draw(ctx, sprite, param).unwrap();
+    graphics::draw(ctx, image, param).unwrap();
 }
 
 fn render_circle(ctx: &mut ggez::Context, pos: &Point2f, radius: f32, color: u32, mode: graphics::DrawMode) {
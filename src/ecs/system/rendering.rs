@@ -3,10 +3,14 @@ use super::{
     render_fill_circle, render_fill_rect, render_fill_sprite, render_line, render_polygon, render_sprite,
     render_stroke_circle,
 };
-use crate::{assets::*, entity, math::*, shader, ui::ImGuiSystem};
-use ggez::{graphics, Context};
+use crate::{assets::*, atlas::Atlas, entity, math::*, shader, ui::ImGuiSystem};
+use ggez::{
+    graphics::{self, spritebatch::SpriteBatch, ShaderLock},
+    Context,
+};
 use itertools::Itertools;
 use specs::{Entities, Join, Read, ReadExpect, ReadStorage, System, Write};
+use std::collections::HashMap;
 
 pub struct ParticleRenderSystem<'a>(pub &'a mut Context);
 impl<'a> System<'a> for ParticleRenderSystem<'a> {
@@ -20,7 +24,11 @@ impl<'a> System<'a> for ParticleRenderSystem<'a> {
             .sorted_by_key(|(_, _, def)| def.spritesheet.id())
             .group_by(|(_, _, def)| &def.spritesheet);
         for (asset, group) in groups.into_iter() {
-            let mut batch = graphics::spritebatch::SpriteBatch::new(asset.as_ref().as_ref().clone());
+            let image = match asset.image() {
+                Some(image) => image,
+                None => continue,
+            };
+            let mut batch = SpriteBatch::new(image.clone());
             for (transform, prop, def) in group {
                 let scale = Vec2f::new(
                     (def.size.width * def.sheet_width as f32) / asset.width() as f32,
@@ -64,21 +72,77 @@ impl<'a> System<'a> for UiRenderSystem<'_> {
     }
 }
 
+// shader a sprite is drawn with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Effect {
+    None,
+    Blink,
+    Outline,
+}
+
+/// Sprites are drawn in y order, consecutive ones from the same atlas page
+/// and with the same effect go into one batch. Those which are not in
+/// the atlas (or the atlas wasn't built at all) are drawn on their own.
+/// Animated sprites are spritesheets, only the current frame is drawn.
 pub struct SpriteRenderSystem<'a>(pub &'a mut Context);
+impl SpriteRenderSystem<'_> {
+    // sprites are still drawn, just without effects, if shaders fail to load
+    fn use_effect(&mut self, assets: &mut AssetManager, effect: Effect, image: &graphics::Image) -> Option<ShaderLock> {
+        match effect {
+            Effect::None => None,
+            Effect::Blink => {
+                let s = assets.get::<ShaderAsset<shader::Silhouette>>("/shaders/silhouette.frag", self.0);
+                s.ok().map(|s| graphics::use_shader(self.0, &s))
+            },
+            Effect::Outline => {
+                let s = assets.get::<ShaderAsset<shader::Outline>>("/shaders/outline.frag", self.0);
+                s.ok().map(|s| {
+                    s.send(self.0, shader::Outline {
+                        step: [4.0 / image.width() as f32, 4.0 / image.height() as f32],
+                        ..shader::Outline::default()
+                    })
+                    .unwrap();
+                    graphics::use_shader(self.0, &s)
+                })
+            },
+        }
+    }
+
+    fn flush(&mut self, assets: &mut AssetManager, batch: Option<(usize, Effect, SpriteBatch)>, atlas: &Atlas) {
+        if let Some((page, effect, batch)) = batch {
+            let _lock = self.use_effect(assets, effect, atlas.page(page));
+            graphics::draw(self.0, &batch, graphics::DrawParam::default()).unwrap();
+        }
+    }
+}
 impl<'a> System<'a> for SpriteRenderSystem<'_> {
     type SystemData = (
         Entities<'a>,
         Write<'a, AssetManager>,
+        Read<'a, Atlas>,
         Read<'a, InteractionCache>,
         ReadStorage<'a, Transform>,
         ReadStorage<'a, Sprite>,
         ReadStorage<'a, SpriteBlink>,
         ReadStorage<'a, Directional>,
+        ReadStorage<'a, AnimatedSprite>,
+        ReadStorage<'a, SharedAnimationDef>,
     );
 
     fn run(
         &mut self,
-        (entities, mut assets, interaction, transforms, sprites, blinks, directionals): Self::SystemData,
+        (
+            entities,
+            mut assets,
+            atlas,
+            interaction,
+            transforms,
+            sprites,
+            blinks,
+            directionals,
+            animated,
+            animations,
+        ): Self::SystemData,
     ) {
         let mut stream =
             (&entities, &transforms, &sprites, (&directionals).maybe(), (&blinks).maybe()).join().collect::<Vec<_>>();
@@ -87,36 +151,78 @@ impl<'a> System<'a> for SpriteRenderSystem<'_> {
                 .partial_cmp(&(t2.1.pos.y + t2.2.size.height * 0.5))
                 .unwrap_or(std::cmp::Ordering::Less)
         });
+
+        let mut batch: Option<(usize, Effect, SpriteBatch)> = None;
         for (e, transform, sprite, directional_opt, blink_opt) in stream.into_iter() {
             let img = match &sprite.asset {
-                SpriteAsset::Single { value } => Some(value),
-                SpriteAsset::Directional { north, east, south, west } => {
-                    if let Some(Directional { direction }) = directional_opt {
-                        Some(directional!(direction => north, east, south, west))
-                    } else {
-                        None
-                    }
+                SpriteAsset::Single { value } => value,
+                SpriteAsset::Directional { north, east, south, west } => match directional_opt {
+                    Some(Directional { direction }) => directional!(direction => north, east, south, west),
+                    None => continue,
                 },
             };
-            if let Some(img) = img {
-                let _lock = if blink_opt.is_some() {
-                    let s = assets.get::<ShaderAsset<shader::Silhouette>>("/shaders/silhouette.frag", self.0).unwrap();
-                    Some(graphics::use_shader(self.0, &s))
-                } else if interaction.near_inventory == Some(e) || interaction.near_level_changer == Some(e) {
-                    let s = assets.get::<ShaderAsset<shader::Outline>>("/shaders/outline.frag", self.0).unwrap();
-                    s.send(self.0, shader::Outline {
-                        step: [4.0 / img.width() as f32, 4.0 / img.height() as f32],
-                        ..shader::Outline::default()
-                    })
-                    .unwrap();
-                    Some(graphics::use_shader(self.0, &s))
-                } else {
-                    None
-                };
+            let effect = if blink_opt.is_some() {
+                Effect::Blink
+            } else if interaction.is_near(e) {
+                Effect::Outline
+            } else {
+                Effect::None
+            };
 
-                render_sprite(self.0, &img, &transform.pos, &transform.rotation, &sprite.size);
+            // normalized part of the image to draw
+            let frame = match (animated.get(e), animations.get(e)) {
+                (Some(animation), Some(def)) => {
+                    let (width, height) = (def.sheet_width.max(1), def.sheet_height.max(1));
+                    let index = def.frame(animation);
+                    let (w, h) = (1.0 / width as f32, 1.0 / height as f32);
+                    graphics::Rect::new((index % width) as f32 * w, (index / width) as f32 * h, w, h)
+                },
+                _ => graphics::Rect::one(),
+            };
+
+            match atlas.region(img.key()) {
+                Some(region) => {
+                    let same = |(page, batch_effect, _): &(usize, Effect, SpriteBatch)| {
+                        *page == region.page && *batch_effect == effect
+                    };
+                    if !batch.as_ref().map_or(false, same) {
+                        self.flush(&mut assets, batch.take(), &atlas);
+                        batch = Some((region.page, effect, SpriteBatch::new(atlas.page(region.page).clone())));
+                    }
+                    let uv = graphics::Rect::new(
+                        region.uv.x + frame.x * region.uv.w,
+                        region.uv.y + frame.y * region.uv.h,
+                        frame.w * region.uv.w,
+                        frame.h * region.uv.h,
+                    );
+                    let (width, height) = (region.width * frame.w, region.height * frame.h);
+                    let param = graphics::DrawParam::default()
+                        .src(uv)
+                        .scale(Vec2f::new(sprite.size.width / width, sprite.size.height / height))
+                        .offset(Point2f::new(0.5, 0.5))
+                        .rotation(transform.rotation.radians)
+                        .dest(transform.pos.to_point());
+                    batch.as_mut().unwrap().2.add(param);
+                },
+                None => {
+                    let image = match img.image() {
+                        Some(image) => image,
+                        None => continue,
+                    };
+                    self.flush(&mut assets, batch.take(), &atlas);
+                    let _lock = self.use_effect(&mut assets, effect, image);
+                    let (width, height) = (img.width() as f32 * frame.w, img.height() as f32 * frame.h);
+                    let param = graphics::DrawParam::default()
+                        .src(frame)
+                        .scale(Vec2f::new(sprite.size.width / width, sprite.size.height / height))
+                        .offset(Point2f::new(0.5, 0.5))
+                        .rotation(transform.rotation.radians)
+                        .dest(transform.pos.to_point());
+                    graphics::draw(self.0, image, param).unwrap();
+                },
             }
         }
+        self.flush(&mut assets, batch.take(), &atlas);
     }
 }
 
@@ -127,12 +233,18 @@ impl MapRenderingSystem<'_> {
     const WATER_TILE: f32 = 50.0;
 }
 impl<'a> System<'a> for MapRenderingSystem<'_> {
-    type SystemData = (Read<'a, Camera>, Write<'a, AssetManager>, Read<'a, Arena>);
+    type SystemData = (Read<'a, Camera>, Write<'a, AssetManager>, Read<'a, Arena>, Read<'a, Chunks>);
 
-    fn run(&mut self, (camera, mut assets, arena): Self::SystemData) {
-        let size = graphics::window(self.0).get_inner_size().unwrap();
-        let space = assets.get::<ImageAsset>("/sprites/map/space.png", self.0).unwrap();
-        let water = assets.get::<ImageAsset>("/sprites/map/water.png", self.0).unwrap();
+    fn run(&mut self, (camera, mut assets, arena, chunks): Self::SystemData) {
+        // zooming out shows more of the map
+        let size = camera.visible_size();
+        let (space, water) = match (
+            assets.get::<ImageAsset>("/sprites/map/space.png", self.0),
+            assets.get::<ImageAsset>("/sprites/map/water.png", self.0),
+        ) {
+            (Ok(space), Ok(water)) => (space, water),
+            _ => return,
+        };
 
         let parallax_offset = Vec2f::new((camera.pos.x * -0.1) % Self::TILE, (camera.pos.y * -0.1) % Self::TILE);
         render_fill_sprite(
@@ -141,20 +253,59 @@ impl<'a> System<'a> for MapRenderingSystem<'_> {
             &(camera.pos + parallax_offset),
             &Angle2f::zero(),
             &Size2f::new(Self::TILE, Self::TILE),
-            &Size2f::new(size.width as f32 + Self::TILE2, size.height as f32 + Self::TILE2),
+            &Size2f::new(size.width + Self::TILE2, size.height + Self::TILE2),
         );
 
+        // open world has no borders, so water just follows the camera
+        let (water_pos, water_size) = if chunks.seed.is_some() {
+            let snapped = (camera.pos / Self::WATER_TILE).round() * Self::WATER_TILE;
+            (snapped, Size2f::new(size.width + Self::TILE2, size.height + Self::TILE2))
+        } else {
+            (Vec2f::zero(), arena.size)
+        };
         render_fill_sprite(
             self.0,
             &water,
-            &Vec2f::zero(),
+            &water_pos,
             &Angle2f::zero(),
             &Size2f::new(Self::WATER_TILE, Self::WATER_TILE),
-            &arena.size,
+            &water_size,
         );
     }
 }
 
+/// Drawn in screen space after the camera is reverted, so the size of
+/// numbers doesn't depend on it. Every text is queued and then drawn at once.
+/// Texts are laid out once and kept by their id, only their color fades.
+pub struct CombatTextRenderSystem<'a>(pub &'a mut Context, pub &'a mut HashMap<u32, graphics::Text>);
+impl<'a> System<'a> for CombatTextRenderSystem<'_> {
+    type SystemData = (Read<'a, Camera>, Read<'a, CombatText>);
+
+    fn run(&mut self, (camera, combat_text): Self::SystemData) {
+        if combat_text.texts.is_empty() {
+            self.1.clear();
+            return;
+        }
+        // texts which are gone are not put back
+        let mut laid_out = std::mem::take(self.1);
+        for text in &combat_text.texts {
+            let drawable = laid_out.remove(&text.id).unwrap_or_else(|| {
+                let fragment =
+                    graphics::TextFragment::from(text.text.as_str()).scale(graphics::Scale::uniform(text.size));
+                graphics::Text::new(fragment)
+            });
+            let mut color = graphics::Color::from_rgba_u32(text.color);
+            color.a *= 1.0 - (text.time / CombatText::LIFETIME).powi(2);
+            let pos = camera.to_screen(&text.pos.to_point());
+            let dest = Point2f::new(pos.x - drawable.width(self.0) as f32 * 0.5, pos.y - text.size);
+            // fragments have no color of their own, so this one is used
+            graphics::queue_text(self.0, &drawable, dest, Some(color));
+            self.1.insert(text.id, drawable);
+        }
+        graphics::draw_queued_text(self.0, graphics::DrawParam::default(), None, graphics::FilterMode::Linear).unwrap();
+    }
+}
+
 pub struct DebugTargetRenderSystem<'a>(pub &'a mut Context);
 impl<'a> System<'a> for DebugTargetRenderSystem<'_> {
     type SystemData = (
@@ -3,17 +3,33 @@ use crate::{
     assets::*,
     ecs::{
         component,
-        component::{ShapeshifterData, ShapeshifterForm},
+        component::{DefStr, ShapeshifterData, ShapeshifterForm},
         resource, tag,
     },
     math::*,
+    script::{self, Command, ScriptApi, ScriptAsset},
 };
 use nphysics2d::ncollide2d::pipeline::object::CollisionGroups;
 use specs::{Entity, LazyUpdate, World, WorldExt};
+use std::sync::Arc;
 
 // see `build/build.rs` for code generation
 include!(concat!(env!("OUT_DIR"), "/generated/entity.rs"));
 
+/// Built-in forms by their struct names, as they are named in yaml
+pub fn form(name: &str) -> Option<Arc<dyn ShapeshifterForm>> {
+    match name {
+        "CrabUnderwaterForm" => Some(Arc::new(CrabUnderwaterForm)),
+        "CrabShooterForm" => Some(Arc::new(CrabShooterForm)),
+        "CrabJrUnderwaterForm" => Some(Arc::new(CrabJrUnderwaterForm)),
+        "CrabJrShooterForm" => Some(Arc::new(CrabJrShooterForm)),
+        "CrabJrShockedForm" => Some(Arc::new(CrabJrShockedForm)),
+        "MimicSleep" => Some(Arc::new(MimicSleep)),
+        "MimicAttack" => Some(Arc::new(MimicAttack)),
+        _ => None,
+    }
+}
+
 struct CrabUnderwaterForm;
 impl ShapeshifterForm for CrabUnderwaterForm {
     fn time(&self) -> f32 { 4.0 }
@@ -23,11 +39,12 @@ impl ShapeshifterForm for CrabUnderwaterForm {
     }
 
     fn on_begin(&self, e: Entity, update: &LazyUpdate, (ctx, assets): ShapeshifterData) {
-        let new_asset = assets.get::<ImageAsset>("/sprites/entity/crab-underwater.png", ctx).unwrap();
-        update.insert(e, component::Sprite {
-            asset: component::SpriteAsset::Single { value: new_asset },
-            size: Size2f::new(40.0, 80.0),
-        });
+        if let Ok(new_asset) = assets.get::<ImageAsset>("/sprites/entity/crab-underwater.png", ctx) {
+            update.insert(e, component::Sprite {
+                asset: component::SpriteAsset::Single { value: new_asset },
+                size: Size2f::new(40.0, 80.0),
+            });
+        }
         update.insert(e, component::FollowTarget { keep_distance: 50.0, follow_distance: 500.0 });
         update.exec(move |world| {
             let physics = world.read_storage::<component::Physic>();
@@ -67,11 +84,12 @@ impl ShapeshifterForm for CrabShooterForm {
     fn time(&self) -> f32 { 6.0 }
 
     fn on_begin(&self, e: Entity, update: &LazyUpdate, (ctx, assets): ShapeshifterData) {
-        let new_asset = assets.get::<ImageAsset>("/sprites/entity/crab-shoot.png", ctx).unwrap();
-        update.insert(e, component::Sprite {
-            asset: component::SpriteAsset::Single { value: new_asset },
-            size: Size2f::new(120.0, 80.0),
-        });
+        if let Ok(new_asset) = assets.get::<ImageAsset>("/sprites/entity/crab-shoot.png", ctx) {
+            update.insert(e, component::Sprite {
+                asset: component::SpriteAsset::Single { value: new_asset },
+                size: Size2f::new(120.0, 80.0),
+            });
+        }
         update.insert(e, component::ShootTarget { radius: 400.0 });
     }
 
@@ -89,11 +107,12 @@ impl ShapeshifterForm for CrabJrUnderwaterForm {
     }
 
     fn on_begin(&self, e: Entity, update: &LazyUpdate, (ctx, assets): ShapeshifterData) {
-        let new_asset = assets.get::<ImageAsset>("/sprites/entity/crab-jr-underwater.png", ctx).unwrap();
-        update.insert(e, component::Sprite {
-            asset: component::SpriteAsset::Single { value: new_asset },
-            size: Size2f::new(100.0, 40.0),
-        });
+        if let Ok(new_asset) = assets.get::<ImageAsset>("/sprites/entity/crab-jr-underwater.png", ctx) {
+            update.insert(e, component::Sprite {
+                asset: component::SpriteAsset::Single { value: new_asset },
+                size: Size2f::new(100.0, 40.0),
+            });
+        }
         update.insert(e, component::FollowTarget { keep_distance: 100.0, follow_distance: 400.0 });
     }
 }
@@ -107,11 +126,12 @@ impl ShapeshifterForm for CrabJrShooterForm {
     }
 
     fn on_begin(&self, e: Entity, update: &LazyUpdate, (ctx, assets): ShapeshifterData) {
-        let new_asset = assets.get::<ImageAsset>("/sprites/entity/crab-jr-shoot.png", ctx).unwrap();
-        update.insert(e, component::Sprite {
-            asset: component::SpriteAsset::Single { value: new_asset },
-            size: Size2f::new(100.0, 40.0),
-        });
+        if let Ok(new_asset) = assets.get::<ImageAsset>("/sprites/entity/crab-jr-shoot.png", ctx) {
+            update.insert(e, component::Sprite {
+                asset: component::SpriteAsset::Single { value: new_asset },
+                size: Size2f::new(100.0, 40.0),
+            });
+        }
         update.remove::<component::FollowTarget>(e);
     }
 }
@@ -121,11 +141,12 @@ impl ShapeshifterForm for CrabJrShockedForm {
     fn time(&self) -> f32 { 4.0 }
 
     fn on_begin(&self, e: Entity, update: &LazyUpdate, (ctx, assets): ShapeshifterData) {
-        let new_asset = assets.get::<ImageAsset>("/sprites/entity/crab-jr-shock.png", ctx).unwrap();
-        update.insert(e, component::Sprite {
-            asset: component::SpriteAsset::Single { value: new_asset },
-            size: Size2f::new(100.0, 40.0),
-        });
+        if let Ok(new_asset) = assets.get::<ImageAsset>("/sprites/entity/crab-jr-shock.png", ctx) {
+            update.insert(e, component::Sprite {
+                asset: component::SpriteAsset::Single { value: new_asset },
+                size: Size2f::new(100.0, 40.0),
+            });
+        }
         update.insert(e, component::ShootTarget { radius: 400.0 });
     }
 
@@ -134,59 +155,6 @@ impl ShapeshifterForm for CrabJrShockedForm {
     }
 }
 
-struct WhaleWait;
-impl ShapeshifterForm for WhaleWait {
-    fn time(&self) -> f32 { 4.0 }
-
-    fn can_update(&self, e: Entity, world: &World) -> bool {
-        world.read_storage::<component::Target>().get(e).and_then(|t| t.target).is_some()
-    }
-
-    fn on_begin(&self, e: Entity, update: &LazyUpdate, (ctx, assets): ShapeshifterData) {
-        let new_asset = assets.get::<ImageAsset>("/sprites/entity/whale.png", ctx).unwrap();
-        update.insert(e, component::Sprite {
-            asset: component::SpriteAsset::Single { value: new_asset },
-            size: Size2f::new(200.0, 118.0),
-        });
-        update.insert(e, component::FollowTarget { keep_distance: 300.0, follow_distance: 600.0 });
-    }
-
-    fn on_end(&self, e: Entity, update: &LazyUpdate, (ctx, assets): ShapeshifterData) {
-        update.remove::<component::FollowTarget>(e);
-    }
-}
-
-struct WhaleAttack;
-impl ShapeshifterForm for WhaleAttack {
-    fn time(&self) -> f32 { 0.25 }
-
-    fn on_begin(&self, e: Entity, update: &LazyUpdate, (ctx, assets): ShapeshifterData) {
-        let new_asset = assets.get::<ImageAsset>("/sprites/entity/whale-splash.png", ctx).unwrap();
-        update.insert(e, component::Sprite {
-            asset: component::SpriteAsset::Single { value: new_asset },
-            size: Size2f::new(200.0, 118.0),
-        });
-        update.insert(e, component::ShootTarget { radius: 500.0 });
-    }
-
-    fn on_end(&self, e: Entity, update: &LazyUpdate, (ctx, assets): ShapeshifterData) {
-        update.remove::<component::ShootTarget>(e);
-    }
-}
-
-struct WhaleCooldown;
-impl ShapeshifterForm for WhaleCooldown {
-    fn time(&self) -> f32 { 0.25 }
-
-    fn on_begin(&self, e: Entity, update: &LazyUpdate, (ctx, assets): ShapeshifterData) {
-        let new_asset = assets.get::<ImageAsset>("/sprites/entity/whale.png", ctx).unwrap();
-        update.insert(e, component::Sprite {
-            asset: component::SpriteAsset::Single { value: new_asset },
-            size: Size2f::new(200.0, 118.0),
-        });
-    }
-}
-
 struct MimicSleep;
 impl ShapeshifterForm for MimicSleep {
     fn time(&self) -> f32 { 0.0 }
@@ -196,11 +164,12 @@ impl ShapeshifterForm for MimicSleep {
     }
 
     fn on_begin(&self, e: Entity, update: &LazyUpdate, (ctx, assets): ShapeshifterData) {
-        let new_asset = assets.get::<ImageAsset>("/sprites/entity/chest-closed.png", ctx).unwrap();
-        update.insert(e, component::Sprite {
-            asset: component::SpriteAsset::Single { value: new_asset },
-            size: Size2f::new(40.0, 37.0),
-        });
+        if let Ok(new_asset) = assets.get::<ImageAsset>("/sprites/entity/chest-closed.png", ctx) {
+            update.insert(e, component::Sprite {
+                asset: component::SpriteAsset::Single { value: new_asset },
+                size: Size2f::new(40.0, 37.0),
+            });
+        }
         // make immune so sniper rifle wouldn't just kill it right after spawn
         update.exec(move |world| {
             if let Some(dmg_reciever) = world.write_storage::<component::DamageReciever>().get_mut(e) {
@@ -221,10 +190,73 @@ impl ShapeshifterForm for MimicAttack {
     }
 
     fn on_begin(&self, e: Entity, update: &LazyUpdate, (ctx, assets): ShapeshifterData) {
-        let new_asset = assets.get::<ImageAsset>("/sprites/entity/chest-funny.png", ctx).unwrap();
-        update.insert(e, component::Sprite {
-            asset: component::SpriteAsset::Single { value: new_asset },
-            size: Size2f::new(71.0, 37.0),
-        });
+        if let Ok(new_asset) = assets.get::<ImageAsset>("/sprites/entity/chest-funny.png", ctx) {
+            update.insert(e, component::Sprite {
+                asset: component::SpriteAsset::Single { value: new_asset },
+                size: Size2f::new(71.0, 37.0),
+            });
+        }
+    }
+}
+
+/// Shapeshifter form implemented by a script (see `crate::script`). `on_begin`
+/// and `on_end` hooks only know the form `time`, `can_update` sees the whole entity.
+/// `on_animation_end` also knows the `clip` which is over.
+pub struct ScriptedForm {
+    pub script: DefStr,
+    pub time: f32,
+}
+impl ScriptedForm {
+    fn run(&self, hook: &str, clip: &str, e: Entity, update: &LazyUpdate, (ctx, assets): ShapeshifterData) {
+        let script = match assets.get::<ScriptAsset>(&self.script, &mut Headless) {
+            Ok(script) => script,
+            Err(err) => {
+                log::error!("Unable to load shapeshifter script: {}", err);
+                return;
+            },
+        };
+        let mut api = ScriptApi::new(e.id() as u64);
+        api.time = self.time;
+        api.clip = clip.to_owned();
+        script.call(hook, &mut api);
+
+        // sprites need graphics context, so they are the only thing applied right away
+        let (sprites, commands): (Vec<_>, Vec<_>) =
+            api.commands.into_iter().partition(|c| matches!(c, Command::Sprite(..)));
+        for command in sprites {
+            if let Command::Sprite(path, size) = command {
+                match assets.get::<ImageAsset>(&path, ctx) {
+                    Ok(value) => {
+                        update.insert(e, component::Sprite { asset: component::SpriteAsset::Single { value }, size })
+                    },
+                    Err(err) => log::error!("Unable to load sprite {:?} from script: {}", path, err),
+                }
+            }
+        }
+        let script_path = self.script.clone();
+        update.exec(move |world| script::apply(e, &script_path, commands, world));
+    }
+}
+impl ShapeshifterForm for ScriptedForm {
+    fn time(&self) -> f32 { self.time }
+
+    fn can_update(&self, e: Entity, world: &World) -> bool {
+        let script = match world.write_resource::<AssetManager>().get::<ScriptAsset>(&self.script, &mut Headless) {
+            Ok(script) => script,
+            Err(_) => return true,
+        };
+        let mut api = ScriptApi::for_entity(e, world);
+        api.time = self.time;
+        script.call("can_update", &mut api).and_then(|r| r.as_bool().ok()).unwrap_or(true)
+    }
+
+    fn on_begin(&self, e: Entity, update: &LazyUpdate, data: ShapeshifterData) {
+        self.run("on_begin", "", e, update, data)
+    }
+
+    fn on_end(&self, e: Entity, update: &LazyUpdate, data: ShapeshifterData) { self.run("on_end", "", e, update, data) }
+
+    fn on_animation_end(&self, e: Entity, clip: &str, update: &LazyUpdate, data: ShapeshifterData) {
+        self.run("on_animation_end", clip, e, update, data)
     }
 }
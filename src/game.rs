@@ -1,17 +1,32 @@
 use crate::{
+    arena,
     assets::*,
+    atlas::Atlas,
+    controls::{Action, Controls},
     ecs::{component::*, resource::*, system::*, tag},
-    entity, item,
+    entity,
+    hot_reload::HotReload,
+    lobby::Lobby,
     main_menu::MainMenu,
+    manifest,
     math::*,
+    net::{self, Session, Status},
     particle,
+    prefab::{self, Prefabs},
+    replay::{self, Frame, Recorder, Replay},
+    save::{self, SaveData},
     scene::{Scene, SceneCommand},
+    settings::{Overrides, Settings},
+    simulation::TIMESTEP,
     ui::ImGuiSystem,
 };
 use ggez::{
-    event::EventHandler,
+    event::{Axis, Button, EventHandler},
     graphics,
-    input::keyboard::{KeyCode, KeyMods},
+    input::{
+        gamepad::GamepadId,
+        keyboard::{KeyCode, KeyMods},
+    },
     timer, Context, GameResult,
 };
 use itertools::Itertools;
@@ -20,113 +35,423 @@ use nphysics2d::{
     ncollide2d::{pipeline::object::CollisionGroups, shape},
     object::{BodyPartHandle, BodyStatus, ColliderDesc, RigidBodyDesc},
 };
+use rand::Rng;
 use specs::prelude::*;
+use std::collections::HashMap;
 
 pub struct Game {
     world: World,
     dispatcher: Dispatcher<'static, 'static>,
     imgui: ImGuiSystem,
+    recorder: Option<Recorder>,
+    replay: Option<Replay>,
+    // connection to the other player of a network game
+    session: Option<Session>,
+    hot_reload: Option<HotReload>,
+    // manifest of the current arena
+    manifest: Option<String>,
+    // gamepad state from events, gameplay sees it once per update
+    gamepad: GamepadInputs,
+    // laid out combat text by its id
+    combat_text: HashMap<u32, graphics::Text>,
+}
+
+// how far from each other ships of local players are spawned
+const PLAYER_SPACING: f32 = 100.0;
+
+/// Create a world with all resources and components registered alongside with
+/// a dispatcher of every gameplay system. Nothing here requires ggez context.
+pub fn build_world(seed: u64) -> (World, Dispatcher<'static, 'static>) {
+    let mut world = World::new();
+    let mut dispatcher = DispatcherBuilder::new()
+        .with(ArenaSystem, "arena_system", &[])
+        .with(ChunkSystem, "chunk_system", &["arena_system"])
+        .with(ConsumablesSystem, "consumables_system", &[])
+        .with(InteractionSystem, "interaction_system", &[])
+        .with(CameraSystem, "camera_system", &[])
+        .with(ParticlesSystem, "particles_system", &[])
+        .with(AnimationSystem, "animation_system", &[])
+        .with(StatusEffectsSystem, "status_effects_system", &[])
+        .with(SpriteDamageBlinkSystem::default(), "sprite_damage_blink_system", &[])
+        .with(SearchForTargetSystem, "search_for_target_system", &[])
+        .with(FollowTargetSystem::default(), "follow_target_system", &["search_for_target_system"])
+        .with(ShootTargetSystem::default(), "shoot_target_system", &["search_for_target_system"])
+        .with(InputsSystem::default(), "inputs_system", &[])
+        .with(DirectionalSystem, "directional_system", &[])
+        .with(DirectionalCollidersSystem::default(), "directional_colliders_system", &["directional_system"])
+        .with(PhysicTransformSyncSystem::default(), "physic_transform_sync_system", &[])
+        .with(PhysicSystem, "physic_system", &[
+            "directional_colliders_system",
+            "physic_transform_sync_system",
+            "status_effects_system",
+        ])
+        .with(DistanceCounterSystem, "distance_counter_system", &["physic_system"])
+        .with(ContainerSinkSystem, "container_sink_system", &[])
+        .with(ItemMoveSystem, "item_move_system", &[])
+        .with(InventoryMaintenanceSystem, "inv_maintenance_system", &["item_move_system"])
+        .with(RandomizedWeaponsSystem::default(), "randomized_weapons_system", &[])
+        .with(ProjectileSystem, "projectile_system", &["physic_system"])
+        .with(ImpactDamageSystem, "impact_damage_system", &["physic_system"])
+        .with(ShotsDodgerSystem, "shots_dodger_system", &["projectile_system", "impact_damage_system"])
+        .with(DamageSystem, "damage_system", &["shots_dodger_system", "projectile_system", "impact_damage_system"])
+        .with(WeaponrySystem, "weaponry_system", &["inputs_system", "damage_system"])
+        .with(CombatTextSystem, "combat_text_system", &["damage_system"])
+        .with(DistanceLimitingSystem, "distance_limiting_system", &["distance_counter_system"])
+        // barrier for "on destruction" systems
+        .with_barrier()
+        .with(ExplodeOnDeathSystem, "explode_on_death_system", &[])
+        .with(LootGenerateSystem, "loot_generate_system", &[])
+        // Force destruction system to run the last
+        .with_thread_local(DestructionSystem)
+        .build();
+    world.insert(DeltaTime(std::time::Duration::new(0, 0)));
+    world.insert(Camera::default());
+    world.insert(UiHub::default());
+    world.insert(SpawnQueue::default());
+    world.insert(AudioQueue::default());
+    world.insert(ShakeQueue::default());
+    world.insert(CombatText::default());
+    world.insert(PlayerCount::default());
+    world.insert(LocalPlayer::default());
+    // the real ones are loaded along with the window
+    world.insert(Controls::default());
+    world.insert(Settings::default());
+    world.insert(Overrides::default());
+    world.insert(AnimationEvents::default());
+    world.insert(AssetManager::default());
+    // built along with the first scene which draws anything
+    world.insert(Atlas::default());
+    world.insert(Prefabs::load());
+    world.insert(SceneControls { is_debug: std::env::args().any(|a| a == "--debug"), ..SceneControls::default() });
+    world.insert(Arena::default());
+    world.insert(Chunks::default());
+    world.insert(RandomGenerator::new(seed));
+    world.insert(PhysicWorld::new(Vec2f::new(0.0, 0.0)));
+    world.register::<tag::Player>();
+    world.register::<PlayerIndex>();
+    world.register::<tag::LevelChanger>();
+    world.register::<AvoidShots>();
+    world.register::<Reflection>();
+    world.register::<Shapeshifter>();
+    world.register::<BossPhases>();
+    world.register::<DistanceCounter>();
+    world.register::<DistanceLimited>();
+    world.register::<ChunkMember>();
+    world.register::<Movement>();
+    world.register::<Transform>();
+    world.register::<Sprite>();
+    world.register::<SpriteBlink>();
+    world.register::<SoundEffects>();
+    world.register::<Target>();
+    world.register::<SearchForTarget>();
+    world.register::<FollowTarget>();
+    world.register::<ShootTarget>();
+    world.register::<Faction>();
+    world.register::<Physic>();
+    world.register::<Directional>();
+    world.register::<Inventory>();
+    world.register::<Named>();
+    world.register::<Quality>();
+    world.register::<Stackable>();
+    world.register::<RandomizedWeaponProperties>();
+    world.register::<WeaponProperties>();
+    world.register::<WeaponAttack>();
+    world.register::<Weaponry>();
+    world.register::<Hotbar>();
+    world.register::<HealthPool>();
+    world.register::<DamageDealer>();
+    world.register::<DamageReciever>();
+    world.register::<Projectile>();
+    world.register::<SharedParticleDef>();
+    world.register::<ParticleProperties>();
+    world.register::<AnimatedSprite>();
+    world.register::<SharedAnimationDef>();
+    world.register::<Consumable>();
+    world.register::<Consumer>();
+    world.register::<StatusEffects>();
+    dispatcher.setup(&mut world);
+    (world, dispatcher)
+}
+
+/// Spawn ships of every local player next to each other and point the camera at them
+pub fn spawn_players(world: &World, ctx: &mut dyn AssetContext, count: usize) {
+    let mut assets = world.write_resource::<AssetManager>();
+    let mut targets = vec![];
+    for index in 0..count {
+        let player = prefab::spawn(entity::ID::Player, world, ctx, &mut assets);
+        world.write_storage::<PlayerIndex>().insert(player, PlayerIndex(index)).unwrap();
+        if let Some(transform) = world.write_storage::<Transform>().get_mut(player) {
+            transform.pos.x += index as f32 * PLAYER_SPACING;
+        }
+        targets.push(player);
+    }
+    world.write_resource::<Camera>().targets = targets;
+    *world.write_resource::<PlayerCount>() = PlayerCount(count);
+}
+
+/// Head for the open world instead of arenas. Its seed is drawn from the run's
+/// one, so replays of the run end up in the same world.
+pub fn start_exploring(world: &World) {
+    let seed = world.write_resource::<RandomGenerator>().gen();
+    world.write_resource::<Arena>().change_to = Some(arena::Kind::Open { seed });
+}
+
+/// Systems can spawn new stuff using SpawnQueue resource,
+/// this will actually spawn it
+// TODO: make this LazyUpdate system
+pub fn process_spawn_queue(world: &World, ctx: &mut dyn AssetContext) {
+    for item in world.write_resource::<SpawnQueue>().0.drain(..) {
+        let mut assets = world.write_resource::<AssetManager>();
+        match item {
+            SpawnItem::Entity(id, pos, items) => {
+                let e = prefab::spawn(id, world, ctx, &mut assets);
+                if let Some(transform) = world.write_storage::<Transform>().get_mut(e) {
+                    transform.pos = pos.to_vector();
+                }
+                // can't create entities while any storage is borrowed
+                if world.read_storage::<Inventory>().contains(e) {
+                    let items = items.into_iter().map(|id| prefab::spawn(id, world, ctx, &mut assets)).collect_vec();
+                    if let Some(inventory) = world.write_storage::<Inventory>().get_mut(e) {
+                        for item in items {
+                            inventory.content.add(world, item);
+                        }
+                    }
+                }
+            },
+            SpawnItem::Prefab(id, pos) => {
+                let spawned = world.read_resource::<Prefabs>().spawn(&id, world, ctx, &mut assets);
+                match spawned {
+                    Ok(e) => {
+                        if let Some(transform) = world.write_storage::<Transform>().get_mut(e) {
+                            transform.pos = pos.to_vector();
+                        }
+                    },
+                    Err(err) => log::error!("{:#}", err),
+                }
+            },
+            SpawnItem::ChunkEntity(def, chunk) => {
+                let e = prefab::spawn(def.id, world, ctx, &mut assets);
+                if let Some(transform) = world.write_storage::<Transform>().get_mut(e) {
+                    transform.pos = def.pos.to_vector();
+                }
+                if let (Some(hp), Some(hpool)) = (def.hp, world.write_storage::<HealthPool>().get_mut(e)) {
+                    hpool.hp = hp;
+                }
+                world.write_storage::<ChunkMember>().insert(e, ChunkMember { chunk }).unwrap();
+            },
+            SpawnItem::Particle(id, pos) => {
+                if id == particle::ID::Explosion {
+                    world.write_resource::<ShakeQueue>().shake(pos, 0.6);
+                }
+                let e = prefab::spawn(id, world, ctx, &mut assets);
+                if let Some(transform) = world.write_storage::<Transform>().get_mut(e) {
+                    transform.pos = pos.to_vector();
+                }
+            },
+            SpawnItem::Item(id, count, to_e) => {
+                let e = prefab::spawn(id, world, ctx, &mut assets);
+                if let Some(stack) = world.write_storage::<Stackable>().get_mut(e) {
+                    stack.current = count;
+                }
+                if let Some(inventory) = world.write_storage::<Inventory>().get_mut(to_e) {
+                    inventory.content.add(world, e);
+                }
+            },
+            SpawnItem::Projectile(def) => {
+                let mut phys_world = world.write_resource::<PhysicWorld>();
+                let body = phys_world.bodies.insert(
+                    RigidBodyDesc::new()
+                        .status(BodyStatus::Kinematic)
+                        .position(Isometry::translation(def.pos.x, def.pos.y))
+                        .velocity(Velocity::linear(def.velocity.x, def.velocity.y))
+                        .build(),
+                );
+                let shape =
+                    shape::ShapeHandle::new(shape::Cuboid::new([def.size.width * 0.5, def.size.height * 0.5].into()));
+                let collider = phys_world.colliders.insert(
+                    ColliderDesc::new(shape.clone())
+                        .sensor(true)
+                        .collision_groups(
+                            CollisionGroups::new()
+                                .with_membership(&[CollisionGroup::Projectiles as usize])
+                                .with_blacklist(
+                                    &def.ignore_groups.iter().cloned().map(|g| g as usize).collect::<Vec<usize>>(),
+                                ),
+                        )
+                        .build(BodyPartHandle(body, 0)),
+                );
+                let mut builder = world.create_entity_unchecked();
+                if let Some(Ok(value)) = def.asset.as_ref().map(|asset| assets.get::<ImageAsset>(&asset, ctx)) {
+                    builder = builder.with(Sprite { asset: SpriteAsset::Single { value }, size: def.size })
+                }
+                let entity = builder
+                    .with(Transform {
+                        pos: def.pos.to_vector(),
+                        rotation: if def.rotate_projectile {
+                            def.velocity.angle_from_x_axis()
+                        } else {
+                            Angle2f::zero()
+                        },
+                        ..Transform::default()
+                    })
+                    .with(DistanceLimited { limit: def.distance })
+                    .with(DistanceCounter::default())
+                    .with(DamageDealer { damage: def.damage.0, damage_type: def.damage.1 })
+                    .with(Physic {
+                        body: body,
+                        colliders: PhysicColliders {
+                            real: (collider, CollideShapeHandle::Single { value: shape.clone() }),
+                            hitbox: None,
+                        },
+                    })
+                    .with(Projectile { def: def })
+                    .build();
+                phys_world.bodies.rigid_body_mut(body).unwrap().set_user_data(Some(Box::new(entity)));
+                phys_world.colliders.get_mut(collider).unwrap().set_user_data(Some(Box::new(entity)));
+            },
+        }
+    }
 }
 
 impl Game {
-    fn prespawn(&mut self, ctx: &mut Context) {
-        let player = entity::spawn_player(&self.world, ctx, &mut self.world.write_resource::<AssetManager>());
-        self.world.write_resource::<Camera>().target = Some(player);
+    fn prespawn(&mut self, ctx: &mut Context, players: usize) {
+        *self.world.write_resource::<Atlas>() = Atlas::load(ctx);
+        {
+            let mut assets = self.world.write_resource::<AssetManager>();
+            manifest::preload("game", Some(&self.world.read_resource::<Prefabs>()), ctx, &mut assets);
+        }
+        spawn_players(&self.world, ctx, players);
     }
 
-    pub fn new(ctx: &mut Context) -> Self {
+    fn with_seed(ctx: &mut Context, seed: u64, players: usize) -> Self {
         let imgui = ImGuiSystem::new(ctx);
-        let mut world = World::new();
-        let mut dispatcher = DispatcherBuilder::new()
-            .with(ArenaSystem, "arena_system", &[])
-            .with(ConsumablesSystem, "consumables_system", &[])
-            .with(InteractionSystem, "interaction_system", &[])
-            .with(CameraSystem, "camera_system", &[])
-            .with(ParticlesSystem, "particles_system", &[])
-            .with(SpriteDamageBlinkSystem::default(), "sprite_damage_blink_system", &[])
-            .with(SearchForTargetSystem, "search_for_target_system", &[])
-            .with(FollowTargetSystem::default(), "follow_target_system", &["search_for_target_system"])
-            .with(ShootTargetSystem::default(), "shoot_target_system", &["search_for_target_system"])
-            .with(InputsSystem, "inputs_system", &[])
-            .with(DirectionalSystem, "directional_system", &[])
-            .with(DirectionalCollidersSystem::default(), "directional_colliders_system", &["directional_system"])
-            .with(PhysicTransformSyncSystem::default(), "physic_transform_sync_system", &[])
-            .with(PhysicSystem, "physic_system", &["directional_colliders_system", "physic_transform_sync_system"])
-            .with(DistanceCounterSystem, "distance_counter_system", &["physic_system"])
-            .with(ContainerSinkSystem, "container_sink_system", &[])
-            .with(InventoryMaintenanceSystem, "inv_maintenance_system", &[])
-            .with(RandomizedWeaponsSystem::default(), "randomized_weapons_system", &[])
-            .with(ProjectileSystem, "projectile_system", &["physic_system"])
-            .with(ImpactDamageSystem, "impact_damage_system", &["physic_system"])
-            .with(ShotsDodgerSystem, "shots_dodger_system", &["projectile_system", "impact_damage_system"])
-            .with(DamageSystem, "damage_system", &["shots_dodger_system", "projectile_system", "impact_damage_system"])
-            .with(WeaponrySystem, "weaponry_system", &["inputs_system", "damage_system"])
-            .with(DistanceLimitingSystem, "distance_limiting_system", &["distance_counter_system"])
-            // barrier for "on destruction" systems
-            .with_barrier()
-            .with(ExplodeOnDeathSystem, "explode_on_death_system", &[])
-            .with(LootGenerateSystem, "loot_generate_system", &[])
-            // Force destruction system to run the last
-            .with_thread_local(DestructionSystem)
-            .build();
-        world.insert(DeltaTime(std::time::Duration::new(0, 0)));
-        world.insert(Camera::default());
-        world.insert(UiHub::default());
-        world.insert(SpawnQueue::default());
-        world.insert(AssetManager::default());
-        world.insert(SceneControls { is_debug: std::env::args().any(|a| a == "--debug"), ..SceneControls::default() });
-        world.insert(Arena::default());
-        world.insert(PhysicWorld::new(Vec2f::new(0.0, 0.0)));
-        world.register::<tag::Player>();
-        world.register::<tag::LevelChanger>();
-        world.register::<AvoidShots>();
-        world.register::<Reflection>();
-        world.register::<Shapeshifter>();
-        world.register::<DistanceCounter>();
-        world.register::<DistanceLimited>();
-        world.register::<Movement>();
-        world.register::<Transform>();
-        world.register::<Sprite>();
-        world.register::<SpriteBlink>();
-        world.register::<Target>();
-        world.register::<SearchForTarget>();
-        world.register::<FollowTarget>();
-        world.register::<ShootTarget>();
-        world.register::<Faction>();
-        world.register::<Physic>();
-        world.register::<Directional>();
-        world.register::<Inventory>();
-        world.register::<Named>();
-        world.register::<Quality>();
-        world.register::<Stackable>();
-        world.register::<RandomizedWeaponProperties>();
-        world.register::<WeaponProperties>();
-        world.register::<WeaponAttack>();
-        world.register::<Weaponry>();
-        world.register::<Hotbar>();
-        world.register::<HealthPool>();
-        world.register::<DamageDealer>();
-        world.register::<DamageReciever>();
-        world.register::<Projectile>();
-        world.register::<SharedParticleDef>();
-        world.register::<ParticleProperties>();
-        world.register::<Consumable>();
-        world.register::<Consumer>();
-        dispatcher.setup(&mut world);
-
-        let mut game = Self { world, dispatcher, imgui };
-        game.prespawn(ctx);
+        let (mut world, dispatcher) = build_world(seed);
+        world.insert(Controls::load());
+        let (settings, overrides) = (Settings::load(), Overrides::from_args());
+        {
+            let current = settings.with(&overrides);
+            let mut scene_controls = world.write_resource::<SceneControls>();
+            scene_controls.is_debug |= current.debug;
+            scene_controls.is_debug_info = current.debug_info;
+            scene_controls.is_debug_targeting = current.debug_targeting;
+            scene_controls.is_debug_physic = current.debug_physic;
+        }
+        world.insert(settings);
+        world.insert(overrides);
+        let hot_reload = if world.read_resource::<SceneControls>().is_debug { Some(HotReload::new()) } else { None };
+        let mut game = Self {
+            world,
+            dispatcher,
+            imgui,
+            recorder: None,
+            replay: None,
+            session: None,
+            hot_reload,
+            manifest: None,
+            gamepad: GamepadInputs::default(),
+            combat_text: HashMap::new(),
+        };
+        game.prespawn(ctx, players);
+        game
+    }
+
+    /// Create a new game for `players` local players, each with their own ship
+    pub fn new(ctx: &mut Context, players: usize) -> Self { Self::start(ctx, players, false) }
+
+    /// Create a new game in the open world instead of arenas
+    pub fn explore(ctx: &mut Context, players: usize) -> Self { Self::start(ctx, players, true) }
+
+    fn start(ctx: &mut Context, players: usize, explore: bool) -> Self {
+        let seed = crate::seed_from_args();
+        let mut game = Self::with_seed(ctx, seed, players);
+        if explore {
+            start_exploring(&game.world);
+        }
+        game.record(seed, players, explore);
+        game
+    }
+
+    fn record(&mut self, seed: u64, players: usize, explore: bool) {
+        match Recorder::create(replay::RECORD_PATH, seed, players, explore) {
+            Ok(recorder) => self.recorder = Some(recorder),
+            Err(err) => log::error!("Unable to record this run: {}", err),
+        }
+    }
+
+    /// Create a new game shared with the peer of the session, where this machine
+    /// has only its own player. Network runs are recorded along with both players.
+    pub fn network(ctx: &mut Context, session: Session) -> Self {
+        let mut game = Self::with_seed(ctx, session.seed(), net::PLAYERS);
+        game.record(session.seed(), net::PLAYERS, false);
+        *game.world.write_resource::<LocalPlayer>() = LocalPlayer(Some(session.local_player()));
+        game.session = Some(session);
+        game
+    }
+
+    /// Create a new game and feed inputs from the replay instead of real ones
+    pub fn replay(ctx: &mut Context, replay: Replay) -> Self {
+        let mut game = Self::with_seed(ctx, replay.seed, replay.players);
+        if replay.explore {
+            start_exploring(&game.world);
+        }
+        game.replay = Some(replay);
+        game
+    }
+
+    /// Create a new game and restore previously saved run into it.
+    /// Loaded runs are not recorded, as replays always start from scratch.
+    pub fn load(ctx: &mut Context, data: SaveData) -> Self {
+        let game = Self::with_seed(ctx, data.seed, data.player_count);
+        data.restore(&game.world, ctx);
         game
     }
 }
 
+// Lockstep with the peer: the local player is sent ahead and, once it's time for
+// the next tick, inputs of both players for it are returned (if the peer's are there)
+fn exchange_inputs(world: &World, session: &mut Session, delta: std::time::Duration) -> Option<Frame> {
+    session.receive();
+    {
+        let mut ui = world.write_resource::<UiHub>();
+        let mut inputs = world.write_resource::<Inputs>();
+        // pause is for this machine only
+        if inputs.players[0].clicked_actions.remove(&Action::Pause) {
+            ui.pause.is_opened = true;
+        }
+        if ui.pause.is_opened {
+            // the ship keeps aiming where it was
+            inputs.players[0] = PlayerInputs { mouse_aim: inputs.players[0].mouse_aim, ..PlayerInputs::default() };
+        }
+        inputs.players.swap(0, session.local_player());
+        session.sample(Frame::capture(&DeltaTime(TIMESTEP), &inputs));
+    }
+    session.send();
+    let frame = session.next_frame(delta);
+
+    let mut ui = world.write_resource::<UiHub>();
+    ui.network.is_waiting = session.is_waiting();
+    ui.network.desync = session.desync();
+    if let Status::Closed(reason) = session.status() {
+        ui.network.closed = Some(reason.clone());
+    }
+    frame
+}
+
 impl Scene for Game {
-    fn next_command(&self) -> Option<SceneCommand> {
+    fn next_command(&mut self) -> Option<SceneCommand> {
         let scene_controls = self.world.read_resource::<SceneControls>();
         if scene_controls.queue_exit {
             Some(SceneCommand::ReplaceAll(|ctx| Box::new(MainMenu::new(ctx))))
         } else if scene_controls.queue_restart {
-            Some(SceneCommand::ReplaceAll(|ctx| Box::new(Self::new(ctx))))
+            // the peer can't be restarted from here, so it's back to hosting or joining
+            if self.session.is_some() {
+                return Some(SceneCommand::ReplaceAll(|ctx| Box::new(Lobby::new(ctx))));
+            }
+            // with the same players, and no way to capture their count
+            match self.world.read_resource::<PlayerCount>().0 {
+                1 => Some(SceneCommand::ReplaceAll(|ctx| Box::new(Self::new(ctx, 1)))),
+                _ => Some(SceneCommand::ReplaceAll(|ctx| Box::new(Self::new(ctx, 2)))),
+            }
         } else {
             None
         }
@@ -143,131 +468,122 @@ impl EventHandler for Game {
             delta.0 = timer::delta(ctx);
         };
 
-        {
+        let is_mouse_moved = {
             // update inputs
             use ggez::input::{keyboard, mouse};
             let mut inputs = self.world.write_resource::<Inputs>();
             inputs.clicked_keys = inputs.pressed_keys.difference(&keyboard::pressed_keys(ctx)).copied().collect();
             inputs.pressed_keys = keyboard::pressed_keys(ctx).to_owned();
-            inputs.mouse_pos = Point2f::from(mouse::position(ctx));
+            let mouse_pos = Point2f::from(mouse::position(ctx));
+            let is_mouse_moved = mouse_pos != inputs.mouse_pos;
+            inputs.mouse_pos = mouse_pos;
             let new_press: std::collections::HashSet<mouse::MouseButton> =
-                [mouse::MouseButton::Left, mouse::MouseButton::Right]
+                [mouse::MouseButton::Left, mouse::MouseButton::Right, mouse::MouseButton::Middle]
                     .iter()
                     .cloned()
                     .filter(|btn| mouse::button_pressed(ctx, *btn))
                     .collect();
             inputs.mouse_clicked = inputs.mouse_pressed.difference(&new_press).copied().collect();
             inputs.mouse_pressed = new_press;
-        }
+            inputs.gamepad = inputs.gamepad.next_frame(&self.gamepad);
+            is_mouse_moved
+        };
 
-        // Systems can spawn new stuff using SpawnQueue resource
-        // TODO: make this LazyUpdate system
-        for item in self.world.write_resource::<SpawnQueue>().0.drain(..) {
+        // assets of a new arena should be there before anything from it is spawned
+        let arena_manifest = self.world.read_resource::<Arena>().current.map(manifest::arena);
+        let arena_changed = arena_manifest != self.manifest;
+        if arena_changed {
             let mut assets = self.world.write_resource::<AssetManager>();
-            match item {
-                SpawnItem::Entity(id, pos, items) => {
-                    let e = entity::spawn(id, &self.world, ctx, &mut assets);
-                    if let Some(transform) = self.world.write_storage::<Transform>().get_mut(e) {
-                        transform.pos = pos.to_vector();
-                    }
-                    // can't create entities while any storage is borrowed
-                    if self.world.read_storage::<Inventory>().contains(e) {
-                        let items =
-                            items.into_iter().map(|id| item::spawn(id, &self.world, ctx, &mut assets)).collect_vec();
-                        if let Some(inventory) = self.world.write_storage::<Inventory>().get_mut(e) {
-                            for item in items {
-                                inventory.content.add(&self.world, item);
-                            }
-                        }
-                    }
-                },
-                SpawnItem::Particle(id, pos) => {
-                    let e = particle::spawn(id, &self.world, ctx, &mut assets);
-                    if let Some(transform) = self.world.write_storage::<Transform>().get_mut(e) {
-                        transform.pos = pos.to_vector();
-                    }
-                },
-                SpawnItem::Item(id, count, to_e) => {
-                    let e = item::spawn(id, &self.world, ctx, &mut assets);
-                    if let Some(stack) = self.world.write_storage::<Stackable>().get_mut(e) {
-                        stack.current = count;
-                    }
-                    if let Some(inventory) = self.world.write_storage::<Inventory>().get_mut(to_e) {
-                        inventory.content.add(&self.world, e);
-                    }
-                },
-                SpawnItem::Projectile(def) => {
-                    let mut phys_world = self.world.write_resource::<PhysicWorld>();
-                    let body = phys_world.bodies.insert(
-                        RigidBodyDesc::new()
-                            .status(BodyStatus::Kinematic)
-                            .position(Isometry::translation(def.pos.x, def.pos.y))
-                            .velocity(Velocity::linear(def.velocity.x, def.velocity.y))
-                            .build(),
-                    );
-                    let shape = shape::ShapeHandle::new(shape::Cuboid::new(
-                        [def.size.width * 0.5, def.size.height * 0.5].into(),
-                    ));
-                    let collider = phys_world.colliders.insert(
-                        ColliderDesc::new(shape.clone())
-                            .sensor(true)
-                            .collision_groups(
-                                CollisionGroups::new()
-                                    .with_membership(&[CollisionGroup::Projectiles as usize])
-                                    .with_blacklist(
-                                        &def.ignore_groups.iter().cloned().map(|g| g as usize).collect::<Vec<usize>>(),
-                                    ),
-                            )
-                            .build(BodyPartHandle(body, 0)),
-                    );
-                    let mut builder = self.world.create_entity_unchecked();
-                    if let Some(asset) = &def.asset {
-                        builder = builder.with(Sprite {
-                            asset: SpriteAsset::Single { value: assets.get::<ImageAsset>(&asset, ctx).unwrap() },
-                            size: def.size,
-                        })
-                    }
-                    let entity = builder
-                        .with(Transform {
-                            pos: def.pos.to_vector(),
-                            rotation: if def.rotate_projectile {
-                                def.velocity.angle_from_x_axis()
-                            } else {
-                                Angle2f::zero()
-                            },
-                            ..Transform::default()
-                        })
-                        .with(DistanceLimited { limit: def.distance })
-                        .with(DistanceCounter::default())
-                        .with(DamageDealer { damage: def.damage.0, damage_type: def.damage.1 })
-                        .with(Physic {
-                            body: body,
-                            colliders: PhysicColliders {
-                                real: (collider, CollideShapeHandle::Single { value: shape.clone() }),
-                                hitbox: None,
-                            },
-                        })
-                        .with(Projectile { def: def })
-                        .build();
-                    phys_world.bodies.rigid_body_mut(body).unwrap().set_user_data(Some(Box::new(entity)));
-                    phys_world.colliders.get_mut(collider).unwrap().set_user_data(Some(Box::new(entity)));
-                },
+            if let Some(old) = self.manifest.take() {
+                assets.release(&old);
             }
+            if let Some(new) = &arena_manifest {
+                manifest::preload(new, Some(&self.world.read_resource::<Prefabs>()), ctx, &mut assets);
+            }
+            self.manifest = arena_manifest;
         }
 
+        process_spawn_queue(&self.world, ctx);
+        if let Some(hot_reload) = &mut self.hot_reload {
+            hot_reload.update(&self.world, ctx);
+        }
         self.world.maintain();
+        if arena_changed {
+            // entities of the old arena are gone by now, and so can be their assets
+            let evicted = self.world.write_resource::<AssetManager>().evict_unused();
+            log::debug!("Evicted {} unused assets", evicted);
+        }
 
         // run ui system before any other system so it can
         // consume input events
+        let ui_scale = self.world.read_resource::<Settings>().with(&self.world.read_resource::<Overrides>()).ui_scale;
+        self.imgui.set_scale(ui_scale);
         UiSystem(ctx, &mut self.imgui).run_now(&self.world);
-        if self.world.read_resource::<UiHub>().pause.is_opened {
+        if std::mem::replace(&mut self.world.write_resource::<SceneControls>().queue_save, false) {
+            if let Err(err) = save::write(&self.world) {
+                log::error!("Unable to save current run: {}", err);
+            }
+        }
+        // network games don't stop for the pause of one of the players
+        if self.world.read_resource::<UiHub>().pause.is_opened && self.session.is_none() {
+            // the wheel is only for ui while paused
+            self.world.write_resource::<Inputs>().mouse_scroll = 0.0;
             return Ok(());
         }
+        {
+            // ui already took the clicks it wanted
+            let is_navigating = !self.world.read_resource::<UiHub>().inventory_window.show_inventories_for.is_empty();
+            // every device of this machine is for its own player in a network game
+            let players = if self.session.is_some() { 1 } else { self.world.read_resource::<PlayerCount>().0 };
+            let mut inputs = self.world.write_resource::<Inputs>();
+            self.world.read_resource::<Controls>().resolve(&mut inputs, players, is_navigating);
+            // the mouse goes with the first player of this machine
+            inputs.players[0].mouse_aim = Some(self.world.read_resource::<Camera>().project(&inputs.mouse_pos));
+            inputs.players[0].mouse_moved = is_mouse_moved;
+        }
+
+        // gameplay sees either inputs of both peers, recorded inputs or the real ones (which are recorded)
+        if let Some(session) = &mut self.session {
+            match exchange_inputs(&self.world, session, timer::delta(ctx)) {
+                Some(frame) => frame.apply(
+                    &mut self.world.write_resource::<DeltaTime>(),
+                    &mut self.world.write_resource::<Inputs>(),
+                ),
+                None => {
+                    self.world.write_resource::<Inputs>().mouse_scroll = 0.0;
+                    return Ok(());
+                },
+            }
+        } else if let Some(replay) = &mut self.replay {
+            match replay.next_frame() {
+                Some(frame) => frame.apply(
+                    &mut self.world.write_resource::<DeltaTime>(),
+                    &mut self.world.write_resource::<Inputs>(),
+                ),
+                None => {
+                    log::info!("Replay is over");
+                    self.replay = None;
+                    *self.world.write_resource::<Inputs>() = Inputs::default();
+                },
+            }
+        }
+        if let Some(recorder) = &mut self.recorder {
+            let frame = Frame::capture(&self.world.read_resource::<DeltaTime>(), &self.world.read_resource::<Inputs>());
+            if let Err(err) = recorder.record(&frame) {
+                log::error!("Unable to record frame, recording stopped: {}", err);
+                self.recorder = None;
+            }
+        }
 
         self.dispatcher.dispatch(&self.world);
-        // shapeshifter is a special kind of system, as it requires
-        // ggez context
+        // boss phases and shapeshifter are special kinds of systems,
+        // as they require ggez context
+        BossPhaseSystem(ctx).run_now(&self.world);
         ShapeshifterSystem(ctx).run_now(&self.world);
+        AudioSystem(ctx).run_now(&self.world);
+        if let Some(session) = &mut self.session {
+            session.simulated(&self.world);
+        }
 
         // reset inputs
         self.world.write_resource::<Inputs>().mouse_scroll = 0.0;
@@ -292,6 +608,7 @@ impl EventHandler for Game {
             DebugPhysicRenderSystem(ctx).run_now(&self.world);
         }
         self.world.write_resource::<Camera>().revert(ctx);
+        CombatTextRenderSystem(ctx, &mut self.combat_text).run_now(&self.world);
         UiRenderSystem(ctx, &mut self.imgui).run_now(&self.world);
         graphics::present(ctx)
     }
@@ -302,6 +619,18 @@ impl EventHandler for Game {
 
     fn key_down_event(&mut self, _: &mut Context, _: KeyCode, _: KeyMods, _: bool) {}
 
+    fn gamepad_button_down_event(&mut self, _: &mut Context, button: Button, _: GamepadId) {
+        self.gamepad.button(button, true);
+    }
+
+    fn gamepad_button_up_event(&mut self, _: &mut Context, button: Button, _: GamepadId) {
+        self.gamepad.button(button, false);
+    }
+
+    fn gamepad_axis_event(&mut self, _: &mut Context, axis: Axis, value: f32, _: GamepadId) {
+        self.gamepad.axis(axis, value);
+    }
+
     fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) {
         graphics::set_screen_coordinates(ctx, graphics::Rect::new(0.0, 0.0, width, height)).unwrap();
     }
@@ -0,0 +1,155 @@
+use crate::{
+    assets::{AssetContext, AssetManager, ImageAsset},
+    atlas::Atlas,
+    ecs::{
+        component::{DirOrSingle, Reflection, Sprite},
+        resource::DeltaTime,
+    },
+    manifest::MANIFESTS_DIR,
+    prefab::{Group, Prefab, Prefabs, GROUPS, MODS_PATH, RESOURCES_PATH},
+};
+use anyhow::Result;
+use specs::{Entity, Join, World, WorldExt};
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+// seconds between checks of modification times
+const POLL_INTERVAL: f32 = 1.0;
+
+/// Watches `resources` and `mods` for changes (only in debug mode). Changed
+/// entity, item and particle definitions are loaded into `Prefabs` and applied
+/// to live entities with the same `Reflection` id, changed assets are loaded anew.
+/// Arenas and spawn groups are compiled into the game, so they still need a rebuild.
+#[derive(Default)]
+pub struct HotReload {
+    modified: HashMap<PathBuf, SystemTime>,
+    timer: f32,
+}
+
+impl HotReload {
+    pub fn new() -> Self {
+        log::info!("Watching {:?} and {:?} for changes", RESOURCES_PATH, MODS_PATH);
+        Self { modified: scan(), timer: POLL_INTERVAL }
+    }
+
+    pub fn update(&mut self, world: &World, ctx: &mut dyn AssetContext) {
+        self.timer -= world.read_resource::<DeltaTime>().0.as_secs_f32();
+        if self.timer > 0.0 {
+            return;
+        }
+        self.timer = POLL_INTERVAL;
+
+        let modified = scan();
+        let mut changed: Vec<&PathBuf> =
+            modified.iter().filter(|(path, time)| self.modified.get(*path) != Some(time)).map(|(p, _)| p).collect();
+        changed.sort();
+        for path in changed {
+            if let Err(err) = reload(path, world, ctx) {
+                log::error!("Unable to reload {:?}: {:#}", path, err);
+            }
+        }
+        self.modified = modified;
+    }
+}
+
+fn scan() -> HashMap<PathBuf, SystemTime> {
+    let mut files = HashMap::new();
+    let mut dirs = vec![PathBuf::from(RESOURCES_PATH), PathBuf::from(MODS_PATH)];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir).into_iter().flatten().filter_map(|r| r.ok()) {
+            match entry.metadata() {
+                Ok(meta) if meta.is_dir() => dirs.push(entry.path()),
+                Ok(meta) => {
+                    if let Ok(time) = meta.modified() {
+                        files.insert(entry.path(), time);
+                    }
+                },
+                Err(_) => {},
+            }
+        }
+    }
+    files
+}
+
+fn reload(path: &Path, world: &World, ctx: &mut dyn AssetContext) -> Result<()> {
+    let is_yaml = path.extension().map_or(false, |ext| ext == "yaml");
+    let dir = path.parent().and_then(Path::file_name);
+    match (is_yaml, GROUPS.iter().find(|group| dir == Some(OsStr::new(group.dir())))) {
+        (true, Some(group)) => reload_prefab(*group, path, world, ctx),
+        (true, None) if dir == Some(OsStr::new(MANIFESTS_DIR)) => {
+            log::info!("{:?} changed, it's used the next time it's preloaded", path);
+            Ok(())
+        },
+        (true, None) => {
+            log::warn!("{:?} is compiled into the game, rebuild it to see the changes", path);
+            Ok(())
+        },
+        (false, _) => reload_asset(path, world, ctx),
+    }
+}
+
+fn reload_prefab(group: Group, path: &Path, world: &World, ctx: &mut dyn AssetContext) -> Result<()> {
+    let prefab = Prefab::load(group, path)?;
+    let id = prefab.id.clone();
+    if let Some(current) = world.read_resource::<Prefabs>().get(&id) {
+        if current.path != path {
+            log::warn!("{:?} is overridden by {:?}, skipping", path, current.path);
+            return Ok(());
+        }
+    }
+    world.write_resource::<Prefabs>().insert(prefab);
+
+    let live: Vec<Entity> = (&world.entities(), &world.read_storage::<Reflection>())
+        .join()
+        .filter(|(_, reflection)| reflection.id == id)
+        .map(|(e, _)| e)
+        .collect();
+    let prefabs = world.read_resource::<Prefabs>();
+    let prefab = prefabs.get(&id).unwrap();
+    let mut assets = world.write_resource::<AssetManager>();
+    for e in &live {
+        prefab.reapply(*e, &prefabs, world, ctx, &mut assets)?;
+    }
+    log::info!("Reloaded {:?}, {} live entities updated", id, live.len());
+    Ok(())
+}
+
+fn reload_asset(path: &Path, world: &World, ctx: &mut dyn AssetContext) -> Result<()> {
+    // assets are only loaded from `resources`, keys are absolute paths inside of it
+    let key = match path.strip_prefix(RESOURCES_PATH) {
+        Ok(relative) => format!("/{}", relative.iter().map(|c| c.to_string_lossy()).collect::<Vec<_>>().join("/")),
+        Err(_) => return Ok(()),
+    };
+    // the atlas has an old copy, so this one is drawn on its own from now on
+    world.write_resource::<Atlas>().remove(&key);
+    let mut assets = world.write_resource::<AssetManager>();
+    let old_image = assets.cached::<ImageAsset>(&key);
+    if !assets.invalidate(&key) {
+        // nobody uses it yet
+        return Ok(());
+    }
+    log::info!("Reloaded asset {:?}", key);
+
+    // images are held by sprites, so those should be updated right away
+    if let Some(old_image) = old_image {
+        let new_image = assets.get::<ImageAsset>(&key, ctx)?;
+        for sprite in (&mut world.write_storage::<Sprite>()).join() {
+            let images = match &mut sprite.asset {
+                DirOrSingle::Single { value } => vec![value],
+                DirOrSingle::Directional { north, east, south, west } => vec![north, east, south, west],
+            };
+            for image in images.into_iter().filter(|image| Arc::ptr_eq(image, &old_image)) {
+                *image = new_image.clone();
+            }
+        }
+        // particle definitions hold their spritesheets
+        world.read_resource::<Prefabs>().reset_shared();
+    }
+    Ok(())
+}
@@ -1,7 +1,11 @@
 #![allow(warnings)]
 use crate::{
     assets::*,
-    ecs::{component, resource, tag},
+    ecs::{
+        component::{self, DamageType, DefStr, Modifier, Stacking, Stat, StatusEffectDef},
+        resource, tag,
+    },
+    script::{self, ScriptApi, ScriptAsset},
 };
 use specs::{Entity, LazyUpdate, WorldExt};
 use std::sync::Arc;
@@ -15,101 +19,134 @@ pub trait ConsumeBehaviour: Sync + Send {
     fn update(&self, dt: f32, time: f32, e: Entity, update: &LazyUpdate) -> bool;
 }
 
-struct Orange;
-impl ConsumeBehaviour for Orange {
-    fn description(&self) -> &str { "Heals 20 HP points every seconds for 3 seconds." }
-
-    fn icon(&self, ctx: &mut ggez::Context, assets: &mut AssetManager) -> Option<Arc<ImageAsset>> {
-        assets.get::<ImageAsset>("/sprites/ui/healing.png", ctx).ok()
+/// Built-in behaviours by their struct names, as they are named in yaml
+pub fn behaviour(name: &str) -> Option<Arc<dyn ConsumeBehaviour>> {
+    match name {
+        "Pitaya" => Some(Arc::new(Pitaya)),
+        "Coconut" => Some(Arc::new(Coconut)),
+        "Starfruit" => Some(Arc::new(Starfruit)),
+        _ => None,
     }
+}
 
-    fn update(&self, dt: f32, time: f32, e: Entity, update: &LazyUpdate) -> bool {
-        // we just crossed second mark
-        if (time - dt == 0.0) || time.floor() != (time - dt).floor() {
-            update.exec(move |world| {
-                if let Some(hpool) = world.write_storage::<component::HealthPool>().get_mut(e) {
-                    hpool.hp = (hpool.hp + 20).min(hpool.max_hp);
-                }
-            });
+// built-in consumables only apply a status effect, which does the rest
+fn apply_effect(def: &'static StatusEffectDef, source: &'static str, e: Entity, update: &LazyUpdate) {
+    update.exec(move |world| {
+        if let Ok(entry) = world.write_storage::<component::StatusEffects>().entry(e) {
+            entry.or_insert_with(Default::default).apply(def, source);
         }
-        time >= 3.0
-    }
+    });
 }
 
+static PITAYA: StatusEffectDef = StatusEffectDef {
+    id: "pitaya",
+    description: "2x damage",
+    icon: Some("/sprites/ui/attack.png"),
+    duration: 10.0,
+    stacking: Stacking::Refresh,
+    modifiers: &[Modifier::Multiply(Stat::Damage, 2.0)],
+};
+
 struct Pitaya;
 impl ConsumeBehaviour for Pitaya {
     fn description(&self) -> &str { "Deals 2x damage for the next 10 seconds." }
 
     fn icon(&self, ctx: &mut ggez::Context, assets: &mut AssetManager) -> Option<Arc<ImageAsset>> {
-        assets.get::<ImageAsset>("/sprites/ui/attack.png", ctx).ok()
+        assets.get::<ImageAsset>(PITAYA.icon?, ctx).ok()
     }
 
-    fn update(&self, dt: f32, time: f32, e: Entity, update: &LazyUpdate) -> bool {
-        if (time - dt == 0.0) {
-            update.exec(move |world| {
-                if let Some(weaponry) = world.write_storage::<component::Weaponry>().get_mut(e) {
-                    weaponry.damage_multiplier *= 2.0;
-                }
-            });
-        } else if time >= 10.0 {
-            update.exec(move |world| {
-                if let Some(weaponry) = world.write_storage::<component::Weaponry>().get_mut(e) {
-                    weaponry.damage_multiplier /= 2.0;
-                }
-            });
-        }
-        time >= 10.0
+    fn update(&self, _: f32, _: f32, e: Entity, update: &LazyUpdate) -> bool {
+        apply_effect(&PITAYA, "i_pitaya", e, update);
+        true
     }
 }
 
+static COCONUT: StatusEffectDef = StatusEffectDef {
+    id: "coconut",
+    description: "Increased speed and control",
+    icon: Some("/sprites/ui/speed.png"),
+    duration: 20.0,
+    stacking: Stacking::Refresh,
+    modifiers: &[
+        Modifier::Multiply(Stat::MaxVelocity, 1.5),
+        Modifier::Multiply(Stat::Acceleration, 2.0),
+        Modifier::Add(Stat::Steering, -0.07),
+    ],
+};
+
 struct Coconut;
 impl ConsumeBehaviour for Coconut {
     fn description(&self) -> &str { "Increased speed and control for the next 20 seconds." }
 
     fn icon(&self, ctx: &mut ggez::Context, assets: &mut AssetManager) -> Option<Arc<ImageAsset>> {
-        assets.get::<ImageAsset>("/sprites/ui/speed.png", ctx).ok()
+        assets.get::<ImageAsset>(COCONUT.icon?, ctx).ok()
     }
 
-    fn update(&self, dt: f32, time: f32, e: Entity, update: &LazyUpdate) -> bool {
-        if (time - dt == 0.0) {
-            update.exec(move |world| {
-                if let Some(movement) = world.write_storage::<component::Movement>().get_mut(e) {
-                    movement.max_velocity *= 1.5;
-                    movement.acceleration_flat *= 2.0;
-                    movement.steering_difficulty -= 0.07;
-                }
-            });
-        } else if time >= 20.0 {
-            update.exec(move |world| {
-                if let Some(movement) = world.write_storage::<component::Movement>().get_mut(e) {
-                    movement.max_velocity /= 1.5;
-                    movement.acceleration_flat /= 2.0;
-                    movement.steering_difficulty += 0.07;
-                }
-            });
-        }
-        time >= 20.0
+    fn update(&self, _: f32, _: f32, e: Entity, update: &LazyUpdate) -> bool {
+        apply_effect(&COCONUT, "i_coconut", e, update);
+        true
     }
 }
 
+static STARFRUIT: StatusEffectDef = StatusEffectDef {
+    id: "starfruit",
+    description: "Immune to any damage",
+    icon: Some("/sprites/ui/uber.png"),
+    duration: 10.0,
+    stacking: Stacking::Extend,
+    modifiers: &[
+        Modifier::Immunity(DamageType::Physical),
+        Modifier::Immunity(DamageType::Impact),
+        Modifier::Immunity(DamageType::Lightning),
+        Modifier::Immunity(DamageType::Fire),
+    ],
+};
+
 struct Starfruit;
 impl ConsumeBehaviour for Starfruit {
     fn description(&self) -> &str { "Nothing can stop you now.\n(For at least 10 seconds)" }
 
     fn icon(&self, ctx: &mut ggez::Context, assets: &mut AssetManager) -> Option<Arc<ImageAsset>> {
-        assets.get::<ImageAsset>("/sprites/ui/uber.png", ctx).ok()
+        assets.get::<ImageAsset>(STARFRUIT.icon?, ctx).ok()
+    }
+
+    fn update(&self, _: f32, _: f32, e: Entity, update: &LazyUpdate) -> bool {
+        apply_effect(&STARFRUIT, "i_starfruit", e, update);
+        true
+    }
+}
+
+/// Consumable implemented by the `update` hook of a script (see `crate::script`),
+/// which is called every frame while it's being consumed
+pub struct Scripted {
+    pub description: DefStr,
+    pub icon: DefStr,
+    pub duration: f32,
+    pub script: DefStr,
+}
+impl ConsumeBehaviour for Scripted {
+    fn description(&self) -> &str { &self.description }
+
+    fn icon(&self, ctx: &mut ggez::Context, assets: &mut AssetManager) -> Option<Arc<ImageAsset>> {
+        assets.get::<ImageAsset>(&self.icon, ctx).ok()
     }
 
     fn update(&self, dt: f32, time: f32, e: Entity, update: &LazyUpdate) -> bool {
-        if (time - dt == 0.0) {
-            update.exec(move |world| {
-                if let Some(dmg_reciever) = world.write_storage::<component::DamageReciever>().get_mut(e) {
-                    for damage_type in &component::DAMAGE_TYPES {
-                        dmg_reciever.update_immunity(*damage_type, 10.0);
-                    }
-                }
-            });
-        }
-        time >= 10.0
+        let script_path = self.script.clone();
+        update.exec(move |world| {
+            let script = match world.write_resource::<AssetManager>().get::<ScriptAsset>(&script_path, &mut Headless) {
+                Ok(script) => script,
+                Err(err) => {
+                    log::error!("Unable to load consumable script: {}", err);
+                    return;
+                },
+            };
+            let mut api = ScriptApi::for_entity(e, world);
+            api.dt = dt;
+            api.time = time;
+            script.call("update", &mut api);
+            script::apply(e, &script_path, api.commands, world);
+        });
+        time >= self.duration
     }
 }
@@ -0,0 +1,67 @@
+//! Everything the game is made of. The binary only parses arguments and
+//! starts either the window or a headless simulation, so the same code can
+//! be driven from tests and tools.
+
+use anyhow::Result;
+
+#[macro_use]
+pub mod math;
+
+pub mod arena;
+pub mod assets;
+pub mod atlas;
+pub mod attack;
+pub mod controls;
+pub mod ecs;
+pub mod entity;
+pub mod game;
+pub mod hot_reload;
+pub mod item;
+pub mod lobby;
+pub mod main_menu;
+pub mod manifest;
+pub mod net;
+pub mod particle;
+pub mod prefab;
+pub mod replay;
+pub mod save;
+pub mod scene;
+pub mod script;
+pub mod settings;
+pub mod shader;
+pub mod simulation;
+pub mod ui;
+
+pub fn setup_logging() -> Result<()> {
+    use fern::colors::{Color, ColoredLevelConfig};
+    let colors = ColoredLevelConfig::default().info(Color::Blue).debug(Color::Green).trace(Color::Magenta);
+    fern::Dispatch::new()
+        .format(move |out, message, record| {
+            out.finish(format_args!("[{:<5}][{}] {}", colors.color(record.level()), record.target(), message))
+        })
+        // Filter out unnecessary stuff
+        .level(if std::env::args().any(|a| a == "--debug") { log::LevelFilter::Trace } else { log::LevelFilter::Info })
+        .level_for("gfx", log::LevelFilter::Off)
+        .level_for("gfx_device_gl", log::LevelFilter::Off)
+        .level_for("gilrs", log::LevelFilter::Off)
+        .chain(std::io::stdout())
+        .apply()?;
+    Ok(())
+}
+
+/// Value of `--name <value>` command line argument
+pub fn arg_value(name: &str) -> Option<String> {
+    std::env::args().skip_while(|a| a != name).nth(1)
+}
+
+/// Seed from `--seed <n>` argument, or a random one if there is none
+pub fn seed_from_args() -> u64 {
+    match arg_value("--seed").map(|s| s.parse()) {
+        Some(Ok(seed)) => seed,
+        Some(Err(err)) => {
+            log::warn!("Invalid --seed argument ({}), using random seed", err);
+            rand::random()
+        },
+        None => rand::random(),
+    }
+}
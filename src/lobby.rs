@@ -0,0 +1,123 @@
+use crate::{
+    game::Game,
+    main_menu::MainMenu,
+    net::{Session, Status},
+    scene::{Scene, SceneCommand},
+    settings::{Overrides, Settings},
+    ui::{self, ImGuiSystem},
+};
+use anyhow::{anyhow, Result};
+use ggez::{event::EventHandler, graphics, timer, Context, GameResult};
+use std::convert::TryFrom;
+
+/// Hosting or joining a network game, which starts as soon as both sides are connected
+pub struct Lobby {
+    ui: ui::Lobby,
+    imgui: ImGuiSystem,
+    ui_scale: f32,
+    session: Option<Session>,
+}
+
+impl Lobby {
+    pub fn new(ctx: &mut Context) -> Self {
+        Self {
+            ui: ui::Lobby::default(),
+            imgui: ImGuiSystem::new(ctx),
+            ui_scale: Settings::load().with(&Overrides::from_args()).ui_scale,
+            session: None,
+        }
+    }
+
+    /// Lobby which hosts or joins right away, as told by `--host <port>` or `--join <address>`
+    pub fn from_args(ctx: &mut Context) -> Self {
+        let mut lobby = Self::new(ctx);
+        if let Some(port) = crate::arg_value("--host") {
+            match port.parse() {
+                Ok(port) => lobby.ui.port = port,
+                Err(err) => log::warn!("Invalid --host argument ({}), using port {}", err, lobby.ui.port),
+            }
+            lobby.host();
+        } else if let Some(address) = crate::arg_value("--join") {
+            lobby.ui.set_address(&address);
+            lobby.join();
+        }
+        lobby
+    }
+
+    fn host(&mut self) {
+        let port = self.ui.port;
+        let session = match u16::try_from(port) {
+            Ok(port) if port != 0 => Session::host(port),
+            _ => Err(anyhow!("Invalid port {}", port)),
+        };
+        self.start(session, format!("Waiting for the other player on port {}", port));
+    }
+
+    fn join(&mut self) {
+        let address = self.ui.address.to_str().trim().to_owned();
+        self.start(Session::join(&address), format!("Joining {}", address));
+    }
+
+    fn start(&mut self, session: Result<Session>, status: String) {
+        match session {
+            Ok(session) => {
+                self.session = Some(session);
+                self.ui.status = status;
+            },
+            Err(err) => {
+                log::error!("{:#}", err);
+                self.ui.status = format!("{:#}", err);
+            },
+        }
+    }
+}
+
+impl Scene for Lobby {
+    fn next_command(&mut self) -> Option<SceneCommand> {
+        if self.ui.is_back {
+            return Some(SceneCommand::ReplaceAll(|ctx| Box::new(MainMenu::new(ctx))));
+        }
+        if self.session.as_ref().map_or(false, |s| *s.status() == Status::Connected) {
+            let session = self.session.take().unwrap();
+            let game = move |ctx: &mut Context| -> Box<dyn Scene> { Box::new(Game::network(ctx, session)) };
+            return Some(SceneCommand::ReplaceAllWith(Box::new(game)));
+        }
+        None
+    }
+
+    fn draw_prev(&self) -> bool { false }
+}
+impl EventHandler for Lobby {
+    fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
+        self.imgui.set_scale(self.ui_scale);
+        self.imgui.update(ctx, timer::delta(ctx), &mut self.ui, ());
+        if self.ui.is_host {
+            self.host();
+        } else if self.ui.is_join {
+            self.join();
+        } else if self.ui.is_cancel {
+            self.session = None;
+            self.ui.status.clear();
+        }
+
+        if let Some(session) = &mut self.session {
+            session.receive();
+        }
+        if let Some(Status::Closed(reason)) = self.session.as_ref().map(Session::status) {
+            self.ui.status = reason.clone();
+            self.session = None;
+        }
+        self.ui.is_busy = self.session.is_some();
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
+        graphics::clear(ctx, graphics::Color::from_rgb_u32(0x151515));
+        self.imgui.render(ctx);
+        graphics::present(ctx)
+    }
+
+    fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) {
+        graphics::set_screen_coordinates(ctx, graphics::Rect::new(0.0, 0.0, width, height)).unwrap();
+    }
+}
@@ -1,59 +1,75 @@
 use anyhow::Result;
-use ggez::{
-    conf::{FullscreenType, NumSamples, WindowMode, WindowSetup},
-    event, ContextBuilder,
-};
+use ggez::{event, ContextBuilder};
 use log::info;
+use planes_of_booty::{
+    arena, arg_value, ecs, game, lobby, main_menu, replay, scene, settings, setup_logging, simulation,
+};
+use specs::{Join, WorldExt};
 
-#[macro_use]
-mod math;
-
-mod arena;
-mod assets;
-mod attack;
-mod ecs;
-mod entity;
-mod game;
-mod item;
-mod main_menu;
-mod particle;
-mod scene;
-mod shader;
-mod ui;
-
-pub fn setup_logging() -> Result<()> {
-    use fern::colors::{Color, ColoredLevelConfig};
-    let colors = ColoredLevelConfig::default().info(Color::Blue).debug(Color::Green).trace(Color::Magenta);
-    fern::Dispatch::new()
-        .format(move |out, message, record| {
-            out.finish(format_args!("[{:<5}][{}] {}", colors.color(record.level()), record.target(), message))
-        })
-        // Filter out unnecessary stuff
-        .level(if std::env::args().any(|a| a == "--debug") { log::LevelFilter::Trace } else { log::LevelFilter::Info })
-        .level_for("gfx", log::LevelFilter::Off)
-        .level_for("gfx_device_gl", log::LevelFilter::Off)
-        .level_for("gilrs", log::LevelFilter::Off)
-        .chain(std::io::stdout())
-        .apply()?;
+fn run_simulation(ticks: u64, mut replay: Option<replay::Replay>) -> Result<()> {
+    let mut sim = match &replay {
+        Some(replay) => simulation::Simulation::new(simulation::TIMESTEP, replay.seed, replay.players),
+        None => simulation::Simulation::default(),
+    };
+    if replay.as_ref().map_or(false, |replay| replay.explore) {
+        game::start_exploring(sim.world());
+    }
+    let seed = sim.world().read_resource::<ecs::resource::RandomGenerator>().seed;
+    info!("Running headless simulation for {} ticks (seed {})", ticks, seed);
+    let start = std::time::Instant::now();
+    match &mut replay {
+        Some(replay) => {
+            for _ in 0..ticks {
+                match replay.next_frame() {
+                    Some(frame) => sim.replay_step(&frame),
+                    None => break,
+                }
+            }
+        },
+        None => {
+            // without a player to walk into the portal, go straight to the fight
+            sim.world().write_resource::<ecs::resource::Arena>().change_to = Some(arena::Kind::Static(arena::IDS[0]));
+            sim.run(ticks);
+        },
+    }
+    let alive = sim.world().entities().join().count();
+    info!(
+        "Simulated {:.1}s of gameplay in {:.3}s ({} entities alive)",
+        sim.elapsed().as_secs_f32(),
+        start.elapsed().as_secs_f32(),
+        alive
+    );
     Ok(())
 }
 
 fn run() -> Result<()> {
     info!("Running {} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
 
+    let replay = match arg_value("--replay") {
+        Some(path) => Some(replay::Replay::open(path)?),
+        None => None,
+    };
+    if let Some(ticks) = arg_value("--simulate") {
+        return run_simulation(ticks.parse()?, replay);
+    }
+
+    let settings = settings::Settings::load().with(&settings::Overrides::from_args());
     let (mut ctx, mut event_loop) = ContextBuilder::new("planes-of-booty", "")
-        .window_setup(WindowSetup {
-            title: "Planes of Booty".to_owned(),
-            samples: NumSamples::Four,
-            vsync: true,
-            ..WindowSetup::default()
-        })
-        .window_mode(WindowMode { fullscreen_type: FullscreenType::True, borderless: true, ..WindowMode::default() })
+        .window_setup(settings.window_setup())
+        .window_mode(settings.window_mode())
         .add_resource_path("resources")
         .build()?;
 
     let mut scene_manager = scene::SceneManager::new();
-    scene_manager.send_command(scene::SceneCommand::Push(|ctx| Box::new(main_menu::MainMenu::new(ctx))));
+    if let Some(replay) = replay {
+        let game =
+            move |ctx: &mut ggez::Context| -> Box<dyn scene::Scene> { Box::new(game::Game::replay(ctx, replay)) };
+        scene_manager.send_command(scene::SceneCommand::ReplaceAllWith(Box::new(game)));
+    } else if arg_value("--host").is_some() || arg_value("--join").is_some() {
+        scene_manager.send_command(scene::SceneCommand::Push(|ctx| Box::new(lobby::Lobby::from_args(ctx))));
+    } else {
+        scene_manager.send_command(scene::SceneCommand::Push(|ctx| Box::new(main_menu::MainMenu::new(ctx))));
+    }
     event::run(&mut ctx, &mut event_loop, &mut scene_manager).map_err(|err| anyhow::Error::new(err))
 }
 
@@ -1,7 +1,11 @@
 use crate::{
     assets::AssetManager,
     game::Game,
+    lobby::Lobby,
+    manifest,
+    save::{self, SaveData},
     scene::{Scene, SceneCommand},
+    settings::{Overrides, Settings},
     ui::{self, ImGuiSystem},
 };
 use ggez::{event::EventHandler, graphics, timer, Context, GameResult};
@@ -10,18 +14,57 @@ pub struct MainMenu {
     ui: ui::MainMenu,
     assets: AssetManager,
     imgui: ImGuiSystem,
+    // read upfront, so "Continue" is only there when the run can be continued
+    save: Option<SaveData>,
 }
 
 impl MainMenu {
     pub fn new(ctx: &mut Context) -> Self {
-        Self { ui: ui::MainMenu::default(), assets: AssetManager::default(), imgui: ImGuiSystem::new(ctx) }
+        let mut assets = AssetManager::default();
+        manifest::preload("main_menu", None, ctx, &mut assets);
+        let (save, save_error) = match save::exists() {
+            false => (None, None),
+            true => match save::read() {
+                Ok(data) => (Some(data), None),
+                Err(err) => {
+                    log::error!("Unable to load saved run: {:#}", err);
+                    (None, Some(format!("{:#}", err)))
+                },
+            },
+        };
+        Self {
+            ui: ui::MainMenu {
+                settings: Settings::load(),
+                overrides: Overrides::from_args(),
+                has_save: save.is_some(),
+                save_error,
+                ..ui::MainMenu::default()
+            },
+            assets,
+            imgui: ImGuiSystem::new(ctx),
+            save,
+        }
     }
 }
 
 impl Scene for MainMenu {
-    fn next_command(&self) -> Option<SceneCommand> {
+    fn next_command(&mut self) -> Option<SceneCommand> {
         if self.ui.is_play {
-            Some(SceneCommand::ReplaceAll(|ctx| Box::new(Game::new(ctx))))
+            match self.ui.is_coop {
+                false => Some(SceneCommand::ReplaceAll(|ctx| Box::new(Game::new(ctx, 1)))),
+                true => Some(SceneCommand::ReplaceAll(|ctx| Box::new(Game::new(ctx, 2)))),
+            }
+        } else if self.ui.is_explore {
+            match self.ui.is_coop {
+                false => Some(SceneCommand::ReplaceAll(|ctx| Box::new(Game::explore(ctx, 1)))),
+                true => Some(SceneCommand::ReplaceAll(|ctx| Box::new(Game::explore(ctx, 2)))),
+            }
+        } else if self.ui.is_continue {
+            let data = self.save.take()?;
+            let game = move |ctx: &mut Context| -> Box<dyn Scene> { Box::new(Game::load(ctx, data)) };
+            Some(SceneCommand::ReplaceAllWith(Box::new(game)))
+        } else if self.ui.is_network {
+            Some(SceneCommand::ReplaceAll(|ctx| Box::new(Lobby::new(ctx))))
         } else {
             None
         }
@@ -31,6 +74,7 @@ impl Scene for MainMenu {
 }
 impl EventHandler for MainMenu {
     fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
+        self.imgui.set_scale(self.ui.settings.with(&self.ui.overrides).ui_scale);
         self.imgui.update(ctx, timer::delta(ctx), &mut self.ui, &mut self.assets);
         if self.ui.is_exit {
             ggez::event::quit(ctx);
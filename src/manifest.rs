@@ -0,0 +1,115 @@
+use crate::{
+    arena,
+    assets::{AssetContext, AssetManager, AudioAsset, ImageAsset},
+    prefab::{Prefabs, RESOURCES_PATH},
+    script::ScriptAsset,
+};
+use anyhow::{Context as _, Result};
+use serde::Deserialize;
+use std::{fs, path::Path, time::Instant};
+
+/// Directory inside of `resources` with manifests, named after
+/// the scene (`main_menu`, `game`) or the arena they are for
+pub const MANIFESTS_DIR: &str = "manifests";
+
+/// Assets to load before they are needed, so nothing is read from disk in
+/// the middle of a frame. Preloaded assets stay loaded until the manifest
+/// is released, even if nothing uses them at the moment.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct Manifest {
+    // other manifests to preload along with this one
+    pub include: Vec<String>,
+    // prefab ids, every asset their definitions mention is preloaded
+    pub prefabs: Vec<String>,
+    pub images: Vec<String>,
+    pub sounds: Vec<String>,
+    pub scripts: Vec<String>,
+}
+
+impl Manifest {
+    /// None if there is no manifest with such name
+    pub fn load(name: &str) -> Result<Option<Self>> {
+        let path = Path::new(RESOURCES_PATH).join(MANIFESTS_DIR).join(format!("{}.yaml", name));
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path).with_context(|| format!("Can't read {:?}", path))?;
+        Ok(Some(serde_yaml::from_str(&content).with_context(|| format!("Invalid manifest {:?}", path))?))
+    }
+
+    pub fn extend(&mut self, other: &Manifest) {
+        self.include.extend(other.include.iter().cloned());
+        self.prefabs.extend(other.prefabs.iter().cloned());
+        self.images.extend(other.images.iter().cloned());
+        self.sounds.extend(other.sounds.iter().cloned());
+        self.scripts.extend(other.scripts.iter().cloned());
+    }
+
+    // replace includes and prefabs with assets they bring
+    fn flatten(mut self, prefabs: Option<&Prefabs>, visited: &mut Vec<String>) -> Result<Self> {
+        for name in std::mem::take(&mut self.include) {
+            if visited.contains(&name) {
+                continue;
+            }
+            visited.push(name.clone());
+            match Manifest::load(&name)? {
+                Some(included) => self.extend(&included.flatten(prefabs, visited)?),
+                None => log::warn!("Included manifest {:?} doesn't exist", name),
+            }
+        }
+        for id in std::mem::take(&mut self.prefabs) {
+            match prefabs.and_then(|prefabs| prefabs.get(&id)) {
+                Some(prefab) => self.extend(&prefab.assets),
+                None => log::warn!("Manifest mentions unknown prefab {:?}", id),
+            }
+        }
+        for keys in &mut [&mut self.images, &mut self.sounds, &mut self.scripts] {
+            keys.sort();
+            keys.dedup();
+        }
+        Ok(self)
+    }
+}
+
+/// Name of the manifest to preload for the arena
+pub fn arena(kind: arena::Kind) -> String {
+    match kind {
+        arena::Kind::Static(id) => id.name().to_owned(),
+        arena::Kind::Generated { .. } => "generated".to_owned(),
+        arena::Kind::Open { .. } => "open".to_owned(),
+        arena::Kind::Boss { .. } => "boss".to_owned(),
+    }
+}
+
+/// Load every asset of the manifest, if there is one, and keep them until
+/// `AssetManager::release` is called with the same name. Assets which fail
+/// to load are logged by the manager and skipped.
+pub fn preload(name: &str, prefabs: Option<&Prefabs>, ctx: &mut dyn AssetContext, assets: &mut AssetManager) {
+    let flatten = |manifest: Manifest| manifest.flatten(prefabs, &mut vec![name.to_owned()]);
+    let manifest = match Manifest::load(name).and_then(|m| m.map(flatten).transpose()) {
+        Ok(Some(manifest)) => manifest,
+        Ok(None) => {
+            log::debug!("No manifest for {:?}, its assets are loaded on demand", name);
+            return;
+        },
+        Err(err) => {
+            log::error!("Unable to preload {:?}: {:#}", name, err);
+            return;
+        },
+    };
+
+    let start = Instant::now();
+    let mut failed = 0;
+    for key in &manifest.images {
+        failed += assets.preload::<ImageAsset>(name, key, ctx).is_err() as usize;
+    }
+    for key in &manifest.sounds {
+        failed += assets.preload::<AudioAsset>(name, key, ctx).is_err() as usize;
+    }
+    for key in &manifest.scripts {
+        failed += assets.preload::<ScriptAsset>(name, key, ctx).is_err() as usize;
+    }
+    let total = manifest.images.len() + manifest.sounds.len() + manifest.scripts.len();
+    log::info!("Preloaded {:?}: {} assets ({} failed) in {:?}", name, total - failed, failed, start.elapsed());
+}
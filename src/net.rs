@@ -0,0 +1,489 @@
+use crate::{
+    ecs::{
+        component::{HealthPool, Transform},
+        resource::{DeltaTime, Inputs},
+    },
+    replay::Frame,
+    simulation::TIMESTEP,
+};
+use anyhow::{Context as _, Result};
+use specs::prelude::*;
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    hash::Hasher,
+    io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    time::{Duration, Instant},
+};
+
+/// Players of a network game, one on each side
+pub const PLAYERS: usize = 2;
+/// Port of the host when no other one is given
+pub const DEFAULT_PORT: u16 = 27960;
+const MAGIC: &[u8; 4] = b"PBNT";
+// bump this every time any message (or `Frame`) changes
+const VERSION: u8 = 2;
+/// Inputs are sampled that many ticks before they are simulated, so they have
+/// time to reach the peer and the game doesn't wait for every single packet
+pub const INPUT_DELAY: u64 = 4;
+/// World state is compared with the peer's one every that many ticks
+pub const HASH_INTERVAL: u64 = 60;
+// nothing from the peer for that long means they are gone
+const TIMEOUT: Duration = Duration::from_secs(10);
+// hello is repeated until the host answers
+const HELLO_INTERVAL: Duration = Duration::from_millis(250);
+// the peer waited for longer than that is shown on the screen
+const WAITING_TIME: Duration = Duration::from_millis(300);
+// unacknowledged inputs which are sent in a single packet
+const MAX_FRAMES: usize = 16;
+
+enum Message {
+    // client looking for the host
+    Hello,
+    // host accepting the client, along with the seed of the run
+    Welcome { seed: u64 },
+    // inputs of the sender from the `first` tick on. Everything before `ack` is
+    // received from the other side, and `hash` is the last world hash of the sender.
+    Inputs { ack: u64, first: u64, frames: Vec<Frame>, hash: Option<(u64, u64)> },
+    Bye,
+}
+impl Message {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.push(VERSION);
+        match self {
+            Message::Hello => out.push(0),
+            Message::Welcome { seed } => {
+                out.push(1);
+                out.extend_from_slice(&seed.to_le_bytes());
+            },
+            Message::Inputs { ack, first, frames, hash } => {
+                out.push(2);
+                out.extend_from_slice(&ack.to_le_bytes());
+                out.extend_from_slice(&first.to_le_bytes());
+                out.push(hash.is_some() as u8);
+                let (tick, value) = hash.unwrap_or_default();
+                out.extend_from_slice(&tick.to_le_bytes());
+                out.extend_from_slice(&value.to_le_bytes());
+                out.push(frames.len() as u8);
+                for frame in frames {
+                    frame.write(&mut out).expect("Writing into memory can't fail");
+                }
+            },
+            Message::Bye => out.push(3),
+        }
+        out
+    }
+
+    fn decode(buf: &[u8]) -> Option<Self> {
+        if buf.len() < 6 || &buf[..4] != MAGIC || buf[4] != VERSION {
+            return None;
+        }
+        let u64_at = |i: usize| {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(buf.get(i..i + 8)?);
+            Some(u64::from_le_bytes(bytes))
+        };
+        match buf[5] {
+            0 => Some(Message::Hello),
+            1 => Some(Message::Welcome { seed: u64_at(6)? }),
+            2 => {
+                let hash = match buf.get(22)? {
+                    0 => None,
+                    _ => Some((u64_at(23)?, u64_at(31)?)),
+                };
+                let count = *buf.get(39)? as usize;
+                let mut rest = buf.get(40..)?;
+                let frames = (0..count).map(|_| Frame::read(&mut rest).ok()).collect::<Option<Vec<_>>>()?;
+                Some(Message::Inputs { ack: u64_at(6)?, first: u64_at(14)?, frames, hash })
+            },
+            3 => Some(Message::Bye),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Status {
+    /// Host waiting for someone to join, or a client waiting for the host to answer
+    Connecting,
+    Connected,
+    /// With the reason to show
+    Closed(String),
+}
+
+/// Connection to the other player of a network game. Both sides run the whole
+/// simulation and only exchange inputs (lockstep): a tick is simulated once inputs
+/// of both players for it are there, so the same seed and the same inputs lead to
+/// the same game. Anything which makes the simulations drift apart is caught by
+/// comparing world hashes every `HASH_INTERVAL` ticks.
+pub struct Session {
+    socket: UdpSocket,
+    peer: Option<SocketAddr>,
+    is_host: bool,
+    seed: u64,
+    status: Status,
+    last_heard: Instant,
+    last_hello: Option<Instant>,
+    waiting_since: Option<Instant>,
+    // gameplay time not simulated yet
+    time: Duration,
+    // next tick to be simulated
+    tick: u64,
+    // tick of the next sampled local inputs
+    next_input: u64,
+    // everything before it is received by the peer
+    peer_ack: u64,
+    // sampled between ticks, waiting for the next one
+    pending: Option<Frame>,
+    // own inputs which are not simulated or not received by the peer yet
+    local: BTreeMap<u64, Frame>,
+    remote: BTreeMap<u64, Frame>,
+    last_hash: Option<(u64, u64)>,
+    // hashes which are not compared yet
+    own_hashes: BTreeMap<u64, u64>,
+    remote_hashes: BTreeMap<u64, u64>,
+    desync: Option<u64>,
+}
+
+impl Session {
+    /// Wait for a client on the given port of every interface
+    pub fn host(port: u16) -> Result<Self> {
+        let socket = UdpSocket::bind(("0.0.0.0", port)).with_context(|| format!("Can't listen on port {}", port))?;
+        log::info!("Hosting on port {}", port);
+        Self::new(socket, None, crate::seed_from_args())
+    }
+
+    /// Connect to the host at `address` (`host:port`, or just the host for the default port)
+    pub fn join(address: &str) -> Result<Self> {
+        let address = if address.contains(':') { address.to_owned() } else { format!("{}:{}", address, DEFAULT_PORT) };
+        let peer = address
+            .to_socket_addrs()
+            .with_context(|| format!("Can't resolve {:?}", address))?
+            .find(|a| a.is_ipv4())
+            .with_context(|| format!("No IPv4 address for {:?}", address))?;
+        let socket = UdpSocket::bind(("0.0.0.0", 0)).context("Can't open a socket")?;
+        log::info!("Joining {}", peer);
+        Self::new(socket, Some(peer), 0)
+    }
+
+    fn new(socket: UdpSocket, peer: Option<SocketAddr>, seed: u64) -> Result<Self> {
+        socket.set_nonblocking(true)?;
+        let idle = Frame::capture(&DeltaTime(TIMESTEP), &Inputs::default());
+        Ok(Self {
+            socket,
+            is_host: peer.is_none(),
+            peer,
+            seed,
+            status: Status::Connecting,
+            last_heard: Instant::now(),
+            last_hello: None,
+            waiting_since: None,
+            time: Duration::default(),
+            tick: 0,
+            next_input: INPUT_DELAY,
+            peer_ack: 0,
+            pending: None,
+            // nobody has any inputs for the first few ticks
+            local: (0..INPUT_DELAY).map(|tick| (tick, idle.clone())).collect(),
+            remote: BTreeMap::new(),
+            last_hash: None,
+            own_hashes: BTreeMap::new(),
+            remote_hashes: BTreeMap::new(),
+            desync: None,
+        })
+    }
+
+    pub fn status(&self) -> &Status { &self.status }
+
+    /// Seed of the run, chosen by the host
+    pub fn seed(&self) -> u64 { self.seed }
+
+    /// Host is the first player, and the one who joined is the second
+    pub fn local_player(&self) -> usize { if self.is_host { 0 } else { 1 } }
+
+    fn remote_player(&self) -> usize { if self.is_host { 1 } else { 0 } }
+
+    /// First tick with different world hashes, if there is one
+    pub fn desync(&self) -> Option<u64> { self.desync }
+
+    /// Whether the game is stuck waiting for inputs of the peer
+    pub fn is_waiting(&self) -> bool { self.waiting_since.map_or(false, |since| since.elapsed() > WAITING_TIME) }
+
+    /// Read everything the peer has sent, should be called every frame
+    pub fn receive(&mut self) {
+        if let Status::Closed(_) = self.status {
+            return;
+        }
+        let mut buf = [0u8; 2048];
+        loop {
+            let (len, from) = match self.socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    log::debug!("Unable to receive: {}", err);
+                    break;
+                },
+            };
+            let message = match Message::decode(&buf[..len]) {
+                Some(message) => message,
+                None => {
+                    log::debug!("Ignored unknown packet from {}", from);
+                    continue;
+                },
+            };
+            if self.peer.map_or(false, |peer| peer != from) {
+                log::debug!("Ignored packet from {}, already playing with someone else", from);
+                continue;
+            }
+            self.last_heard = Instant::now();
+            match message {
+                Message::Hello if self.is_host => {
+                    if self.peer.is_none() {
+                        log::info!("{} has joined", from);
+                        self.peer = Some(from);
+                        self.status = Status::Connected;
+                    }
+                    // repeated for every hello, as the previous welcome could be lost
+                    self.send_message(&Message::Welcome { seed: self.seed });
+                },
+                Message::Welcome { seed } if !self.is_host && self.status == Status::Connecting => {
+                    log::info!("Joined {} (seed {})", from, seed);
+                    self.seed = seed;
+                    self.status = Status::Connected;
+                },
+                Message::Inputs { ack, first, frames, hash } if self.status == Status::Connected => {
+                    self.peer_ack = self.peer_ack.max(ack);
+                    for (tick, frame) in (first..).zip(frames) {
+                        if tick >= self.tick {
+                            self.remote.entry(tick).or_insert(frame);
+                        }
+                    }
+                    if let Some((tick, hash)) = hash {
+                        self.remote_hashes.insert(tick, hash);
+                    }
+                },
+                Message::Bye => self.close("The other player has left"),
+                _ => (),
+            }
+        }
+        self.compare_hashes();
+
+        match self.status {
+            Status::Connecting if !self.is_host => {
+                if self.last_heard.elapsed() > TIMEOUT {
+                    self.close("The host doesn't answer");
+                } else if self.last_hello.map_or(true, |last| last.elapsed() > HELLO_INTERVAL) {
+                    self.send_message(&Message::Hello);
+                    self.last_hello = Some(Instant::now());
+                }
+            },
+            Status::Connected if self.last_heard.elapsed() > TIMEOUT => {
+                self.close("The other player stopped responding");
+            },
+            _ => (),
+        }
+    }
+
+    /// Local inputs of this frame (only the local player is taken from them). They are
+    /// simulated `INPUT_DELAY` ticks later, and clicks of frames between ticks are kept
+    /// for the next one, so none of them is lost.
+    pub fn sample(&mut self, mut frame: Frame) {
+        if let Some(earlier) = self.pending.take() {
+            frame.keep_clicks(&earlier);
+        }
+        if self.next_input < self.tick + INPUT_DELAY {
+            self.local.insert(self.next_input, frame);
+            self.next_input += 1;
+        } else {
+            self.pending = Some(frame);
+        }
+    }
+
+    /// Send local inputs the peer doesn't have yet, should be called every frame
+    pub fn send(&mut self) {
+        if self.status != Status::Connected {
+            return;
+        }
+        let mut ack = self.tick;
+        while self.remote.contains_key(&ack) {
+            ack += 1;
+        }
+        let unacked = self.local.range(self.peer_ack..);
+        let first = unacked.clone().next().map_or(self.next_input, |(tick, _)| *tick);
+        let frames = unacked.take(MAX_FRAMES).map(|(_, frame)| frame.clone()).collect();
+        self.send_message(&Message::Inputs { ack, first, frames, hash: self.last_hash });
+    }
+
+    /// Inputs of both players for the next tick, once it's time for it (ticks are
+    /// simulated with the fixed timestep) and inputs of the peer are there
+    pub fn next_frame(&mut self, delta: Duration) -> Option<Frame> {
+        if self.status != Status::Connected {
+            return None;
+        }
+        // a single tick per frame at most, so the peer is not outrun after a hiccup
+        self.time = (self.time + delta).min(TIMESTEP * 2);
+        if self.time < TIMESTEP {
+            return None;
+        }
+        let frame = match (self.local.get(&self.tick), self.remote.get(&self.tick)) {
+            (Some(local), Some(remote)) => {
+                let mut frame = local.clone();
+                frame.merge(remote, self.remote_player());
+                frame
+            },
+            _ => {
+                self.waiting_since.get_or_insert_with(Instant::now);
+                return None;
+            },
+        };
+        self.waiting_since = None;
+        self.time -= TIMESTEP;
+        self.remote.remove(&self.tick);
+        self.tick += 1;
+        self.local = self.local.split_off(&self.tick.min(self.peer_ack));
+        Some(frame)
+    }
+
+    /// Should be called after every simulated tick, to compare the world with the peer's one
+    pub fn simulated(&mut self, world: &World) {
+        let tick = self.tick - 1;
+        if tick % HASH_INTERVAL == 0 {
+            let hash = world_hash(world);
+            self.own_hashes.insert(tick, hash);
+            self.last_hash = Some((tick, hash));
+            self.compare_hashes();
+        }
+    }
+
+    fn compare_hashes(&mut self) {
+        while let Some((&tick, &remote)) = self.remote_hashes.iter().next() {
+            match self.own_hashes.get(&tick) {
+                Some(&own) => {
+                    if own != remote && self.desync.is_none() {
+                        log::error!(
+                            "Desync at tick {}: world hash is {:016x}, the peer has {:016x}",
+                            tick,
+                            own,
+                            remote
+                        );
+                        self.desync = Some(tick);
+                    }
+                    self.own_hashes = self.own_hashes.split_off(&(tick + 1));
+                },
+                // not simulated here yet
+                None if tick >= self.tick => break,
+                None => (),
+            }
+            self.remote_hashes.remove(&tick);
+        }
+    }
+
+    fn close(&mut self, reason: &str) {
+        log::info!("Connection closed: {}", reason);
+        self.status = Status::Closed(reason.to_owned());
+    }
+
+    fn send_message(&self, message: &Message) {
+        if let Some(peer) = self.peer {
+            if let Err(err) = self.socket.send_to(&message.encode(), peer) {
+                log::debug!("Unable to send to {}: {}", peer, err);
+            }
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) { self.send_message(&Message::Bye); }
+}
+
+/// Hash of the state which matters the most and is the first one to drift apart:
+/// where everything is and how healthy it is. Entities are joined in the order of
+/// their ids, which is the same on both sides as long as the simulation is.
+pub fn world_hash(world: &World) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    let (entities, transforms, hpools) =
+        (world.entities(), world.read_storage::<Transform>(), world.read_storage::<HealthPool>());
+    for (e, transform, hpool) in (&entities, &transforms, hpools.maybe()).join() {
+        hasher.write_u32(e.id());
+        hasher.write_u32(transform.pos.x.to_bits());
+        hasher.write_u32(transform.pos.y.to_bits());
+        hasher.write_u32(transform.rotation.radians.to_bits());
+        if let Some(hpool) = hpool {
+            hasher.write_u32(hpool.hp);
+        }
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{Point2f, Vec2f};
+    use std::thread;
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.register::<Transform>();
+        world.register::<HealthPool>();
+        world
+    }
+
+    // one frame of a player who moves with `stick` and aims with the mouse the same way
+    fn play(session: &mut Session, world: &World, stick: Vec2f) -> Option<Frame> {
+        session.receive();
+        let mut inputs = Inputs::default();
+        inputs.players[0].move_stick = stick;
+        inputs.players[0].mouse_aim = Some(stick.to_point());
+        inputs.players.swap(0, session.local_player());
+        session.sample(Frame::capture(&DeltaTime(TIMESTEP), &inputs));
+        session.send();
+        let frame = session.next_frame(TIMESTEP)?;
+        session.simulated(world);
+        Some(frame)
+    }
+
+    #[test]
+    fn loopback_session() {
+        let mut host = Session::host(0).unwrap();
+        let port = host.socket.local_addr().unwrap().port();
+        let mut client = Session::join(&format!("127.0.0.1:{}", port)).unwrap();
+        let (host_stick, client_stick) = (Vec2f::new(1.0, 0.0), Vec2f::new(0.0, -1.0));
+        let mut worlds = [world(), world()];
+
+        // both sides simulate the same inputs of both players, and so the same worlds
+        let ticks = HASH_INTERVAL as usize * 2;
+        let (mut host_frames, mut client_frames) = (vec![], vec![]);
+        for _ in 0..100_000 {
+            host_frames.extend(play(&mut host, &worlds[0], host_stick));
+            client_frames.extend(play(&mut client, &worlds[1], client_stick));
+            if host_frames.len() > ticks && client_frames.len() > ticks {
+                break;
+            }
+            thread::sleep(Duration::from_micros(50));
+        }
+        assert_eq!((host.status(), client.status()), (&Status::Connected, &Status::Connected));
+        assert_eq!(host.seed(), client.seed());
+        assert_eq!(host_frames[..ticks], client_frames[..ticks]);
+        let mut inputs = Inputs::default();
+        host_frames[ticks - 1].apply(&mut DeltaTime(TIMESTEP), &mut inputs);
+        assert_eq!(inputs.players[0].move_stick, host_stick);
+        assert_eq!(inputs.players[0].mouse_aim, Some(Point2f::new(1.0, 0.0)));
+        assert_eq!(inputs.players[1].move_stick, client_stick);
+        assert_eq!(inputs.players[1].mouse_aim, Some(Point2f::new(0.0, -1.0)));
+        assert_eq!((host.desync(), client.desync()), (None, None));
+
+        // and notice once they don't
+        worlds[1].create_entity().with(Transform::default()).build();
+        for _ in 0..100_000 {
+            play(&mut host, &worlds[0], host_stick);
+            play(&mut client, &worlds[1], client_stick);
+            if host.desync().is_some() && client.desync().is_some() {
+                break;
+            }
+            thread::sleep(Duration::from_micros(50));
+        }
+        assert!(host.desync().is_some());
+        assert_eq!(host.desync(), client.desync());
+    }
+}
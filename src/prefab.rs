@@ -0,0 +1,1318 @@
+use crate::{
+    arena,
+    assets::{AssetContext, AssetManager, ImageAsset},
+    attack::{self, AttackPattern, SubProjectiles},
+    ecs::{
+        component::*,
+        resource::{Arena, PhysicWorld, RandomGenerator, SpawnItem, SpawnQueue},
+        tag,
+    },
+    entity,
+    item::{self, ConsumeBehaviour},
+    manifest::Manifest,
+    math::*,
+    particle,
+};
+use anyhow::{anyhow, bail, Context as _, Result};
+use def::{ArenaDef, ComponentDef, EntityDef, PartValue, SpawnGroupDef, COMPONENTS};
+use enum_map::EnumMap;
+use heck::CamelCase;
+use nphysics2d::{
+    material::{BasicMaterial, MaterialHandle},
+    nalgebra::Point2,
+    ncollide2d::{
+        pipeline::object::CollisionGroups,
+        shape::{ConvexPolygon, ShapeHandle},
+    },
+    object::{BodyPartHandle, BodyStatus, ColliderDesc, RigidBodyDesc},
+};
+use rand::{
+    distributions::{weighted::alias_method::WeightedIndex, Distribution},
+    seq::SliceRandom,
+    Rng,
+};
+use serde::de::DeserializeOwned;
+use specs::{world::Builder, Component, Entity, LazyUpdate, World, WorldExt};
+use std::{
+    collections::{BTreeMap as Map, HashMap, HashSet},
+    fs,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+// the same yaml schema the build script generates code from
+#[allow(dead_code)]
+#[path = "../build/def.rs"]
+mod def;
+
+/// Built-in definitions, the same ones codegen uses
+pub const RESOURCES_PATH: &str = "resources";
+/// Every directory in here is laid out like `resources` and
+/// its definitions replace built-in ones with the same id
+pub const MODS_PATH: &str = "mods";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Group {
+    Entity,
+    Item,
+    Particle,
+}
+pub const GROUPS: [Group; 3] = [Group::Entity, Group::Item, Group::Particle];
+impl Group {
+    pub fn dir(self) -> &'static str {
+        match self {
+            Group::Entity => "entities",
+            Group::Item => "items",
+            Group::Particle => "particles",
+        }
+    }
+
+    /// Prefabs are keyed by the same ids `Reflection` component uses: `e_crab`, `i_orange`, ...
+    pub fn id(self, name: &str) -> String {
+        let prefix = match self {
+            Group::Entity => "e",
+            Group::Item => "i",
+            Group::Particle => "p",
+        };
+        format!("{}_{}", prefix, name)
+    }
+}
+
+/// Every entity, item, particle, arena and spawn group definition loaded
+/// at runtime, so definitions can be tweaked (or added by mods) without a rebuild
+#[derive(Default)]
+pub struct Prefabs {
+    prefabs: HashMap<String, Prefab>,
+    // both are keyed by file names, sorted so the choice of a spawn group is deterministic
+    arenas: Map<String, ArenaDef>,
+    spawn_groups: Map<String, SpawnGroup>,
+}
+
+impl Prefabs {
+    /// Load built-in definitions and then mods. Broken files are
+    /// reported and skipped, they never stop the game from starting.
+    pub fn load() -> Self {
+        let mut prefabs = Self::default();
+        prefabs.load_dir(Path::new(RESOURCES_PATH));
+        if let Ok(mods) = fs::read_dir(MODS_PATH) {
+            let mut mods: Vec<_> =
+                mods.filter_map(|r| r.ok()).map(|entry| entry.path()).filter(|p| p.is_dir()).collect();
+            mods.sort();
+            for path in mods {
+                log::info!("Loading mod {:?}", path);
+                prefabs.load_dir(&path);
+            }
+        }
+        log::info!(
+            "Loaded {} prefabs, {} arenas and {} spawn groups",
+            prefabs.prefabs.len(),
+            prefabs.arenas.len(),
+            prefabs.spawn_groups.len()
+        );
+        prefabs
+    }
+
+    pub fn load_dir(&mut self, root: &Path) {
+        for group in &GROUPS {
+            for path in files(&root.join(group.dir())) {
+                match Prefab::load(*group, &path) {
+                    Ok(prefab) => self.insert(prefab),
+                    Err(err) => log::error!("Unable to load prefab {:?}: {:#}", path, err),
+                }
+            }
+        }
+        for path in files(&root.join("arenas")) {
+            match read_def::<ArenaDef>(&path) {
+                Ok((name, def)) => {
+                    self.arenas.insert(name, def);
+                },
+                Err(err) => log::error!("Unable to load arena {:?}: {:#}", path, err),
+            }
+        }
+        for path in files(&root.join("spawn_groups")) {
+            match read_def::<SpawnGroupDef>(&path).and_then(|(name, def)| Ok((name, SpawnGroup::new(&def)?))) {
+                Ok((name, group)) => {
+                    self.spawn_groups.insert(name, group);
+                },
+                Err(err) => log::error!("Unable to load spawn group {:?}: {:#}", path, err),
+            }
+        }
+    }
+
+    /// Add new prefab or replace the one with the same id
+    pub fn insert(&mut self, prefab: Prefab) { self.prefabs.insert(prefab.id.clone(), prefab); }
+
+    pub fn get(&self, id: &str) -> Option<&Prefab> { self.prefabs.get(id) }
+
+    /// Forget shared components, so they are created anew with the next spawn
+    pub fn reset_shared(&self) {
+        for prefab in self.prefabs.values() {
+            prefab.shared.lock().unwrap().take();
+        }
+    }
+
+    pub fn spawn(
+        &self,
+        id: &str,
+        world: &World,
+        ctx: &mut dyn AssetContext,
+        assets: &mut AssetManager,
+    ) -> Result<Entity> {
+        let prefab = self.get(id).ok_or_else(|| anyhow!("There is no prefab {:?}", id))?;
+        let e = world.create_entity_unchecked().with(Reflection { id: prefab.id.clone().into() }).build();
+        match prefab.build(e, self, world, ctx, assets) {
+            Ok(()) => Ok(e),
+            Err(err) => {
+                discard(world, e);
+                Err(err.context(format!("Unable to spawn {:?}", id)))
+            },
+        }
+    }
+
+    /// Set up a hand-made arena. If its definition is missing, the compiled one is used instead.
+    pub fn set_arena(
+        &self,
+        id: arena::ID,
+        arena: &mut Arena,
+        spawn_queue: &mut SpawnQueue,
+        rng: &mut RandomGenerator,
+    ) {
+        let def = match self.arenas.get(id.name()) {
+            Some(def) => def,
+            None => {
+                log::error!("There is no arena {:?}, using compiled definition", id.name());
+                return arena::set(id, arena, spawn_queue, rng);
+            },
+        };
+        arena.size = Size2f::new(def.width, def.height);
+        for entity in &def.entities {
+            let pos = Point2f::new(entity.pos.x, entity.pos.y);
+            spawn_queue.0.push_back(match entity::ID::from_name(&entity.id) {
+                Some(id) => SpawnItem::Entity(id, pos, vec![]),
+                // entities added by mods have no compiled ids
+                None => SpawnItem::Prefab(Group::Entity.id(&entity.id), pos),
+            });
+        }
+        for point in &def.spawn_points {
+            let halfr = point.radius * 0.5;
+            for id in self.spawn_group(arena.difficulty, rng) {
+                let pos = Point2f::new(
+                    point.pos.x + rng.gen_range(-halfr, halfr),
+                    point.pos.y + rng.gen_range(-halfr, halfr),
+                );
+                spawn_queue.0.push_back(SpawnItem::Entity(id, pos, vec![]));
+            }
+        }
+    }
+
+    /// Enemies of a random spawn group, there are more of them with higher difficulty.
+    /// Compiled spawn groups are used if none were loaded.
+    pub fn spawn_group(&self, difficulty: f32, rng: &mut RandomGenerator) -> Vec<entity::ID> {
+        if self.spawn_groups.is_empty() {
+            return arena::SPAWN_GROUPS.choose(rng).unwrap().spawn(difficulty, rng);
+        }
+        let index = rng.gen_range(0, self.spawn_groups.len());
+        self.spawn_groups.values().nth(index).unwrap().spawn(difficulty, rng)
+    }
+}
+
+/// Enemies spawned together, see `resources/spawn_groups`
+struct SpawnGroup {
+    start: u32,
+    grow: f32,
+    choices: Vec<entity::ID>,
+    weights: WeightedIndex<u8>,
+}
+impl SpawnGroup {
+    fn new(def: &SpawnGroupDef) -> Result<Self> {
+        let choices = def
+            .weighted
+            .iter()
+            .map(|w| entity::ID::from_name(&w.id).ok_or_else(|| anyhow!("Unknown entity {:?}", w.id)))
+            .collect::<Result<_>>()?;
+        let weights = WeightedIndex::new(def.weighted.iter().map(|w| w.weight).collect())
+            .map_err(|err| anyhow!("Invalid weights: {}", err))?;
+        Ok(Self { start: def.start, grow: def.grow, choices, weights })
+    }
+
+    fn spawn(&self, difficulty: f32, rng: &mut RandomGenerator) -> Vec<entity::ID> {
+        let size = self.start + (difficulty * self.grow).floor() as u32;
+        (0..=size).map(|_| self.choices[self.weights.sample(rng)]).collect()
+    }
+}
+
+fn files(dir: &Path) -> Vec<PathBuf> {
+    match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|r| r.ok()).map(|entry| entry.path()).filter(|p| p.is_file()).collect(),
+        Err(_) => vec![],
+    }
+}
+
+// definitions are named after their files
+fn read_def<T: DeserializeOwned>(path: &Path) -> Result<(String, T)> {
+    let name = path.file_stem().and_then(|s| s.to_str()).ok_or_else(|| anyhow!("Invalid file name"))?;
+    Ok((name.to_owned(), serde_yaml::from_str(&fs::read_to_string(path)?)?))
+}
+
+/// Delete entity which failed to spawn along with everything it has created
+/// so far: physic body with its colliders, weapons and weapons of boss phases
+fn discard(world: &World, e: Entity) {
+    let mut owned: Vec<Entity> = vec![];
+    if let Some(weaponry) = world.read_storage::<Weaponry>().get(e) {
+        owned.extend(weaponry.primary.iter().chain(weaponry.secondary.iter()));
+    }
+    if let Some(boss) = world.read_storage::<BossPhases>().get(e) {
+        owned.extend(boss.phases.iter().filter_map(|phase| phase.weapon));
+    }
+    if let Some(physic) = world.read_storage::<Physic>().get(e) {
+        let mut physic_world = world.write_resource::<PhysicWorld>();
+        physic_world.colliders.remove(physic.colliders.real.0);
+        if let Some((hitbox, _)) = physic.colliders.hitbox {
+            physic_world.colliders.remove(hitbox);
+        }
+        physic_world.bodies.remove(physic.body);
+    }
+    for item in owned {
+        discard(world, item);
+    }
+    world.entities().delete(e).ok();
+}
+
+/// Ids generated from `resources` are still around as typed handles
+/// for the code which needs some particular thing. They are spawned
+/// from the registry too, so tweaks of their files apply without a rebuild.
+pub trait Handle: Copy {
+    const GROUP: Group;
+    fn name(self) -> &'static str;
+    fn spawn_compiled(self, world: &World, ctx: &mut dyn AssetContext, assets: &mut AssetManager) -> Entity;
+}
+impl Handle for entity::ID {
+    const GROUP: Group = Group::Entity;
+
+    fn name(self) -> &'static str { entity::ID::name(&self) }
+
+    fn spawn_compiled(self, world: &World, ctx: &mut dyn AssetContext, assets: &mut AssetManager) -> Entity {
+        entity::spawn(self, world, ctx, assets)
+    }
+}
+impl Handle for item::ID {
+    const GROUP: Group = Group::Item;
+
+    fn name(self) -> &'static str { item::ID::name(&self) }
+
+    fn spawn_compiled(self, world: &World, ctx: &mut dyn AssetContext, assets: &mut AssetManager) -> Entity {
+        item::spawn(self, world, ctx, assets)
+    }
+}
+impl Handle for particle::ID {
+    const GROUP: Group = Group::Particle;
+
+    fn name(self) -> &'static str { particle::ID::name(&self) }
+
+    fn spawn_compiled(self, world: &World, ctx: &mut dyn AssetContext, assets: &mut AssetManager) -> Entity {
+        particle::spawn(self, world, ctx, assets)
+    }
+}
+
+/// Spawn typed handle from the registry. If its definition is broken or missing,
+/// the compiled one is used instead, because the game relies on it being there.
+pub fn spawn<H: Handle>(handle: H, world: &World, ctx: &mut dyn AssetContext, assets: &mut AssetManager) -> Entity {
+    let id = H::GROUP.id(handle.name());
+    let spawned = world.read_resource::<Prefabs>().spawn(&id, world, ctx, assets);
+    match spawned {
+        Ok(e) => e,
+        Err(err) => {
+            log::error!("{:#}, using compiled definition", err);
+            handle.spawn_compiled(world, ctx, assets)
+        },
+    }
+}
+
+/// Definition with everything that doesn't need assets (strings, attack patterns,
+/// shapes, ...) already resolved, so spawning it only clones them
+pub struct Prefab {
+    pub id: String,
+    // file it was loaded from
+    pub path: PathBuf,
+    tags: Vec<String>,
+    components: Vec<(String, Fields)>,
+    shared_components: Vec<(String, Fields)>,
+    // shared components are created on the first spawn, since some of them need assets
+    shared: Mutex<Option<Vec<Shared>>>,
+    /// Images, sounds and scripts mentioned anywhere in the definition
+    pub assets: Manifest,
+}
+
+enum Shared {
+    DropTable(Arc<DropTable>),
+    ParticleDef(Arc<ParticleDef>),
+    AnimationDef(Arc<AnimationDef>),
+}
+
+impl Prefab {
+    pub fn load(group: Group, path: &Path) -> Result<Self> {
+        let (name, def) = read_def::<EntityDef>(path)?;
+        Self::new(group.id(&name), path, &def)
+    }
+
+    fn new(id: String, path: &Path, def: &EntityDef) -> Result<Self> {
+        let resolve_all = |components: &Map<String, ComponentDef>| -> Result<Vec<(String, Fields)>> {
+            components.iter().map(|(name, def)| Ok((name.to_owned(), Fields::from_component(name, def)?))).collect()
+        };
+        Ok(Self {
+            id,
+            path: path.to_owned(),
+            tags: def.tags.clone(),
+            components: resolve_all(&def.components)?,
+            shared_components: resolve_all(&def.shared_components)?,
+            shared: Mutex::new(None),
+            assets: collect_assets(def),
+        })
+    }
+
+    fn build(
+        &self,
+        e: Entity,
+        prefabs: &Prefabs,
+        world: &World,
+        ctx: &mut dyn AssetContext,
+        assets: &mut AssetManager,
+    ) -> Result<()> {
+        for tag in &self.tags {
+            insert_tag(world, e, tag)?;
+        }
+        for (name, fields) in &self.components {
+            insert_component(world, e, name, fields, prefabs, ctx, assets)
+                .with_context(|| format!("Invalid {} component", name))?;
+        }
+
+        let mut shared = self.shared.lock().unwrap();
+        if shared.is_none() {
+            let created = self
+                .shared_components
+                .iter()
+                .map(|(name, fields)| {
+                    create_shared(name, fields, ctx, assets).with_context(|| format!("Invalid shared {}", name))
+                })
+                .collect::<Result<_>>()?;
+            shared.replace(created);
+        }
+        for component in shared.iter().flatten() {
+            match component {
+                Shared::DropTable(arc) => insert(world, e, SharedDropTable::from(arc.clone()))?,
+                Shared::ParticleDef(arc) => insert(world, e, SharedParticleDef::from(arc.clone()))?,
+                Shared::AnimationDef(arc) => insert(world, e, SharedAnimationDef::from(arc.clone()))?,
+            }
+        }
+
+        if let Some(shapeshifter) = world.read_storage::<Shapeshifter>().get(e) {
+            if let Some(form) = shapeshifter.forms.get(shapeshifter.current) {
+                form.on_begin(e, &world.read_resource::<LazyUpdate>(), (ctx, assets));
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace definition values of an already spawned entity, keeping its
+    /// runtime state (velocity, current hp, clip, ...) where it makes sense
+    pub fn reapply(
+        &self,
+        e: Entity,
+        prefabs: &Prefabs,
+        world: &World,
+        ctx: &mut dyn AssetContext,
+        assets: &mut AssetManager,
+    ) -> Result<()> {
+        // shapeshifter forms change sprites on their own
+        let is_shapeshifter = world.read_storage::<Shapeshifter>().contains(e);
+        let movement = world.read_storage::<Movement>().get(e).map(|m| (m.velocity, m.target_acceleration_normal));
+        let hp = world.read_storage::<HealthPool>().get(e).map(|h| h.hp);
+        let weapon = world
+            .read_storage::<WeaponProperties>()
+            .get(e)
+            .map(|w| (w.target_pos, w.is_shooting, w.clip, w.reloading, w.cooldown));
+        let stack = world.read_storage::<Stackable>().get(e).map(|s| s.current);
+
+        for (name, fields) in &self.components {
+            let reloadable = COMPONENTS.iter().any(|(component, reloadable)| component == name && *reloadable);
+            if reloadable && !(is_shapeshifter && name == "Sprite") {
+                insert_component(world, e, name, fields, prefabs, ctx, assets)
+                    .with_context(|| format!("Invalid {} component", name))?;
+            }
+        }
+
+        if let (Some((velocity, normal)), Some(m)) = (movement, world.write_storage::<Movement>().get_mut(e)) {
+            m.velocity = velocity;
+            m.target_acceleration_normal = normal;
+        }
+        if let (Some(hp), Some(h)) = (hp, world.write_storage::<HealthPool>().get_mut(e)) {
+            h.hp = hp.min(h.max_hp);
+        }
+        if let (Some((target_pos, is_shooting, clip, reloading, cooldown)), Some(w)) =
+            (weapon, world.write_storage::<WeaponProperties>().get_mut(e))
+        {
+            w.target_pos = target_pos;
+            w.is_shooting = is_shooting;
+            w.clip = clip.min(w.clip_size);
+            w.reloading = reloading;
+            w.cooldown = cooldown;
+        }
+        if let (Some(current), Some(s)) = (stack, world.write_storage::<Stackable>().get_mut(e)) {
+            s.current = current.min(s.stack_size);
+        }
+        Ok(())
+    }
+}
+
+fn insert<T: Component>(world: &World, e: Entity, component: T) -> Result<()> {
+    world.write_storage::<T>().insert(e, component)?;
+    Ok(())
+}
+
+fn insert_tag(world: &World, e: Entity, tag: &str) -> Result<()> {
+    match tag.to_camel_case().as_str() {
+        "Player" => insert(world, e, tag::Player),
+        "LevelChanger" => insert(world, e, tag::LevelChanger),
+        "PendingDestruction" => insert(world, e, tag::PendingDestruction),
+        "Container" => insert(world, e, tag::Container),
+        "LastShot" => insert(world, e, tag::LastShot),
+        _ => bail!("Unknown tag {:?}", tag),
+    }
+}
+
+fn insert_component(
+    world: &World,
+    e: Entity,
+    name: &str,
+    f: &Fields,
+    prefabs: &Prefabs,
+    ctx: &mut dyn AssetContext,
+    assets: &mut AssetManager,
+) -> Result<()> {
+    match name {
+        "Sprite" => insert(world, e, Sprite {
+            asset: dir_or_single(f.value("asset")?, |v| image(v, ctx, assets))?,
+            size: f.req("size")?,
+        }),
+        "Physic" => insert_physic(world, e, f),
+        "Transform" => {
+            let d = Transform::default();
+            insert(world, e, Transform {
+                pos: f.get("pos", d.pos)?,
+                rotation: Angle2f::radians(f.get("rotation", d.rotation.radians)?),
+                mirror: f.get("mirror", d.mirror)?,
+            })
+        },
+        "Movement" => {
+            let d = Movement::default();
+            insert(world, e, Movement {
+                velocity: f.get("velocity", d.velocity)?,
+                target_acceleration_normal: f.get("target_acceleration_normal", d.target_acceleration_normal)?,
+                max_velocity: f.get("max_velocity", d.max_velocity)?,
+                acceleration_flat: f.get("acceleration_flat", d.acceleration_flat)?,
+                steering_difficulty: f.get("steering_difficulty", d.steering_difficulty)?,
+            })
+        },
+        "Directional" => insert(world, e, Directional::default()),
+        "Named" => insert(world, e, Named { name: f.req("name")?, description: f.req("description")? }),
+        "Quality" => insert(world, e, Quality { rarity: f.req("rarity")? }),
+        "Stackable" => {
+            let d = Stackable::default();
+            insert(world, e, Stackable {
+                current: f.get("current", d.current)?,
+                stack_size: f.get("stack_size", d.stack_size)?,
+            })
+        },
+        "WeaponAttack" => insert(world, e, WeaponAttack { pattern: f.req("pattern")? }),
+        "WeaponProperties" => {
+            let d = WeaponProperties::default();
+            insert(world, e, WeaponProperties {
+                clip_size: f.get("clip_size", d.clip_size)?,
+                clip: f.get("clip", d.clip)?,
+                reloading_time: f.get("reloading_time", d.reloading_time)?,
+                reloading: f.get("reloading", d.reloading)?,
+                cooldown_time: f.get("cooldown_time", d.cooldown_time)?,
+                cooldown: f.get("cooldown", d.cooldown)?,
+                damage: f.get("damage", d.damage)?,
+                accuracy: f.get("accuracy", d.accuracy)?,
+                passive_reloading: f.get("passive_reloading", d.passive_reloading)?,
+                ..d
+            })
+        },
+        "RandomizedWeaponProperties" => insert(world, e, RandomizedWeaponProperties {
+            clip_size: f.get("clip_size", None)?,
+            reloading_time: f.get("reloading_time", None)?,
+            cooldown_time: f.get("cooldown_time", None)?,
+            damage: f.get("damage", None)?,
+            accuracy: f.get("accuracy", None)?,
+        }),
+        "Weaponry" => {
+            let mut item = |key: &str| -> Result<ItemBox> {
+                match f.values.get(key) {
+                    Some(Value::Item(id)) => Ok(Some(prefabs.spawn(id, world, ctx, assets)?)),
+                    Some(other) => Err(other.unexpected("item")),
+                    None if f.default => Ok(None),
+                    None => Err(f.missing(key)),
+                }
+            };
+            let damage_multiplier = f.get("damage_multiplier", Weaponry::default().damage_multiplier)?;
+            match (item("primary"), item("secondary")) {
+                (Ok(primary), Ok(secondary)) => insert(world, e, Weaponry { primary, secondary, damage_multiplier }),
+                (primary, secondary) => {
+                    // the other weapon may be spawned already
+                    primary.iter().chain(secondary.iter()).flatten().for_each(|item| discard(world, *item));
+                    Err(primary.and(secondary).unwrap_err())
+                },
+            }
+        },
+        "Inventory" => insert(world, e, Inventory::default()),
+        "Hotbar" => insert(world, e, Hotbar::default()),
+        "Consumer" => insert(world, e, Consumer::default()),
+        "Consumable" => insert(world, e, Consumable { behaviour: f.req("behaviour")? }),
+        "Shapeshifter" => insert(world, e, Shapeshifter {
+            current: f.get("current", 0)?,
+            time: f.get("time", 0.0)?,
+            forms: f.req("forms")?,
+        }),
+        "BossPhases" => {
+            let (name, current) = (f.req("name")?, f.get("current", 0)?);
+            let phases: Vec<Result<BossPhase>> = match f.value("phases")? {
+                Value::Seq(values) => {
+                    values.iter().map(|value| boss_phase(value, prefabs, world, ctx, assets)).collect()
+                },
+                other => return Err(other.unexpected("sequence of boss phases")),
+            };
+            if let Some(index) = phases.iter().position(Result::is_err) {
+                // weapons of other phases may be spawned already
+                phases.iter().flatten().filter_map(|phase| phase.weapon).for_each(|weapon| discard(world, weapon));
+                return Err(phases.into_iter().nth(index).unwrap().unwrap_err());
+            }
+            let phases = phases.into_iter().flatten().collect();
+            insert(world, e, BossPhases { name, current, phases, events: vec![] })
+        },
+        "HealthPool" => {
+            let d = HealthPool::default();
+            insert(world, e, HealthPool { max_hp: f.get("max_hp", d.max_hp)?, hp: f.get("hp", d.hp)? })
+        },
+        "DamageReciever" => {
+            let d = DamageReciever::default();
+            insert(world, e, DamageReciever {
+                resistances: f.get("resistances", d.resistances)?,
+                burning_time: f.get("burning_time", d.burning_time)?,
+                shock_time: f.get("shock_time", d.shock_time)?,
+                ..d
+            })
+        },
+        "Faction" => insert(world, e, Faction { id: f.req("id")? }),
+        "Target" => insert(world, e, Target::default()),
+        "SearchForTarget" => {
+            let d = SearchForTarget::default();
+            insert(world, e, SearchForTarget {
+                from_factions: f.get("from_factions", d.from_factions)?,
+                radius: f.get("radius", d.radius)?,
+            })
+        },
+        "FollowTarget" => {
+            let d = FollowTarget::default();
+            insert(world, e, FollowTarget {
+                keep_distance: f.get("keep_distance", d.keep_distance)?,
+                follow_distance: f.get("follow_distance", d.follow_distance)?,
+            })
+        },
+        "ShootTarget" => insert(world, e, ShootTarget { radius: f.get("radius", ShootTarget::default().radius)? }),
+        "AvoidShots" => insert(world, e, AvoidShots { count: f.req("count")? }),
+        "SoundEffects" => {
+            let d = SoundEffects::default();
+            insert(world, e, SoundEffects {
+                attack: f.get("attack", d.attack)?,
+                hit: f.get("hit", d.hit)?,
+                death: f.get("death", d.death)?,
+                consume: f.get("consume", d.consume)?,
+            })
+        },
+        "ParticleProperties" => {
+            let d = ParticleProperties::default();
+            insert(world, e, ParticleProperties {
+                current_frame: f.get("current_frame", d.current_frame)?,
+                frame_time: f.get("frame_time", d.frame_time)?,
+            })
+        },
+        "StatusEffects" => insert(world, e, StatusEffects::default()),
+        "AnimatedSprite" => {
+            let d = AnimatedSprite::default();
+            insert(world, e, AnimatedSprite {
+                clip: f.get("clip", d.clip)?,
+                frame: f.get("frame", d.frame)?,
+                frame_time: f.get("frame_time", d.frame_time)?,
+                finished: f.get("finished", d.finished)?,
+            })
+        },
+        _ => bail!("Unknown component"),
+    }
+}
+
+// phase weapons are spawned along with the boss, so it's not a `FromValue`
+fn boss_phase(
+    value: &Value,
+    prefabs: &Prefabs,
+    world: &World,
+    ctx: &mut dyn AssetContext,
+    assets: &mut AssetManager,
+) -> Result<BossPhase> {
+    let (name, f) = match value {
+        Value::BossPhase(name, f) => (name, f),
+        other => return Err(other.unexpected("boss phase")),
+    };
+    let d = BossPhase::DEFAULT;
+    let mut phase = BossPhase {
+        name: name.clone().into(),
+        hp_below: f.get("hp_below", d.hp_below)?,
+        event: f.get("event", d.event)?,
+        forms: f.get("forms", d.forms)?,
+        weapon: None,
+        max_velocity: f.get("max_velocity", d.max_velocity)?,
+        acceleration_flat: f.get("acceleration_flat", d.acceleration_flat)?,
+        sprite: f.values.get("sprite").map(|value| image(value, ctx, assets)).transpose()?,
+    };
+    // weapon goes last, so it's not left behind when some other field is invalid
+    phase.weapon = match f.values.get("weapon") {
+        Some(Value::Item(id)) => Some(prefabs.spawn(id, world, ctx, assets)?),
+        Some(other) => return Err(other.unexpected("item")),
+        None => None,
+    };
+    Ok(phase)
+}
+
+fn insert_physic(world: &World, e: Entity, f: &Fields) -> Result<()> {
+    let (status, mass) = match f.value("body")? {
+        Value::Body { status, mass } => (*status, *mass),
+        other => return Err(other.unexpected("body")),
+    };
+    let (sensor, membership, shape, hitbox) = match f.value("colliders")? {
+        Value::Collide { sensor, membership, shape, hitbox } => (*sensor, membership, shape, hitbox),
+        other => return Err(other.unexpected("colliders")),
+    };
+    let shapes = dir_or_single(shape, |v| v.shape().map(ShapeHandle::clone))?;
+    let hitbox_shapes = hitbox.as_ref().map(|h| dir_or_single(h, |v| v.shape().map(ShapeHandle::clone))).transpose()?;
+
+    let mut physic_world = world.write_resource::<PhysicWorld>();
+    let body = physic_world.bodies.insert(RigidBodyDesc::new().mass(mass).status(status).build());
+    physic_world.bodies.rigid_body_mut(body).unwrap().set_user_data(Some(Box::new(e)));
+
+    let collider = physic_world.colliders.insert(
+        ColliderDesc::new(first(&shapes).clone())
+            .sensor(sensor)
+            .material(MaterialHandle::new(BasicMaterial::new(0.8, 0.8)))
+            .collision_groups(CollisionGroups::new().with_membership(membership))
+            .build(BodyPartHandle(body, 0)),
+    );
+    physic_world.colliders.get_mut(collider).unwrap().set_user_data(Some(Box::new(e)));
+    let hitbox = hitbox_shapes.map(|shapes| {
+        let mut hitbox_membership = vec![CollisionGroup::Hitbox as usize];
+        hitbox_membership.extend(membership);
+        let hitbox = physic_world.colliders.insert(
+            ColliderDesc::new(first(&shapes).clone())
+                .sensor(true)
+                .collision_groups(CollisionGroups::new().with_membership(&hitbox_membership))
+                .build(BodyPartHandle(body, 0)),
+        );
+        physic_world.colliders.get_mut(hitbox).unwrap().set_user_data(Some(Box::new(e)));
+        (hitbox, shapes)
+    });
+
+    insert(world, e, Physic { body, colliders: PhysicColliders { real: (collider, shapes), hitbox } })
+}
+
+fn create_shared(name: &str, f: &Fields, ctx: &mut dyn AssetContext, assets: &mut AssetManager) -> Result<Shared> {
+    match name {
+        "DropTable" => {
+            let d = DropTable::default();
+            Ok(Shared::DropTable(Arc::new(DropTable {
+                drop_chance: f.get("drop_chance", d.drop_chance)?,
+                any_common: f.get("any_common", d.any_common)?,
+                any_rare: f.get("any_rare", d.any_rare)?,
+                any_legendary: f.get("any_legendary", d.any_legendary)?,
+                ..d
+            })))
+        },
+        "ParticleDef" => Ok(Shared::ParticleDef(Arc::new(ParticleDef {
+            spritesheet: image(f.value("spritesheet")?, ctx, assets)?,
+            sheet_width: f.req("sheet_width")?,
+            sheet_height: f.req("sheet_height")?,
+            time_per_frame: f.req("time_per_frame")?,
+            frames: f.req("frames")?,
+            size: f.req("size")?,
+        }))),
+        "AnimationDef" => Ok(Shared::AnimationDef(Arc::new(AnimationDef {
+            sheet_width: f.req("sheet_width")?,
+            sheet_height: f.req("sheet_height")?,
+            clips: f.req("clips")?,
+        }))),
+        _ => bail!("Unknown shared component"),
+    }
+}
+
+fn image(value: &Value, ctx: &mut dyn AssetContext, assets: &mut AssetManager) -> Result<Arc<ImageAsset>> {
+    match value {
+        Value::Image(path) => Ok(assets.get::<ImageAsset>(path, ctx)?),
+        other => Err(other.unexpected("image")),
+    }
+}
+
+fn dir_or_single<T>(value: &Value, mut f: impl FnMut(&Value) -> Result<T>) -> Result<DirOrSingle<T>> {
+    match value {
+        Value::Single(value) => Ok(DirOrSingle::Single { value: f(value)? }),
+        Value::Directional { north, east, south, west } => {
+            Ok(DirOrSingle::Directional { north: f(north)?, east: f(east)?, south: f(south)?, west: f(west)? })
+        },
+        other => Err(other.unexpected("single or directional")),
+    }
+}
+
+fn first<T>(value: &DirOrSingle<T>) -> &T {
+    match value {
+        DirOrSingle::Single { value } => value,
+        DirOrSingle::Directional { north, .. } => north,
+    }
+}
+
+fn collect_assets(def: &EntityDef) -> Manifest {
+    fn collect(part: &PartValue, assets: &mut Manifest) {
+        match part {
+            PartValue::Image(path) => assets.images.push(path.to_owned()),
+            PartValue::Sound(path) => assets.sounds.push(path.to_owned()),
+            PartValue::AttackPattern(_, fields)
+            | PartValue::ConsumableBehaviour(_, fields)
+            | PartValue::ScriptedForm(fields)
+            | PartValue::BossPhase(_, fields)
+            | PartValue::SubProjectiles(fields) => {
+                if let Some(PartValue::Str(script)) = fields.get("script") {
+                    assets.scripts.push(script.to_owned());
+                }
+                fields.values().for_each(|part| collect(part, assets))
+            },
+            PartValue::Seq(parts) | PartValue::ShapeshifterForms(parts) => {
+                parts.iter().for_each(|part| collect(part, assets))
+            },
+            PartValue::Range(start, end) => {
+                collect(start, assets);
+                collect(end, assets);
+            },
+            PartValue::Directional { north, east, west, south } => {
+                [north, east, west, south].iter().for_each(|part| collect(part, assets))
+            },
+            PartValue::Single { value } => collect(value, assets),
+            _ => {},
+        }
+    }
+
+    let mut assets = Manifest::default();
+    for component in def.components.values().chain(def.shared_components.values()) {
+        component.parts.values().for_each(|part| collect(part, &mut assets));
+    }
+    assets
+}
+
+/// `PartValue` with everything that doesn't need assets resolved
+enum Value {
+    Seq(Vec<Value>),
+    Str(String),
+    Int(i32),
+    Float(f32),
+    Bool(bool),
+    Image(String),
+    Sound(String),
+    Faction(FactionId),
+    CollisionGroup(usize),
+    Rarity(Rarity),
+    DamageType(DamageType),
+    PerDamageType(EnumMap<DamageType, f32>),
+    AttackPattern(Arc<dyn AttackPattern>),
+    ConsumeBehaviour(Arc<dyn ConsumeBehaviour>),
+    ShapeshifterForms(Vec<Arc<dyn ShapeshifterForm>>),
+    SubProjectiles(SubProjectiles),
+    AnimationClip(AnimationClip),
+    BossPhase(String, Fields),
+    // id of the item prefab
+    Item(String),
+    Range(Box<Value>, Box<Value>),
+    Directional { north: Box<Value>, east: Box<Value>, south: Box<Value>, west: Box<Value> },
+    Single(Box<Value>),
+    Size(Size2f),
+    Vec(Vec2f),
+    Body { status: BodyStatus, mass: f32 },
+    Shape(ShapeHandle<f32>),
+    Collide { sensor: bool, membership: Vec<usize>, shape: Box<Value>, hitbox: Option<Box<Value>> },
+}
+
+impl Value {
+    fn resolve(part: &PartValue) -> Result<Self> {
+        Ok(match part {
+            PartValue::Seq(values) => Value::Seq(values.iter().map(Value::resolve).collect::<Result<_>>()?),
+            PartValue::Str(value) => Value::Str(value.to_owned()),
+            PartValue::Numi(value) => Value::Int(*value),
+            PartValue::Numf(value) => Value::Float(*value),
+            PartValue::Bool(value) => Value::Bool(*value),
+            PartValue::Image(path) => Value::Image(path.to_owned()),
+            PartValue::Sound(path) => Value::Sound(path.to_owned()),
+            PartValue::Item(name) => Value::Item(Group::Item.id(name)),
+            PartValue::Faction(faction) => Value::Faction(match faction.to_camel_case().as_str() {
+                "Good" => FactionId::Good,
+                "Pirates" => FactionId::Pirates,
+                "Crabs" => FactionId::Crabs,
+                "Mythical" => FactionId::Mythical,
+                _ => bail!("Unknown faction {:?}", faction),
+            }),
+            PartValue::CollisionGroup(group) => {
+                let group = match group.to_camel_case().as_str() {
+                    "Players" => CollisionGroup::Players,
+                    "Enemies" => CollisionGroup::Enemies,
+                    "Props" => CollisionGroup::Props,
+                    "Projectiles" => CollisionGroup::Projectiles,
+                    "Hitbox" => CollisionGroup::Hitbox,
+                    _ => bail!("Unknown collision group {:?}", group),
+                };
+                Value::CollisionGroup(group as usize)
+            },
+            PartValue::Rarity(rarity) => Value::Rarity(match rarity.to_camel_case().as_str() {
+                "Common" => Rarity::Common,
+                "Rare" => Rarity::Rare,
+                "Legendary" => Rarity::Legendary,
+                _ => bail!("Unknown rarity {:?}", rarity),
+            }),
+            PartValue::DamageType(damage_type) => Value::DamageType(damage_type_by_name(damage_type)?),
+            PartValue::PerDamageType(values) => {
+                let mut map = EnumMap::default();
+                for (damage_type, value) in values {
+                    map[damage_type_by_name(damage_type)?] =
+                        f32::from_value(&Value::resolve(value)?).with_context(|| damage_type.clone())?;
+                }
+                Value::PerDamageType(map)
+            },
+            PartValue::AttackPattern(pattern, fields) => Value::AttackPattern(attack_pattern(pattern, fields)?),
+            PartValue::ConsumableBehaviour(behaviour, fields) => {
+                Value::ConsumeBehaviour(consume_behaviour(behaviour, fields)?)
+            },
+            PartValue::ShapeshifterForms(forms) => Value::ShapeshifterForms(shapeshifter_forms(forms)?),
+            PartValue::ScriptedForm(..) => bail!("Scripted form outside of `shapeshifter_forms`"),
+            PartValue::AnimationClip(name, fields) => {
+                // every field but the name can be omitted
+                let f = Fields { default: true, ..Fields::from_map(&format!("clip {:?}", name), fields)? };
+                let d = AnimationClip::DEFAULT;
+                Value::AnimationClip(AnimationClip {
+                    name: name.to_owned().into(),
+                    first: f.get("first", d.first)?,
+                    frames: f.get("frames", d.frames)?,
+                    time_per_frame: f.get("time_per_frame", d.time_per_frame)?,
+                    looping: f.get("looping", d.looping)?,
+                    next: f.get("next", d.next)?,
+                })
+            },
+            PartValue::BossPhase(name, fields) => {
+                // every field but the name can be omitted, weapon needs the world to be spawned
+                let f = Fields { default: true, ..Fields::from_map(&format!("phase {:?}", name), fields)? };
+                Value::BossPhase(name.to_owned(), f)
+            },
+            PartValue::SubProjectiles(fields) => {
+                let f = Fields::from_map("sub projectiles", fields)?;
+                let d = SubProjectiles::DEFAULT;
+                Value::SubProjectiles(SubProjectiles {
+                    count: f.get("count", d.count)?,
+                    spread: f.get("spread", d.spread)?,
+                    speed: f.get("speed", d.speed)?,
+                    distance: f.get("distance", d.distance)?,
+                    damage_multiplier: f.get("damage_multiplier", d.damage_multiplier)?,
+                })
+            },
+            PartValue::Range(start, end) => {
+                Value::Range(Box::new(Value::resolve(start)?), Box::new(Value::resolve(end)?))
+            },
+            PartValue::Directional { north, east, south, west } => Value::Directional {
+                north: Box::new(Value::resolve(north)?),
+                east: Box::new(Value::resolve(east)?),
+                south: Box::new(Value::resolve(south)?),
+                west: Box::new(Value::resolve(west)?),
+            },
+            PartValue::Single { value } => Value::Single(Box::new(Value::resolve(value)?)),
+            PartValue::Size { width, height } => Value::Size(Size2f::new(*width, *height)),
+            PartValue::Vec { x, y } => Value::Vec(Vec2f::new(*x, *y)),
+            PartValue::Body { status, mass } => Value::Body {
+                status: match status.to_camel_case().as_str() {
+                    "Dynamic" => BodyStatus::Dynamic,
+                    "Static" => BodyStatus::Static,
+                    "Kinematic" => BodyStatus::Kinematic,
+                    "Disabled" => BodyStatus::Disabled,
+                    _ => bail!("Unknown body status {:?}", status),
+                },
+                mass: *mass,
+            },
+            PartValue::Box { x, y, width, height, .. } => {
+                let (x, y, width, height) = (*x, *y, *width, *height);
+                let points = [
+                    Point2::new(x, y),
+                    Point2::new(x + width, y),
+                    Point2::new(x + width, y + height),
+                    Point2::new(x, y + height),
+                ];
+                let polygon = ConvexPolygon::try_from_points(&points).ok_or_else(|| anyhow!("Invalid box shape"))?;
+                Value::Shape(ShapeHandle::new(polygon))
+            },
+            PartValue::Collide { sensor, collision_membership, shape, hitbox } => Value::Collide {
+                sensor: *sensor,
+                membership: collision_membership
+                    .iter()
+                    .map(|group| match Value::resolve(group)? {
+                        Value::CollisionGroup(group) => Ok(group),
+                        other => Err(other.unexpected("collision group")),
+                    })
+                    .collect::<Result<_>>()?,
+                shape: Box::new(Value::resolve(shape)?),
+                hitbox: hitbox.as_ref().map(|hitbox| Value::resolve(hitbox).map(Box::new)).transpose()?,
+            },
+        })
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Value::Seq(..) => "sequence",
+            Value::Str(..) => "string",
+            Value::Int(..) => "integer",
+            Value::Float(..) => "float",
+            Value::Bool(..) => "bool",
+            Value::Image(..) => "image",
+            Value::Sound(..) => "sound",
+            Value::Faction(..) => "faction",
+            Value::CollisionGroup(..) => "collision group",
+            Value::Rarity(..) => "rarity",
+            Value::DamageType(..) => "damage type",
+            Value::PerDamageType(..) => "map of damage types",
+            Value::AttackPattern(..) => "attack pattern",
+            Value::ConsumeBehaviour(..) => "consumable behaviour",
+            Value::ShapeshifterForms(..) => "shapeshifter forms",
+            Value::SubProjectiles(..) => "sub projectiles",
+            Value::AnimationClip(..) => "animation clip",
+            Value::BossPhase(..) => "boss phase",
+            Value::Item(..) => "item",
+            Value::Range(..) => "range",
+            Value::Directional { .. } => "directional",
+            Value::Single(..) => "single",
+            Value::Size(..) => "size",
+            Value::Vec(..) => "vector",
+            Value::Body { .. } => "body",
+            Value::Shape(..) => "shape",
+            Value::Collide { .. } => "colliders",
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> anyhow::Error { anyhow!("Expected {}, got {}", expected, self.kind()) }
+
+    fn shape(&self) -> Result<&ShapeHandle<f32>> {
+        match self {
+            Value::Shape(shape) => Ok(shape),
+            other => Err(other.unexpected("box shape")),
+        }
+    }
+}
+
+fn damage_type_by_name(name: &str) -> Result<DamageType> {
+    Ok(match name.to_camel_case().as_str() {
+        "Physical" => DamageType::Physical,
+        "Impact" => DamageType::Impact,
+        "Lightning" => DamageType::Lightning,
+        "Fire" => DamageType::Fire,
+        _ => bail!("Unknown damage type {:?}", name),
+    })
+}
+
+fn attack_pattern(pattern: &str, fields: &Map<String, PartValue>) -> Result<Arc<dyn AttackPattern>> {
+    let f = Fields::from_map(&format!("attack pattern {:?}", pattern), fields)?;
+    Ok(match pattern.to_camel_case().as_str() {
+        "Generic" => {
+            let d = attack::Generic::DEFAULT;
+            Arc::new(attack::Generic {
+                description: f.get("description", d.description)?,
+                projectile: f.get("projectile", d.projectile)?,
+                projectile_size: f.get("projectile_size", d.projectile_size)?,
+                rotate_projectile: f.get("rotate_projectile", d.rotate_projectile)?,
+                damage_type: f.get("damage_type", d.damage_type)?,
+                count: f.get("count", d.count)?,
+                spread: f.get("spread", d.spread)?,
+                speed: f.get("speed", d.speed)?,
+                distance: f.get("distance", d.distance)?,
+                recoil: f.get("recoil", d.recoil)?,
+                knockback: f.get("knockback", d.knockback)?,
+                pierce: f.get("pierce", d.pierce)?,
+                on_hit: f.get("on_hit", d.on_hit)?,
+                on_end: f.get("on_end", d.on_end)?,
+            })
+        },
+        "Scripted" => Arc::new(attack::Scripted { description: f.req("description")?, script: f.req("script")? }),
+        "Ram" => Arc::new(attack::Ram { power: f.req("power")? }),
+        "SelfDestruct" => Arc::new(attack::SelfDestruct),
+        "Lightning" => Arc::new(attack::Lightning),
+        _ => bail!("Unknown attack pattern {:?}", pattern),
+    })
+}
+
+fn consume_behaviour(behaviour: &str, fields: &Map<String, PartValue>) -> Result<Arc<dyn ConsumeBehaviour>> {
+    match behaviour.to_camel_case().as_str() {
+        "Scripted" => {
+            let f = Fields::from_map("scripted consumable", fields)?;
+            Ok(Arc::new(item::Scripted {
+                description: f.req("description")?,
+                icon: f.req("icon")?,
+                duration: f.req("duration")?,
+                script: f.req("script")?,
+            }))
+        },
+        name => item::behaviour(name).ok_or_else(|| anyhow!("Unknown consumable behaviour {:?}", behaviour)),
+    }
+}
+
+fn shapeshifter_forms(forms: &[PartValue]) -> Result<Vec<Arc<dyn ShapeshifterForm>>> {
+    forms
+        .iter()
+        .map(|form| match form {
+            PartValue::Str(name) => entity::form(name).ok_or_else(|| anyhow!("Unknown shapeshifter form {:?}", name)),
+            PartValue::ScriptedForm(fields) => {
+                let f = Fields::from_map("scripted form", fields)?;
+                let form: Arc<dyn ShapeshifterForm> =
+                    Arc::new(entity::ScriptedForm { script: f.req("script")?, time: f.req("time")? });
+                Ok(form)
+            },
+            _ => bail!("Shapeshifter form should be either a name or a script"),
+        })
+        .collect()
+}
+
+/// Resolved parts of a component or any other struct described in yaml.
+/// Just like with codegen, fields can be omitted only with `__default: true`.
+struct Fields {
+    owner: String,
+    default: bool,
+    values: Map<String, Value>,
+}
+
+impl Fields {
+    fn from_component(name: &str, def: &ComponentDef) -> Result<Self> {
+        let values = def
+            .parts
+            .iter()
+            .map(|(key, part)| Ok((key.to_owned(), Value::resolve(part).with_context(|| format!("{}.{}", name, key))?)))
+            .collect::<Result<_>>()?;
+        Ok(Self { owner: name.to_owned(), default: def.default, values })
+    }
+
+    fn from_map(owner: &str, fields: &Map<String, PartValue>) -> Result<Self> {
+        let default = matches!(fields.get("__default"), Some(PartValue::Bool(true)));
+        let values = fields
+            .iter()
+            .filter(|(key, _)| *key != "__default")
+            .map(|(key, part)| {
+                Ok((key.to_owned(), Value::resolve(part).with_context(|| format!("{}.{}", owner, key))?))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { owner: owner.to_owned(), default, values })
+    }
+
+    fn missing(&self, key: &str) -> anyhow::Error {
+        anyhow!("{} has no {:?} field (add `__default: true` to omit it)", self.owner, key)
+    }
+
+    fn value(&self, key: &str) -> Result<&Value> { self.values.get(key).ok_or_else(|| self.missing(key)) }
+
+    /// Field which is required even with `__default: true`
+    fn req<T: FromValue>(&self, key: &str) -> Result<T> {
+        T::from_value(self.value(key)?).with_context(|| format!("{}.{}", self.owner, key))
+    }
+
+    /// Field which falls back to `default` if it's allowed to be omitted
+    fn get<T: FromValue>(&self, key: &str, default: T) -> Result<T> {
+        match self.values.get(key) {
+            Some(value) => T::from_value(value).with_context(|| format!("{}.{}", self.owner, key)),
+            None if self.default => Ok(default),
+            None => Err(self.missing(key)),
+        }
+    }
+}
+
+trait FromValue: Sized {
+    fn from_value(value: &Value) -> Result<Self>;
+}
+
+macro_rules! from_value {
+    ($($type:ty, $expected:expr => $($pattern:pat => $result:expr),+;)+) => {$(
+        impl FromValue for $type {
+            fn from_value(value: &Value) -> Result<Self> {
+                match value {
+                    $($pattern => Ok($result),)+
+                    other => Err(other.unexpected($expected)),
+                }
+            }
+        }
+    )+};
+}
+from_value! {
+    f32, "number" => Value::Float(v) => *v, Value::Int(v) => *v as f32;
+    Option<f32>, "number" => Value::Float(v) => Some(*v), Value::Int(v) => Some(*v as f32);
+    bool, "bool" => Value::Bool(v) => *v;
+    DefStr, "string" => Value::Str(v) => v.clone().into();
+    Option<DefStr>, "sound" => Value::Sound(v) => Some(v.clone().into()), Value::Str(v) => Some(v.clone().into());
+    Size2f, "size" => Value::Size(v) => *v;
+    Vec2f, "vector" => Value::Vec(v) => *v;
+    FactionId, "faction" => Value::Faction(v) => *v;
+    Rarity, "rarity" => Value::Rarity(v) => *v;
+    DamageType, "damage type" => Value::DamageType(v) => *v;
+    EnumMap<DamageType, f32>, "map of damage types" => Value::PerDamageType(v) => *v;
+    Arc<dyn AttackPattern>, "attack pattern" => Value::AttackPattern(v) => v.clone();
+    Arc<dyn ConsumeBehaviour>, "consumable behaviour" => Value::ConsumeBehaviour(v) => v.clone();
+    Vec<Arc<dyn ShapeshifterForm>>, "shapeshifter forms" => Value::ShapeshifterForms(v) => v.clone();
+    Option<SubProjectiles>, "sub projectiles" => Value::SubProjectiles(v) => Some(*v);
+}
+
+macro_rules! from_int {
+    ($($type:ty),+) => {$(
+        impl FromValue for $type {
+            fn from_value(value: &Value) -> Result<Self> {
+                match value {
+                    Value::Int(v) => Ok(<$type as std::convert::TryFrom<i32>>::try_from(*v)?),
+                    other => Err(other.unexpected("non-negative integer")),
+                }
+            }
+        }
+    )+};
+}
+from_int!(u8, u16, u32, usize);
+
+impl<T: FromValue> FromValue for Option<RangeInclusive<T>> {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Range(start, end) => Ok(Some(T::from_value(start)?..=T::from_value(end)?)),
+            other => Err(other.unexpected("range")),
+        }
+    }
+}
+
+impl FromValue for Vec<AnimationClip> {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Seq(values) => values
+                .iter()
+                .map(|value| match value {
+                    Value::AnimationClip(clip) => Ok(clip.clone()),
+                    other => Err(other.unexpected("animation clip")),
+                })
+                .collect(),
+            other => Err(other.unexpected("sequence of animation clips")),
+        }
+    }
+}
+
+impl FromValue for HashSet<FactionId> {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Seq(values) => values.iter().map(FactionId::from_value).collect(),
+            other => Err(other.unexpected("sequence of factions")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assets::Headless, game};
+    use def::{ATTACK_PATTERNS, CONSUMABLE_BEHAVIOURS, SHAPESHIFTER_FORMS, SHARED_COMPONENTS, TAGS};
+    use specs::Join;
+
+    #[test]
+    fn resources_load_and_spawn() {
+        for group in &GROUPS {
+            for path in files(&Path::new(RESOURCES_PATH).join(group.dir())) {
+                Prefab::load(*group, &path).unwrap_or_else(|err| panic!("{:?}: {:#}", path, err));
+            }
+        }
+
+        let (world, _) = game::build_world(0);
+        let mut assets = AssetManager::default();
+        let prefabs = world.read_resource::<Prefabs>();
+        assert!(!prefabs.arenas.is_empty() && !prefabs.spawn_groups.is_empty());
+        for id in prefabs.prefabs.keys() {
+            prefabs.spawn(id, &world, &mut Headless, &mut assets).unwrap_or_else(|err| panic!("{:#}", err));
+        }
+    }
+
+    #[test]
+    fn failed_spawn_leaves_nothing_behind() {
+        let (mut world, _) = game::build_world(0);
+        let def: EntityDef = serde_yaml::from_str(
+            r#"
+            components:
+                Physic:
+                    body: { status: "dynamic", mass: 1.0 }
+                    colliders:
+                        collision_membership: [ { collision_group: "enemies" } ]
+                        sensor: false
+                        shape: { single: { pos: { x: 0.0, y: 0.0 }, size: { width: 10.0, height: 10.0 } } }
+                Weaponry:
+                    primary: { item: "pirate_tnt" }
+                    __default: true
+            shared_components:
+                AnimationDef:
+                    sheet_width: 2
+            "#,
+        )
+        .unwrap();
+        let prefab = Prefab::new("e_broken".to_owned(), Path::new("broken.yaml"), &def).unwrap();
+        world.write_resource::<Prefabs>().insert(prefab);
+
+        let count = |world: &World| {
+            let physic_world = world.read_resource::<PhysicWorld>();
+            (world.entities().join().count(), physic_world.bodies.iter().count(), physic_world.colliders.iter().count())
+        };
+        let before = count(&world);
+        let mut assets = AssetManager::default();
+        let spawned = world.read_resource::<Prefabs>().spawn("e_broken", &world, &mut Headless, &mut assets);
+        assert!(spawned.is_err());
+        world.maintain();
+        assert_eq!(count(&world), before);
+    }
+
+    #[test]
+    fn listed_components_are_known() {
+        let (world, _) = game::build_world(0);
+        let (prefabs, mut assets) = (Prefabs::default(), AssetManager::default());
+        // every field is missing, so only unknown names are different from other errors
+        let empty = Fields { owner: "test".to_owned(), default: true, values: Map::new() };
+        let is_unknown = |result: Result<()>| result.err().map_or(false, |err| err.to_string().starts_with("Unknown"));
+
+        for (name, _) in COMPONENTS.iter() {
+            let e = world.create_entity_unchecked().build();
+            let result = insert_component(&world, e, name, &empty, &prefabs, &mut Headless, &mut assets);
+            assert!(!is_unknown(result), "{} component is not built", name);
+        }
+        for name in SHARED_COMPONENTS.iter() {
+            let result = create_shared(name, &empty, &mut Headless, &mut assets);
+            assert!(!is_unknown(result.map(drop)), "{} shared component is not built", name);
+        }
+        for tag in TAGS.iter() {
+            let e = world.create_entity_unchecked().build();
+            assert!(!is_unknown(insert_tag(&world, e, tag)), "{} tag is not inserted", tag);
+        }
+    }
+
+    #[test]
+    fn listed_behaviours_are_known() {
+        let is_unknown = |result: Result<()>| result.err().map_or(false, |err| err.to_string().starts_with("Unknown"));
+        for name in ATTACK_PATTERNS.iter() {
+            assert!(!is_unknown(attack_pattern(name, &Map::new()).map(drop)), "{} attack pattern is not built", name);
+        }
+        for name in CONSUMABLE_BEHAVIOURS.iter() {
+            let result = consume_behaviour(name, &Map::new());
+            assert!(!is_unknown(result.map(drop)), "{} consumable behaviour is not built", name);
+        }
+        for name in SHAPESHIFTER_FORMS.iter() {
+            assert!(entity::form(name).is_some(), "{} shapeshifter form is not built", name);
+        }
+    }
+}
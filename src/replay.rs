@@ -0,0 +1,298 @@
+use crate::{
+    controls::Action,
+    ecs::resource::{DeltaTime, Inputs, ItemMove, ItemSlot, PlayerInputs, Slot, MAX_PLAYERS},
+    math::{Point2f, Vec2f},
+};
+use anyhow::{bail, Context as _, Result};
+use std::{
+    collections::HashSet,
+    fs::File,
+    hash::Hash,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+    time::Duration,
+};
+
+/// Every new run is recorded here
+pub const RECORD_PATH: &str = "last_run.replay";
+const MAGIC: &[u8; 4] = b"PBRP";
+// bump this every time the layout of Frame changes
+const VERSION: u8 = 8;
+
+/// Everything gameplay systems know about the players in one tick. Actions are
+/// recorded instead of keys, so replays don't depend on the current controls.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Frame {
+    pub delta: Duration,
+    players: [PlayerFrame; MAX_PLAYERS],
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct PlayerFrame {
+    // bits are indices in `Action::ALL`
+    pressed_actions: u32,
+    clicked_actions: u32,
+    move_stick: Vec2f,
+    aim_stick: Vec2f,
+    item_move: Option<ItemMove>,
+    mouse_aim: Option<Point2f>,
+    mouse_moved: bool,
+}
+
+// actions, sticks, a moved item and the mouse
+const PLAYER_SIZE: usize = 52;
+// bits of the mouse flags
+const HAS_MOUSE: u32 = 1;
+const MOUSE_MOVED: u32 = 1 << 1;
+
+fn to_mask<T: Eq + Hash>(table: &[T], set: &HashSet<T>) -> u32 {
+    table.iter().enumerate().filter(|(_, v)| set.contains(v)).fold(0, |mask, (i, _)| mask | 1 << i)
+}
+
+fn from_mask<T: Eq + Hash + Copy>(table: &[T], mask: u32) -> HashSet<T> {
+    table.iter().enumerate().filter(|(i, _)| mask & 1 << i != 0).map(|(_, v)| *v).collect()
+}
+
+impl Frame {
+    // delta, then every player
+    pub const SIZE: usize = 4 + MAX_PLAYERS * PLAYER_SIZE;
+
+    pub fn capture(delta: &DeltaTime, inputs: &Inputs) -> Self {
+        let mut players = [PlayerFrame::default(); MAX_PLAYERS];
+        for (frame, player) in players.iter_mut().zip(inputs.players.iter()) {
+            *frame = PlayerFrame {
+                pressed_actions: to_mask(&Action::ALL, &player.pressed_actions),
+                clicked_actions: to_mask(&Action::ALL, &player.clicked_actions),
+                move_stick: player.move_stick,
+                aim_stick: player.aim_stick,
+                item_move: player.item_move,
+                mouse_aim: player.mouse_aim,
+                mouse_moved: player.mouse_moved,
+            };
+        }
+        Self { delta: delta.0, players }
+    }
+
+    pub fn apply(&self, delta: &mut DeltaTime, inputs: &mut Inputs) {
+        delta.0 = self.delta;
+        for (frame, player) in self.players.iter().zip(inputs.players.iter_mut()) {
+            *player = PlayerInputs {
+                pressed_actions: from_mask(&Action::ALL, frame.pressed_actions),
+                clicked_actions: from_mask(&Action::ALL, frame.clicked_actions),
+                move_stick: frame.move_stick,
+                aim_stick: frame.aim_stick,
+                item_move: frame.item_move,
+                mouse_aim: frame.mouse_aim,
+                mouse_moved: frame.mouse_moved,
+            };
+        }
+    }
+
+    /// Take inputs of `player` from another frame, along with their mouse
+    pub fn merge(&mut self, other: &Frame, player: usize) { self.players[player] = other.players[player]; }
+
+    /// Keep clicks of an earlier frame which is not simulated on its own
+    pub fn keep_clicks(&mut self, earlier: &Frame) {
+        for (player, earlier) in self.players.iter_mut().zip(earlier.players.iter()) {
+            player.clicked_actions |= earlier.clicked_actions;
+            player.item_move = player.item_move.or(earlier.item_move);
+            player.mouse_moved |= earlier.mouse_moved;
+        }
+    }
+
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(&(self.delta.as_micros() as u32).to_le_bytes())?;
+        for player in &self.players {
+            out.write_all(&player.pressed_actions.to_le_bytes())?;
+            out.write_all(&player.clicked_actions.to_le_bytes())?;
+            let (m, a) = (player.move_stick, player.aim_stick);
+            for value in &[m.x, m.y, a.x, a.y] {
+                out.write_all(&value.to_le_bytes())?;
+            }
+            let (from, to) = match player.item_move {
+                Some(ItemMove { from, to }) => ((from.owner, Some(from.slot)), (to.owner, Some(to.slot))),
+                None => ((0, None), (0, None)),
+            };
+            for (owner, slot) in &[from, to] {
+                out.write_all(&owner.to_le_bytes())?;
+                out.write_all(&Slot::to_bits(*slot).to_le_bytes())?;
+            }
+            let flags = if player.mouse_aim.is_some() { HAS_MOUSE } else { 0 }
+                | if player.mouse_moved { MOUSE_MOVED } else { 0 };
+            out.write_all(&flags.to_le_bytes())?;
+            let aim = player.mouse_aim.unwrap_or_else(Point2f::origin);
+            for value in &[aim.x, aim.y] {
+                out.write_all(&value.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read(input: &mut impl Read) -> io::Result<Self> {
+        let mut buf = [0u8; Self::SIZE];
+        input.read_exact(&mut buf)?;
+        let u32_at = |i: usize| u32::from_le_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+        let vec_at = |i: usize| Vec2f::new(f32::from_bits(u32_at(i)), f32::from_bits(u32_at(i + 4)));
+        let mut players = [PlayerFrame::default(); MAX_PLAYERS];
+        let item_slot_at = |i: usize| Slot::from_bits(u32_at(i + 4)).map(|slot| ItemSlot { owner: u32_at(i), slot });
+        for (i, player) in players.iter_mut().enumerate() {
+            let at = 4 + i * PLAYER_SIZE;
+            let flags = u32_at(at + 40);
+            *player = PlayerFrame {
+                pressed_actions: u32_at(at),
+                clicked_actions: u32_at(at + 4),
+                move_stick: vec_at(at + 8),
+                aim_stick: vec_at(at + 16),
+                item_move: match (item_slot_at(at + 24), item_slot_at(at + 32)) {
+                    (Some(from), Some(to)) => Some(ItemMove { from, to }),
+                    _ => None,
+                },
+                mouse_aim: if flags & HAS_MOUSE != 0 { Some(vec_at(at + 44).to_point()) } else { None },
+                mouse_moved: flags & MOUSE_MOVED != 0,
+            };
+        }
+        Ok(Self { delta: Duration::from_micros(u32_at(0) as u64), players })
+    }
+}
+
+/// Writes frames of the current run into a replay file
+pub struct Recorder {
+    out: BufWriter<File>,
+}
+impl Recorder {
+    pub fn create(path: impl AsRef<Path>, seed: u64, players: usize, explore: bool) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path).with_context(|| format!("Can't create {:?}", path))?;
+        let mut out = BufWriter::new(file);
+        out.write_all(MAGIC)?;
+        out.write_all(&[VERSION])?;
+        out.write_all(&seed.to_le_bytes())?;
+        out.write_all(&[players as u8, explore as u8])?;
+        Ok(Self { out })
+    }
+
+    pub fn record(&mut self, frame: &Frame) -> Result<()> { Ok(frame.write(&mut self.out)?) }
+}
+
+/// Reads frames of a previously recorded run
+pub struct Replay {
+    pub seed: u64,
+    pub players: usize,
+    /// Whether the run is in the open world rather than arenas
+    pub explore: bool,
+    input: BufReader<File>,
+}
+impl Replay {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("Can't open {:?}", path))?;
+        let mut input = BufReader::new(file);
+        let mut header = [0u8; 15];
+        input.read_exact(&mut header).with_context(|| format!("{:?} is not a replay file", path))?;
+        if &header[..4] != MAGIC {
+            bail!("{:?} is not a replay file", path);
+        }
+        if header[4] != VERSION {
+            bail!("Replay version {} is not supported (expected {})", header[4], VERSION);
+        }
+        let players = header[13] as usize;
+        if players == 0 || players > MAX_PLAYERS {
+            bail!("Replay has {} players, which is not supported", players);
+        }
+        let explore = match header[14] {
+            0 => false,
+            1 => true,
+            mode => bail!("Replay has unknown mode {}", mode),
+        };
+        let mut seed = [0u8; 8];
+        seed.copy_from_slice(&header[5..13]);
+        Ok(Self { seed: u64::from_le_bytes(seed), players, explore, input })
+    }
+
+    /// Next recorded frame, or None if the replay is over
+    pub fn next_frame(&mut self) -> Option<Frame> {
+        match Frame::read(&mut self.input) {
+            Ok(frame) => Some(frame),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => None,
+            Err(err) => {
+                log::error!("Unable to read replay frame: {}", err);
+                None
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame() -> Frame {
+        let mut inputs = Inputs::default();
+        inputs.players[0].mouse_aim = Some(Point2f::new(300.0, 40.25));
+        inputs.players[0].mouse_moved = true;
+        inputs.players[0].pressed_actions.insert(Action::ALL[0]);
+        inputs.players[0].clicked_actions.insert(Action::ALL[Action::ALL.len() - 1]);
+        inputs.players[0].item_move = Some(ItemMove {
+            from: ItemSlot { owner: 7, slot: Slot::Inventory(12) },
+            to: ItemSlot { owner: 7, slot: Slot::Secondary },
+        });
+        inputs.players[1].move_stick = Vec2f::new(0.5, -1.0);
+        inputs.players[1].aim_stick = Vec2f::new(-0.25, 0.75);
+        Frame::capture(&DeltaTime(Duration::from_micros(16_667)), &inputs)
+    }
+
+    #[test]
+    fn frame_round_trip() {
+        let mut out = vec![];
+        frame().write(&mut out).unwrap();
+        assert_eq!(out.len(), Frame::SIZE);
+        assert_eq!(Frame::read(&mut out.as_slice()).unwrap(), frame());
+        assert!(Frame::read(&mut &out[1..]).is_err());
+    }
+
+    #[test]
+    fn merge_takes_the_mouse_along() {
+        let mut inputs = Inputs::default();
+        inputs.players[1].mouse_aim = Some(Point2f::new(-10.0, 20.0));
+        let remote = Frame::capture(&DeltaTime(Duration::from_micros(16_667)), &inputs);
+
+        let mut merged = frame();
+        merged.merge(&remote, 1);
+        merged.apply(&mut DeltaTime(Duration::default()), &mut inputs);
+        assert_eq!(inputs.players[0].mouse_aim, Some(Point2f::new(300.0, 40.25)));
+        assert_eq!(inputs.players[1].mouse_aim, Some(Point2f::new(-10.0, 20.0)));
+        assert_eq!(inputs.players[1].move_stick, Vec2f::zero());
+    }
+
+    #[test]
+    fn slots_round_trip() {
+        let slots = [None, Some(Slot::Inventory(0)), Some(Slot::Hotbar(3)), Some(Slot::Primary), Some(Slot::Secondary)];
+        for slot in &slots {
+            assert_eq!(Slot::from_bits(Slot::to_bits(*slot)), *slot);
+        }
+    }
+
+    #[test]
+    fn recorded_run_round_trip() {
+        let path = std::env::temp_dir().join(format!("planes-of-booty-{}.replay", std::process::id()));
+        let mut recorder = Recorder::create(&path, 42, 2, true).unwrap();
+        for _ in 0..3 {
+            recorder.record(&frame()).unwrap();
+        }
+        drop(recorder);
+
+        let mut replay = Replay::open(&path).unwrap();
+        assert_eq!((replay.seed, replay.players, replay.explore), (42, 2, true));
+        for _ in 0..3 {
+            assert_eq!(replay.next_frame(), Some(frame()));
+        }
+        assert_eq!(replay.next_frame(), None);
+
+        // replays of other versions are rejected
+        let mut content = std::fs::read(&path).unwrap();
+        content[4] = VERSION - 1;
+        std::fs::write(&path, content).unwrap();
+        assert!(Replay::open(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
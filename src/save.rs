@@ -0,0 +1,361 @@
+use crate::{
+    arena,
+    assets::{AssetContext, AssetManager},
+    ecs::{
+        component::*,
+        resource::{Arena, PlayerCount, RandomGenerator, MAX_PLAYERS},
+        tag,
+    },
+    prefab::Prefabs,
+};
+use anyhow::{anyhow, bail, Context as _, Result};
+use serde::{Deserialize, Serialize};
+use specs::{Entity, Join, World, WorldExt};
+use std::{fs, path::Path};
+
+pub const SAVE_PATH: &str = "save.yaml";
+// bump this every time the layout of SaveData changes
+const VERSION: u32 = 5;
+
+#[derive(Serialize, Deserialize)]
+pub struct SaveData {
+    pub version: u32,
+    // seed of the run, so a continued run is as reproducible as a new one
+    pub seed: u64,
+    pub arena: ArenaSave,
+    // players the run was started with, sunk ones are not in `players`
+    pub player_count: usize,
+    pub players: Vec<PlayerSave>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ArenaSave {
+    pub difficulty: f32,
+    pub level: u32,
+    pub current: Option<ArenaKindSave>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum ArenaKindSave {
+    // name of the arena
+    Static(String),
+    Generated(u64),
+    Open(u64),
+    Boss(u64),
+}
+impl From<arena::Kind> for ArenaKindSave {
+    fn from(kind: arena::Kind) -> Self {
+        match kind {
+            arena::Kind::Static(id) => ArenaKindSave::Static(id.name().to_owned()),
+            arena::Kind::Generated { seed } => ArenaKindSave::Generated(seed),
+            arena::Kind::Open { seed } => ArenaKindSave::Open(seed),
+            arena::Kind::Boss { seed } => ArenaKindSave::Boss(seed),
+        }
+    }
+}
+impl ArenaKindSave {
+    fn kind(&self) -> Option<arena::Kind> {
+        match self {
+            ArenaKindSave::Static(name) => arena::ID::from_name(name).map(arena::Kind::Static),
+            ArenaKindSave::Generated(seed) => Some(arena::Kind::Generated { seed: *seed }),
+            ArenaKindSave::Open(seed) => Some(arena::Kind::Open { seed: *seed }),
+            ArenaKindSave::Boss(seed) => Some(arena::Kind::Boss { seed: *seed }),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PlayerSave {
+    // `PlayerIndex` of the ship
+    pub index: usize,
+    pub hp: u32,
+    pub max_hp: u32,
+    pub inventory: Vec<ItemSave>,
+    pub hotbar: Vec<Option<ItemSave>>,
+    pub primary: Option<ItemSave>,
+    pub secondary: Option<ItemSave>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ItemSave {
+    // Reflection id of the item
+    pub id: String,
+    pub count: Option<u32>,
+    pub weapon: Option<WeaponSave>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WeaponSave {
+    pub clip_size: u8,
+    pub clip: u8,
+    pub reloading_time: f32,
+    pub cooldown_time: f32,
+    pub damage: u32,
+    pub accuracy: f32,
+    pub passive_reloading: bool,
+}
+
+// Only used to check version before parsing the whole file
+#[derive(Deserialize)]
+struct Versioned {
+    version: u32,
+}
+
+pub fn exists() -> bool { Path::new(SAVE_PATH).is_file() }
+
+pub fn write(world: &World) -> Result<()> {
+    let data = SaveData::collect(world).ok_or_else(|| anyhow!("There is no player to save"))?;
+    fs::write(SAVE_PATH, serde_yaml::to_string(&data)?).with_context(|| format!("Can't write {:?}", SAVE_PATH))?;
+    log::info!("Run saved to {:?}", SAVE_PATH);
+    Ok(())
+}
+
+pub fn read() -> Result<SaveData> {
+    let content = fs::read_to_string(SAVE_PATH).with_context(|| format!("Can't read {:?}", SAVE_PATH))?;
+    parse(&content)
+}
+
+fn parse(content: &str) -> Result<SaveData> {
+    let Versioned { version } = serde_yaml::from_str(content)?;
+    if version != VERSION {
+        bail!("Save file version {} is not supported (expected {})", version, VERSION);
+    }
+    let data: SaveData = serde_yaml::from_str(content)?;
+    if data.player_count == 0 || data.player_count > MAX_PLAYERS {
+        bail!("Save file has {} players, which is not supported", data.player_count);
+    }
+    Ok(data)
+}
+
+fn item_save(world: &World, item: Entity) -> Option<ItemSave> {
+    let id = world.read_storage::<Reflection>().get(item)?.id.to_string();
+    let count = world.read_storage::<Stackable>().get(item).map(|s| s.current);
+    let weapon = world.read_storage::<WeaponProperties>().get(item).map(|prop| WeaponSave {
+        clip_size: prop.clip_size,
+        clip: prop.clip,
+        reloading_time: prop.reloading_time,
+        cooldown_time: prop.cooldown_time,
+        damage: prop.damage,
+        accuracy: prop.accuracy,
+        passive_reloading: prop.passive_reloading,
+    });
+    Some(ItemSave { id, count, weapon })
+}
+
+fn spawn_item(save: ItemSave, world: &World, ctx: &mut dyn AssetContext, assets: &mut AssetManager) -> Option<Entity> {
+    // reflection ids are prefab ids, so modded items are restored as well
+    let spawned = world.read_resource::<Prefabs>().spawn(&save.id, world, ctx, assets);
+    let e = match spawned {
+        Ok(e) => e,
+        Err(err) => {
+            log::warn!("Unknown item {:?} in save file, skipping ({:#})", save.id, err);
+            return None;
+        },
+    };
+    if let (Some(count), Some(stack)) = (save.count, world.write_storage::<Stackable>().get_mut(e)) {
+        stack.current = count;
+    }
+    if let Some(weapon) = save.weapon {
+        if let Some(prop) = world.write_storage::<WeaponProperties>().get_mut(e) {
+            prop.clip_size = weapon.clip_size;
+            prop.clip = weapon.clip;
+            prop.reloading_time = weapon.reloading_time;
+            prop.cooldown_time = weapon.cooldown_time;
+            prop.damage = weapon.damage;
+            prop.accuracy = weapon.accuracy;
+            prop.passive_reloading = weapon.passive_reloading;
+        }
+        // keep rolled properties instead of rolling new ones
+        world.write_storage::<RandomizedWeaponProperties>().remove(e);
+    }
+    Some(e)
+}
+
+fn player_save(world: &World, player: Entity, index: usize) -> Option<PlayerSave> {
+    let (hp, max_hp) = world.read_storage::<HealthPool>().get(player).map(|h| (h.hp, h.max_hp))?;
+    let inventory = world
+        .read_storage::<Inventory>()
+        .get(player)
+        .map(|inv| inv.content.iter().filter_map(|i| i.and_then(|i| item_save(world, i))).collect())
+        .unwrap_or_default();
+    let hotbar = world
+        .read_storage::<Hotbar>()
+        .get(player)
+        .map(|hotbar| hotbar.content.iter().map(|i| i.and_then(|i| item_save(world, i))).collect())
+        .unwrap_or_default();
+    let (primary, secondary) = world
+        .read_storage::<Weaponry>()
+        .get(player)
+        .map(|w| (w.primary.and_then(|i| item_save(world, i)), w.secondary.and_then(|i| item_save(world, i))))
+        .unwrap_or((None, None));
+
+    Some(PlayerSave { index, hp, max_hp, inventory, hotbar, primary, secondary })
+}
+
+fn restore_player(save: PlayerSave, player: Entity, world: &World, ctx: &mut dyn AssetContext) {
+    if let Some(hpool) = world.write_storage::<HealthPool>().get_mut(player) {
+        hpool.max_hp = save.max_hp;
+        hpool.hp = save.hp;
+    }
+
+    // get rid of the default equipment
+    let mut old_items: Vec<Entity> = vec![];
+    if let Some(weaponry) = world.write_storage::<Weaponry>().get_mut(player) {
+        old_items.extend(weaponry.primary.take());
+        old_items.extend(weaponry.secondary.take());
+    }
+    if let Some(hotbar) = world.write_storage::<Hotbar>().get_mut(player) {
+        old_items.extend(hotbar.content.iter_mut().filter_map(|i| i.take()));
+    }
+    if let Some(inventory) = world.write_storage::<Inventory>().get_mut(player) {
+        old_items.extend(inventory.content.iter_mut().filter_map(|i| i.take()));
+        inventory.content = Content::default();
+    }
+    for e in old_items {
+        world.entities().delete(e).unwrap();
+    }
+
+    let mut assets = world.write_resource::<AssetManager>();
+    let PlayerSave { inventory, hotbar, primary, secondary, .. } = save;
+    let primary = primary.and_then(|i| spawn_item(i, world, ctx, &mut assets));
+    let secondary = secondary.and_then(|i| spawn_item(i, world, ctx, &mut assets));
+    let hotbar: Vec<ItemBox> =
+        hotbar.into_iter().map(|i| i.and_then(|i| spawn_item(i, world, ctx, &mut assets))).collect();
+    let inventory: Vec<Entity> = inventory.into_iter().filter_map(|i| spawn_item(i, world, ctx, &mut assets)).collect();
+
+    if let Some(weaponry) = world.write_storage::<Weaponry>().get_mut(player) {
+        weaponry.primary = primary;
+        weaponry.secondary = secondary;
+    }
+    if let Some(player_hotbar) = world.write_storage::<Hotbar>().get_mut(player) {
+        for (item_box, item) in player_hotbar.content.iter_mut().zip(hotbar) {
+            *item_box = item;
+        }
+    }
+    if let Some(player_inventory) = world.write_storage::<Inventory>().get_mut(player) {
+        for item in inventory {
+            player_inventory.content.add(world, item);
+        }
+    }
+}
+
+impl SaveData {
+    fn collect(world: &World) -> Option<Self> {
+        let players: Vec<PlayerSave> =
+            (&world.entities(), &world.read_storage::<tag::Player>(), &world.read_storage::<PlayerIndex>())
+                .join()
+                .filter_map(|(e, _, index)| player_save(world, e, index.0))
+                .collect();
+        if players.is_empty() {
+            return None;
+        }
+
+        let arena = world.read_resource::<Arena>();
+        Some(Self {
+            version: VERSION,
+            seed: world.read_resource::<RandomGenerator>().seed,
+            arena: ArenaSave {
+                difficulty: arena.difficulty,
+                level: arena.level,
+                current: arena.current.map(ArenaKindSave::from),
+            },
+            player_count: world.read_resource::<PlayerCount>().0,
+            players,
+        })
+    }
+
+    /// Restore saved run into a freshly created world with already spawned
+    /// ships of `player_count` players. Ships of those who were sunk are removed.
+    pub fn restore(mut self, world: &World, ctx: &mut dyn AssetContext) {
+        {
+            let mut arena = world.write_resource::<Arena>();
+            arena.difficulty = self.arena.difficulty;
+            arena.level = self.arena.level;
+            arena.change_to = self.arena.current.as_ref().and_then(ArenaKindSave::kind);
+        }
+
+        let players: Vec<(Entity, usize)> =
+            (&world.entities(), &world.read_storage::<tag::Player>(), &world.read_storage::<PlayerIndex>())
+                .join()
+                .map(|(e, _, index)| (e, index.0))
+                .collect();
+        for (player, index) in players {
+            match self.players.iter().position(|p| p.index == index) {
+                Some(i) => restore_player(self.players.swap_remove(i), player, world, ctx),
+                None => {
+                    world.write_storage::<tag::PendingDestruction>().insert(player, tag::PendingDestruction).unwrap();
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        assets::Headless,
+        game::{build_world, spawn_players},
+    };
+
+    fn world(players: usize) -> World {
+        let (mut world, _) = build_world(7);
+        spawn_players(&world, &mut Headless, players);
+        world.maintain();
+        world
+    }
+
+    fn hp(world: &World, index: usize) -> Option<u32> {
+        (&world.read_storage::<PlayerIndex>(), &world.read_storage::<HealthPool>())
+            .join()
+            .find(|(i, _)| i.0 == index)
+            .map(|(_, hpool)| hpool.hp)
+    }
+
+    #[test]
+    fn round_trip() {
+        let world = world(2);
+        {
+            let mut arena = world.write_resource::<Arena>();
+            arena.level = 3;
+            arena.difficulty = 1.5;
+            arena.current = Some(arena::Kind::Boss { seed: 99 });
+        }
+        for hpool in (&mut world.write_storage::<HealthPool>()).join() {
+            hpool.hp = 1;
+        }
+        let saved = serde_yaml::to_string(&SaveData::collect(&world).unwrap()).unwrap();
+
+        let mut restored = self::world(2);
+        let data = parse(&saved).unwrap();
+        assert_eq!(data.seed, 7);
+        data.restore(&restored, &mut Headless);
+        restored.maintain();
+        let arena = restored.read_resource::<Arena>();
+        assert_eq!((arena.level, arena.difficulty), (3, 1.5));
+        assert_eq!(arena.change_to, Some(arena::Kind::Boss { seed: 99 }));
+        assert_eq!((hp(&restored, 0), hp(&restored, 1)), (Some(1), Some(1)));
+        drop(arena);
+
+        // restoring doesn't lose or duplicate anything
+        let mut resaved = SaveData::collect(&restored).unwrap();
+        resaved.arena.current = Some(arena::Kind::Boss { seed: 99 }.into());
+        assert_eq!(serde_yaml::to_string(&resaved).unwrap(), saved);
+    }
+
+    #[test]
+    fn sunk_players_are_removed() {
+        let save = SaveData::collect(&world(1)).unwrap();
+        let restored = world(2);
+        save.restore(&restored, &mut Headless);
+        assert_eq!(restored.read_storage::<tag::PendingDestruction>().join().count(), 1);
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let saved = serde_yaml::to_string(&SaveData::collect(&world(1)).unwrap()).unwrap();
+        let outdated = saved.replace(&format!("version: {}", VERSION), "version: 1");
+        assert!(parse(&saved).is_ok());
+        assert!(parse(&outdated).is_err());
+    }
+}
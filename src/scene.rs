@@ -11,9 +11,12 @@ use ggez::{
 pub enum SceneCommand {
     Push(fn(&mut ggez::Context) -> Box<dyn Scene>),
     ReplaceAll(fn(&mut ggez::Context) -> Box<dyn Scene>),
+    /// Same as `ReplaceAll`, for scenes which are built from something
+    /// the previous one hands over (like a read save, an opened replay or a connection)
+    ReplaceAllWith(Box<dyn FnOnce(&mut ggez::Context) -> Box<dyn Scene>>),
 }
 pub trait Scene: EventHandler {
-    fn next_command(&self) -> Option<SceneCommand>;
+    fn next_command(&mut self) -> Option<SceneCommand>;
     fn draw_prev(&self) -> bool;
 }
 
@@ -42,6 +45,10 @@ impl EventHandler for SceneManager {
                     self.stack.clear();
                     func(context)
                 },
+                SceneCommand::ReplaceAllWith(func) => {
+                    self.stack.clear();
+                    func(context)
+                },
             };
             let size = ggez::graphics::window(context).get_inner_size().unwrap();
             new.resize_event(context, size.width as f32, size.height as f32);
@@ -0,0 +1,328 @@
+use crate::{
+    assets::{Asset, AssetContext},
+    attack::{exclude_shooter, ProjectileDef, ScriptedProjectile},
+    ecs::{
+        component::{
+            AnimatedSprite, BossPhases, CollisionGroup, DamageType, DefStr, Faction, FollowTarget, HealthPool,
+            Physic, SharedAnimationDef, ShootTarget, Target, Transform,
+        },
+        resource::{PhysicWorld, RandomGenerator, SpawnItem, SpawnQueue},
+    },
+    math::*,
+    particle,
+    prefab::Group,
+};
+use anyhow::anyhow;
+use log::{debug, error, warn};
+use nphysics2d::{algebra::ForceType, math::Force, object::Body};
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+use rhai::{Dynamic, Engine, EvalAltResult, Map, RegisterFn, Scope, AST, FLOAT, INT};
+use specs::{Entity, World, WorldExt};
+use std::{fs, io::Read, path::Path};
+
+/// Compiled rhai script. Scripts are plain functions named after the hooks
+/// of traits they implement (`attack`, `on_hit`, `update`, `on_begin`, ...),
+/// every hook is called as a method of `ScriptApi`, available as `this`.
+/// Scripts are free to skip hooks they don't care about.
+pub struct ScriptAsset {
+    id: u32,
+    engine: Engine,
+    ast: AST,
+}
+
+impl Asset for ScriptAsset {
+    type Context = dyn AssetContext;
+
+    fn load(key: &str, id: u32, ctx: &mut Self::Context) -> anyhow::Result<Self> {
+        debug!("Loading script asset {:?}", key);
+        let source = match ctx.ggez() {
+            Some(ctx) => {
+                let mut source = String::new();
+                ggez::filesystem::open(ctx, key)?.read_to_string(&mut source)?;
+                source
+            },
+            // same place ggez looks for resources
+            None => fs::read_to_string(Path::new("resources").join(key.trim_start_matches('/')))?,
+        };
+        let engine = new_engine();
+        let ast = engine.compile(&source).map_err(|err| anyhow!("Unable to compile {:?}: {}", key, err))?;
+        Ok(ScriptAsset { id, engine, ast })
+    }
+
+    fn id(&self) -> u32 { self.id }
+}
+
+impl ScriptAsset {
+    /// Call `hook` of the script with `api` as `this`. Returns None if
+    /// the script has no such hook or it failed.
+    pub fn call(&self, hook: &str, api: &mut ScriptApi) -> Option<Dynamic> {
+        let mut this = Dynamic::from(api.clone());
+        let result = self.engine.call_fn_dynamic(&mut Scope::new(), &self.ast, hook, Some(&mut this), Vec::new());
+        if let Some(changed) = this.try_cast::<ScriptApi>() {
+            *api = changed;
+        }
+        match result {
+            Ok(value) => Some(value),
+            Err(err) => match *err {
+                EvalAltResult::ErrorFunctionNotFound(ref signature, _) if signature.starts_with(hook) => None,
+                _ => {
+                    error!("Script hook {:?} failed: {}", hook, err);
+                    None
+                },
+            },
+        }
+    }
+}
+
+/// Things scripts ask the game to do. Not every context can do everything:
+/// weapons can't change sprites, for example. Those are skipped with a warning.
+#[derive(Debug, Clone)]
+pub enum Command {
+    Projectile(ScriptProjectile),
+    Particle(particle::ID, Point2f),
+    // id of the entity prefab, so scripts can spawn modded entities too
+    Spawn(String, Point2f),
+    Heal(u32),
+    Push(Vec2f),
+    Sprite(String, Size2f),
+    // name of the clip of `AnimatedSprite`
+    Animate(String),
+    Follow { keep_distance: f32, follow_distance: f32 },
+    Unfollow,
+    Shoot(f32),
+    StopShooting,
+    // event of `BossPhases` which may start the next phase
+    RaiseEvent(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct ScriptProjectile {
+    pub asset: Option<String>,
+    pub pos: Point2f,
+    pub velocity: Vec2f,
+    pub distance: f32,
+    pub size: Size2f,
+    pub damage: (u32, DamageType),
+    pub rotate_projectile: bool,
+    // projectile calls `on_hit` and `on_end` hooks of the same script
+    pub scripted: bool,
+}
+impl ScriptProjectile {
+    pub fn into_def(self, ignore_groups: Vec<CollisionGroup>, script: DefStr) -> ProjectileDef {
+        ProjectileDef {
+            asset: self.asset,
+            damage: self.damage,
+            rotate_projectile: self.rotate_projectile,
+            velocity: self.velocity,
+            distance: self.distance,
+            pos: self.pos,
+            size: self.size,
+            ignore_groups,
+            behaviour: if self.scripted { Some(Box::new(ScriptedProjectile { script })) } else { None },
+            ..ProjectileDef::default()
+        }
+    }
+}
+
+/// Safe view of the entity a script is running for. Scripts read its
+/// properties and queue commands, which are applied once the hook is done.
+#[derive(Debug, Clone)]
+pub struct ScriptApi {
+    pub pos: Point2f,
+    pub target: Point2f,
+    pub velocity: Vec2f,
+    pub hp: u32,
+    pub max_hp: u32,
+    pub has_target: bool,
+    pub damage: u32,
+    pub time: f32,
+    pub dt: f32,
+    pub clip: String,
+    pub commands: Vec<Command>,
+    rng: StdRng,
+}
+
+impl ScriptApi {
+    /// Seed keeps scripts deterministic: it should come from `RandomGenerator`
+    pub fn new(seed: u64) -> Self {
+        Self {
+            pos: Point2f::zero(),
+            target: Point2f::zero(),
+            velocity: Vec2f::zero(),
+            hp: 0,
+            max_hp: 0,
+            has_target: false,
+            damage: 0,
+            time: 0.0,
+            dt: 0.0,
+            clip: String::new(),
+            commands: vec![],
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Api with components of the given entity
+    pub fn for_entity(e: Entity, world: &World) -> Self {
+        let mut api = Self::new(world.write_resource::<RandomGenerator>().next_u64());
+        if let Some(transform) = world.read_storage::<Transform>().get(e) {
+            api.pos = transform.pos.to_point();
+        }
+        if let Some(hpool) = world.read_storage::<HealthPool>().get(e) {
+            api.hp = hpool.hp;
+            api.max_hp = hpool.max_hp;
+        }
+        api.has_target = world.read_storage::<Target>().get(e).and_then(|t| t.target).is_some();
+        if let Some(sprite) = world.read_storage::<AnimatedSprite>().get(e) {
+            api.clip = sprite.clip.to_string();
+        }
+        api
+    }
+
+    fn projectile(&mut self, def: Map) {
+        let float = |key: &str, default: f32| def.get(key).and_then(to_float).unwrap_or(default);
+        let damage_type = match def.get("damage_type").and_then(|d| d.as_str().ok()) {
+            Some(name) => damage_type_from_name(name).unwrap_or_else(|| {
+                warn!("Unknown damage type {:?} in script, using physical", name);
+                DamageType::Physical
+            }),
+            None => DamageType::Physical,
+        };
+        self.commands.push(Command::Projectile(ScriptProjectile {
+            asset: def.get("sprite").and_then(|s| s.as_str().ok()).map(str::to_owned),
+            pos: Point2f::new(float("x", self.pos.x), float("y", self.pos.y)),
+            velocity: Vec2f::new(float("vx", 0.0), float("vy", 0.0)),
+            distance: float("distance", 0.0),
+            size: Size2f::new(float("width", 10.0), float("height", 10.0)),
+            damage: (float("damage", self.damage as f32) as u32, damage_type),
+            rotate_projectile: def.get("rotate").and_then(|r| r.as_bool().ok()).unwrap_or(false),
+            scripted: def.get("scripted").and_then(|r| r.as_bool().ok()).unwrap_or(false),
+        }));
+    }
+
+    fn particle(&mut self, name: &str, x: FLOAT, y: FLOAT) {
+        match particle::ID::from_name(name) {
+            Some(id) => self.commands.push(Command::Particle(id, Point2f::new(x as f32, y as f32))),
+            None => warn!("Unknown particle {:?} in script", name),
+        }
+    }
+
+    fn spawn(&mut self, name: &str, x: FLOAT, y: FLOAT) {
+        self.commands.push(Command::Spawn(Group::Entity.id(name), Point2f::new(x as f32, y as f32)));
+    }
+}
+
+fn to_float(value: &Dynamic) -> Option<f32> {
+    value.as_float().ok().or_else(|| value.as_int().ok().map(|i| i as FLOAT)).map(|f| f as f32)
+}
+
+fn damage_type_from_name(name: &str) -> Option<DamageType> {
+    match name {
+        "physical" => Some(DamageType::Physical),
+        "impact" => Some(DamageType::Impact),
+        "lightning" => Some(DamageType::Lightning),
+        "fire" => Some(DamageType::Fire),
+        _ => None,
+    }
+}
+
+fn new_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.register_type_with_name::<ScriptApi>("Api");
+    engine.register_get("x", |api: &mut ScriptApi| api.pos.x as FLOAT);
+    engine.register_get("y", |api: &mut ScriptApi| api.pos.y as FLOAT);
+    engine.register_get("target_x", |api: &mut ScriptApi| api.target.x as FLOAT);
+    engine.register_get("target_y", |api: &mut ScriptApi| api.target.y as FLOAT);
+    engine.register_get("vx", |api: &mut ScriptApi| api.velocity.x as FLOAT);
+    engine.register_get("vy", |api: &mut ScriptApi| api.velocity.y as FLOAT);
+    engine.register_get("hp", |api: &mut ScriptApi| api.hp as INT);
+    engine.register_get("max_hp", |api: &mut ScriptApi| api.max_hp as INT);
+    engine.register_get("has_target", |api: &mut ScriptApi| api.has_target);
+    engine.register_get("damage", |api: &mut ScriptApi| api.damage as INT);
+    engine.register_get("time", |api: &mut ScriptApi| api.time as FLOAT);
+    engine.register_get("dt", |api: &mut ScriptApi| api.dt as FLOAT);
+    engine.register_get("clip", |api: &mut ScriptApi| api.clip.clone());
+    engine.register_fn("rand", |api: &mut ScriptApi, from: FLOAT, to: FLOAT| api.rng.gen_range(from, to));
+    engine.register_fn("projectile", ScriptApi::projectile);
+    engine.register_fn("particle", |api: &mut ScriptApi, name: &str, x: FLOAT, y: FLOAT| api.particle(name, x, y));
+    engine.register_fn("spawn", |api: &mut ScriptApi, name: &str, x: FLOAT, y: FLOAT| api.spawn(name, x, y));
+    engine.register_fn("heal", |api: &mut ScriptApi, hp: INT| api.commands.push(Command::Heal(hp.max(0) as u32)));
+    engine.register_fn("push", |api: &mut ScriptApi, x: FLOAT, y: FLOAT| {
+        api.commands.push(Command::Push(Vec2f::new(x as f32, y as f32)))
+    });
+    engine.register_fn("sprite", |api: &mut ScriptApi, path: &str, width: FLOAT, height: FLOAT| {
+        api.commands.push(Command::Sprite(path.to_owned(), Size2f::new(width as f32, height as f32)))
+    });
+    engine.register_fn("animate", |api: &mut ScriptApi, clip: &str| {
+        api.commands.push(Command::Animate(clip.to_owned()))
+    });
+    engine.register_fn("follow", |api: &mut ScriptApi, keep_distance: FLOAT, follow_distance: FLOAT| {
+        api.commands.push(Command::Follow {
+            keep_distance: keep_distance as f32,
+            follow_distance: follow_distance as f32,
+        })
+    });
+    engine.register_fn("unfollow", |api: &mut ScriptApi| api.commands.push(Command::Unfollow));
+    engine.register_fn("shoot", |api: &mut ScriptApi, radius: FLOAT| api.commands.push(Command::Shoot(radius as f32)));
+    engine.register_fn("stop_shooting", |api: &mut ScriptApi| api.commands.push(Command::StopShooting));
+    engine.register_fn("raise", |api: &mut ScriptApi, event: &str| {
+        api.commands.push(Command::RaiseEvent(event.to_owned()))
+    });
+    engine
+}
+
+/// Apply commands of the `script` which was running for the entity `e`.
+/// Projectiles fired this way don't hit allies of `e`.
+pub fn apply(e: Entity, script: &DefStr, commands: Vec<Command>, world: &World) {
+    for command in commands {
+        match command {
+            Command::Projectile(def) => {
+                let faction = world.read_storage::<Faction>().get(e).map(|f| f.id);
+                let def = def.into_def(exclude_shooter(faction.as_ref()), script.clone());
+                world.write_resource::<SpawnQueue>().0.push_back(SpawnItem::Projectile(def));
+            },
+            Command::Particle(id, pos) => {
+                world.write_resource::<SpawnQueue>().0.push_back(SpawnItem::Particle(id, pos))
+            },
+            Command::Spawn(id, pos) => world.write_resource::<SpawnQueue>().0.push_back(SpawnItem::Prefab(id, pos)),
+            Command::Heal(hp) => {
+                if let Some(hpool) = world.write_storage::<HealthPool>().get_mut(e) {
+                    hpool.hp = (hpool.hp + hp).min(hpool.max_hp);
+                }
+            },
+            Command::Push(push) => {
+                if let Some(physic) = world.read_storage::<Physic>().get(e) {
+                    let mut physic_world = world.write_resource::<PhysicWorld>();
+                    if let Some(body) = physic_world.bodies.get_mut(physic.body) {
+                        body.apply_force(0, &Force::linear([push.x, push.y].into()), ForceType::VelocityChange, true);
+                    }
+                }
+            },
+            Command::Follow { keep_distance, follow_distance } => {
+                let follow = FollowTarget { keep_distance, follow_distance };
+                world.write_storage::<FollowTarget>().insert(e, follow).unwrap();
+            },
+            Command::Unfollow => {
+                world.write_storage::<FollowTarget>().remove(e);
+            },
+            Command::Shoot(radius) => {
+                world.write_storage::<ShootTarget>().insert(e, ShootTarget { radius }).unwrap();
+            },
+            Command::StopShooting => {
+                world.write_storage::<ShootTarget>().remove(e);
+            },
+            Command::RaiseEvent(event) => match world.write_storage::<BossPhases>().get_mut(e) {
+                Some(boss) => boss.events.push(event),
+                None => warn!("Only bosses have phase events, skipping {:?}", event),
+            },
+            Command::Animate(name) => {
+                let defs = world.read_storage::<SharedAnimationDef>();
+                let clip = defs.get(e).and_then(|def| def.clip(&name)).map(|clip| clip.name.clone());
+                match (clip, world.write_storage::<AnimatedSprite>().get_mut(e)) {
+                    (Some(clip), Some(sprite)) => sprite.play(clip),
+                    _ => warn!("Entity has no clip {:?} to animate", name),
+                }
+            },
+            Command::Sprite(..) => warn!("Scripts can't change sprites here, skipping {:?}", command),
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use anyhow::{Context as _, Result};
+use ggez::{
+    conf::{FullscreenType, NumSamples, WindowMode, WindowSetup},
+    graphics, Context, GameResult,
+};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// User settings, written by the options window
+pub const SETTINGS_PATH: &str = "settings.yaml";
+
+/// Resolutions offered by the options window, any other one can be set in the file
+pub const RESOLUTIONS: [[u32; 2]; 6] =
+    [[1280, 720], [1366, 768], [1600, 900], [1920, 1080], [2560, 1440], [3840, 2160]];
+pub const MSAA: [u8; 4] = [1, 2, 4, 8];
+pub const UI_SCALES: [f32; 5] = [0.75, 1.0, 1.25, 1.5, 2.0];
+const UI_SCALE_RANGE: std::ops::RangeInclusive<f32> = 0.5..=3.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowKind {
+    Fullscreen,
+    // fullscreen window without changing the video mode
+    Borderless,
+    Windowed,
+}
+impl WindowKind {
+    pub const ALL: [WindowKind; 3] = [WindowKind::Fullscreen, WindowKind::Borderless, WindowKind::Windowed];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WindowKind::Fullscreen => "fullscreen",
+            WindowKind::Borderless => "borderless",
+            WindowKind::Windowed => "windowed",
+        }
+    }
+}
+
+/// Everything which is kept between runs and is not a part of the run itself.
+/// Missing values are taken from the defaults, so old files keep working.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub window_mode: WindowKind,
+    pub resolution: [u32; 2],
+    pub msaa: u8,
+    pub vsync: bool,
+    // applied to the whole ui, not only to fonts
+    pub ui_scale: f32,
+    // same as `--debug`
+    pub debug: bool,
+    // defaults of toggles from the debug menu
+    pub debug_info: bool,
+    pub debug_targeting: bool,
+    pub debug_physic: bool,
+}
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            window_mode: WindowKind::Fullscreen,
+            resolution: [1280, 720],
+            msaa: 4,
+            vsync: true,
+            ui_scale: 1.0,
+            debug: false,
+            debug_info: false,
+            debug_targeting: false,
+            debug_physic: false,
+        }
+    }
+}
+
+impl Settings {
+    /// Settings from the file, or the defaults if there is none
+    pub fn load() -> Self {
+        if !Path::new(SETTINGS_PATH).is_file() {
+            return Self::default();
+        }
+        match Self::read() {
+            Ok(settings) => settings,
+            Err(err) => {
+                log::error!("Unable to load settings, using defaults: {:#}", err);
+                Self::default()
+            },
+        }
+    }
+
+    fn read() -> Result<Self> {
+        let content = fs::read_to_string(SETTINGS_PATH).with_context(|| format!("Can't read {:?}", SETTINGS_PATH))?;
+        let settings: Self =
+            serde_yaml::from_str(&content).with_context(|| format!("Invalid settings {:?}", SETTINGS_PATH))?;
+        Ok(settings.sanitized())
+    }
+
+    pub fn write(&self) -> Result<()> {
+        fs::write(SETTINGS_PATH, serde_yaml::to_string(self)?)
+            .with_context(|| format!("Can't write {:?}", SETTINGS_PATH))?;
+        log::info!("Settings saved to {:?}", SETTINGS_PATH);
+        Ok(())
+    }
+
+    // values which would break the window or the ui are replaced with defaults
+    fn sanitized(mut self) -> Self {
+        let default = Self::default();
+        if !MSAA.contains(&self.msaa) {
+            log::warn!("Unsupported msaa {}, using {}", self.msaa, default.msaa);
+            self.msaa = default.msaa;
+        }
+        if self.resolution[0] == 0 || self.resolution[1] == 0 {
+            log::warn!("Invalid resolution {:?}, using {:?}", self.resolution, default.resolution);
+            self.resolution = default.resolution;
+        }
+        if !UI_SCALE_RANGE.contains(&self.ui_scale) {
+            log::warn!("Invalid ui scale {}, using {}", self.ui_scale, default.ui_scale);
+            self.ui_scale = default.ui_scale;
+        }
+        self
+    }
+
+    /// These settings as they are used in this run
+    pub fn with(&self, overrides: &Overrides) -> Self {
+        Self {
+            window_mode: overrides.window_mode.unwrap_or(self.window_mode),
+            resolution: overrides.resolution.unwrap_or(self.resolution),
+            msaa: overrides.msaa.unwrap_or(self.msaa),
+            vsync: overrides.vsync.unwrap_or(self.vsync),
+            ui_scale: overrides.ui_scale.unwrap_or(self.ui_scale),
+            debug: overrides.debug || self.debug,
+            ..self.clone()
+        }
+    }
+
+    pub fn window_setup(&self) -> WindowSetup {
+        let samples = match self.msaa {
+            1 => NumSamples::One,
+            2 => NumSamples::Two,
+            8 => NumSamples::Eight,
+            _ => NumSamples::Four,
+        };
+        WindowSetup { title: "Planes of Booty".to_owned(), samples, vsync: self.vsync, ..WindowSetup::default() }
+    }
+
+    pub fn window_mode(&self) -> WindowMode {
+        let (fullscreen_type, borderless) = match self.window_mode {
+            WindowKind::Fullscreen => (FullscreenType::True, true),
+            WindowKind::Borderless => (FullscreenType::Desktop, true),
+            WindowKind::Windowed => (FullscreenType::Windowed, false),
+        };
+        let [width, height] = self.resolution;
+        WindowMode {
+            width: width as f32,
+            height: height as f32,
+            fullscreen_type,
+            borderless,
+            ..WindowMode::default()
+        }
+    }
+
+    /// Window mode and resolution can be changed on the fly, msaa and vsync only on start
+    pub fn apply_window(&self, ctx: &mut Context) -> GameResult { graphics::set_mode(ctx, self.window_mode()) }
+}
+
+/// Settings from command line arguments, they win over the file for the whole run
+/// (and are not written into it)
+#[derive(Default, Debug, Clone)]
+pub struct Overrides {
+    window_mode: Option<WindowKind>,
+    resolution: Option<[u32; 2]>,
+    msaa: Option<u8>,
+    vsync: Option<bool>,
+    ui_scale: Option<f32>,
+    debug: bool,
+}
+impl Overrides {
+    pub fn from_args() -> Self {
+        Self {
+            window_mode: arg("--window-mode", |v| WindowKind::ALL.iter().copied().find(|k| k.name() == v)),
+            resolution: arg("--resolution", |v| {
+                let mut parts = v.split('x').map(|n| n.parse().ok());
+                match (parts.next().flatten(), parts.next().flatten(), parts.next()) {
+                    (Some(width), Some(height), None) if width > 0 && height > 0 => Some([width, height]),
+                    _ => None,
+                }
+            }),
+            msaa: arg("--msaa", |v| v.parse().ok().filter(|n| MSAA.contains(n))),
+            vsync: arg("--vsync", |v| v.parse().ok()),
+            ui_scale: arg("--ui-scale", |v| v.parse().ok().filter(|s| UI_SCALE_RANGE.contains(s))),
+            debug: std::env::args().any(|a| a == "--debug"),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.window_mode.is_none()
+            && self.resolution.is_none()
+            && self.msaa.is_none()
+            && self.vsync.is_none()
+            && self.ui_scale.is_none()
+            && !self.debug
+    }
+}
+
+// value of `--name <value>` argument, warns when it can't be parsed
+fn arg<T>(name: &str, parse: impl Fn(&str) -> Option<T>) -> Option<T> {
+    let value = crate::arg_value(name)?;
+    let parsed = parse(&value);
+    if parsed.is_none() {
+        log::warn!("Invalid {} argument ({:?}), using settings", name, value);
+    }
+    parsed
+}
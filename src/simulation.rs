@@ -0,0 +1,71 @@
+use crate::{
+    assets::Headless,
+    ecs::{
+        resource::*,
+        system::{AudioSystem, BossPhaseSystem, ShapeshifterSystem},
+    },
+    game::{build_world, process_spawn_queue, spawn_players},
+    replay::Frame,
+};
+use specs::prelude::*;
+use std::time::Duration;
+
+/// Default fixed timestep of the simulation (60 ticks per second)
+pub const TIMESTEP: Duration = Duration::from_micros(16_667);
+
+/// Gameplay world without any window, graphics or ui. Assets are
+/// loaded headless, and every tick advances time by a fixed timestep,
+/// so the same inputs always produce the same amount of work.
+pub struct Simulation {
+    world: World,
+    dispatcher: Dispatcher<'static, 'static>,
+    timestep: Duration,
+    elapsed: Duration,
+}
+
+impl Simulation {
+    pub fn new(timestep: Duration, seed: u64, players: usize) -> Self {
+        let (mut world, dispatcher) = build_world(seed);
+        world.insert(DeltaTime(timestep));
+        spawn_players(&world, &mut Headless, players);
+        Self { world, dispatcher, timestep, elapsed: Duration::default() }
+    }
+
+    pub fn world(&self) -> &World { &self.world }
+
+    /// Total amount of simulated gameplay time
+    pub fn elapsed(&self) -> Duration { self.elapsed }
+
+    /// Advance the simulation by one tick. Mirrors `Game::update` minus ui.
+    pub fn step(&mut self) {
+        self.world.write_resource::<DeltaTime>().0 = self.timestep;
+        self.tick();
+    }
+
+    /// Advance the simulation by one recorded tick, using its inputs and delta time
+    pub fn replay_step(&mut self, frame: &Frame) {
+        frame.apply(&mut self.world.write_resource::<DeltaTime>(), &mut self.world.write_resource::<Inputs>());
+        self.tick();
+    }
+
+    fn tick(&mut self) {
+        self.elapsed += self.world.read_resource::<DeltaTime>().0;
+        process_spawn_queue(&self.world, &mut Headless);
+        self.world.maintain();
+        self.dispatcher.dispatch(&self.world);
+        BossPhaseSystem(&mut Headless).run_now(&self.world);
+        ShapeshifterSystem(&mut Headless).run_now(&self.world);
+        AudioSystem(&mut Headless).run_now(&self.world);
+        self.world.write_resource::<Inputs>().mouse_scroll = 0.0;
+    }
+
+    pub fn run(&mut self, ticks: u64) {
+        for _ in 0..ticks {
+            self.step();
+        }
+    }
+}
+
+impl Default for Simulation {
+    fn default() -> Self { Self::new(TIMESTEP, crate::seed_from_args(), 1) }
+}
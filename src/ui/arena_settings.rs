@@ -4,6 +4,7 @@ use crate::{
     ecs::{component::*, resource::*, tag},
 };
 use imgui::*;
+use rand::Rng;
 use specs::Join;
 use std::{fs::File, io::prelude::*};
 
@@ -62,9 +63,24 @@ impl<'a> UiBuilder<(&mut UiData<'a>, &mut bool)> for ArenaSettingsWindow {
                 });
                 if ui.button(im_str!("Load"), [300.0, 20.0]) {
                     if let Some(id) = self.selected_arena {
-                        data.arena.change_to = Some(id);
+                        data.arena.change_to = Some(arena::Kind::Static(id));
                     }
                 }
+                if ui.button(im_str!("Generate"), [300.0, 20.0]) {
+                    data.arena.change_to = Some(arena::Kind::Generated { seed: data.rng.gen() });
+                }
+                if ui.button(im_str!("Open world"), [300.0, 20.0]) {
+                    data.arena.change_to = Some(arena::Kind::Open { seed: data.rng.gen() });
+                }
+                if ui.button(im_str!("Boss fight"), [300.0, 20.0]) {
+                    data.arena.change_to = Some(arena::Kind::Boss { seed: data.rng.gen() });
+                }
+                if let Some(arena::Kind::Generated { seed })
+                | Some(arena::Kind::Open { seed })
+                | Some(arena::Kind::Boss { seed }) = data.arena.current
+                {
+                    ui.text(format!("Current arena seed: {}", seed));
+                }
                 ui.separator();
                 if ui.button(im_str!("Save current as level.yaml"), [300.0, 20.0]) {
                     self.write_level_to_file(data).unwrap();
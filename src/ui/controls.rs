@@ -0,0 +1,139 @@
+use super::system::{UiBuilder, UiContext};
+use crate::{
+    centered_text,
+    controls::{conflict_label, Action, Binding, Controls},
+    ecs::resource::*,
+    within_tooltip,
+};
+use imgui::*;
+
+#[derive(Default, Debug)]
+pub struct ControlsWindow {
+    pub is_opened: bool,
+    // whose bindings are shown
+    player: usize,
+    // action waiting for a new binding, with seconds left to press something
+    capturing: Option<(Action, f32)>,
+    // held down when capturing started, so they are not bound right away
+    held: Vec<Binding>,
+}
+impl ControlsWindow {
+    const CAPTURE_TIME: f32 = 5.0;
+
+    fn capture(&mut self, controls: &mut Controls, inputs: &Inputs, dt: f32) {
+        let (action, time_left) = match &mut self.capturing {
+            Some(capturing) => capturing,
+            None => return,
+        };
+        let pressed = Binding::all_pressed(inputs);
+        *time_left -= dt;
+        if let Some(binding) = pressed.iter().find(|b| !self.held.contains(b)) {
+            controls.bind(self.player, *action, *binding);
+            self.capturing = None;
+        } else if *time_left <= 0.0 {
+            self.capturing = None;
+        }
+        self.held = pressed;
+    }
+}
+impl<'a> UiBuilder<&mut UiData<'a>> for ControlsWindow {
+    fn build<'ctx>(&mut self, ui: &mut Ui, _: &mut UiContext<'ctx>, data: &mut UiData<'a>) {
+        if self.is_opened {
+            ui.open_popup(im_str!("controls"))
+        }
+        // before any button, so the click which started capturing is not captured
+        self.capture(&mut data.controls, &data.inputs, ui.io().delta_time);
+
+        let conflicts = data.controls.conflicts();
+        let token = ui.push_style_colors(&[(StyleColor::ModalWindowDimBg, [0.0, 0.0, 0.0, 0.7])]);
+        ui.popup_modal(im_str!("controls"))
+            .title_bar(false)
+            .movable(false)
+            .resizable(false)
+            .always_auto_resize(true)
+            .build(|| {
+                centered_text!(ui; "Controls"; width);
+                ui.spacing();
+                for player in 0..MAX_PLAYERS {
+                    if player > 0 {
+                        ui.same_line(0.0);
+                    }
+                    ui.radio_button(&ImString::new(format!("Player {}", player + 1)), &mut self.player, player);
+                }
+                ui.text_disabled("In co-op the first player has the mouse, the second one has gamepads");
+                ui.spacing();
+                for action in &Action::ALL {
+                    ui.text(action.label());
+                    for (i, binding) in data.controls.bindings(self.player, *action).to_vec().into_iter().enumerate() {
+                        ui.same_line(if i == 0 { 220.0 } else { 0.0 });
+                        let used_by = conflicts
+                            .iter()
+                            .find(|(b, actions)| *b == binding && actions.contains(&(self.player, *action)))
+                            .map(|(_, actions)| actions);
+                        let token = used_by.map(|_| ui.push_style_color(StyleColor::Text, [0.9, 0.3, 0.3, 1.0]));
+                        let label = ImString::new(format!("{}##{}_{}", binding.name(), action.name(), i));
+                        if ui.small_button(&label) {
+                            data.controls.unbind(self.player, *action, binding);
+                        }
+                        if let Some(token) = token {
+                            token.pop(ui);
+                        }
+                        if ui.is_item_hovered() {
+                            within_tooltip!(ui => {
+                                ui.text("Click to unbind");
+                                if let Some(actions) = used_by {
+                                    let labels: Vec<String> =
+                                        actions.iter().map(|(p, action)| conflict_label(*p, action)).collect();
+                                    ui.text_colored([0.9, 0.3, 0.3, 1.0], &format!("Bound to: {}", labels.join(", ")));
+                                }
+                            });
+                        }
+                    }
+                    ui.same_line(if data.controls.bindings(self.player, *action).is_empty() { 220.0 } else { 0.0 });
+                    if ui.small_button(&ImString::new(format!("+##{}", action.name()))) {
+                        self.capturing = Some((*action, Self::CAPTURE_TIME));
+                        self.held = Binding::all_pressed(&data.inputs);
+                    }
+                }
+
+                ui.spacing();
+                ui.separator();
+                if conflicts.is_empty() {
+                    ui.text_disabled("Every binding is used by a single action");
+                }
+                for (binding, actions) in &conflicts {
+                    let labels: Vec<String> =
+                        actions.iter().map(|(player, action)| conflict_label(*player, action)).collect();
+                    ui.text_colored(
+                        [0.9, 0.3, 0.3, 1.0],
+                        &format!("{} is bound to several actions: {}", binding.name(), labels.join(", ")),
+                    );
+                }
+                ui.spacing();
+                if ui.button(im_str!("Reset to defaults"), [300.0, 50.0]) {
+                    *data.controls = Controls::default();
+                }
+                ui.same_line(0.0);
+                if ui.button(im_str!("Save and close"), [300.0, 50.0]) {
+                    if let Err(err) = data.controls.write() {
+                        log::error!("Unable to save controls: {:#}", err);
+                    }
+                    ui.close_current_popup();
+                    self.is_opened = false;
+                }
+
+                // nothing to click in there, every click is a binding
+                if let Some((action, time_left)) = self.capturing {
+                    ui.open_popup(im_str!("capture"));
+                    ui.popup_modal(im_str!("capture")).title_bar(false).always_auto_resize(true).build(|| {
+                        ui.text(format!("Press a key, a button or scroll the wheel for \"{}\"", action.label()));
+                        ui.text_disabled(format!("Cancelled in {:.0}s", time_left.ceil()));
+                    });
+                } else {
+                    // closed by `capture` between frames
+                    ui.popup_modal(im_str!("capture")).build(|| ui.close_current_popup());
+                }
+            });
+        token.pop(ui);
+    }
+}
@@ -73,6 +73,14 @@ impl<'a> UiBuilder<(&mut UiData<'a>, &mut bool)> for DebugWindow {
                         ));
                     }
                 }
+                ui.columns(1, im_str!("assets_col"), false);
+                ui.separator();
+
+                let stats = data.assets.stats();
+                ui.text(im_str!("Assets:"));
+                ui.text(&format!("{} cached, {} evicted", stats.cached, stats.evicted));
+                ui.text(&format!("{} loaded ({} lazily) in {:.2?}", stats.loaded, stats.lazy, stats.load_time));
+                ui.text(&format!("{} cache hits, {} failed", stats.hits, stats.failed));
             });
     }
 }
@@ -19,9 +19,13 @@ impl<'a> UiBuilder<&mut UiData<'a>> for GameOverWindow {
             .always_auto_resize(true)
             .build(|| {
                 ui.set_cursor_pos([80.0, 0.0]);
-                let game_over = data.assets.get::<ImageAsset>("/sprites/ui/game-over.png", ctx.as_mut()).unwrap();
-                Image::new(ctx.get_texture_id_for(&game_over), [540.0, 380.0]).build(ui);
-                ui.dummy([0.0, 100.0]);
+                match data.assets.get::<ImageAsset>("/sprites/ui/game-over.png", ctx.as_mut()) {
+                    Ok(game_over) => Image::new(ctx.get_texture_id_for(&game_over), [540.0, 380.0]).build(ui),
+                    Err(_) => ui.dummy([540.0, 380.0]),
+                }
+                ui.dummy([0.0, 40.0]);
+                centered_text!(ui; &format!("Seed: {}", data.rng.seed); width);
+                ui.dummy([0.0, 40.0]);
 
                 if ui.button(im_str!("Restart"), [300.0, 50.0]) {
                     data.scene_controls.queue_restart = true;
@@ -3,102 +3,139 @@ use crate::{
     assets::*,
     centered_text,
     ecs::{component::*, resource::UiData},
-    within_window,
+    within_group, within_tooltip, within_window,
 };
 use imgui::*;
-use specs::Join;
+use specs::{Entity, Join};
 
 #[derive(Default, Debug)]
 pub struct Hud;
 impl<'a> UiBuilder<&mut UiData<'a>> for Hud {
     fn build<'ctx>(&mut self, ui: &mut Ui, ctx: &mut UiContext<'ctx>, data: &mut UiData<'a>) {
-        if let Some((hpool, _)) = (&data.hpools, &data.player_tag).join().next() {
-            within_window!(Window::new(im_str!("HealthPool"))
-                .position([0.0, ui.io().display_size[1]], Condition::Always)
-                .position_pivot([0.0, 1.0])
+        // every player gets their own part of the bottom of the screen
+        let players: Vec<(Entity, usize)> =
+            (&data.entities, &data.player_tag, &data.player_indices).join().map(|(e, _, i)| (e, i.0)).collect();
+        let [display_width, height] = ui.io().display_size;
+        let width = display_width / data.player_count.0 as f32;
+
+        // the whole screen fights the same boss
+        if let Some((boss, hpool)) = (&data.bosses, &data.hpools).join().next() {
+            within_window!(Window::new(im_str!("BossHealth"))
+                .position([display_width * 0.5, 10.0], Condition::Always)
+                .position_pivot([0.5, 0.0])
                 .resizable(false)
                 .movable(false)
                 .collapsible(false)
                 .title_bar(false)
                 .focus_on_appearing(false)
-                .size([90.0, 125.0], Condition::Always), &ui => {
-                    ui.set_cursor_pos([15.0, 10.0]);
-                    let hp_base = data.assets.get::<ImageAsset>("/sprites/ui/hp-base.png", ctx.as_mut()).unwrap();
-                    let hp_fill = data.assets.get::<ImageAsset>("/sprites/ui/hp-fill.png", ctx.as_mut()).unwrap();
-
-                    let pos = ui.cursor_pos();
-                    Image::new(ctx.get_texture_id_for(&hp_base), [60.0, 60.0]).build(ui);
-
-                    let hp_lack = 1.0 - (hpool.hp as f32 / hpool.max_hp as f32);
-                    ui.set_cursor_pos([pos[0], pos[1] + 60.0 * hp_lack]);
-                    Image::new(ctx.get_texture_id_for(&hp_fill), [60.0, 60.0 * (1.0 - hp_lack)])
-                        .uv0([0.0, hp_lack])
+                .size([500.0, 0.0], Condition::Always), &ui => {
+                    centered_text!(ui; &*boss.name; width);
+                    let overlay = ImString::new(format!("{} / {}", hpool.hp, hpool.max_hp));
+                    ProgressBar::new(hpool.hp as f32 / hpool.max_hp.max(1) as f32)
+                        .size([-1.0, 20.0])
+                        .overlay_text(&overlay)
                         .build(ui);
-
-                    centered_text!(ui; format!("Health:\n{} / {}", hpool.hp, hpool.max_hp); width);
             });
         }
+        for (e, index) in players {
+            let left = width * index as f32;
+            if let Some(hpool) = data.hpools.get(e) {
+                let name = ImString::new(format!("HealthPool##{}", index));
+                within_window!(Window::new(&name)
+                    .position([left, height], Condition::Always)
+                    .position_pivot([0.0, 1.0])
+                    .resizable(false)
+                    .movable(false)
+                    .collapsible(false)
+                    .title_bar(false)
+                    .focus_on_appearing(false)
+                    .size([90.0, 125.0], Condition::Always), &ui => {
+                        ui.set_cursor_pos([15.0, 10.0]);
+                        let hp_base = data.assets.get::<ImageAsset>("/sprites/ui/hp-base.png", ctx.as_mut());
+                        let hp_fill = data.assets.get::<ImageAsset>("/sprites/ui/hp-fill.png", ctx.as_mut());
 
-        if let Some((weaponry, _)) = (&data.weaponries, &data.player_tag).join().next() {
-            within_window!(Window::new(im_str!("Ammo"))
-                .position(ui.io().display_size, Condition::Always)
-                .position_pivot([1.0, 1.0])
-                .resizable(false)
-                .movable(false)
-                .collapsible(false)
-                .title_bar(false)
-                .focus_on_appearing(false)
-                .size([90.0, 125.0], Condition::Always), &ui => {
-                    if let Some(weapon) = weaponry.primary {
-                        if let Some(Sprite{ asset: SpriteAsset::Single { value }, ..}) = data.sprites.get(weapon) {
-                            ui.set_cursor_pos([15.0, 10.0]);
-                            Image::new(ctx.get_texture_id_for(&value), [60.0, 60.0]).build(ui);
+                        let pos = ui.cursor_pos();
+                        if let (Ok(hp_base), Ok(hp_fill)) = (hp_base, hp_fill) {
+                            Image::new(ctx.get_texture_id_for(&hp_base), [60.0, 60.0]).build(ui);
+
+                            let hp_lack = 1.0 - (hpool.hp as f32 / hpool.max_hp as f32);
+                            ui.set_cursor_pos([pos[0], pos[1] + 60.0 * hp_lack]);
+                            Image::new(ctx.get_texture_id_for(&hp_fill), [60.0, 60.0 * (1.0 - hp_lack)])
+                                .uv0([0.0, hp_lack])
+                                .build(ui);
+                        } else {
+                            ui.dummy([60.0, 60.0]);
                         }
-                        if let Some(prop) = data.wpn_props.get(weapon) {
-                            centered_text!(ui; format!("Clip:\n{} / {}", prop.clip, prop.clip_size); width);
-                            if prop.reloading > 0.0 {
-                                centered_text!(ui; "> Reload"; width);
-                            } else if prop.cooldown > 0.0 {
-                                centered_text!(ui; format!("> {:.0}%", (1.0 - prop.cooldown / prop.cooldown_time) * 100.0); width);
-                            } else {
-                                centered_text!(ui; "> Ready"; width);
+
+                        centered_text!(ui; format!("Health:\n{} / {}", hpool.hp, hpool.max_hp); width);
+                });
+            }
+
+            if let Some(weaponry) = data.weaponries.get(e) {
+                let name = ImString::new(format!("Ammo##{}", index));
+                within_window!(Window::new(&name)
+                    .position([left + width, height], Condition::Always)
+                    .position_pivot([1.0, 1.0])
+                    .resizable(false)
+                    .movable(false)
+                    .collapsible(false)
+                    .title_bar(false)
+                    .focus_on_appearing(false)
+                    .size([90.0, 125.0], Condition::Always), &ui => {
+                        if let Some(weapon) = weaponry.primary {
+                            if let Some(Sprite{ asset: SpriteAsset::Single { value }, ..}) = data.sprites.get(weapon) {
+                                ui.set_cursor_pos([15.0, 10.0]);
+                                Image::new(ctx.get_texture_id_for(&value), [60.0, 60.0]).build(ui);
                             }
+                            if let Some(prop) = data.wpn_props.get(weapon) {
+                                centered_text!(ui; format!("Clip:\n{} / {}", prop.clip, prop.clip_size); width);
+                                if prop.reloading > 0.0 {
+                                    centered_text!(ui; "> Reload"; width);
+                                } else if prop.cooldown > 0.0 {
+                                    centered_text!(ui; format!("> {:.0}%", (1.0 - prop.cooldown / prop.cooldown_time) * 100.0); width);
+                                } else {
+                                    centered_text!(ui; "> Ready"; width);
+                                }
+                            }
+                        } else {
+                            centered_text!(ui; "Nothing\nEquiped"; width, height);
                         }
-                    } else {
-                        centered_text!(ui; "Nothing\nEquiped"; width, height);
-                    }
-            });
-        }
+                });
+            }
 
-        if let Some((hotbar, _)) = (&data.hotbars, &data.player_tag).join().next() {
-            within_window!(Window::new(im_str!("Hotbar"))
-                .position([ui.io().display_size[0] * 0.5, ui.io().display_size[1]], Condition::Always)
-                .position_pivot([0.5, 1.0])
-                .resizable(false)
-                .movable(false)
-                .collapsible(false)
-                .title_bar(false)
-                .focus_on_appearing(false)
-                .size([0.0, 0.0], Condition::Always), &ui => {
-                    for (i, item_box) in hotbar.content.iter().enumerate() {
-                        let [x, y] = ui.cursor_start_pos();
-                        ui.set_cursor_pos([x + i as f32 * 70.0, y]);
-                        let frame = data.assets.get::<ImageAsset>("/sprites/ui/item-frame.png", ctx.as_mut()).unwrap();
-                        let pos = ui.cursor_pos();
-                        Image::new(ctx.get_texture_id_for(&frame), [50.0, 50.0]).build(ui);
-                        if let Some(Sprite{ asset: SpriteAsset::Single { value }, ..}) = item_box.and_then(|i| data.sprites.get(i)) {
-                            ui.set_cursor_pos(pos);
-                            Image::new(ctx.get_texture_id_for(&value), [50.0, 50.0]).build(ui);
+            if let Some(hotbar) = data.hotbars.get(e) {
+                let name = ImString::new(format!("Hotbar##{}", index));
+                within_window!(Window::new(&name)
+                    .position([left + width * 0.5, height], Condition::Always)
+                    .position_pivot([0.5, 1.0])
+                    .resizable(false)
+                    .movable(false)
+                    .collapsible(false)
+                    .title_bar(false)
+                    .focus_on_appearing(false)
+                    .size([0.0, 0.0], Condition::Always), &ui => {
+                        for (i, item_box) in hotbar.content.iter().enumerate() {
+                            let [x, y] = ui.cursor_start_pos();
+                            ui.set_cursor_pos([x + i as f32 * 70.0, y]);
+                            let pos = ui.cursor_pos();
+                            match data.assets.get::<ImageAsset>("/sprites/ui/item-frame.png", ctx.as_mut()) {
+                                Ok(frame) => Image::new(ctx.get_texture_id_for(&frame), [50.0, 50.0]).build(ui),
+                                Err(_) => ui.dummy([50.0, 50.0]),
+                            }
+                            if let Some(Sprite{ asset: SpriteAsset::Single { value }, ..}) = item_box.and_then(|i| data.sprites.get(i)) {
+                                ui.set_cursor_pos(pos);
+                                Image::new(ctx.get_texture_id_for(&value), [50.0, 50.0]).build(ui);
+                            }
+                            ui.set_cursor_pos([pos[0] + 17.0, 45.0]);
+                            ui.text(&format!("[{}]", i + 1));
                         }
-                        ui.set_cursor_pos([pos[0] + 17.0, 45.0]);
-                        ui.text(&format!("[{}]", i + 1));
-                    }
-            });
-        }
+                });
+            }
 
-        if let Some((consumer, _)) = (&data.consumers, &data.player_tag).join().next() {
-            within_window!(Window::new(im_str!("Buffs"))
-                .position([ui.io().display_size[0] * 0.5, ui.io().display_size[1] - 70.0], Condition::Always)
+            let (consumer, effects) = (data.consumers.get(e), data.status_effects.get(e));
+            let name = ImString::new(format!("Buffs##{}", index));
+            within_window!(Window::new(&name)
+                .position([left + width * 0.5, height - 70.0], Condition::Always)
                 .position_pivot([0.5, 1.0])
                 .resizable(false)
                 .draw_background(false)
@@ -107,8 +144,30 @@ impl<'a> UiBuilder<&mut UiData<'a>> for Hud {
                 .title_bar(false)
                 .scroll_bar(false)
                 .focus_on_appearing(false)
-                .size([0.0, 40.0], Condition::Always), &ui => {
-                    for handle in &consumer.handles {
+                .size([0.0, 60.0], Condition::Always), &ui => {
+                    for effect in effects.iter().flat_map(|effects| effects.active.iter()) {
+                        let icon = effect.def.icon.and_then(|i| data.assets.get::<ImageAsset>(i, ctx.as_mut()).ok());
+                        within_group!(ui => {
+                            match icon {
+                                Some(icon) => Image::new(ctx.get_texture_id_for(&icon), [30.0, 30.0]).build(ui),
+                                None => ui.dummy([30.0, 30.0]),
+                            }
+                            if effect.stacks > 1 {
+                                ui.text(format!("{:.0}s x{}", effect.time_left.ceil(), effect.stacks));
+                            } else {
+                                ui.text(format!("{:.0}s", effect.time_left.ceil()));
+                            }
+                        });
+                        if ui.is_item_hovered() {
+                            within_tooltip!(ui => {
+                                ui.text(effect.def.description);
+                                ui.text_disabled(format!("from {}", effect.source));
+                            });
+                        }
+                        ui.same_line(0.0);
+                    }
+                    // scripted consumables are not status effects
+                    for handle in consumer.iter().flat_map(|consumer| consumer.handles.iter()) {
                         if let Some(icon) = handle.behaviour.icon(ctx.as_mut(), &mut data.assets) {
                             Image::new(ctx.get_texture_id_for(&icon), [30.0, 30.0]).build(ui);
                             ui.same_line(0.0);
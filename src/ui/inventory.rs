@@ -1,7 +1,10 @@
 use super::system::{UiBuilder, UiContext};
 use crate::{
     assets::*,
-    ecs::{component::*, resource::UiData},
+    ecs::{
+        component::*,
+        resource::{Inputs, ItemMove, ItemSlot, Slot, UiData},
+    },
     styled, within_group, within_tooltip, within_window,
 };
 use imgui::*;
@@ -13,76 +16,47 @@ use std::collections::BTreeSet as Set;
 // Run and never look back. Never return.
 // You have been warned.
 
-// Non owning view of ItemBox
-#[derive(Debug)]
-struct ItemBoxView {
-    pub view: *mut ItemBox,
-}
-// It is ok to use pointer in ui because ui logic is purely
-// single-threaded and always will
-unsafe impl Send for ItemBoxView {}
-unsafe impl Sync for ItemBoxView {}
-
-#[derive(Debug)]
+#[derive(Default, Debug)]
 pub struct InventoryWindow {
     pub show_inventories_for: Set<Entity>,
-    dragging_item: ItemBoxView,
-}
-impl Default for InventoryWindow {
-    fn default() -> Self {
-        Self { show_inventories_for: Set::new(), dragging_item: ItemBoxView { view: std::ptr::null_mut() } }
-    }
+    // where the dragged item was picked up from
+    dragging: Option<(ItemSlot, Entity)>,
 }
 impl InventoryWindow {
     const CELL: f32 = 50.0;
     const PAD: f32 = 10.0;
     const PCELL: f32 = Self::CELL + Self::PAD;
 
-    pub fn dragging_item(&self) -> &ItemBox {
-        unsafe {
-            match self.dragging_item.view.as_ref() {
-                Some(item) => &item,
-                None => &None,
-            }
+    pub fn dragging_item(&self) -> ItemBox { self.dragging.map(|(_, item)| item) }
+
+    pub fn reset_dragging(&mut self) { self.dragging = None; }
+
+    // The item is moved by `ItemMoveSystem`, here it is only sent along with
+    // other inputs. Dropping onto a different item keeps it dragged.
+    fn drop_into(
+        dragging: &mut Option<(ItemSlot, Entity)>,
+        to: ItemSlot,
+        target: ItemBox,
+        reflections: &ReadStorage<Reflection>,
+        inputs: &mut Inputs,
+    ) {
+        let (from, item) = match *dragging {
+            Some(dragging) => dragging,
+            None => return,
+        };
+        let same_kind = |other: Entity| reflections.get(other).map(|r| &r.id) == reflections.get(item).map(|r| &r.id);
+        if target.map_or(true, |target| target != item && same_kind(target)) {
+            inputs.item_move = Some(ItemMove { from, to });
+            *dragging = None;
         }
     }
-
-    pub fn reset_dragging(&mut self) { self.dragging_item.view = std::ptr::null_mut(); }
-}
-macro_rules! drag_and_drop {
-    ($item_box_to:expr, $item_box_from:expr, $data:expr) => {
-        if let Some(mut viewed) = unsafe { $item_box_from.view.as_mut() } {
-            if let (Some(to), Some(from)) = ($item_box_to, &mut viewed) {
-                let (id_to, id_from) =
-                    ($data.reflections.get(*to).unwrap().id, $data.reflections.get(*from).unwrap().id);
-
-                if id_to == id_from && from != to {
-                    let mut current = $data.stacks.get(*from).map(|s| s.current).unwrap_or(1);
-                    if let Some(mut stack_to) = $data.stacks.get_mut(*to) {
-                        let transfer_count = current.min(stack_to.stack_size - stack_to.current);
-                        stack_to.current += transfer_count;
-                        current = current.saturating_sub(transfer_count);
-                    }
-                    $data.stacks.get_mut(*from).unwrap().current = current;
-                    if current == 0 {
-                        $data.entities.delete(viewed.take().unwrap()).unwrap();
-                        $item_box_from.view = std::ptr::null_mut();
-                    }
-                }
-            } else {
-                $item_box_to.replace(viewed.take().unwrap());
-                $item_box_from.view = std::ptr::null_mut();
-            }
-        }
-    };
 }
-
 // TODO: somehow bypass macro hygiene with self, ui, ctx and data
 macro_rules! item_tooltip {
     ($self:expr, $item:expr, $ui:expr, $ctx:expr, $data:expr) => {
         let token = $ui.push_text_wrap_pos(400.0);
         if let Some(named) = $data.named.get($item) {
-            $ui.bullet_text(&ImString::new(named.name));
+            $ui.bullet_text(&ImString::new(&*named.name));
             $ui.text(&named.description);
         }
         if let (Some(attack), Some(props)) = ($data.wpn_attacks.get($item), $data.wpn_props.get($item)) {
@@ -117,21 +91,23 @@ macro_rules! item_tooltip {
 }
 // Returns true if there is drag and drop for this box
 macro_rules! item_box {
-    ($self:expr, $item_box:expr, $pos:expr, $ui:expr, $ctx:expr, $data:expr) => {{
+    ($self:expr, $item_box:expr, $slot:expr, $pos:expr, $ui:expr, $ctx:expr, $data:expr) => {{
         $ui.set_cursor_pos($pos);
         let w_pos = $ui.window_pos();
         let [w_x, w_y] = [w_pos[0] - $ui.scroll_x(), w_pos[1] - $ui.scroll_y()];
-        let frame_asset = if $ui.is_mouse_hovering_rect(
+        let frame_key = if $ui.is_mouse_hovering_rect(
             [$pos[0] + w_x, $pos[1] + w_y],
             [$pos[0] + Self::CELL + w_x, $pos[1] + Self::CELL + w_y]
         ) && ($item_box.is_some() || $self.dragging_item().is_some()) {
-            $data.assets.get::<ImageAsset>("/sprites/ui/item-frame-hov.png", $ctx.as_mut()).unwrap()
+            "/sprites/ui/item-frame-hov.png"
         } else {
-            $data.assets.get::<ImageAsset>("/sprites/ui/item-frame.png", $ctx.as_mut()).unwrap()
+            "/sprites/ui/item-frame.png"
         };
 
-        let frame = $ctx.get_texture_id_for(&frame_asset);
-        Image::new(frame, [Self::CELL, Self::CELL]).build($ui);
+        match $data.assets.get::<ImageAsset>(frame_key, $ctx.as_mut()) {
+            Ok(frame_asset) => Image::new($ctx.get_texture_id_for(&frame_asset), [Self::CELL, Self::CELL]).build($ui),
+            Err(_) => $ui.dummy([Self::CELL, Self::CELL]),
+        }
         $ui.set_cursor_pos($pos);
 
         if let Some(item) = $item_box {
@@ -166,8 +142,8 @@ macro_rules! item_box {
             if $self.dragging_item().is_some() {
                 true
             } else {
-                if $item_box.is_some() {
-                    $self.dragging_item.view = $item_box as &mut ItemBox;
+                if let Some(item) = $item_box {
+                    $self.dragging = Some(($slot, *item));
                 }
                 false
             }
@@ -177,12 +153,13 @@ macro_rules! item_box {
     }};
 }
 macro_rules! items {
-    ($self:expr, $inv:expr, $ui:expr, $ctx:expr, $data:expr) => {
+    ($self:expr, $owner:expr, $inv:expr, $ui:expr, $ctx:expr, $data:expr) => {
         let per_row = (($ui.window_content_region_width() - Self::PAD) / (Self::PCELL)).ceil() as usize;
-        for (i, mut item_box) in $inv.content.iter_mut().enumerate() {
+        for (i, item_box) in $inv.content.iter().enumerate() {
             let pos = [Self::PAD + Self::PCELL * (i % per_row) as f32, Self::PAD + Self::PCELL * (i / per_row) as f32];
-            if item_box!($self, &mut item_box, pos, $ui, $ctx, $data) {
-                drag_and_drop!(&mut item_box, &mut $self.dragging_item, $data);
+            let slot = ItemSlot { owner: $owner.id(), slot: Slot::Inventory(i) };
+            if item_box!($self, item_box, slot, pos, $ui, $ctx, $data) {
+                Self::drop_into(&mut $self.dragging, slot, *item_box, &$data.reflections, &mut $data.inputs);
             }
         }
     };
@@ -197,7 +174,7 @@ impl<'a> UiBuilder<&mut UiData<'a>> for InventoryWindow {
             .focus_on_appearing(true)
             .opened(&mut is_opened)
             .size([0.0, 0.0], Condition::Once), &ui => {
-                if let Some(mut inventory) = data.inventories.get_mut(*e) {
+                if let Some(inventory) = data.inventories.get(*e) {
                     ui.bullet_text(im_str!("Content:"));
                     within_window!(ChildWindow::new("inv").size([380.0, 260.0]).border(true), &ui => {
                         if inventory.content.is_empty() {
@@ -207,46 +184,50 @@ impl<'a> UiBuilder<&mut UiData<'a>> for InventoryWindow {
                             ui.set_cursor_pos([(max_w - text_w) * 0.5, (max_h - text_h) * 0.5]);
                             ui.text(txt);
                         } else {
-                            items!(self, &mut inventory, ui, ctx, data);
+                            items!(self, e, inventory, ui, ctx, data);
                         }
                     });
                 }
-                if let Some(hotbar) = data.hotbars.get_mut(*e) {
+                if let Some(hotbar) = data.hotbars.get(*e) {
                     ui.bullet_text(im_str!("Hotbar:"));
                     within_window!(ChildWindow::new("hotbar").size([380.0, 70.0]), &ui => {
                         let slots_size = hotbar.content.len();
                         let [max_w, _] = ui.content_region_max();
-                        for (i, mut item_box) in hotbar.content.iter_mut().enumerate() {
+                        for (i, item_box) in hotbar.content.iter().enumerate() {
                             let pos = [
                                 (Self::PCELL * i as f32) + (max_w - (Self::PCELL) * slots_size as f32) * 0.5,
                                 Self::PAD
                             ];
 
-                            if item_box!(self, item_box, pos, ui, ctx, data) {
+                            let slot = ItemSlot { owner: e.id(), slot: Slot::Hotbar(i) };
+                            if item_box!(self, item_box, slot, pos, ui, ctx, data) {
                                 let item = self.dragging_item().unwrap();
                                 if data.consumables.get(item).is_some() {
-                                    drag_and_drop!(&mut item_box, &mut self.dragging_item, data);
+                                    let (reflections, inputs) = (&data.reflections, &mut data.inputs);
+                                    Self::drop_into(&mut self.dragging, slot, *item_box, reflections, inputs);
                                 }
                             }
                         }
                     });
                 }
-                if let Some(weaponry) = data.weaponries.get_mut(*e) {
+                if let Some(weaponry) = data.weaponries.get(*e) {
                     ui.bullet_text(im_str!("Weapons:"));
                     within_window!(ChildWindow::new("weapon").size([380.0, 70.0]), &ui => {
-                        let mut slots = [&mut weaponry.primary, &mut weaponry.secondary];
+                        let slots = [(Slot::Primary, &weaponry.primary), (Slot::Secondary, &weaponry.secondary)];
                         let slots_size = slots.len();
                         let [max_w, _] = ui.content_region_max();
-                        for (i, mut item_box) in slots.iter_mut().enumerate() {
+                        for (i, (slot, item_box)) in slots.iter().enumerate() {
                             let pos = [
                                 (Self::PCELL * i as f32) + (max_w - (Self::PCELL) * slots_size as f32) * 0.5,
                                 Self::PAD
                             ];
 
-                            if item_box!(self, item_box, pos, ui, ctx, data) {
+                            let slot = ItemSlot { owner: e.id(), slot: *slot };
+                            if item_box!(self, *item_box, slot, pos, ui, ctx, data) {
                                 let item = self.dragging_item().unwrap();
                                 if data.wpn_props.get(item).is_some() && data.wpn_attacks.get(item).is_some() {
-                                    drag_and_drop!(&mut item_box, &mut self.dragging_item, data);
+                                    let (reflections, inputs) = (&data.reflections, &mut data.inputs);
+                                    Self::drop_into(&mut self.dragging, slot, **item_box, reflections, inputs);
                                 }
                             }
                         }
@@ -0,0 +1,76 @@
+use super::system::{UiBuilder, UiContext};
+use crate::{centered_text, net, within_window};
+use imgui::*;
+
+#[derive(Debug)]
+pub struct Lobby {
+    pub port: i32,
+    pub address: ImString,
+    /// What the lobby is up to, set by the lobby scene
+    pub status: String,
+    // hosting or joining is in progress
+    pub is_busy: bool,
+    pub is_host: bool,
+    pub is_join: bool,
+    pub is_cancel: bool,
+    pub is_back: bool,
+}
+impl Default for Lobby {
+    fn default() -> Self {
+        let mut lobby = Self {
+            port: net::DEFAULT_PORT as i32,
+            address: ImString::with_capacity(64),
+            status: String::new(),
+            is_busy: false,
+            is_host: false,
+            is_join: false,
+            is_cancel: false,
+            is_back: false,
+        };
+        lobby.set_address(&format!("127.0.0.1:{}", net::DEFAULT_PORT));
+        lobby
+    }
+}
+impl Lobby {
+    pub fn set_address(&mut self, address: &str) {
+        // input text can't grow the buffer by itself
+        self.address = ImString::with_capacity(64);
+        self.address.push_str(address);
+    }
+}
+impl UiBuilder<()> for Lobby {
+    fn build<'ctx>(&mut self, ui: &mut Ui, _: &mut UiContext<'ctx>, _: ()) {
+        let [w, h] = ui.io().display_size;
+        within_window!(Window::new(im_str!("Lobby"))
+            .position([w * 0.5, h * 0.5], Condition::Always)
+            .position_pivot([0.5, 0.5])
+            .title_bar(false)
+            .movable(false)
+            .resizable(false)
+            .collapsible(false)
+            .always_auto_resize(true), &ui => {
+                centered_text!(ui; "Network co-op"; width);
+                ui.spacing();
+                ui.text_disabled("The host plays as the first player, the one who joins as the second");
+                ui.spacing();
+
+                if self.is_busy {
+                    ui.text(&self.status);
+                    ui.spacing();
+                    self.is_cancel = ui.button(im_str!("Cancel"), [300.0, 50.0]);
+                } else {
+                    ui.input_int(im_str!("Port"), &mut self.port).build();
+                    self.is_host = ui.button(im_str!("Host"), [300.0, 50.0]);
+                    ui.spacing();
+                    ui.input_text(im_str!("Address"), &mut self.address).build();
+                    self.is_join = ui.button(im_str!("Join"), [300.0, 50.0]);
+                    ui.spacing();
+                    if !self.status.is_empty() {
+                        ui.text_colored([0.9, 0.3, 0.3, 1.0], &self.status);
+                        ui.spacing();
+                    }
+                    self.is_back = ui.button(im_str!("Back"), [300.0, 50.0]);
+                }
+        });
+    }
+}
@@ -1,10 +1,29 @@
-use super::system::{UiBuilder, UiContext};
-use crate::{assets::*, centered_text, within_window};
+use super::{
+    options::OptionsWindow,
+    system::{UiBuilder, UiContext},
+};
+use crate::{
+    assets::*,
+    centered_text,
+    settings::{Overrides, Settings},
+    within_window,
+};
 use imgui::*;
 
 #[derive(Default, Debug)]
 pub struct MainMenu {
+    pub settings: Settings,
+    pub overrides: Overrides,
+    pub options: OptionsWindow,
+    pub has_save: bool,
+    // why the saved run can't be continued, if it can't
+    pub save_error: Option<String>,
+    pub is_continue: bool,
     pub is_play: bool,
+    pub is_explore: bool,
+    // new runs are for two players
+    pub is_coop: bool,
+    pub is_network: bool,
     pub is_how_to_play: bool,
     pub is_exit: bool,
 }
@@ -23,17 +42,41 @@ impl<'a> UiBuilder<&mut AssetManager> for MainMenu {
                 let [ww, wh] = ui.window_size();
 
                 ui.set_cursor_pos([(ww - 430.0) * 0.5, wh * 0.1]);
-                let logo = assets.get::<ImageAsset>("/sprites/ui/logo.png", ctx.as_mut()).unwrap();
-                Image::new(ctx.get_texture_id_for(&logo), [430.0, 190.0]).build(ui);
+                match assets.get::<ImageAsset>("/sprites/ui/logo.png", ctx.as_mut()) {
+                    Ok(logo) => Image::new(ctx.get_texture_id_for(&logo), [430.0, 190.0]).build(ui),
+                    Err(_) => ui.dummy([430.0, 190.0]),
+                }
+
+                if self.has_save {
+                    ui.set_cursor_pos([(ww - 300.0) * 0.5, ui.cursor_pos()[1] + 30.0]);
+                    self.is_continue = ui.button(im_str!("Continue"), [300.0, 50.0]);
+                } else if let Some(error) = &self.save_error {
+                    ui.set_cursor_pos([(ww - 300.0) * 0.5, ui.cursor_pos()[1] + 30.0]);
+                    ui.text_colored([0.9, 0.3, 0.3, 1.0], &format!("Saved run can't be continued:\n{}", error));
+                }
 
                 ui.set_cursor_pos([(ww - 300.0) * 0.5, ui.cursor_pos()[1] + 30.0]);
                 self.is_play = ui.button(im_str!("Play"), [300.0, 50.0]);
 
+                ui.set_cursor_pos([(ww - 300.0) * 0.5, ui.cursor_pos()[1] + 30.0]);
+                self.is_explore = ui.button(im_str!("Explore"), [300.0, 50.0]);
+
+                ui.set_cursor_pos([(ww - 300.0) * 0.5, ui.cursor_pos()[1] + 10.0]);
+                ui.checkbox(im_str!("Co-op with a second player"), &mut self.is_coop);
+
+                ui.set_cursor_pos([(ww - 300.0) * 0.5, ui.cursor_pos()[1] + 30.0]);
+                self.is_network = ui.button(im_str!("Network co-op"), [300.0, 50.0]);
+
                 ui.set_cursor_pos([(ww - 300.0) * 0.5, ui.cursor_pos()[1] + 30.0]);
                 if ui.button(im_str!("How to play"), [300.0, 50.0]) {
                     self.is_how_to_play = true;
                 }
 
+                ui.set_cursor_pos([(ww - 300.0) * 0.5, ui.cursor_pos()[1] + 30.0]);
+                if ui.button(im_str!("Options"), [300.0, 50.0]) {
+                    self.options.is_opened = true;
+                }
+
                 ui.set_cursor_pos([(ww - 300.0) * 0.5, ui.cursor_pos()[1] + 30.0]);
                 self.is_exit = ui.button(im_str!("Exit"), [300.0, 50.0]);
 
@@ -56,6 +99,10 @@ impl<'a> UiBuilder<&mut AssetManager> for MainMenu {
                     of enemies will wait for you. Use fruits power ups in a dire moment to \
                     really turn the tide of the battle. \
                     "));
+                    ui.text_wrapped(im_str!("\
+                    Or choose to explore an endless open sea instead, which gets more \
+                    dangerous the further you sail from the start. \
+                    "));
                     ui.spacing();
                     ui.bullet_text(im_str!("Controls"));
                     ui.text_wrapped(im_str!("\
@@ -67,8 +114,27 @@ impl<'a> UiBuilder<&mut AssetManager> for MainMenu {
                     [I] -- Open inventory\n\
                     [Mouse wheel] -- Change primary/secondary weapon\n\
                     [Mouse left button] -- Shoot your primary gun\n\
+                    [1-4] -- Use hotbar items\n\
+                    [Z] -- Frame nearby enemies\n\
+                    [Escape] -- Pause\n\
+                    "));
+                    ui.text_wrapped(im_str!("\
+                    These are the defaults, every one of them can be changed in Controls of the pause menu. \
+                    "));
+                    ui.spacing();
+                    ui.bullet_text(im_str!("Co-op"));
+                    ui.text_wrapped(im_str!("\
+                    With co-op checked, a second ship joins the run. The first player keeps the keyboard \
+                    and the mouse, the second one plays with a gamepad or the numpad. The run is over \
+                    only when both ships are sunk. \
+                    "));
+                    ui.text_wrapped(im_str!("\
+                    Network co-op is the same, but the second player sails from another computer: \
+                    one of you hosts the game, and the other one joins it by the address of the host. \
                     "));
             });
         }
+
+        self.options.build(ui, ctx, (&mut self.settings, &self.overrides));
     }
 }
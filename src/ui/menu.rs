@@ -17,11 +17,14 @@ impl<'a> UiBuilder<&mut UiData<'a>> for Menu {
 
             if data.scene_controls.is_debug{
                 ui.menu(im_str!("Debug"), true, || {
-                    if ui.small_button(im_str!("Spawn window")) {
-                        self.is_show_spawn_window = !self.is_show_spawn_window;
-                    }
-                    if ui.small_button(im_str!("Arena settings")) {
-                        self.is_show_arena_settings = !self.is_show_arena_settings;
+                    // those would desync network games
+                    if !data.local_player.is_network() {
+                        if ui.small_button(im_str!("Spawn window")) {
+                            self.is_show_spawn_window = !self.is_show_spawn_window;
+                        }
+                        if ui.small_button(im_str!("Arena settings")) {
+                            self.is_show_arena_settings = !self.is_show_arena_settings;
+                        }
                     }
                     ui.checkbox(im_str!("Render debug info"), &mut data.scene_controls.is_debug_info);
                     ui.checkbox(im_str!("Render targeting"), &mut data.scene_controls.is_debug_targeting);
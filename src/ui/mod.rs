@@ -1,20 +1,28 @@
 pub mod arena_settings;
+pub mod controls;
 pub mod debug;
 pub mod game_over;
 pub mod hud;
 pub mod inventory;
+pub mod lobby;
 pub mod main_menu;
 pub mod menu;
+pub mod network;
+pub mod options;
 pub mod pause;
 pub mod system;
 
 pub use arena_settings::ArenaSettingsWindow;
+pub use controls::ControlsWindow;
 pub use debug::DebugWindow;
 pub use game_over::GameOverWindow;
 pub use hud::Hud;
 pub use inventory::InventoryWindow;
+pub use lobby::Lobby;
 pub use main_menu::MainMenu;
 pub use menu::Menu;
+pub use network::NetworkWindow;
+pub use options::OptionsWindow;
 pub use pause::PauseWindow;
 pub use system::{ImGuiSystem, UiBuilder, UiContext};
 
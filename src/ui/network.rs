@@ -0,0 +1,60 @@
+use super::system::{UiBuilder, UiContext};
+use crate::{centered_text, ecs::resource::*, within_window};
+use imgui::*;
+
+/// State of the connection in a network game, kept up to date by the game
+#[derive(Default, Debug)]
+pub struct NetworkWindow {
+    pub is_waiting: bool,
+    // first tick which is not the same for both players
+    pub desync: Option<u64>,
+    // reason of the connection loss
+    pub closed: Option<String>,
+}
+impl<'a> UiBuilder<&mut UiData<'a>> for NetworkWindow {
+    fn build<'ctx>(&mut self, ui: &mut Ui, _: &mut UiContext<'ctx>, data: &mut UiData<'a>) {
+        if self.is_waiting || self.desync.is_some() {
+            let [w, _] = ui.io().display_size;
+            within_window!(Window::new(im_str!("network"))
+                .position([w * 0.5, 30.0], Condition::Always)
+                .position_pivot([0.5, 0.0])
+                .title_bar(false)
+                .movable(false)
+                .resizable(false)
+                .focus_on_appearing(false)
+                .always_auto_resize(true), &ui => {
+                    if self.is_waiting {
+                        ui.text("Waiting for the other player...");
+                    }
+                    if let Some(tick) = self.desync {
+                        ui.text_colored(
+                            [0.9, 0.3, 0.3, 1.0],
+                            &format!("Desync at tick {}, the game is not the same for both players anymore", tick),
+                        );
+                    }
+            });
+        }
+
+        if self.closed.is_some() {
+            ui.open_popup(im_str!("connection_lost"))
+        }
+        let token = ui.push_style_colors(&[(StyleColor::ModalWindowDimBg, [0.0, 0.0, 0.0, 0.7])]);
+        ui.popup_modal(im_str!("connection_lost"))
+            .title_bar(false)
+            .movable(false)
+            .resizable(false)
+            .always_auto_resize(true)
+            .build(|| {
+                centered_text!(ui; "Connection lost"; width);
+                ui.spacing();
+                if let Some(reason) = &self.closed {
+                    ui.text(reason);
+                }
+                ui.spacing();
+                if ui.button(im_str!("Exit"), [300.0, 50.0]) {
+                    data.scene_controls.queue_exit = true;
+                }
+            });
+        token.pop(ui);
+    }
+}
@@ -0,0 +1,99 @@
+use super::system::{UiBuilder, UiContext};
+use crate::{
+    centered_text,
+    settings::{Overrides, Settings, WindowKind, MSAA, RESOLUTIONS, UI_SCALES},
+};
+use imgui::*;
+
+#[derive(Default, Debug)]
+pub struct OptionsWindow {
+    pub is_opened: bool,
+}
+impl UiBuilder<(&mut Settings, &Overrides)> for OptionsWindow {
+    fn build<'ctx>(
+        &mut self,
+        ui: &mut Ui,
+        ctx: &mut UiContext<'ctx>,
+        (settings, overrides): (&mut Settings, &Overrides),
+    ) {
+        if self.is_opened {
+            ui.open_popup(im_str!("options"))
+        }
+
+        let token = ui.push_style_colors(&[(StyleColor::ModalWindowDimBg, [0.0, 0.0, 0.0, 0.7])]);
+        ui.popup_modal(im_str!("options"))
+            .title_bar(false)
+            .movable(false)
+            .resizable(false)
+            .always_auto_resize(true)
+            .build(|| {
+                centered_text!(ui; "Options"; width);
+                ui.spacing();
+
+                ui.text("Window");
+                for (i, kind) in WindowKind::ALL.iter().enumerate() {
+                    ui.same_line(if i == 0 { 160.0 } else { 0.0 });
+                    ui.radio_button(&ImString::new(kind.name()), &mut settings.window_mode, *kind);
+                }
+                ui.text("Resolution");
+                for (i, resolution) in RESOLUTIONS.iter().enumerate() {
+                    ui.same_line(if i == 0 { 160.0 } else { 0.0 });
+                    let label = ImString::new(format!("{}x{}", resolution[0], resolution[1]));
+                    ui.radio_button(&label, &mut settings.resolution, *resolution);
+                }
+                if !RESOLUTIONS.contains(&settings.resolution) {
+                    ui.same_line(0.0);
+                    ui.text_disabled(format!("{}x{}", settings.resolution[0], settings.resolution[1]));
+                }
+                ui.text("Ui scale");
+                for (i, scale) in UI_SCALES.iter().enumerate() {
+                    ui.same_line(if i == 0 { 160.0 } else { 0.0 });
+                    let label = ImString::new(format!("{:.0}%", scale * 100.0));
+                    ui.radio_button(&label, &mut settings.ui_scale, *scale);
+                }
+                ui.text("Antialiasing");
+                for (i, msaa) in MSAA.iter().enumerate() {
+                    ui.same_line(if i == 0 { 160.0 } else { 0.0 });
+                    let label = ImString::new(if *msaa == 1 { "off".to_owned() } else { format!("{}x", msaa) });
+                    ui.radio_button(&label, &mut settings.msaa, *msaa);
+                }
+                ui.text("Vsync");
+                ui.same_line(160.0);
+                ui.checkbox(im_str!("##vsync"), &mut settings.vsync);
+                ui.text_disabled("Antialiasing and vsync are changed after restart");
+
+                ui.spacing();
+                ui.separator();
+                ui.text("Debug defaults");
+                ui.checkbox(im_str!("Debug tools"), &mut settings.debug);
+                ui.checkbox(im_str!("Render debug info"), &mut settings.debug_info);
+                ui.checkbox(im_str!("Render targeting"), &mut settings.debug_targeting);
+                ui.checkbox(im_str!("Render physic"), &mut settings.debug_physic);
+                ui.text_disabled("Debug defaults are used from the next run");
+
+                if !overrides.is_empty() {
+                    ui.spacing();
+                    ui.text_colored(
+                        [0.9, 0.3, 0.3, 1.0],
+                        im_str!("Some of these are overridden by command line arguments"),
+                    );
+                }
+                ui.spacing();
+                if ui.button(im_str!("Reset to defaults"), [300.0, 50.0]) {
+                    *settings = Settings::default();
+                }
+                ui.same_line(0.0);
+                if ui.button(im_str!("Save and close"), [300.0, 50.0]) {
+                    if let Err(err) = settings.write() {
+                        log::error!("Unable to save settings: {:#}", err);
+                    }
+                    if let Err(err) = settings.with(overrides).apply_window(ctx.as_mut()) {
+                        log::error!("Unable to change window mode: {}", err);
+                    }
+                    ui.close_current_popup();
+                    self.is_opened = false;
+                }
+            });
+        token.pop(ui);
+    }
+}
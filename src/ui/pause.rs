@@ -1,14 +1,21 @@
-use super::system::{UiBuilder, UiContext};
+use super::{
+    controls::ControlsWindow,
+    options::OptionsWindow,
+    system::{UiBuilder, UiContext},
+};
 use crate::{centered_text, ecs::resource::*};
 use imgui::*;
 
 #[derive(Default, Debug)]
 pub struct PauseWindow {
     pub is_opened: bool,
+    pub controls: ControlsWindow,
+    pub options: OptionsWindow,
 }
 impl<'a> UiBuilder<&mut UiData<'a>> for PauseWindow {
-    fn build<'ctx>(&mut self, ui: &mut Ui, _: &mut UiContext<'ctx>, data: &mut UiData<'a>) {
-        if self.is_opened {
+    fn build<'ctx>(&mut self, ui: &mut Ui, ctx: &mut UiContext<'ctx>, data: &mut UiData<'a>) {
+        // controls and options take the place of this one until they are closed
+        if self.is_opened && !self.controls.is_opened && !self.options.is_opened {
             ui.open_popup(im_str!("pause"))
         }
 
@@ -26,6 +33,20 @@ impl<'a> UiBuilder<&mut UiData<'a>> for PauseWindow {
                     self.is_opened = false;
                 }
                 ui.spacing();
+                if ui.button(im_str!("Save"), [300.0, 50.0]) {
+                    data.scene_controls.queue_save = true;
+                }
+                ui.spacing();
+                if ui.button(im_str!("Controls"), [300.0, 50.0]) {
+                    ui.close_current_popup();
+                    self.controls.is_opened = true;
+                }
+                ui.spacing();
+                if ui.button(im_str!("Options"), [300.0, 50.0]) {
+                    ui.close_current_popup();
+                    self.options.is_opened = true;
+                }
+                ui.spacing();
                 if ui.button(im_str!("Restart"), [300.0, 50.0]) {
                     data.scene_controls.queue_restart = true;
                 }
@@ -35,5 +56,7 @@ impl<'a> UiBuilder<&mut UiData<'a>> for PauseWindow {
                 }
             });
         token.pop(ui);
+        self.controls.build(ui, ctx, data);
+        self.options.build(ui, ctx, (&mut *data.settings, &*data.overrides));
     }
 }
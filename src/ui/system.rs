@@ -1,5 +1,6 @@
 use crate::{
     assets::{Asset, ImageAsset},
+    ecs::resource::GamepadInputs,
     math::Point2f,
 };
 use gfx::{
@@ -8,7 +9,10 @@ use gfx::{
     memory::Typed,
     Factory,
 };
-use ggez::graphics::{self, BackendSpec, GlBackendSpec};
+use ggez::{
+    event::Button,
+    graphics::{self, BackendSpec, GlBackendSpec},
+};
 use imgui;
 use imgui_gfx_renderer::*;
 use std::cell::RefCell;
@@ -28,10 +32,18 @@ impl UiContext<'_> {
             return id;
         }
 
+        let image = match asset.image() {
+            Some(image) => image,
+            None => {
+                log::warn!("ImageAsset {:?} was loaded headless and can't be shown by Imgui", asset.key());
+                return id;
+            },
+        };
+
         log::debug!("Associating a new ImageAsset with Imgui: {:?}", id);
-        let resource_view = ShaderResourceView::new(asset.as_ref().raw_shader_resource_view().to_owned());
+        let resource_view = ShaderResourceView::new(image.raw_shader_resource_view().to_owned());
         let (factory, _, _, _, _) = graphics::gfx_objects(self.1);
-        let sampler = factory.create_sampler(*asset.as_ref().sampler_info());
+        let sampler = factory.create_sampler(*image.sampler_info());
         self.0.textures().replace(id, (resource_view, sampler));
         id
     }
@@ -44,12 +56,15 @@ pub struct ImGuiSystem {
     imgui: imgui::Context,
     renderer: RefCell<Renderer<Rgba8, <GlBackendSpec as BackendSpec>::Resources>>,
     next_frame: RefCell<Option<imgui::Ui<'static>>>,
+    scale: f32,
 }
 
 impl ImGuiSystem {
     pub fn new(ctx: &mut ggez::Context) -> ImGuiSystem {
         let mut imgui = imgui::Context::create();
         imgui.set_ini_filename(None);
+        imgui.io_mut().config_flags.insert(imgui::ConfigFlags::NAV_ENABLE_GAMEPAD);
+        imgui.io_mut().backend_flags.insert(imgui::BackendFlags::HAS_GAMEPAD);
 
         let (factory, device, _, _, _) = graphics::gfx_objects(ctx);
         let shaders = {
@@ -69,7 +84,39 @@ impl ImGuiSystem {
             }
         };
         let renderer = Renderer::init(&mut imgui, &mut *factory, shaders).unwrap();
-        Self { imgui: imgui, renderer: RefCell::new(renderer), next_frame: RefCell::new(None) }
+        Self { imgui: imgui, renderer: RefCell::new(renderer), next_frame: RefCell::new(None), scale: 1.0 }
+    }
+
+    /// Scale of the whole ui (widgets, fonts and hardcoded sizes alike)
+    /// starting from the next `update`
+    pub fn set_scale(&mut self, scale: f32) { self.scale = scale; }
+
+    /// Gamepad navigation for the next `update`: d-pad or left stick
+    /// to move between widgets, south to activate and east to cancel
+    pub fn set_gamepad(&mut self, gamepad: &GamepadInputs) {
+        use imgui::NavInput;
+
+        let io = self.imgui.io_mut();
+        let pressed = |button: Button| if gamepad.pressed.contains(&button) { 1.0 } else { 0.0 };
+        let (stick_x, stick_y) = (gamepad.left_stick.x, gamepad.left_stick.y);
+        let inputs = [
+            (NavInput::Activate, pressed(Button::South)),
+            (NavInput::Cancel, pressed(Button::East)),
+            (NavInput::Menu, pressed(Button::West)),
+            (NavInput::DpadLeft, pressed(Button::DPadLeft)),
+            (NavInput::DpadRight, pressed(Button::DPadRight)),
+            (NavInput::DpadUp, pressed(Button::DPadUp)),
+            (NavInput::DpadDown, pressed(Button::DPadDown)),
+            (NavInput::LStickLeft, (-stick_x).max(0.0)),
+            (NavInput::LStickRight, stick_x.max(0.0)),
+            (NavInput::LStickUp, (-stick_y).max(0.0)),
+            (NavInput::LStickDown, stick_y.max(0.0)),
+            (NavInput::FocusPrev, pressed(Button::LeftTrigger)),
+            (NavInput::FocusNext, pressed(Button::RightTrigger)),
+        ];
+        for (input, value) in &inputs {
+            io.nav_inputs[*input as usize] = *value;
+        }
     }
 
     pub fn update<D, B>(
@@ -89,15 +136,18 @@ impl ImGuiSystem {
 
         // it's very important to round so we don't get blurry image
         let hidpi_factor = window.get_hidpi_factor().round();
-        io.display_framebuffer_scale = [hidpi_factor as f32, hidpi_factor as f32];
+        // imgui sees a smaller screen which is stretched to the window
+        let scale = self.scale;
+        io.display_framebuffer_scale = [hidpi_factor as f32 * scale, hidpi_factor as f32 * scale];
         if let Some(logical_size) = window.get_inner_size() {
             // convert size using our rounded hidpi factor
             let rounded_size = logical_size.to_physical(window.get_hidpi_factor()).to_logical(hidpi_factor);
-            io.display_size = [rounded_size.width as f32, rounded_size.height as f32];
+            io.display_size = [rounded_size.width as f32 / scale, rounded_size.height as f32 / scale];
         }
 
-        let rounded_position =
-            Point2f::from(ggez::input::mouse::position(ctx)) * window.get_hidpi_factor() as f32 / hidpi_factor as f32;
+        let rounded_position = Point2f::from(ggez::input::mouse::position(ctx)) * window.get_hidpi_factor() as f32
+            / hidpi_factor as f32
+            / scale;
         io.mouse_pos = [rounded_position.x as f32, rounded_position.y as f32];
 
         io.mouse_down = [
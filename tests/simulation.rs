@@ -0,0 +1,85 @@
+use planes_of_booty::{
+    arena,
+    assets::{AssetManager, Headless},
+    ecs::{
+        component::AnimatedSprite,
+        resource::{AnimationEvents, Arena, ShakeQueue},
+    },
+    math::Point2f,
+    net::world_hash,
+    prefab::Prefabs,
+    simulation::{Simulation, TIMESTEP},
+};
+use specs::WorldExt;
+
+const SEED: u64 = 42;
+const TICKS: u64 = 600;
+
+fn hashes(seed: u64, players: usize) -> Vec<u64> {
+    let mut sim = Simulation::new(TIMESTEP, seed, players);
+    sim.world().write_resource::<Arena>().change_to = Some(arena::Kind::Generated { seed });
+    (0..TICKS / 60)
+        .map(|_| {
+            sim.run(60);
+            world_hash(sim.world())
+        })
+        .collect()
+}
+
+#[test]
+fn runs_without_a_window() {
+    let mut sim = Simulation::new(TIMESTEP, SEED, 1);
+    sim.world().write_resource::<Arena>().change_to = Some(arena::Kind::Static(arena::IDS[0]));
+    sim.run(TICKS);
+    assert_eq!(sim.world().read_resource::<Arena>().current, Some(arena::Kind::Static(arena::IDS[0])));
+}
+
+#[test]
+fn same_seed_same_world() {
+    assert_eq!(hashes(SEED, 1), hashes(SEED, 1));
+    assert_eq!(hashes(SEED, 2), hashes(SEED, 2));
+}
+
+#[test]
+fn different_seed_different_world() {
+    assert_ne!(hashes(SEED, 1), hashes(SEED + 1, 1));
+}
+
+#[test]
+fn one_shot_clip_goes_on_and_its_event_lasts_one_tick() {
+    let mut sim = Simulation::new(TIMESTEP, SEED, 1);
+    let whale = {
+        let world = sim.world();
+        let mut assets = world.write_resource::<AssetManager>();
+        world.read_resource::<Prefabs>().spawn("e_whale", world, &mut Headless, &mut assets).unwrap()
+    };
+    // the first form starts its clip once spawned
+    sim.run(1);
+    let clip = |sim: &Simulation| sim.world().read_storage::<AnimatedSprite>().get(whale).unwrap().clip.clone();
+    assert_eq!(clip(&sim), "swim");
+
+    sim.world().write_storage::<AnimatedSprite>().get_mut(whale).unwrap().play("splash".into());
+    for _ in 0..60 {
+        sim.run(1);
+        if clip(&sim) != "splash" {
+            break;
+        }
+    }
+    assert_eq!(clip(&sim), "swim");
+    {
+        let events = sim.world().read_resource::<AnimationEvents>();
+        let ended: Vec<_> = events.0.iter().map(|event| (event.entity, event.clip.clone())).collect();
+        assert_eq!(ended, vec![(whale, "splash".into())]);
+    }
+
+    sim.run(1);
+    assert!(sim.world().read_resource::<AnimationEvents>().0.is_empty());
+}
+
+#[test]
+fn shakes_are_applied_by_the_camera() {
+    let mut sim = Simulation::new(TIMESTEP, SEED, 1);
+    sim.world().write_resource::<ShakeQueue>().shake(Point2f::origin(), 1.0);
+    sim.run(1);
+    assert!(sim.world().read_resource::<ShakeQueue>().0.is_empty());
+}